@@ -0,0 +1,94 @@
+use std::hint::black_box;
+use std::io::Cursor;
+use std::net::Ipv4Addr;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nfdump::nfx_v3::{ExFlowMisc, ExGenericFlow, ExIpv4Flow, RecordV3};
+use nfdump::record::{NfFileRecordHeader, RecordKind};
+use nfdump::writer::NfFileWriter;
+use nfdump::{Compression, NfFileReader};
+
+const RECORDS: u64 = 100_000;
+
+fn record(i: u64) -> RecordV3 {
+    RecordV3 {
+        generic_flow: Some(ExGenericFlow {
+            msec_first: 1_700_000_000_000 + i,
+            msec_last: 1_700_000_001_000 + i,
+            msec_received: 0,
+            in_packets: i % 100 + 1,
+            in_bytes: (i % 100 + 1) * 576,
+            src_port: (i % 60000) as u16,
+            dst_port: 443,
+            proto: 6,
+            tcp_flags: 0x1b,
+            fwd_status: 0,
+            src_tos: 0,
+        }),
+        ipv4_flow: Some(ExIpv4Flow {
+            src_addr: Ipv4Addr::from(0x0a00_0000 + (i % 65536) as u32),
+            dst_addr: Ipv4Addr::new(192, 0, 2, (i % 256) as u8),
+        }),
+        flow_misc: Some(ExFlowMisc {
+            input: 1,
+            output: 2,
+            src_mask: 24,
+            dst_mask: 24,
+            dir: 0,
+            dst_tos: 0,
+            bi_flow_dir: 0,
+            flow_end_reason: 0,
+            rev_tcp_flags: 0,
+            fill: 0,
+        }),
+        ..Default::default()
+    }
+}
+
+fn file(compression: Compression) -> Vec<u8> {
+    let mut writer = NfFileWriter::new(Cursor::new(Vec::new()), compression).unwrap();
+    for i in 0..RECORDS {
+        writer.write_record(&record(i)).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn block_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_decode");
+    group.throughput(Throughput::Elements(RECORDS));
+    for (name, compression) in [
+        ("plain", Compression::None),
+        ("lzo", Compression::Lzo),
+        ("lz4", Compression::Lz4),
+        ("zstd", Compression::Zstd),
+    ] {
+        let data = file(compression);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &data, |b, data| {
+            b.iter(|| {
+                let mut reader = NfFileReader::new(Cursor::new(data.as_slice())).unwrap();
+                let mut count = 0;
+                while let Ok(r) = reader.read_record() {
+                    if let RecordKind::RecordV3(r) = r {
+                        count += black_box(r).head.num_elements as u64;
+                    }
+                }
+                count
+            })
+        });
+    }
+    group.finish();
+}
+
+fn record_parse(c: &mut Criterion) {
+    let bytes = record(42).to_bytes().unwrap();
+    let header = NfFileRecordHeader {
+        rtype: u16::from_le_bytes([bytes[0], bytes[1]]),
+        size: u16::from_le_bytes([bytes[2], bytes[3]]),
+    };
+    let body = &bytes[4..];
+    c.bench_function("record_parse", |b| {
+        b.iter(|| RecordV3::from_slice(header, black_box(body)).unwrap())
+    });
+}
+
+criterion_group!(benches, block_decode, record_parse);
+criterion_main!(benches);
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::io::{Seek, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::error::NfdumpError;
+use crate::flow::FlowRecord;
+use crate::nfx_v3::{ExCntFlow, ExGenericFlow, ExIpv4Flow, ExIpv6Flow, RecordV3};
+use crate::record::RecordKind;
+use crate::writer::NfFileWriter;
+
+/// `StatElement` is the record field flows are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatElement {
+    SrcIp,
+    DstIp,
+    /// Source and destination address, each flow counted for both.
+    Ip,
+    SrcPort,
+    DstPort,
+    /// Source and destination port, each flow counted for both.
+    Port,
+    Proto,
+}
+
+impl StatElement {
+    /// Returns the column title nfdump uses for the element.
+    pub fn title(&self) -> &'static str {
+        match self {
+            StatElement::SrcIp => "Src IP Addr",
+            StatElement::DstIp => "Dst IP Addr",
+            StatElement::Ip => "IP Addr",
+            StatElement::SrcPort => "Src Port",
+            StatElement::DstPort => "Dst Port",
+            StatElement::Port => "Port",
+            StatElement::Proto => "Protocol",
+        }
+    }
+}
+
+/// `StatKey` is a value of a `StatElement`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StatKey {
+    Ip(IpAddr),
+    Port(u16),
+    Proto(u8),
+}
+
+/// `Counters` holds the totals of a group of flows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub flows: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    /// First seen timestamp in milliseconds since the epoch, 0 if unknown.
+    pub first_msec: u64,
+    /// Last seen timestamp in milliseconds since the epoch.
+    pub last_msec: u64,
+    /// Protocol of the flows, if they all share one.
+    pub proto: Option<u8>,
+}
+
+impl Counters {
+    /// Adds one flow record.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        let proto = record.proto();
+        self.proto = if self.flows == 0 || self.proto == proto { proto } else { None };
+        self.flows += record.flows();
+        self.packets += record.packets().unwrap_or(0);
+        self.bytes += record.bytes().unwrap_or(0);
+        if let Some(first) = record.first_msec().filter(|&f| f != 0) {
+            if self.first_msec == 0 || first < self.first_msec {
+                self.first_msec = first;
+            }
+        }
+        self.last_msec = self.last_msec.max(record.last_msec().unwrap_or(0));
+    }
+
+    /// Returns the time between first and last seen in milliseconds.
+    pub fn duration_msec(&self) -> u64 {
+        self.last_msec.saturating_sub(self.first_msec)
+    }
+}
+
+/// `OrderBy` selects the counter groups are ranked by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderBy {
+    Flows,
+    Packets,
+    Bytes,
+}
+
+impl OrderBy {
+    /// Returns the name nfdump uses for the order.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OrderBy::Flows => "flows",
+            OrderBy::Packets => "packets",
+            OrderBy::Bytes => "bytes",
+        }
+    }
+
+    pub(crate) fn value(&self, c: &Counters) -> u64 {
+        match self {
+            OrderBy::Flows => c.flows,
+            OrderBy::Packets => c.packets,
+            OrderBy::Bytes => c.bytes,
+        }
+    }
+}
+
+/// `Aggregator` groups flow records by a `StatElement`, like the statistics
+/// of `nfdump -s`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::aggregate::{Aggregator, OrderBy, StatElement};
+///
+/// let mut agg = Aggregator::new(StatElement::SrcIp);
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     agg.add_kind(&record);
+/// }
+/// for (ip, counters) in agg.top(10, OrderBy::Bytes) {
+///     println!("{:?} {}", ip, counters.bytes);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Aggregator {
+    element: StatElement,
+    groups: HashMap<StatKey, Counters>,
+    totals: Counters,
+}
+
+impl Aggregator {
+    /// Creates an empty aggregator grouping by `element`.
+    pub fn new(element: StatElement) -> Aggregator {
+        Aggregator {
+            element,
+            groups: HashMap::new(),
+            totals: Counters::default(),
+        }
+    }
+
+    /// Returns the element flows are grouped by.
+    pub fn element(&self) -> StatElement {
+        self.element
+    }
+
+    /// Adds a flow record.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        self.totals.add(record);
+        let keys = match self.element {
+            StatElement::SrcIp => [record.src_addr().map(StatKey::Ip), None],
+            StatElement::DstIp => [record.dst_addr().map(StatKey::Ip), None],
+            StatElement::Ip => [record.src_addr().map(StatKey::Ip), record.dst_addr().map(StatKey::Ip)],
+            StatElement::SrcPort => [record.src_port().map(StatKey::Port), None],
+            StatElement::DstPort => [record.dst_port().map(StatKey::Port), None],
+            StatElement::Port => [record.src_port().map(StatKey::Port), record.dst_port().map(StatKey::Port)],
+            StatElement::Proto => [record.proto().map(StatKey::Proto), None],
+        };
+        let [first, second] = keys;
+        if let Some(key) = first {
+            self.groups.entry(key).or_default().add(record);
+        }
+        if let Some(key) = second.filter(|k| Some(*k) != first) {
+            self.groups.entry(key).or_default().add(record);
+        }
+    }
+
+    /// Adds a flow record of either version; other records are ignored.
+    pub fn add_kind(&mut self, record: &RecordKind) {
+        match record {
+            RecordKind::Record(r) => self.add(r),
+            RecordKind::RecordV3(r) => self.add(r),
+            _ => {}
+        }
+    }
+
+    /// Returns the totals over all records added.
+    pub fn totals(&self) -> &Counters {
+        &self.totals
+    }
+
+    /// Returns the number of distinct keys.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Returns `true` if no record was added.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Returns the counters of `key`.
+    pub fn get(&self, key: &StatKey) -> Option<&Counters> {
+        self.groups.get(key)
+    }
+
+    /// Returns the `n` largest groups ranked by `order`, largest first. Ties
+    /// are ordered by key.
+    pub fn top(&self, n: usize, order: OrderBy) -> Vec<(StatKey, Counters)> {
+        let mut groups: Vec<(StatKey, Counters)> = self.groups.iter().map(|(k, c)| (*k, *c)).collect();
+        groups.sort_unstable_by(|a, b| order.value(&b.1).cmp(&order.value(&a.1)).then(a.0.cmp(&b.0)));
+        groups.truncate(n);
+        groups
+    }
+
+    /// Returns one record per group, ordered by key, holding the key and the
+    /// summed counters of the group. The number of flows is stored in the
+    /// record's `cnt_flow` extension. Fields that are not part of the key
+    /// are left unset.
+    pub fn to_records(&self) -> Vec<RecordV3> {
+        let mut groups: Vec<(&StatKey, &Counters)> = self.groups.iter().collect();
+        groups.sort_unstable_by_key(|(k, _)| **k);
+        groups.into_iter().map(|(k, c)| self.group_record(k, c)).collect()
+    }
+
+    /// Writes the records of `to_records` to `writer`, like `nfdump -a -w`,
+    /// so aggregated results can be kept and read again as an nfdump file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::{Compression, NfFileReader};
+    /// use nfdump::aggregate::{Aggregator, StatElement};
+    /// use nfdump::writer::NfFileWriter;
+    ///
+    /// let mut agg = Aggregator::new(StatElement::DstPort);
+    /// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+    /// while let Ok(record) = reader.read_record() {
+    ///     agg.add_kind(&record);
+    /// }
+    /// let mut writer = NfFileWriter::new(File::create("aggregated").unwrap(), Compression::Lz4).unwrap();
+    /// agg.write_records(&mut writer).unwrap();
+    /// writer.finish().unwrap();
+    /// ```
+    pub fn write_records<W: Write + Seek>(&self, writer: &mut NfFileWriter<W>) -> Result<(), NfdumpError> {
+        for record in self.to_records() {
+            writer.write_record(&record)?;
+        }
+        Ok(())
+    }
+
+    fn group_record(&self, key: &StatKey, counters: &Counters) -> RecordV3 {
+        let mut generic = ExGenericFlow {
+            msec_first: counters.first_msec,
+            msec_last: counters.last_msec,
+            msec_received: 0,
+            in_packets: counters.packets,
+            in_bytes: counters.bytes,
+            src_port: 0,
+            dst_port: 0,
+            proto: counters.proto.unwrap_or(0),
+            tcp_flags: 0,
+            fwd_status: 0,
+            src_tos: 0,
+        };
+        let mut record = RecordV3 {
+            cnt_flow: Some(ExCntFlow {
+                flows: counters.flows,
+                out_packets: 0,
+                out_bytes: 0,
+            }),
+            ..Default::default()
+        };
+
+        // keys matching either side are stored as the source
+        let unspecified = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let (src, dst) = match (key, self.element) {
+            (StatKey::Ip(ip), StatElement::DstIp) => (unspecified, *ip),
+            (StatKey::Ip(ip), _) => (*ip, unspecified),
+            _ => (unspecified, unspecified),
+        };
+        match (src, dst) {
+            (IpAddr::V4(src_addr), IpAddr::V4(dst_addr)) => record.ipv4_flow = Some(ExIpv4Flow { src_addr, dst_addr }),
+            (IpAddr::V6(src_addr), _) => {
+                record.ipv6_flow = Some(ExIpv6Flow {
+                    src_addr,
+                    dst_addr: Ipv6Addr::UNSPECIFIED,
+                })
+            }
+            (_, IpAddr::V6(dst_addr)) => {
+                record.ipv6_flow = Some(ExIpv6Flow {
+                    src_addr: Ipv6Addr::UNSPECIFIED,
+                    dst_addr,
+                })
+            }
+        }
+        match (key, self.element) {
+            (StatKey::Port(port), StatElement::DstPort) => generic.dst_port = *port,
+            (StatKey::Port(port), _) => generic.src_port = *port,
+            (StatKey::Proto(proto), _) => generic.proto = *proto,
+            _ => {}
+        }
+        record.generic_flow = Some(generic);
+        record
+    }
+}
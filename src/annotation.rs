@@ -0,0 +1,142 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use crate::flow::FlowRecord;
+use crate::record::RecordEnvelope;
+
+/// `Annotations` is a typed map of metadata attached to a record, holding
+/// at most one value per type.
+///
+/// Enrichment stages define their own types, or newtypes around common
+/// ones, so their values don't collide.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::annotation::Annotations;
+///
+/// struct Hostname(String);
+///
+/// let mut annotations = Annotations::new();
+/// annotations.insert(Hostname("www.example.com".to_string()));
+/// if let Some(Hostname(name)) = annotations.get::<Hostname>() {
+///     println!("{}", name);
+/// }
+/// ```
+#[derive(Default)]
+pub struct Annotations {
+    values: HashMap<TypeId, (&'static str, Box<dyn Any + Send + Sync>)>,
+}
+
+impl Annotations {
+    /// Creates an empty map.
+    pub fn new() -> Annotations {
+        Annotations::default()
+    }
+
+    /// Attaches `value`, returning the previous value of the same type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.values
+            .insert(TypeId::of::<T>(), (std::any::type_name::<T>(), Box::new(value)))
+            .and_then(|(_, old)| old.downcast().ok().map(|old| *old))
+    }
+
+    /// Returns the value of type `T`.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>()).and_then(|(_, v)| v.downcast_ref())
+    }
+
+    /// Returns the value of type `T` for modification.
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>()).and_then(|(_, v)| v.downcast_mut())
+    }
+
+    /// Returns the value of type `T`, inserting `T::default()` first if
+    /// there is none.
+    pub fn get_or_default<T: Any + Send + Sync + Default>(&mut self) -> &mut T {
+        if !self.contains::<T>() {
+            self.insert(T::default());
+        }
+        self.get_mut().unwrap()
+    }
+
+    /// Detaches and returns the value of type `T`.
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.values
+            .remove(&TypeId::of::<T>())
+            .and_then(|(_, v)| v.downcast().ok().map(|v| *v))
+    }
+
+    /// Returns `true` if a value of type `T` is attached.
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if no value is attached.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Removes all values.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+impl fmt::Debug for Annotations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.values.values().map(|(name, _)| *name).collect();
+        names.sort_unstable();
+        f.debug_tuple("Annotations").field(&names).finish()
+    }
+}
+
+/// `Annotator` is an enrichment stage that attaches metadata to records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::annotation::Annotator;
+/// use nfdump::bogon::{BogonClassifier, BogonMatch};
+///
+/// let bogons = BogonClassifier::new();
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// while let Ok(mut env) = reader.read_record_envelope() {
+///     env.annotate(&bogons);
+///     if let Some(matches) = env.annotations.get::<Vec<BogonMatch>>() {
+///         println!("{:?}", matches);
+///     }
+/// }
+/// ```
+pub trait Annotator {
+    /// Attaches the metadata of `record` to `annotations`.
+    fn annotate(&self, record: &dyn FlowRecord, annotations: &mut Annotations);
+}
+
+impl<A: Annotator + ?Sized> Annotator for Box<A> {
+    fn annotate(&self, record: &dyn FlowRecord, annotations: &mut Annotations) {
+        (**self).annotate(record, annotations)
+    }
+}
+
+impl<A: Annotator + ?Sized> Annotator for std::sync::Arc<A> {
+    fn annotate(&self, record: &dyn FlowRecord, annotations: &mut Annotations) {
+        (**self).annotate(record, annotations)
+    }
+}
+
+impl RecordEnvelope {
+    /// Runs `annotator` on the record if it is a flow record.
+    pub fn annotate(&mut self, annotator: &dyn Annotator) {
+        if let Some(record) = self.record.flow_record() {
+            annotator.annotate(record, &mut self.annotations);
+        }
+    }
+}
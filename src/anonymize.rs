@@ -0,0 +1,118 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::error::NfdumpError;
+use crate::nfx_v3::RecordV3;
+use crate::record::Record;
+
+/// `SubnetAnonymizer` hides hosts by zeroing the host bits of every address
+/// in a record, keeping only the network prefix. Unlike prefix-preserving
+/// schemes it needs no key, but hosts within the same prefix become
+/// indistinguishable.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::anonymize::SubnetAnonymizer;
+/// use nfdump::record::RecordKind;
+///
+/// let anonymizer = SubnetAnonymizer::new(24, 48).unwrap();
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// if let Ok(RecordKind::RecordV3(mut record)) = reader.read_record() {
+///     anonymizer.anonymize(&mut record);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SubnetAnonymizer {
+    v4_mask: u32,
+    v6_mask: u128,
+}
+
+impl SubnetAnonymizer {
+    /// Creates an anonymizer keeping `v4_prefix` bits of IPv4 addresses and
+    /// `v6_prefix` bits of IPv6 addresses.
+    ///
+    /// Returns `NfdumpError::ParseError` if a prefix length is longer than
+    /// the address.
+    pub fn new(v4_prefix: u8, v6_prefix: u8) -> Result<SubnetAnonymizer, NfdumpError> {
+        if v4_prefix > 32 || v6_prefix > 128 {
+            return Err(NfdumpError::ParseError);
+        }
+
+        Ok(SubnetAnonymizer {
+            v4_mask: u32::MAX.checked_shl(32 - v4_prefix as u32).unwrap_or(0),
+            v6_mask: u128::MAX.checked_shl(128 - v6_prefix as u32).unwrap_or(0),
+        })
+    }
+
+    /// Truncates an IPv4 address to the configured prefix.
+    pub fn anonymize_ipv4(&self, addr: Ipv4Addr) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(addr) & self.v4_mask)
+    }
+
+    /// Truncates an IPv6 address to the configured prefix.
+    pub fn anonymize_ipv6(&self, addr: Ipv6Addr) -> Ipv6Addr {
+        Ipv6Addr::from(u128::from(addr) & self.v6_mask)
+    }
+
+    /// Truncates an address to the prefix configured for its family.
+    pub fn anonymize_ip(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(a) => IpAddr::V4(self.anonymize_ipv4(a)),
+            IpAddr::V6(a) => IpAddr::V6(self.anonymize_ipv6(a)),
+        }
+    }
+
+    /// Truncates all addresses of a V3 record: flow, next hop, BGP next hop,
+    /// router, NAT translated and tunnel addresses.
+    pub fn anonymize(&self, record: &mut RecordV3) {
+        if let Some(e) = &mut record.ipv4_flow {
+            e.src_addr = self.anonymize_ipv4(e.src_addr);
+            e.dst_addr = self.anonymize_ipv4(e.dst_addr);
+        }
+        if let Some(e) = &mut record.ipv6_flow {
+            e.src_addr = self.anonymize_ipv6(e.src_addr);
+            e.dst_addr = self.anonymize_ipv6(e.dst_addr);
+        }
+        if let Some(e) = &mut record.bgp_next_hop_ipv4 {
+            e.ip = self.anonymize_ipv4(e.ip);
+        }
+        if let Some(e) = &mut record.bgp_next_hop_ipv6 {
+            e.ip = self.anonymize_ipv6(e.ip);
+        }
+        if let Some(e) = &mut record.ip_next_hop_ipv4 {
+            e.ip = self.anonymize_ipv4(e.ip);
+        }
+        if let Some(e) = &mut record.ip_next_hop_ipv6 {
+            e.ip = self.anonymize_ipv6(e.ip);
+        }
+        if let Some(e) = &mut record.ip_received_ipv4 {
+            e.ip = self.anonymize_ipv4(e.ip);
+        }
+        if let Some(e) = &mut record.ip_received_ipv6 {
+            e.ip = self.anonymize_ipv6(e.ip);
+        }
+        if let Some(e) = &mut record.nsel_xlate_ipv4 {
+            e.src_addr = self.anonymize_ipv4(e.src_addr);
+            e.dst_addr = self.anonymize_ipv4(e.dst_addr);
+        }
+        if let Some(e) = &mut record.nsel_xlate_ipv6 {
+            e.src_addr = self.anonymize_ipv6(e.src_addr);
+            e.dst_addr = self.anonymize_ipv6(e.dst_addr);
+        }
+        if let Some(e) = &mut record.tun_ipv4 {
+            e.src_addr = self.anonymize_ipv4(e.src_addr);
+            e.dst_addr = self.anonymize_ipv4(e.dst_addr);
+        }
+        if let Some(e) = &mut record.tun_ipv6 {
+            e.src_addr = self.anonymize_ipv6(e.src_addr);
+            e.dst_addr = self.anonymize_ipv6(e.dst_addr);
+        }
+    }
+
+    /// Truncates the source and destination addresses of a legacy record.
+    pub fn anonymize_record(&self, record: &mut Record) {
+        record.src_addr = self.anonymize_ip(record.src_addr);
+        record.dst_addr = self.anonymize_ip(record.dst_addr);
+    }
+}
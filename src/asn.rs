@@ -0,0 +1,142 @@
+use std::str::FromStr;
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+
+/// `AsSet` is a set of autonomous system numbers and ranges.
+///
+/// Like nfdump, AS 0 stands for an unknown AS: it matches records that carry
+/// AS 0 as well as records without AS information.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::asn::AsSet;
+///
+/// let set: AsSet = "13335,64512-65534".parse().unwrap();
+/// assert!(set.contains(64600));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AsSet {
+    // sorted, non-overlapping inclusive ranges
+    ranges: Vec<(u32, u32)>,
+}
+
+impl AsSet {
+    /// Creates an empty set.
+    pub fn new() -> AsSet {
+        AsSet { ranges: Vec::new() }
+    }
+
+    /// Adds a single AS number to the set.
+    pub fn insert(&mut self, asn: u32) {
+        self.insert_range(asn, asn);
+    }
+
+    /// Adds the inclusive range `start..=end` to the set.
+    pub fn insert_range(&mut self, start: u32, end: u32) {
+        let (mut start, mut end) = (start.min(end), start.max(end));
+        self.ranges.retain(|&(s, e)| {
+            let overlaps = s <= end.saturating_add(1) && start <= e.saturating_add(1);
+            if overlaps {
+                start = start.min(s);
+                end = end.max(e);
+            }
+            !overlaps
+        });
+        let pos = self.ranges.partition_point(|&(s, _)| s < start);
+        self.ranges.insert(pos, (start, end));
+    }
+
+    /// Returns `true` if the set contains `asn`.
+    pub fn contains(&self, asn: u32) -> bool {
+        let pos = self.ranges.partition_point(|&(s, _)| s <= asn);
+        pos > 0 && self.ranges[pos - 1].1 >= asn
+    }
+
+    /// Returns `true` if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+impl FromStr for AsSet {
+    type Err = NfdumpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = AsSet::new();
+        for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            match item.split_once('-') {
+                Some((start, end)) => {
+                    let start = start.trim().parse::<u32>().map_err(|_| NfdumpError::ParseError)?;
+                    let end = end.trim().parse::<u32>().map_err(|_| NfdumpError::ParseError)?;
+                    if start > end {
+                        return Err(NfdumpError::ParseError);
+                    }
+                    set.insert_range(start, end);
+                }
+                None => set.insert(item.parse::<u32>().map_err(|_| NfdumpError::ParseError)?),
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl FromIterator<u32> for AsSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        let mut set = AsSet::new();
+        iter.into_iter().for_each(|a| set.insert(a));
+        set
+    }
+}
+
+/// `AsField` selects which AS number of a record a filter looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsField {
+    Src,
+    Dst,
+    /// Either the source or the destination AS.
+    SrcOrDst,
+}
+
+/// `AsFilter` selects records whose AS number in `field` is in an `AsSet`.
+///
+/// The AS numbers come from the `as_routing` extension of V3 records or the
+/// optional AS fields of legacy records; missing values count as AS 0.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::asn::{AsField, AsFilter};
+///
+/// let filter = AsFilter::new(AsField::Src, "13335".parse().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsFilter {
+    field: AsField,
+    set: AsSet,
+}
+
+impl AsFilter {
+    /// Creates a filter matching `field` against `set`.
+    pub fn new(field: AsField, set: AsSet) -> AsFilter {
+        AsFilter { field, set }
+    }
+
+    /// Returns the AS numbers of the filter.
+    pub fn set(&self) -> &AsSet {
+        &self.set
+    }
+}
+
+impl Filter for AsFilter {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        let src = || record.src_as().unwrap_or(0);
+        let dst = || record.dst_as().unwrap_or(0);
+        match self.field {
+            AsField::Src => self.set.contains(src()),
+            AsField::Dst => self.set.contains(dst()),
+            AsField::SrcOrDst => self.set.contains(src()) || self.set.contains(dst()),
+        }
+    }
+}
@@ -1,29 +1,82 @@
-use std::io::{Cursor, Read};
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read};
 use byteorder::{LittleEndian, ReadBytesExt};
-use crate::compress::Decompressor;
-use crate::exporter::{read_exporter_record, read_samplerv0_record};
+use crate::compress::{BufferPool, Compression, Decompressor};
+use crate::error::{ErrorContext, NfdumpError};
+use crate::exporter::{read_exporter_record, read_exporter_stats_record, read_if_name_record, read_sampler_record, read_samplerv0_record};
 use crate::nffilev2::StatRecordV2;
 use crate::nfx::read_extension_map;
 use crate::nfx_v3::RecordV3;
+use crate::projection::Projection;
+use crate::pushdown::Pushdown;
 use crate::record::*;
+use crate::read_full;
 
-const TYPE_COMMON_RECORD_V0: u16 = 0x0001;
-const TYPE_EXTENSION_MAP: u16 = 0x0002;
-const TYPE_PORT_HISTOGRAM: u16 = 0x0003;
-const TYPE_BPP_HISTOGRAM: u16 = 0x0004;
-const TYPE_LEGACY_RECORD_1: u16 = 0x0005;
-const TYPE_LEGACY_RECORD_2: u16 = 0x0006;
-const TYPE_EXPORTER_INFO: u16 = 0x0007;
-const TYPE_EXPORTER_STAT: u16 = 0x0008;
-const TYPE_LEGACY_SAMPLER: u16 = 0x0009;
-const TYPE_COMMON_RECORD: u16 = 0x000a;
-const TYPE_RECORD_V3: u16 = 0x000b;
-const TYPE_NBAR_RECORD: u16 = 0x000c;
-const TYPE_IF_NAME_RECORD: u16 = 0x000d;
-const TYPE_VRF_NAME_RECORD: u16 = 0x000e;
-const TYPE_SAMPLER: u16 = 0x000f;
-const TYPE_IDENT: u16 = 0x8001;
-const TYPE_STAT: u16 = 0x8002;
+pub(crate) const TYPE_COMMON_RECORD_V0: u16 = 0x0001;
+pub(crate) const TYPE_EXTENSION_MAP: u16 = 0x0002;
+pub(crate) const TYPE_PORT_HISTOGRAM: u16 = 0x0003;
+pub(crate) const TYPE_BPP_HISTOGRAM: u16 = 0x0004;
+pub(crate) const TYPE_LEGACY_RECORD_1: u16 = 0x0005;
+pub(crate) const TYPE_LEGACY_RECORD_2: u16 = 0x0006;
+pub(crate) const TYPE_EXPORTER_INFO: u16 = 0x0007;
+pub(crate) const TYPE_EXPORTER_STAT: u16 = 0x0008;
+pub(crate) const TYPE_LEGACY_SAMPLER: u16 = 0x0009;
+pub(crate) const TYPE_COMMON_RECORD: u16 = 0x000a;
+pub(crate) const TYPE_RECORD_V3: u16 = 0x000b;
+pub(crate) const TYPE_NBAR_RECORD: u16 = 0x000c;
+pub(crate) const TYPE_IF_NAME_RECORD: u16 = 0x000d;
+pub(crate) const TYPE_VRF_NAME_RECORD: u16 = 0x000e;
+pub(crate) const TYPE_SAMPLER: u16 = 0x000f;
+pub(crate) const TYPE_IDENT: u16 = 0x8001;
+pub(crate) const TYPE_STAT: u16 = 0x8002;
+
+/// `BlockFlags` are the flags of a data block header.
+///
+/// nfdump sets `UNCOMPRESSED` on blocks it stored as they are, e.g. because
+/// compressing them did not pay off, so a file can mix compressed and
+/// uncompressed blocks.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct BlockFlags(u16);
+
+impl BlockFlags {
+    /// The block data is not compressed, whatever the file's compression.
+    pub const UNCOMPRESSED: u16 = 0x0001;
+
+    /// Creates `BlockFlags` from the raw header value.
+    pub fn from_bits(bits: u16) -> BlockFlags {
+        BlockFlags(bits)
+    }
+
+    /// Returns the raw header value.
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns `true` if the block is stored uncompressed.
+    pub fn is_uncompressed(&self) -> bool {
+        self.0 & BlockFlags::UNCOMPRESSED != 0
+    }
+
+    /// Returns the flags this crate does not interpret.
+    pub fn unknown(&self) -> u16 {
+        self.0 & !BlockFlags::UNCOMPRESSED
+    }
+
+    /// Returns the compression of the block in a file compressed with
+    /// `file`.
+    pub fn compression(&self, file: Compression) -> Compression {
+        match self.is_uncompressed() {
+            true => Compression::None,
+            false => file,
+        }
+    }
+}
+
+impl From<u16> for BlockFlags {
+    fn from(bits: u16) -> BlockFlags {
+        BlockFlags(bits)
+    }
+}
 
 #[allow(dead_code)]
 pub(crate) struct DataBlockHeader {
@@ -31,64 +84,299 @@ pub(crate) struct DataBlockHeader {
     pub(crate) size: u32,
     // id is type in v3/v4 block (v2 files only)
     pub(crate) id: u16,
-    pub(crate) flags: u16,
+    pub(crate) flags: BlockFlags,
 }
 
 pub(crate) struct DataBlock {
-    pub(crate) decoder: Box<Decompressor>,
+    pub(crate) decoder: Decompressor,
     pub(crate) _header: DataBlockHeader,
     // pub(crate) data: Vec<u8>,
+    // offset of the next record within the decompressed block
+    pub(crate) offset: u64,
+    pub(crate) last_record_offset: u64,
+    pub(crate) last_record_type: u16,
+    pub(crate) records_read: u32,
+    // records skipped by the pushdown predicates
+    pub(crate) records_pruned: u32,
+    // maximum decompressed size of the block
+    limit: usize,
+    // body of the record being decoded, reused for every record
+    scratch: Vec<u8>,
 }
 
 impl DataBlock {
-    pub(crate) fn new(header: DataBlockHeader, decoder: Box<Decompressor>) -> DataBlock {
+    pub(crate) fn new(header: DataBlockHeader, decoder: Decompressor, pool: &mut BufferPool, limit: usize) -> DataBlock {
         DataBlock {
             _header: header,
             decoder,
+            offset: 0,
+            last_record_offset: 0,
+            last_record_type: 0,
+            records_read: 0,
+            records_pruned: 0,
+            limit,
+            scratch: pool.take(),
         }
     }
 
-    fn _read_record_kind(&mut self, header: &NfFileRecordHeader, ext: &Vec<u16>) -> RecordKind {
-        let mut record_data = vec![0; header.size as usize - 4];
-        _ = self.decoder.read_exact(&mut record_data);
-
-        match header.rtype {
-            TYPE_COMMON_RECORD_V0 => RecordKind::Unimplemented,
-            TYPE_EXTENSION_MAP => RecordKind::ExtensionMap(read_extension_map(*header, record_data).unwrap()),
-            TYPE_PORT_HISTOGRAM => RecordKind::Unimplemented,
-            TYPE_BPP_HISTOGRAM => RecordKind::Unimplemented,
-            TYPE_LEGACY_RECORD_1 => RecordKind::Unimplemented,
-            TYPE_LEGACY_RECORD_2 => RecordKind::Unimplemented,
-            TYPE_EXPORTER_INFO => RecordKind::ExporterInfo(read_exporter_record(*header, record_data).unwrap()),
-            TYPE_EXPORTER_STAT => RecordKind::Unimplemented,
-            TYPE_LEGACY_SAMPLER => RecordKind::SamplerV0(read_samplerv0_record(*header, record_data).unwrap()),
-            TYPE_COMMON_RECORD => RecordKind::Record(new_record(*header, record_data, ext).unwrap()),
-            TYPE_RECORD_V3 => RecordKind::RecordV3(RecordV3::new(*header, record_data).unwrap()),
-            TYPE_NBAR_RECORD => RecordKind::Unimplemented,
-            TYPE_IF_NAME_RECORD => RecordKind::Unimplemented,
-            TYPE_VRF_NAME_RECORD => RecordKind::Unimplemented,
-            TYPE_SAMPLER => RecordKind::Unimplemented,
-            TYPE_IDENT => RecordKind::Ident(record_data),
-            TYPE_STAT => RecordKind::Stat(StatRecordV2::from(record_data)),
-            _ => RecordKind::Unimplemented,
+    pub(crate) fn num_records(&self) -> u32 {
+        self._header.num_records
+    }
+
+    pub(crate) fn recycle(self, pool: &mut BufferPool) {
+        self.decoder.recycle(pool);
+        pool.give(self.scratch);
+    }
+
+    fn read_record_kind(
+        header: &NfFileRecordHeader,
+        record_data: &[u8],
+        ext: &HashMap<u16, Vec<u16>>,
+        projection: &Projection,
+    ) -> Result<RecordKind, NfdumpError> {
+        let unimplemented = || RecordKind::Unimplemented {
+            rtype: header.rtype,
+            data: record_data.to_vec(),
+        };
+        Ok(match header.rtype {
+            TYPE_COMMON_RECORD_V0 => unimplemented(),
+            TYPE_EXTENSION_MAP => RecordKind::ExtensionMap(read_extension_map(*header, record_data.to_vec())?),
+            TYPE_PORT_HISTOGRAM => unimplemented(),
+            TYPE_BPP_HISTOGRAM => unimplemented(),
+            TYPE_LEGACY_RECORD_1 => unimplemented(),
+            TYPE_LEGACY_RECORD_2 => unimplemented(),
+            TYPE_EXPORTER_INFO => match read_exporter_record(*header, record_data.to_vec()) {
+                Ok(e) => RecordKind::ExporterInfo(e),
+                Err(_) => unimplemented(),
+            },
+            TYPE_EXPORTER_STAT => match read_exporter_stats_record(*header, record_data.to_vec()) {
+                Ok(s) => RecordKind::ExporterStats(s),
+                Err(_) => unimplemented(),
+            },
+            TYPE_LEGACY_SAMPLER => RecordKind::SamplerV0(read_samplerv0_record(*header, record_data.to_vec())?),
+            TYPE_COMMON_RECORD => {
+                // the record's extension map id follows its flags
+                let map_id = match record_data.get(2..4) {
+                    Some(b) => u16::from_le_bytes([b[0], b[1]]),
+                    None => 0,
+                };
+                let ext = ext.get(&map_id).map_or(&[][..], Vec::as_slice);
+                RecordKind::Record(parse_record(*header, record_data, ext)?)
+            }
+            TYPE_RECORD_V3 => RecordKind::RecordV3(RecordV3::from_slice_projected(*header, record_data, projection)?),
+            TYPE_NBAR_RECORD => unimplemented(),
+            TYPE_IF_NAME_RECORD => match read_if_name_record(*header, record_data.to_vec()) {
+                Ok(names) => RecordKind::IfName(names),
+                Err(_) => unimplemented(),
+            },
+            TYPE_VRF_NAME_RECORD => unimplemented(),
+            TYPE_SAMPLER => match read_sampler_record(*header, record_data.to_vec()) {
+                Ok(s) => RecordKind::Sampler(s),
+                Err(_) => unimplemented(),
+            },
+            TYPE_IDENT => RecordKind::Ident(record_data.to_vec()),
+            TYPE_STAT => RecordKind::Stat(StatRecordV2::from(record_data.to_vec())),
+            _ => unimplemented(),
+        })
+    }
+
+    fn too_large(&self, record_type: Option<u16>) -> NfdumpError {
+        NfdumpError::BlockTooLarge {
+            limit: self.limit,
+            context: ErrorContext {
+                record: Some(self.records_read),
+                record_offset: Some(self.offset),
+                record_type,
+                ..Default::default()
+            },
         }
     }
 
-    pub(crate) fn read_record(&mut self, ext: &Vec<u16>) -> Option<RecordKind> {
+    // A failure of the block's decompressor, such as corrupt compressed data
+    fn decode_error(&self, record_type: Option<u16>, error: io::Error) -> NfdumpError {
+        if self.decoder.exceeded() {
+            return self.too_large(record_type);
+        }
+        NfdumpError::IoError(error)
+    }
+
+    fn unexpected_eof(&self, record_type: Option<u16>, expected: usize, available: usize) -> NfdumpError {
+        if self.decoder.exceeded() {
+            return self.too_large(record_type);
+        }
+        NfdumpError::UnexpectedEof {
+            context: ErrorContext {
+                record: Some(self.records_read),
+                record_offset: Some(self.offset),
+                record_type,
+                expected: Some(expected),
+                available: Some(available),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Counts the flow records left in the block, skipping over record bodies
+    /// without decoding them.
+    pub(crate) fn count_records(&mut self) -> u64 {
+        let mut count = 0;
         let mut header_data = [0; 4];
-        let record_header = match self.decoder.read_exact(&mut header_data) {
-            Ok(_) => {
-                let mut cursor = Cursor::new(&header_data);
-                NfFileRecordHeader {
-                    rtype: cursor.read_u16::<LittleEndian>().unwrap(),
-                    size: cursor.read_u16::<LittleEndian>().unwrap(),
+        while self.decoder.read_exact(&mut header_data).is_ok() {
+            let mut cursor = Cursor::new(&header_data);
+            let rtype = cursor.read_u16::<LittleEndian>().unwrap();
+            let size = cursor.read_u16::<LittleEndian>().unwrap() as u64;
+            self.offset += size;
+            self.records_read += 1;
+
+            let skip = size.saturating_sub(4);
+            let mut body = (&mut self.decoder).take(skip);
+            if io::copy(&mut body, &mut io::sink()).ok() != Some(skip) {
+                break;
+            }
+            if rtype == TYPE_COMMON_RECORD || rtype == TYPE_RECORD_V3 {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Reads the metadata records left in the block (extension maps,
+    /// exporters, samplers and interface names), skipping over all other
+    /// record bodies without decoding them.
+    pub(crate) fn read_metadata(&mut self) -> Vec<RecordKind> {
+        let mut metadata = Vec::new();
+        let mut header_data = [0; 4];
+        let no_ext = HashMap::new();
+        while self.decoder.read_exact(&mut header_data).is_ok() {
+            let mut cursor = Cursor::new(&header_data);
+            let header = NfFileRecordHeader {
+                rtype: cursor.read_u16::<LittleEndian>().unwrap(),
+                size: cursor.read_u16::<LittleEndian>().unwrap(),
+            };
+            self.offset += header.size as u64;
+            self.records_read += 1;
+
+            let skip = (header.size as u64).saturating_sub(4);
+            match header.rtype {
+                TYPE_EXTENSION_MAP | TYPE_EXPORTER_INFO | TYPE_EXPORTER_STAT | TYPE_LEGACY_SAMPLER
+                | TYPE_SAMPLER | TYPE_IF_NAME_RECORD => {
+                    self.scratch.resize(skip as usize, 0);
+                    if self.decoder.read_exact(&mut self.scratch).is_err() {
+                        break;
+                    }
+                    if let Ok(r) = DataBlock::read_record_kind(&header, &self.scratch, &no_ext, &Projection::all()) {
+                        metadata.push(r);
+                    }
                 }
-            },
-            Err(_) => {
-                return None;
-            },
-        };
+                _ => {
+                    let mut body = (&mut self.decoder).take(skip);
+                    if io::copy(&mut body, &mut io::sink()).ok() != Some(skip) {
+                        break;
+                    }
+                }
+            }
+        }
+        metadata
+    }
+
+    /// Reads the next record of the block, or `None` at the end of the block.
+    ///
+    /// Decoding failures are reported as `NfdumpError::ParseErrorAt` with the
+    /// position within the block filled in. V3 records are decoded as far as
+    /// `projection` asks for. Records ruled out by `pushdown` are skipped,
+    /// V3 records before they are decoded.
+    pub(crate) fn read_record(
+        &mut self,
+        ext: &HashMap<u16, Vec<u16>>,
+        projection: &Projection,
+        pushdown: &Pushdown,
+    ) -> Result<Option<RecordKind>, NfdumpError> {
+        loop {
+            let mut header_data = [0; 4];
+            let n = read_full(&mut self.decoder, &mut header_data).map_err(|e| self.decode_error(None, e))?;
+            if n == 0 {
+                if self.decoder.exceeded() {
+                    return Err(self.too_large(None));
+                }
+                return Ok(None);
+            }
+            if n < header_data.len() {
+                return Err(self.unexpected_eof(None, header_data.len(), n));
+            }
+            let mut cursor = Cursor::new(&header_data);
+            let record_header = NfFileRecordHeader {
+                rtype: cursor.read_u16::<LittleEndian>().unwrap(),
+                size: cursor.read_u16::<LittleEndian>().unwrap(),
+            };
+
+            // records of types not asked for are passed over undecoded
+            if !is_metadata(record_header.rtype) && !pushdown.keeps_type(record_header.rtype) {
+                let skip = (record_header.size as u64).saturating_sub(4);
+                let mut body = (&mut self.decoder).take(skip);
+                let n = io::copy(&mut body, &mut io::sink()).map_err(|e| self.decode_error(Some(record_header.rtype), e))?;
+                if n < skip {
+                    return Err(self.unexpected_eof(Some(record_header.rtype), record_header.size as usize, n as usize + 4));
+                }
+                self.advance(&record_header);
+                self.records_pruned += 1;
+                continue;
+            }
+
+            // a record cut short, e.g. in a truncated block
+            self.scratch.resize((record_header.size as usize).saturating_sub(4), 0);
+            let n = read_full(&mut self.decoder, &mut self.scratch).map_err(|e| self.decode_error(Some(record_header.rtype), e))?;
+            if n < self.scratch.len() {
+                return Err(self.unexpected_eof(Some(record_header.rtype), record_header.size as usize, n + 4));
+            }
+            self.advance(&record_header);
 
-        Some(self._read_record_kind(&record_header, ext))
+            if record_header.rtype == TYPE_RECORD_V3 && !pushdown.keeps_v3(&self.scratch) {
+                self.records_pruned += 1;
+                continue;
+            }
+
+            let size = self.scratch.len();
+            let record = DataBlock::read_record_kind(&record_header, &self.scratch, ext, projection).map_err(|e| {
+                let mut context = match e {
+                    NfdumpError::ParseErrorAt { context } => context,
+                    _ => ErrorContext::default(),
+                };
+                context.record = Some(self.records_read - 1);
+                context.record_offset = Some(self.last_record_offset);
+                context.record_type = Some(record_header.rtype);
+                context.available.get_or_insert(size);
+                NfdumpError::ParseErrorAt { context }
+            })?;
+            if let RecordKind::Record(r) = &record {
+                if !pushdown.keeps_flow(r) {
+                    self.records_pruned += 1;
+                    continue;
+                }
+            }
+            return Ok(Some(record));
+        }
     }
+
+    // Moves past the record with `header`, just read
+    fn advance(&mut self, header: &NfFileRecordHeader) {
+        self.last_record_offset = self.offset;
+        self.last_record_type = header.rtype;
+        self.offset += header.size as u64;
+        self.records_read += 1;
+    }
+}
+
+// Records the reader keeps track of, read regardless of any pushdown
+fn is_metadata(rtype: u16) -> bool {
+    matches!(
+        rtype,
+        TYPE_EXTENSION_MAP
+            | TYPE_EXPORTER_INFO
+            | TYPE_EXPORTER_STAT
+            | TYPE_LEGACY_SAMPLER
+            | TYPE_SAMPLER
+            | TYPE_IF_NAME_RECORD
+            | TYPE_IDENT
+            | TYPE_STAT
+    )
 }
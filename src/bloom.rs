@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::error::NfdumpError;
+use crate::{modified_secs, sidecar_path, sidecar_stale, NfFileReader};
+
+const MAGIC: &[u8; 4] = b"NFBL";
+const VERSION: u16 = 2;
+const SIDECAR_EXTENSION: &str = "bloom";
+
+/// `AddrBloom` is a Bloom filter of the addresses seen in an nfdump file.
+///
+/// A lookup never misses an address that was inserted, and reports an
+/// address that was not with about the false positive rate the filter was
+/// built for. Stored next to a file as a sidecar, it lets multi-file
+/// searches for an address skip the files that cannot contain it.
+///
+/// The hash functions are fixed, so filters written by one version of the
+/// library can be read by another. A filter built from a file records the
+/// file's size and modification time, so a sidecar left behind by a file
+/// that was since rewritten is not used.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::bloom::AddrBloom;
+/// use nfdump::demux::MultiReader;
+///
+/// // once, when the files are closed by the collector
+/// for path in ["nfcapd.202401010000", "nfcapd.202401010005"] {
+///     AddrBloom::build_file(path, 0.01).unwrap().write_sidecar(path).unwrap();
+/// }
+///
+/// // later, only files that may contain the address are read
+/// let addr = "192.0.2.1".parse().unwrap();
+/// let mut reader = MultiReader::open_containing(&["nfcapd.202401010000", "nfcapd.202401010005"], &[addr]).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddrBloom {
+    bits: Vec<u64>,
+    nbits: u64,
+    hashes: u16,
+    // size and modification time of the file the filter was built from
+    file_size: u64,
+    modified: u64,
+}
+
+impl AddrBloom {
+    /// Creates an empty filter sized for `items` addresses at a false
+    /// positive rate of `fp_rate`. It is not tied to a file, so
+    /// `open_sidecar` treats its sidecar as stale unless the file is empty.
+    pub fn new(items: usize, fp_rate: f64) -> AddrBloom {
+        let items = items.max(1) as f64;
+        let fp_rate = fp_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let nbits = (-items * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hashes = ((nbits as f64 / items) * ln2).round().clamp(1.0, 32.0) as u16;
+        AddrBloom {
+            bits: vec![0; nbits.div_ceil(64) as usize],
+            nbits,
+            hashes,
+            file_size: 0,
+            modified: 0,
+        }
+    }
+
+    /// Builds a filter of the source and destination addresses of the
+    /// records of `reader`, read to the end. Only the file size is recorded;
+    /// `build_file` also records the modification time.
+    pub fn build<R: Read + Seek>(reader: &mut NfFileReader<R>, fp_rate: f64) -> Result<AddrBloom, NfdumpError> {
+        let file_size = reader.file_size()?;
+        let mut addrs = HashSet::new();
+        loop {
+            let record = match reader.read_record() {
+                Ok(r) => r,
+                Err(NfdumpError::EOF) => break,
+                Err(e) => return Err(e),
+            };
+            if let Some(r) = record.flow_record() {
+                addrs.extend(r.src_addr());
+                addrs.extend(r.dst_addr());
+            }
+        }
+        let mut bloom = AddrBloom::new(addrs.len(), fp_rate);
+        addrs.into_iter().for_each(|a| bloom.insert(a));
+        bloom.file_size = file_size;
+        Ok(bloom)
+    }
+
+    /// Builds a filter of the addresses in the file at `path`.
+    pub fn build_file<P: AsRef<Path>>(path: P, fp_rate: f64) -> Result<AddrBloom, NfdumpError> {
+        let path = path.as_ref();
+        let mut reader = NfFileReader::new(BufReader::new(File::open(path)?))?;
+        let mut bloom = AddrBloom::build(&mut reader, fp_rate)?;
+        bloom.modified = modified_secs(&fs::metadata(path)?);
+        Ok(bloom)
+    }
+
+    /// Adds an address.
+    pub fn insert(&mut self, addr: IpAddr) {
+        for bit in self.bit_indexes(addr) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns `false` if `addr` was certainly not inserted.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        self.bit_indexes(addr)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Writes the filter in its sidecar format.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), NfdumpError> {
+        w.write_all(MAGIC)?;
+        w.write_u16::<LittleEndian>(VERSION)?;
+        w.write_u16::<LittleEndian>(self.hashes)?;
+        w.write_u64::<LittleEndian>(self.file_size)?;
+        w.write_u64::<LittleEndian>(self.modified)?;
+        w.write_u64::<LittleEndian>(self.nbits)?;
+        for word in &self.bits {
+            w.write_u64::<LittleEndian>(*word)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a filter written by `write_to`. Fails with
+    /// `NfdumpError::ParseError` if the input ends before the bits its
+    /// header declares.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<AddrBloom, NfdumpError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(NfdumpError::ParseError);
+        }
+        if r.read_u16::<LittleEndian>()? != VERSION {
+            return Err(NfdumpError::UnsupportedVersion);
+        }
+        let hashes = r.read_u16::<LittleEndian>()?;
+        let file_size = r.read_u64::<LittleEndian>()?;
+        let modified = r.read_u64::<LittleEndian>()?;
+        let nbits = r.read_u64::<LittleEndian>()?;
+        if hashes == 0 || nbits == 0 || nbits > u32::MAX as u64 * 64 {
+            return Err(NfdumpError::ParseError);
+        }
+        // grows with the data actually there rather than what the header
+        // claims
+        let len = nbits.div_ceil(64) * 8;
+        let mut data = Vec::new();
+        r.take(len).read_to_end(&mut data)?;
+        if data.len() as u64 != len {
+            return Err(NfdumpError::ParseError);
+        }
+        let bits = data.chunks_exact(8).map(|w| u64::from_le_bytes(w.try_into().unwrap())).collect();
+        Ok(AddrBloom { bits, nbits, hashes, file_size, modified })
+    }
+
+    /// Writes the filter to the sidecar of the nfdump file at `path`.
+    pub fn write_sidecar<P: AsRef<Path>>(&self, path: P) -> Result<(), NfdumpError> {
+        let mut w = BufWriter::new(File::create(sidecar_path(path, SIDECAR_EXTENSION))?);
+        self.write_to(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Reads the sidecar of the nfdump file at `path`, or returns `None` if
+    /// there is none or it is stale: written by another version of the
+    /// format, or for a file of another size or modification time.
+    pub fn open_sidecar<P: AsRef<Path>>(path: P) -> Result<Option<AddrBloom>, NfdumpError> {
+        let path = path.as_ref();
+        let bloom = match File::open(sidecar_path(path, SIDECAR_EXTENSION)) {
+            Ok(f) => match AddrBloom::read_from(&mut BufReader::new(f)) {
+                Ok(bloom) => bloom,
+                Err(NfdumpError::UnsupportedVersion) => return Ok(None),
+                Err(e) => return Err(e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let stale = sidecar_stale(path, bloom.file_size, bloom.modified)?;
+        Ok((!stale).then_some(bloom))
+    }
+
+    // double hashing over two hashes of the address
+    fn bit_indexes(&self, addr: IpAddr) -> impl Iterator<Item = u64> {
+        let v = match addr {
+            IpAddr::V4(a) => u32::from(a) as u128,
+            IpAddr::V6(a) => u128::from(a) ^ (1 << 127),
+        };
+        let h1 = mix((v as u64) ^ mix((v >> 64) as u64));
+        let h2 = mix(h1 ^ 0x9e37_79b9_7f4a_7c15) | 1;
+        let nbits = self.nbits;
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % nbits)
+    }
+}
+
+/// Returns the files of `paths` that may contain any of `addrs`: those
+/// whose sidecar may contain one and those without a usable sidecar. A
+/// sidecar that cannot be read or is stale is treated as missing.
+pub fn files_containing<P: AsRef<Path>>(paths: &[P], addrs: &[IpAddr]) -> Result<Vec<PathBuf>, NfdumpError> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let keep = match AddrBloom::open_sidecar(path).ok().flatten() {
+            Some(bloom) => addrs.iter().any(|a| bloom.contains(*a)),
+            None => true,
+        };
+        if keep {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+// splitmix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::net::IpAddr;
+use ipnet::IpNet;
+use crate::annotation::{Annotations, Annotator};
+use crate::filter::{AddrField, Filter};
+use crate::flow::FlowRecord;
+use crate::intel::IpList;
+use crate::prefix::PrefixMap;
+
+/// `AddrClass` is the kind of special purpose address space an address is in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AddrClass {
+    /// Private use: RFC 1918 and IPv6 unique local addresses.
+    Private,
+    /// Shared address space for carrier grade NAT, 100.64.0.0/10.
+    Shared,
+    Loopback,
+    LinkLocal,
+    /// Documentation prefixes like 192.0.2.0/24 and 2001:db8::/32.
+    Documentation,
+    /// Benchmarking prefixes, 198.18.0.0/15 and 2001:2::/48.
+    Benchmarking,
+    Multicast,
+    /// Limited broadcast, 255.255.255.255.
+    Broadcast,
+    /// Other reserved space, like 0.0.0.0/8 and 240.0.0.0/4.
+    Reserved,
+    /// Space not allocated to a registry, from a supplied table.
+    Unallocated,
+}
+
+impl fmt::Display for AddrClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AddrClass::Private => "private",
+            AddrClass::Shared => "shared",
+            AddrClass::Loopback => "loopback",
+            AddrClass::LinkLocal => "link-local",
+            AddrClass::Documentation => "documentation",
+            AddrClass::Benchmarking => "benchmarking",
+            AddrClass::Multicast => "multicast",
+            AddrClass::Broadcast => "broadcast",
+            AddrClass::Reserved => "reserved",
+            AddrClass::Unallocated => "unallocated",
+        })
+    }
+}
+
+// special purpose prefixes of the IANA registries
+const SPECIAL: [(&str, AddrClass); 25] = [
+    ("0.0.0.0/8", AddrClass::Reserved),
+    ("10.0.0.0/8", AddrClass::Private),
+    ("100.64.0.0/10", AddrClass::Shared),
+    ("127.0.0.0/8", AddrClass::Loopback),
+    ("169.254.0.0/16", AddrClass::LinkLocal),
+    ("172.16.0.0/12", AddrClass::Private),
+    ("192.0.0.0/24", AddrClass::Reserved),
+    ("192.0.2.0/24", AddrClass::Documentation),
+    ("192.168.0.0/16", AddrClass::Private),
+    ("198.18.0.0/15", AddrClass::Benchmarking),
+    ("198.51.100.0/24", AddrClass::Documentation),
+    ("203.0.113.0/24", AddrClass::Documentation),
+    ("224.0.0.0/4", AddrClass::Multicast),
+    ("240.0.0.0/4", AddrClass::Reserved),
+    ("255.255.255.255/32", AddrClass::Broadcast),
+    ("::/128", AddrClass::Reserved),
+    ("::1/128", AddrClass::Loopback),
+    ("100::/64", AddrClass::Reserved),
+    ("2001:2::/48", AddrClass::Benchmarking),
+    ("2001:db8::/32", AddrClass::Documentation),
+    ("3fff::/20", AddrClass::Documentation),
+    ("fc00::/7", AddrClass::Private),
+    ("fe80::/10", AddrClass::LinkLocal),
+    ("fec0::/10", AddrClass::Reserved),
+    ("ff00::/8", AddrClass::Multicast),
+];
+
+/// `BogonMatch` records that an address of a flow is in special purpose
+/// space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BogonMatch {
+    /// `AddrField::Src` or `AddrField::Dst`, the address that matched.
+    pub field: AddrField,
+    pub addr: IpAddr,
+    pub class: AddrClass,
+    /// The prefix the address is in.
+    pub prefix: IpNet,
+}
+
+/// `BogonClassifier` flags flows from or to bogon, reserved or unrouted
+/// address space.
+///
+/// The special purpose prefixes of the IANA registries are built in;
+/// unallocated space can be added from a table like a full bogon list. As a
+/// `Filter` it selects records with an address in any class that is not
+/// ignored.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::bogon::{AddrClass, BogonClassifier};
+/// use nfdump::intel::IpList;
+///
+/// let mut bogons = BogonClassifier::new();
+/// bogons.set_unallocated(IpList::open("fullbogons", "fullbogons-ipv4.txt").unwrap());
+/// bogons.ignore(AddrClass::Multicast);
+/// assert_eq!(bogons.classify("192.0.2.1".parse().unwrap()), Some(AddrClass::Documentation));
+/// ```
+#[derive(Clone)]
+pub struct BogonClassifier {
+    special: PrefixMap<AddrClass>,
+    unallocated: Option<IpList>,
+    ignored: Vec<AddrClass>,
+    field: AddrField,
+}
+
+impl BogonClassifier {
+    /// Creates a classifier with the built-in special purpose prefixes,
+    /// looking at source and destination addresses.
+    pub fn new() -> BogonClassifier {
+        BogonClassifier {
+            special: SPECIAL.iter().map(|(net, class)| (net.parse().unwrap(), *class)).collect(),
+            unallocated: None,
+            ignored: Vec::new(),
+            field: AddrField::SrcOrDst,
+        }
+    }
+
+    /// Sets the table of unallocated space. Built-in prefixes take
+    /// precedence over it.
+    pub fn set_unallocated(&mut self, list: IpList) {
+        self.unallocated = Some(list);
+    }
+
+    /// Makes the filter and `lookup` ignore a class, e.g. multicast for
+    /// networks that route it.
+    pub fn ignore(&mut self, class: AddrClass) {
+        if !self.ignored.contains(&class) {
+            self.ignored.push(class);
+        }
+    }
+
+    /// Sets the addresses to look at: `AddrField::Src`, `AddrField::Dst` or
+    /// `AddrField::SrcOrDst`, the default.
+    pub fn set_field(&mut self, field: AddrField) {
+        self.field = field;
+    }
+
+    /// Returns the class of an address, or `None` for ordinary global
+    /// unicast addresses. Ignored classes are still returned.
+    pub fn classify(&self, addr: IpAddr) -> Option<AddrClass> {
+        self.classify_prefix(addr).map(|(_, class)| class)
+    }
+
+    /// Returns the special purpose addresses of `record` that are not in an
+    /// ignored class.
+    pub fn lookup(&self, record: &dyn FlowRecord) -> Vec<BogonMatch> {
+        let (src, dst) = match self.field {
+            AddrField::Src => (record.src_addr(), None),
+            AddrField::Dst => (None, record.dst_addr()),
+            _ => (record.src_addr(), record.dst_addr()),
+        };
+        [(AddrField::Src, src), (AddrField::Dst, dst)]
+            .into_iter()
+            .filter_map(|(field, addr)| {
+                let addr = addr?;
+                let (prefix, class) = self.classify_prefix(addr)?;
+                (!self.ignored.contains(&class)).then_some(BogonMatch { field, addr, class, prefix })
+            })
+            .collect()
+    }
+
+    fn classify_prefix(&self, addr: IpAddr) -> Option<(IpNet, AddrClass)> {
+        // IPv4-mapped addresses are classified by their IPv4 address
+        if let IpAddr::V6(a) = addr {
+            if let Some(v4) = a.to_ipv4_mapped() {
+                return self.classify_prefix(IpAddr::V4(v4));
+            }
+        }
+        if let Some((prefix, class)) = self.special.longest_match(addr) {
+            return Some((prefix, *class));
+        }
+        let prefix = self.unallocated.as_ref()?.lookup(addr)?;
+        Some((prefix, AddrClass::Unallocated))
+    }
+}
+
+impl Default for BogonClassifier {
+    fn default() -> Self {
+        BogonClassifier::new()
+    }
+}
+
+/// Attaches the matches of a record as a `Vec<BogonMatch>`, if there are any.
+impl Annotator for BogonClassifier {
+    fn annotate(&self, record: &dyn FlowRecord, annotations: &mut Annotations) {
+        let matches = self.lookup(record);
+        if !matches.is_empty() {
+            annotations.insert(matches);
+        }
+    }
+}
+
+impl Filter for BogonClassifier {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        !self.lookup(record).is_empty()
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::compress::Compression;
+use crate::error::NfdumpError;
+use crate::info::NfFileInfo;
+use crate::output::json_string;
+
+const MANIFEST_VERSION: u64 = 1;
+
+/// `CatalogEntry` describes one nfdump file of an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    /// Path relative to the catalog root.
+    pub path: PathBuf,
+    /// Identification string of the file.
+    pub ident: String,
+    /// First seen of the earliest flow in milliseconds, from the stat record.
+    pub first_msec: u64,
+    /// Last seen of the latest flow in milliseconds, from the stat record.
+    pub last_msec: u64,
+    /// Number of flows, from the stat record.
+    pub records: u64,
+    pub compression: Compression,
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time in seconds since the epoch, used to detect changed
+    /// files on refresh.
+    pub modified: u64,
+}
+
+impl CatalogEntry {
+    /// Returns the source of the file: the top directory of its path below
+    /// the root, as in the `<source>/yyyy/mm/dd` layout of nfsen and
+    /// nfcapd `-S`, or an empty string for files directly in the root.
+    pub fn source(&self) -> &str {
+        match self.path.components().next() {
+            Some(Component::Normal(dir)) if self.path.components().count() > 1 => dir.to_str().unwrap_or(""),
+            _ => "",
+        }
+    }
+
+    /// Returns `true` if the file holds flows overlapping the window from
+    /// `start` to `end`, in milliseconds.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.records > 0 && self.first_msec <= end && self.last_msec >= start
+    }
+}
+
+/// `Catalog` is a manifest of the nfdump files below a root directory,
+/// built by scanning the tree once, that answers which files cover a time
+/// range without opening them again.
+///
+/// Only the file headers and stat records are read while scanning. Files
+/// that are not nfdump files, like sidecars, are skipped. Manifests are stored as JSON, or in sqlite
+/// with the `sqlite` feature.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::catalog::Catalog;
+///
+/// let mut catalog = Catalog::scan("/var/cache/nfdump").unwrap();
+/// catalog.write_json("/var/cache/nfdump/catalog.json").unwrap();
+///
+/// // later
+/// let mut catalog = Catalog::open_json("/var/cache/nfdump/catalog.json").unwrap();
+/// catalog.refresh().unwrap();
+/// for entry in catalog.covering(1704067200000, 1704070800000, Some("router1")) {
+///     println!("{}", catalog.root().join(&entry.path).display());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Catalog {
+    root: PathBuf,
+    entries: Vec<CatalogEntry>,
+    skipped: usize,
+}
+
+impl Catalog {
+    /// Creates an empty catalog of the tree below `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Catalog {
+        Catalog {
+            root: root.as_ref().to_path_buf(),
+            entries: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Scans the tree below `root`.
+    pub fn scan<P: AsRef<Path>>(root: P) -> Result<Catalog, NfdumpError> {
+        let mut catalog = Catalog::new(root);
+        catalog.refresh()?;
+        Ok(catalog)
+    }
+
+    /// Rescans the tree, reading only files that are new or changed since
+    /// the last scan and dropping the entries of removed files.
+    pub fn refresh(&mut self) -> Result<(), NfdumpError> {
+        let mut known: HashMap<PathBuf, CatalogEntry> = self.entries.drain(..).map(|e| (e.path.clone(), e)).collect();
+        self.skipped = 0;
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            for dirent in fs::read_dir(&dir)? {
+                let dirent = dirent?;
+                let path = dirent.path();
+                let meta = dirent.metadata()?;
+                if meta.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if !meta.is_file() {
+                    continue;
+                }
+                let rel = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+                let size = meta.len();
+                let modified = meta
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_secs());
+                if let Some(entry) = known.remove(&rel) {
+                    if entry.size == size && entry.modified == modified {
+                        self.entries.push(entry);
+                        continue;
+                    }
+                }
+                match NfFileInfo::read(&path) {
+                    Ok(info) => {
+                        let stats = info.stat_record.normalized();
+                        self.entries.push(CatalogEntry {
+                            path: rel,
+                            ident: info.ident,
+                            first_msec: stats.first_seen,
+                            last_msec: stats.last_seen,
+                            records: stats.flows,
+                            compression: info.compression,
+                            size,
+                            modified,
+                        });
+                    }
+                    Err(NfdumpError::IoError(e)) if e.kind() != io::ErrorKind::UnexpectedEof => {
+                        return Err(NfdumpError::IoError(e))
+                    }
+                    Err(_) => self.skipped += 1,
+                }
+            }
+        }
+        self.sort();
+        Ok(())
+    }
+
+    /// Returns the root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the entries, ordered by first seen and path.
+    pub fn entries(&self) -> &[CatalogEntry] {
+        &self.entries
+    }
+
+    /// Returns the number of files the last scan skipped because they are
+    /// not nfdump files.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Returns the files with flows overlapping the window from `start` to
+    /// `end`, in milliseconds, ordered by first seen. With `source` set,
+    /// only files of that source or with that ident are returned.
+    pub fn covering<'a>(&'a self, start: u64, end: u64, source: Option<&'a str>) -> impl Iterator<Item = &'a CatalogEntry> + 'a {
+        self.entries.iter().filter(move |e| {
+            e.overlaps(start, end) && source.is_none_or(|s| e.source() == s || e.ident == s)
+        })
+    }
+
+    /// Returns the sources of the catalog, sorted.
+    pub fn sources(&self) -> Vec<&str> {
+        let mut sources: Vec<&str> = self.entries.iter().map(|e| e.source()).collect();
+        sources.sort_unstable();
+        sources.dedup();
+        sources
+    }
+
+    /// Writes the manifest as JSON.
+    pub fn write_json_to<W: Write>(&self, w: &mut W) -> Result<(), NfdumpError> {
+        write!(
+            w,
+            "{{\"version\":{},\"root\":{},\"files\":[",
+            MANIFEST_VERSION,
+            json_string(&self.root.to_string_lossy())
+        )?;
+        for (i, e) in self.entries.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            write!(
+                w,
+                "\n{{\"path\":{},\"ident\":{},\"first_msec\":{},\"last_msec\":{},\"records\":{},\"compression\":\"{}\",\"size\":{},\"modified\":{}}}",
+                json_string(&e.path.to_string_lossy()),
+                json_string(&e.ident),
+                e.first_msec,
+                e.last_msec,
+                e.records,
+                e.compression,
+                e.size,
+                e.modified
+            )?;
+        }
+        w.write_all(b"\n]}\n")?;
+        Ok(())
+    }
+
+    /// Writes the manifest as JSON to the file at `path`.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<(), NfdumpError> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_json_to(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Reads a manifest written by `write_json_to`.
+    pub fn from_json(text: &str) -> Result<Catalog, NfdumpError> {
+        let mut parser = JsonParser { s: text.as_bytes(), pos: 0 };
+        let doc = parser.value()?;
+        parser.skip_ws();
+        if parser.pos != parser.s.len() {
+            return Err(NfdumpError::ParseError);
+        }
+        if doc.get("version").and_then(Json::as_u64) != Some(MANIFEST_VERSION) {
+            return Err(NfdumpError::UnsupportedVersion);
+        }
+        let root = doc.get("root").and_then(Json::as_str).ok_or(NfdumpError::ParseError)?;
+        let Some(Json::Array(files)) = doc.get("files") else {
+            return Err(NfdumpError::ParseError);
+        };
+        let mut catalog = Catalog::new(root);
+        for f in files {
+            let num = |key| f.get(key).and_then(Json::as_u64).ok_or(NfdumpError::ParseError);
+            let text = |key| f.get(key).and_then(Json::as_str).ok_or(NfdumpError::ParseError);
+            catalog.entries.push(CatalogEntry {
+                path: PathBuf::from(text("path")?),
+                ident: text("ident")?.to_string(),
+                first_msec: num("first_msec")?,
+                last_msec: num("last_msec")?,
+                records: num("records")?,
+                compression: text("compression")?.parse()?,
+                size: num("size")?,
+                modified: num("modified")?,
+            });
+        }
+        catalog.sort();
+        Ok(catalog)
+    }
+
+    /// Reads a JSON manifest from the file at `path`.
+    pub fn open_json<P: AsRef<Path>>(path: P) -> Result<Catalog, NfdumpError> {
+        Catalog::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| a.first_msec.cmp(&b.first_msec).then_with(|| a.path.cmp(&b.path)));
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Catalog {
+    /// Writes the manifest to the sqlite database at `path`, replacing the
+    /// catalog stored there.
+    pub fn write_sqlite<P: AsRef<Path>>(&self, path: P) -> Result<(), NfdumpError> {
+        let mut db = rusqlite::Connection::open(path).map_err(sqlite_error)?;
+        let tx = db.transaction().map_err(sqlite_error)?;
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS catalog (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS files (
+                 path TEXT PRIMARY KEY,
+                 source TEXT NOT NULL,
+                 ident TEXT NOT NULL,
+                 first_msec INTEGER NOT NULL,
+                 last_msec INTEGER NOT NULL,
+                 records INTEGER NOT NULL,
+                 compression TEXT NOT NULL,
+                 size INTEGER NOT NULL,
+                 modified INTEGER NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS files_time ON files (first_msec, last_msec);
+             DELETE FROM catalog;
+             DELETE FROM files;",
+        )
+        .map_err(sqlite_error)?;
+        tx.execute(
+            "INSERT INTO catalog (key, value) VALUES ('version', ?1), ('root', ?2)",
+            (MANIFEST_VERSION.to_string(), self.root.to_string_lossy()),
+        )
+        .map_err(sqlite_error)?;
+        {
+            let mut insert = tx
+                .prepare("INSERT INTO files VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")
+                .map_err(sqlite_error)?;
+            for e in &self.entries {
+                insert
+                    .execute((
+                        e.path.to_string_lossy(),
+                        e.source(),
+                        &e.ident,
+                        e.first_msec as i64,
+                        e.last_msec as i64,
+                        e.records as i64,
+                        e.compression.to_string(),
+                        e.size as i64,
+                        e.modified as i64,
+                    ))
+                    .map_err(sqlite_error)?;
+            }
+        }
+        tx.commit().map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    /// Reads a manifest written by `write_sqlite`.
+    pub fn open_sqlite<P: AsRef<Path>>(path: P) -> Result<Catalog, NfdumpError> {
+        let db = rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(sqlite_error)?;
+        let value = |key: &str| {
+            db.query_row("SELECT value FROM catalog WHERE key = ?1", [key], |row| row.get::<_, String>(0))
+                .map_err(sqlite_error)
+        };
+        if value("version")? != MANIFEST_VERSION.to_string() {
+            return Err(NfdumpError::UnsupportedVersion);
+        }
+        let mut catalog = Catalog::new(value("root")?);
+        let mut query = db
+            .prepare("SELECT path, ident, first_msec, last_msec, records, compression, size, modified FROM files")
+            .map_err(sqlite_error)?;
+        let rows = query
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    [row.get::<_, i64>(2)?, row.get(3)?, row.get(4)?],
+                    row.get::<_, String>(5)?,
+                    [row.get::<_, i64>(6)?, row.get(7)?],
+                ))
+            })
+            .map_err(sqlite_error)?;
+        for row in rows {
+            let (path, ident, [first_msec, last_msec, records], compression, [size, modified]) =
+                row.map_err(sqlite_error)?;
+            catalog.entries.push(CatalogEntry {
+                path: PathBuf::from(path),
+                ident,
+                first_msec: first_msec as u64,
+                last_msec: last_msec as u64,
+                records: records as u64,
+                compression: compression.parse()?,
+                size: size as u64,
+                modified: modified as u64,
+            });
+        }
+        catalog.sort();
+        Ok(catalog)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_error(e: rusqlite::Error) -> NfdumpError {
+    io::Error::other(e).into()
+}
+
+// The subset of JSON written by `write_json_to`: numbers are kept as their
+// text, as manifests only hold unsigned integers
+enum Json {
+    Null,
+    Bool,
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_ws(&mut self) {
+        while self.s.get(self.pos).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_ws();
+        if self.s.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, NfdumpError> {
+        if !self.s[self.pos..].starts_with(word.as_bytes()) {
+            return Err(NfdumpError::ParseError);
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, NfdumpError> {
+        self.skip_ws();
+        match self.s.get(self.pos).ok_or(NfdumpError::ParseError)? {
+            b'{' => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.eat(b'}') {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return Err(NfdumpError::ParseError);
+                    }
+                    members.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Ok(Json::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        return Err(NfdumpError::ParseError);
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']') {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(b']') {
+                        return Ok(Json::Array(items));
+                    }
+                    if !self.eat(b',') {
+                        return Err(NfdumpError::ParseError);
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.literal("true", Json::Bool),
+            b'f' => self.literal("false", Json::Bool),
+            b'n' => self.literal("null", Json::Null),
+            _ => {
+                let start = self.pos;
+                while self
+                    .s
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(c))
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(NfdumpError::ParseError);
+                }
+                Ok(Json::Number(String::from_utf8_lossy(&self.s[start..self.pos]).into_owned()))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, NfdumpError> {
+        if self.s.get(self.pos) != Some(&b'"') {
+            return Err(NfdumpError::ParseError);
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let c = *self.s.get(self.pos).ok_or(NfdumpError::ParseError)?;
+            self.pos += 1;
+            match c {
+                b'"' => return String::from_utf8(out).map_err(|_| NfdumpError::ParseError),
+                b'\\' => {
+                    let e = *self.s.get(self.pos).ok_or(NfdumpError::ParseError)?;
+                    self.pos += 1;
+                    let c = match e {
+                        b'"' | b'\\' | b'/' => e as char,
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(NfdumpError::ParseError),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                _ => out.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, NfdumpError> {
+        let hex = self.s.get(self.pos..self.pos + 4).ok_or(NfdumpError::ParseError)?;
+        self.pos += 4;
+        std::str::from_utf8(hex)
+            .ok()
+            .and_then(|h| u32::from_str_radix(h, 16).ok())
+            .ok_or(NfdumpError::ParseError)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, NfdumpError> {
+        let hi = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&hi) && self.s[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            let lo = self.hex4()?;
+            0x10000 + ((hi - 0xd800) << 10) + (lo.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            hi
+        };
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
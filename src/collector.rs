@@ -0,0 +1,725 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter, Read};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::compress::Compression;
+use crate::error::NfdumpError;
+use crate::exporter::{find_sampler, ExporterInfo, ExporterStat, SamplerRecord};
+use crate::nfx_v3::RecordV3;
+use crate::output::format_msec;
+use crate::record::NfFileRecordHeader;
+use crate::template::TemplateCache;
+use crate::writer::NfFileWriter;
+
+// largest UDP payload
+const MAX_DATAGRAM_SIZE: usize = 65535;
+// how often blocked threads check for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// packets queued per decode worker
+const WORKER_QUEUE_SIZE: usize = 1024;
+const AF_INET: u16 = 2;
+const AF_INET6: u16 = 10;
+// IPFIX message header, whose second field is the message length
+const IPFIX_HEADER_SIZE: usize = 16;
+#[cfg(feature = "sctp")]
+const IPPROTO_SCTP: i32 = 132;
+
+/// `PacketDecoder` turns the datagrams of an export protocol into flow records.
+///
+/// Each decode worker owns its own decoder, and all packets of an exporter are
+/// handled by the same worker, so decoders can keep per-exporter state such as
+/// templates without locking.
+pub trait PacketDecoder: Send {
+    /// Decodes one datagram received from `exporter`.
+    fn decode(&mut self, exporter: SocketAddr, packet: &[u8]) -> Result<Decoded, NfdumpError>;
+
+    /// Restores templates saved by an earlier run, before the first packet is
+    /// decoded.
+    fn load_templates(&mut self, _cache: &TemplateCache) {}
+
+    /// Returns the templates learned so far, to be saved on shutdown.
+    fn templates(&self) -> Option<TemplateCache> {
+        None
+    }
+}
+
+/// `Decoded` is the content of one datagram.
+#[derive(Debug, Default)]
+pub struct Decoded {
+    /// Export protocol version, e.g. 5, 9 or 10 for IPFIX.
+    pub version: u32,
+    /// Observation domain or source id within the exporter.
+    pub observation_domain: u32,
+    pub records: Vec<RecordV3>,
+    /// Samplers announced by the exporter, e.g. decoded with
+    /// `Template::decode_sampler`. `exporter_sysid` is assigned by the runtime.
+    pub samplers: Vec<SamplerRecord>,
+    /// Statistics reported by the exporter, e.g. decoded with
+    /// `Template::decode_exporter_stat`. `sysid` is assigned by the runtime.
+    pub exporter_stat: Option<ExporterStat>,
+}
+
+/// `RotatingWriter` writes flow records to a new file every rotation interval,
+/// like nfcapd.
+///
+/// Records go to `nfcapd.current` in the output directory, which is renamed to
+/// `nfcapd.YYYYMMDDhhmm` after the start of its interval in UTC once the
+/// interval is over. Intervals that are not whole minutes use
+/// `nfcapd.YYYYMMDDhhmmss`, so files of the same minute don't collide.
+/// Exporters and samplers are written to every file, and the latest
+/// statistics of each exporter are written when a file is closed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::{Duration, SystemTime};
+/// use nfdump::Compression;
+/// use nfdump::collector::RotatingWriter;
+/// use nfdump::nfx_v3::RecordV3;
+///
+/// let mut writer = RotatingWriter::new("flows", Duration::from_secs(300), Compression::Zstd);
+/// writer.write_record(SystemTime::now(), &RecordV3::default()).unwrap();
+/// let files = writer.finish().unwrap();
+/// ```
+pub struct RotatingWriter {
+    dir: PathBuf,
+    interval: u64,
+    compression: Compression,
+    ident: String,
+    // start of the current interval and its writer
+    current: Option<(u64, NfFileWriter<BufWriter<File>>)>,
+    exporters: Vec<ExporterInfo>,
+    samplers: Vec<SamplerRecord>,
+    exporter_stats: HashMap<u32, ExporterStat>,
+    files: Vec<PathBuf>,
+}
+
+impl RotatingWriter {
+    /// Creates a writer rotating files in `dir` every `interval`, which is
+    /// rounded to whole seconds.
+    pub fn new<P: AsRef<Path>>(dir: P, interval: Duration, compression: Compression) -> RotatingWriter {
+        RotatingWriter {
+            dir: dir.as_ref().to_path_buf(),
+            interval: interval.as_secs().max(1),
+            compression,
+            ident: String::new(),
+            current: None,
+            exporters: Vec::new(),
+            samplers: Vec::new(),
+            exporter_stats: HashMap::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Sets the identification string of files started from now on.
+    pub fn set_ident(&mut self, ident: &str) {
+        self.ident = ident.to_string();
+    }
+
+    /// Registers an exporter with the current and all following files.
+    pub fn add_exporter(&mut self, exporter: &ExporterInfo) {
+        match self.exporters.iter_mut().find(|e| e.sysid == exporter.sysid) {
+            Some(e) => *e = *exporter,
+            None => self.exporters.push(*exporter),
+        }
+        if let Some((_, writer)) = &mut self.current {
+            writer.add_exporter(exporter);
+        }
+    }
+
+    /// Registers a sampler with the current and all following files.
+    pub fn add_sampler(&mut self, sampler: &SamplerRecord) -> Result<(), NfdumpError> {
+        let known = self
+            .samplers
+            .iter_mut()
+            .find(|s| s.exporter_sysid == sampler.exporter_sysid && s.id == sampler.id);
+        match known {
+            Some(s) => *s = *sampler,
+            None => self.samplers.push(*sampler),
+        }
+        if let Some((_, writer)) = &mut self.current {
+            writer.add_sampler(sampler)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the statistics of an exporter, replacing earlier ones. They are
+    /// written to the current file when it is closed.
+    pub fn set_exporter_stat(&mut self, stat: &ExporterStat) {
+        self.exporter_stats.insert(stat.sysid, *stat);
+    }
+
+    /// Writes a record received at `now`, rotating first if its interval has
+    /// started.
+    pub fn write_record(&mut self, now: SystemTime, record: &RecordV3) -> Result<(), NfdumpError> {
+        self.rotate(now)?;
+        let slot = self.slot(now);
+        if self.current.is_none() {
+            self.open(slot)?;
+        }
+        let (_, writer) = self.current.as_mut().unwrap();
+        writer.write_record(record)
+    }
+
+    /// Finishes the current file if its interval is over at `now`, returning
+    /// its final path.
+    pub fn rotate(&mut self, now: SystemTime) -> Result<Option<PathBuf>, NfdumpError> {
+        match &self.current {
+            Some((start, _)) if *start != self.slot(now) => self.close().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Finishes the current file and returns the paths of all files written.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>, NfdumpError> {
+        if self.current.is_some() {
+            self.close()?;
+        }
+        Ok(self.files)
+    }
+
+    /// Returns the start of the interval `now` falls into.
+    fn slot(&self, now: SystemTime) -> u64 {
+        let secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        secs - secs % self.interval
+    }
+
+    fn open(&mut self, slot: u64) -> Result<(), NfdumpError> {
+        fs::create_dir_all(&self.dir)?;
+        let file = File::create(self.dir.join("nfcapd.current"))?;
+        let mut writer = NfFileWriter::new(BufWriter::new(file), self.compression)?;
+        writer.set_ident(&self.ident);
+        for exporter in &self.exporters {
+            writer.add_exporter(exporter);
+        }
+        for sampler in &self.samplers {
+            writer.add_sampler(sampler)?;
+        }
+        self.current = Some((slot, writer));
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<PathBuf, NfdumpError> {
+        let (start, mut writer) = self.current.take().unwrap();
+        if !self.exporter_stats.is_empty() {
+            let mut stats: Vec<ExporterStat> = self.exporter_stats.drain().map(|(_, s)| s).collect();
+            stats.sort_by_key(|s| s.sysid);
+            writer.write_exporter_stats(&stats)?;
+        }
+        writer.finish()?;
+
+        // YYYYMMDDhhmm[ss] from the formatted timestamp
+        let digits = if self.interval.is_multiple_of(60) { 12 } else { 14 };
+        let stamp: String = format_msec(start * 1000).chars().filter(char::is_ascii_digit).take(digits).collect();
+        let path = self.dir.join(format!("nfcapd.{}", stamp));
+        fs::rename(self.dir.join("nfcapd.current"), &path)?;
+        self.files.push(path.clone());
+        Ok(path)
+    }
+}
+
+/// `CollectorStats` counts what the runtime received and wrote.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollectorStats {
+    pub packets: u64,
+    pub decode_errors: u64,
+    pub records: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    packets: AtomicU64,
+    decode_errors: AtomicU64,
+    records: AtomicU64,
+}
+
+enum Listener {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    #[cfg(feature = "sctp")]
+    Sctp(SocketAddr),
+}
+
+enum Bound {
+    Udp(UdpSocket),
+    Stream(TcpListener),
+}
+
+impl Listener {
+    fn bind(&self) -> io::Result<Bound> {
+        match self {
+            Listener::Udp(addr) => {
+                let socket = UdpSocket::bind(addr)?;
+                socket.set_read_timeout(Some(POLL_INTERVAL))?;
+                Ok(Bound::Udp(socket))
+            }
+            Listener::Tcp(addr) => Ok(Bound::Stream(TcpListener::bind(addr)?)),
+            #[cfg(feature = "sctp")]
+            Listener::Sctp(addr) => {
+                use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+                // a one-to-one style SCTP socket behaves like a TCP socket
+                let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, Some(Protocol::from(IPPROTO_SCTP)))?;
+                socket.set_reuse_address(true)?;
+                socket.bind(&SockAddr::from(*addr))?;
+                socket.listen(128)?;
+                Ok(Bound::Stream(TcpListener::from(socket)))
+            }
+        }
+    }
+}
+
+impl Bound {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Bound::Udp(s) => s.local_addr(),
+            Bound::Stream(s) => s.local_addr(),
+        }
+    }
+}
+
+/// Hands packets to the decode workers.
+#[derive(Clone)]
+struct Dispatcher {
+    queues: Vec<SyncSender<(SocketAddr, Vec<u8>)>>,
+    counters: Arc<Counters>,
+}
+
+impl Dispatcher {
+    /// Queues a packet, returning `false` once the workers are gone.
+    fn dispatch(&self, addr: SocketAddr, packet: Vec<u8>) -> bool {
+        self.counters.packets.fetch_add(1, Ordering::Relaxed);
+        // all packets of an exporter go to the same worker
+        let mut hasher = DefaultHasher::new();
+        addr.ip().hash(&mut hasher);
+        let worker = (hasher.finish() % self.queues.len() as u64) as usize;
+        self.queues[worker].send((addr, packet)).is_ok()
+    }
+}
+
+fn receive_datagrams(socket: UdpSocket, dispatcher: Dispatcher, stop: Arc<AtomicBool>) {
+    let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        let (n, addr) = match socket.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(e) if is_retryable(&e) => continue,
+            Err(_) => break,
+        };
+        if !dispatcher.dispatch(addr, buf[..n].to_vec()) {
+            break;
+        }
+    }
+}
+
+fn accept_streams(listener: TcpListener, dispatcher: Dispatcher, stop: Arc<AtomicBool>) {
+    if listener.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut connections = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                let dispatcher = dispatcher.clone();
+                let stop = Arc::clone(&stop);
+                connections.push(thread::spawn(move || receive_messages(stream, addr, dispatcher, stop)));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    for connection in connections {
+        _ = connection.join();
+    }
+}
+
+/// Splits a TCP or SCTP stream into IPFIX messages by their length field.
+fn receive_messages(mut stream: TcpStream, addr: SocketAddr, dispatcher: Dispatcher, stop: Arc<AtomicBool>) {
+    if stream.set_nonblocking(false).is_err() || stream.set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+    let mut buf = Vec::new();
+    let mut chunk = vec![0; MAX_DATAGRAM_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        match stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if is_retryable(&e) => continue,
+            Err(_) => break,
+        }
+        while buf.len() >= 4 {
+            let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+            if len < IPFIX_HEADER_SIZE {
+                // not IPFIX, the stream cannot be resynchronized
+                return;
+            }
+            if buf.len() < len {
+                break;
+            }
+            let message: Vec<u8> = buf.drain(..len).collect();
+            if !dispatcher.dispatch(addr, message) {
+                return;
+            }
+        }
+    }
+}
+
+fn is_retryable(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+/// `Runtime` is the plumbing of a flow collector: it binds UDP sockets, decodes
+/// datagrams on worker threads and writes the records to rotating files.
+///
+/// IPFIX can also be received over TCP and, with the `sctp` feature, SCTP
+/// (RFC 7011). Stream transports are split into messages by the IPFIX message
+/// length, and each message is decoded like a datagram.
+///
+/// Exporters are identified by address, protocol version and observation
+/// domain, and are assigned a `sysid` in the order they are first seen.
+/// Samplers they announce are written to the files, and with
+/// `set_apply_sampling` packet and byte counters are scaled by the sampling
+/// rate.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::net::SocketAddr;
+/// use nfdump::collector::{Decoded, PacketDecoder, Runtime};
+/// use nfdump::error::NfdumpError;
+///
+/// struct Decoder;
+///
+/// impl PacketDecoder for Decoder {
+///     fn decode(&mut self, _exporter: SocketAddr, _packet: &[u8]) -> Result<Decoded, NfdumpError> {
+///         Ok(Decoded::default())
+///     }
+/// }
+///
+/// let mut runtime = Runtime::new("flows", || Decoder);
+/// runtime.add_listener("0.0.0.0:2055".parse().unwrap());
+/// runtime.set_workers(4);
+/// let handle = runtime.start().unwrap();
+/// // ...
+/// let files = handle.shutdown().unwrap();
+/// ```
+pub struct Runtime<F> {
+    dir: PathBuf,
+    factory: F,
+    listeners: Vec<Listener>,
+    workers: usize,
+    interval: Duration,
+    compression: Compression,
+    ident: String,
+    apply_sampling: bool,
+    template_cache: Option<PathBuf>,
+}
+
+impl<F, D> Runtime<F>
+where
+    F: Fn() -> D,
+    D: PacketDecoder + 'static,
+{
+    /// Creates a runtime writing files to `dir`, with one decoder created by
+    /// `factory` per worker.
+    ///
+    /// By default one worker decodes packets and files rotate every five
+    /// minutes with LZ4 compression.
+    pub fn new<P: AsRef<Path>>(dir: P, factory: F) -> Runtime<F> {
+        Runtime {
+            dir: dir.as_ref().to_path_buf(),
+            factory,
+            listeners: Vec::new(),
+            workers: 1,
+            interval: Duration::from_secs(300),
+            compression: Compression::Lz4,
+            ident: String::new(),
+            apply_sampling: false,
+            template_cache: None,
+        }
+    }
+
+    /// Adds a UDP address to receive datagrams on.
+    pub fn add_listener(&mut self, addr: SocketAddr) {
+        self.listeners.push(Listener::Udp(addr));
+    }
+
+    /// Adds a TCP address to receive IPFIX messages on.
+    pub fn add_tcp_listener(&mut self, addr: SocketAddr) {
+        self.listeners.push(Listener::Tcp(addr));
+    }
+
+    /// Adds an SCTP address to receive IPFIX messages on. Requires the `sctp`
+    /// feature and kernel support for SCTP.
+    #[cfg(feature = "sctp")]
+    pub fn add_sctp_listener(&mut self, addr: SocketAddr) {
+        self.listeners.push(Listener::Sctp(addr));
+    }
+
+    /// Sets the number of decode workers, at least one.
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = workers.max(1);
+    }
+
+    /// Sets the file rotation interval.
+    pub fn set_rotation_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Sets the compression of the written files.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Sets the identification string of the written files.
+    pub fn set_ident(&mut self, ident: &str) {
+        self.ident = ident.to_string();
+    }
+
+    /// Scales packet and byte counters of sampled flows by the sampling rate
+    /// of their exporter's sampler.
+    pub fn set_apply_sampling(&mut self, apply_sampling: bool) {
+        self.apply_sampling = apply_sampling;
+    }
+
+    /// Persists the decoders' templates in the file at `path`: templates are
+    /// loaded from it on start and saved to it on shutdown.
+    pub fn set_template_cache<P: AsRef<Path>>(&mut self, path: P) {
+        self.template_cache = Some(path.as_ref().to_path_buf());
+    }
+
+    /// Binds the sockets and starts receiving.
+    pub fn start(self) -> Result<RuntimeHandle, NfdumpError> {
+        let sockets = self.listeners.iter().map(Listener::bind).collect::<io::Result<Vec<_>>>()?;
+        let local_addrs = sockets.iter().map(|s| s.local_addr()).collect::<io::Result<Vec<_>>>()?;
+        let templates = match &self.template_cache {
+            Some(path) => Some(TemplateCache::load_or_default(path)?),
+            None => None,
+        };
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let counters = Arc::new(Counters::default());
+        let (decoded_tx, decoded_rx) = mpsc::sync_channel::<(SocketAddr, Decoded)>(WORKER_QUEUE_SIZE);
+
+        let mut writer = RotatingWriter::new(&self.dir, self.interval, self.compression);
+        writer.set_ident(&self.ident);
+        let mut sink = Sink {
+            writer,
+            exporters: HashMap::new(),
+            samplers: HashMap::new(),
+            apply_sampling: self.apply_sampling,
+            counters: Arc::clone(&counters),
+        };
+        let writer_thread = thread::spawn(move || {
+            loop {
+                match decoded_rx.recv_timeout(POLL_INTERVAL) {
+                    Ok((addr, decoded)) => sink.write(addr, decoded)?,
+                    Err(RecvTimeoutError::Timeout) => {
+                        sink.writer.rotate(SystemTime::now())?;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            sink.writer.finish()
+        });
+
+        let mut workers = Vec::new();
+        let mut queues = Vec::new();
+        for _ in 0..self.workers {
+            let (tx, rx) = mpsc::sync_channel::<(SocketAddr, Vec<u8>)>(WORKER_QUEUE_SIZE);
+            let mut decoder = (self.factory)();
+            if let Some(templates) = &templates {
+                decoder.load_templates(templates);
+            }
+            let decoded_tx = decoded_tx.clone();
+            let counters = Arc::clone(&counters);
+            workers.push(thread::spawn(move || {
+                for (addr, packet) in rx {
+                    match decoder.decode(addr, &packet) {
+                        Ok(decoded) => {
+                            if decoded_tx.send((addr, decoded)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(_) => {
+                            counters.decode_errors.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+                decoder.templates()
+            }));
+            queues.push(tx);
+        }
+
+        let dispatcher = Dispatcher {
+            queues,
+            counters: Arc::clone(&counters),
+        };
+        let receivers = sockets
+            .into_iter()
+            .map(|socket| {
+                let dispatcher = dispatcher.clone();
+                let stop = Arc::clone(&stop);
+                match socket {
+                    Bound::Udp(s) => thread::spawn(move || receive_datagrams(s, dispatcher, stop)),
+                    Bound::Stream(l) => thread::spawn(move || accept_streams(l, dispatcher, stop)),
+                }
+            })
+            .collect();
+
+        Ok(RuntimeHandle {
+            stop,
+            counters,
+            local_addrs,
+            receivers,
+            workers,
+            writer: writer_thread,
+            template_cache: self.template_cache,
+        })
+    }
+}
+
+/// Assigns exporter ids and writes decoded records.
+struct Sink {
+    writer: RotatingWriter,
+    exporters: HashMap<(IpAddr, u32, u32), u16>,
+    samplers: HashMap<u16, Vec<SamplerRecord>>,
+    apply_sampling: bool,
+    counters: Arc<Counters>,
+}
+
+impl Sink {
+    fn write(&mut self, addr: SocketAddr, decoded: Decoded) -> Result<(), NfdumpError> {
+        let key = (addr.ip(), decoded.version, decoded.observation_domain);
+        let sysid = match self.exporters.get(&key) {
+            Some(&sysid) => sysid,
+            None => {
+                let Some(sysid) = u16::try_from(self.exporters.len()).ok().and_then(|n| n.checked_add(1)) else {
+                    // every sysid is taken, the packet cannot be attributed
+                    self.counters.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                };
+                self.writer.add_exporter(&ExporterInfo {
+                    header: NfFileRecordHeader::default(),
+                    version: decoded.version,
+                    address: addr.ip(),
+                    sa_family: if addr.is_ipv4() { AF_INET } else { AF_INET6 },
+                    sysid,
+                    id: decoded.observation_domain,
+                });
+                self.exporters.insert(key, sysid);
+                sysid
+            }
+        };
+
+        for mut sampler in decoded.samplers {
+            sampler.exporter_sysid = sysid;
+            self.writer.add_sampler(&sampler)?;
+            let samplers = self.samplers.entry(sysid).or_default();
+            match samplers.iter_mut().find(|s| s.id == sampler.id) {
+                Some(s) => *s = sampler,
+                None => samplers.push(sampler),
+            }
+        }
+
+        if let Some(mut stat) = decoded.exporter_stat {
+            stat.sysid = sysid as u32;
+            self.writer.set_exporter_stat(&stat);
+        }
+
+        let now = SystemTime::now();
+        for mut record in decoded.records {
+            record.head.exporter_id = sysid;
+            if self.apply_sampling {
+                self.apply_sampling(sysid, &mut record);
+            }
+            self.writer.write_record(now, &record)?;
+            self.counters.records.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn apply_sampling(&self, sysid: u16, record: &mut RecordV3) {
+        let Some(samplers) = self.samplers.get(&sysid) else {
+            return;
+        };
+        let selector_id = record.sampler_info.as_ref().map_or(-1, |s| s.selector_id as i64);
+        let rate = find_sampler(samplers, selector_id).map_or(1, |s| s.rate());
+        if rate <= 1 {
+            return;
+        }
+        if let Some(g) = &mut record.generic_flow {
+            g.in_packets = g.in_packets.saturating_mul(rate);
+            g.in_bytes = g.in_bytes.saturating_mul(rate);
+        }
+        if let Some(c) = &mut record.cnt_flow {
+            c.out_packets = c.out_packets.saturating_mul(rate);
+            c.out_bytes = c.out_bytes.saturating_mul(rate);
+        }
+    }
+}
+
+/// `RuntimeHandle` controls a started `Runtime`.
+pub struct RuntimeHandle {
+    stop: Arc<AtomicBool>,
+    counters: Arc<Counters>,
+    local_addrs: Vec<SocketAddr>,
+    receivers: Vec<JoinHandle<()>>,
+    workers: Vec<JoinHandle<Option<TemplateCache>>>,
+    writer: JoinHandle<Result<Vec<PathBuf>, NfdumpError>>,
+    template_cache: Option<PathBuf>,
+}
+
+impl RuntimeHandle {
+    /// Returns the addresses the listeners are bound to, in the order they
+    /// were added.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Returns the counters of the runtime so far.
+    pub fn stats(&self) -> CollectorStats {
+        CollectorStats {
+            packets: self.counters.packets.load(Ordering::Relaxed),
+            decode_errors: self.counters.decode_errors.load(Ordering::Relaxed),
+            records: self.counters.records.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stops receiving, decodes and writes the packets already received and
+    /// finishes the current file. Returns the paths of all files written.
+    ///
+    /// With a template cache, the templates of all decoders are saved.
+    pub fn shutdown(self) -> Result<Vec<PathBuf>, NfdumpError> {
+        self.stop.store(true, Ordering::Relaxed);
+        for receiver in self.receivers {
+            _ = receiver.join();
+        }
+        let mut templates = TemplateCache::new();
+        for worker in self.workers {
+            if let Ok(Some(cache)) = worker.join() {
+                for (key, template) in cache.iter() {
+                    templates.insert(*key, template.clone());
+                }
+            }
+        }
+        let files = match self.writer.join() {
+            Ok(result) => result?,
+            Err(_) => return Err(NfdumpError::from(io::Error::other("collector writer panicked"))),
+        };
+        if let Some(path) = &self.template_cache {
+            templates.save(path)?;
+        }
+        Ok(files)
+    }
+}
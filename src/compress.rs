@@ -1,14 +1,155 @@
-use std::io::{self, BufReader, Cursor, Error, ErrorKind, Read};
+use std::fmt;
+use std::io::{self, BufReader, Cursor, Error, ErrorKind, Read, Write};
+use std::str::FromStr;
+use std::ptr;
 use bzip2::read::BzDecoder;
+use bzip2::write::BzEncoder;
+use minilzo_sys::{lzo1x_decompress_safe, lzo_uint, LZO_E_OK, LZO_E_OUTPUT_OVERRUN};
 use zstd::Decoder as ZstdDecoder;
+use crate::error::{ErrorContext, NfdumpError};
 
-pub(crate) const NFDUMP_COMPRESSION_TYPE_PLAIN: u8 = 0;
-pub(crate) const NFDUMP_COMPRESSION_TYPE_LZO: u8 = 1;
-pub(crate) const NFDUMP_COMPRESSION_TYPE_BZ2: u8 = 2;
-pub(crate) const NFDUMP_COMPRESSION_TYPE_LZ4: u8 = 3;
-pub(crate) const NFDUMP_COMPRESSION_TYPE_ZSTD: u8 = 4;
+/// `Compression` is the codec of an nfdump file's data blocks.
+///
+/// The discriminants are the values stored in the v2 file header.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    Lzo = 1,
+    Bz2 = 2,
+    Lz4 = 3,
+    Zstd = 4,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = NfdumpError;
+
+    fn try_from(value: u8) -> Result<Compression, NfdumpError> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lzo),
+            2 => Ok(Compression::Bz2),
+            3 => Ok(Compression::Lz4),
+            4 => Ok(Compression::Zstd),
+            _ => Err(NfdumpError::UnsupportedCompression),
+        }
+    }
+}
+
+impl From<Compression> for u8 {
+    fn from(value: Compression) -> u8 {
+        value as u8
+    }
+}
 
-const BUFSIZE: usize = 5 * 1048576;
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Lzo => "lzo",
+            Compression::Bz2 => "bz2",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
+
+impl FromStr for Compression {
+    type Err = NfdumpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "lzo" => Ok(Compression::Lzo),
+            "bz2" => Ok(Compression::Bz2),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(NfdumpError::UnsupportedCompression),
+        }
+    }
+}
+
+pub(crate) const DEFAULT_MAX_BLOCK_SIZE: usize = 5 * 1048576;
+// ceiling for the maximum block size taken from a v2 file header
+pub(crate) const MAX_HEADER_BLOCK_SIZE: usize = 64 * 1048576;
+
+/// `BufferPool` recycles block buffers between data blocks, so a reader
+/// allocates its compressed and decompressed buffers once instead of once
+/// per block.
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    bufs: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        self.bufs.pop().unwrap_or_default()
+    }
+
+    pub(crate) fn give(&mut self, mut buf: Vec<u8>) {
+        // a block holds at most a compressed, a decompressed and a record buffer
+        if self.bufs.len() < 3 {
+            buf.clear();
+            self.bufs.push(buf);
+        }
+    }
+}
+
+/// Codec parameters used when compressing blocks for writing.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct CompressionSettings {
+    pub(crate) ctype: Compression,
+    pub(crate) zstd_level: i32,
+    // 0 lets zstd pick the window size for the level
+    pub(crate) zstd_window_log: u32,
+    // 0 selects the fast LZ4 compressor
+    #[cfg(feature = "lz4-hc")]
+    pub(crate) lz4_hc_level: i32,
+}
+
+impl CompressionSettings {
+    pub(crate) fn new(ctype: Compression) -> Self {
+        CompressionSettings {
+            ctype,
+            zstd_level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            zstd_window_log: 0,
+            #[cfg(feature = "lz4-hc")]
+            lz4_hc_level: 0,
+        }
+    }
+}
+
+/// Compresses one block of records for writing.
+pub(crate) fn compress(settings: &CompressionSettings, data: &[u8]) -> Result<Vec<u8>, Error> {
+    match settings.ctype {
+        Compression::None => Ok(data.to_vec()),
+        Compression::Lzo => minilzo::compress(data)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Lzo compression failed")),
+        Compression::Bz2 => {
+            // nfdump compresses with the largest (900k) bzip2 block size
+            let mut encoder = BzEncoder::new(Vec::with_capacity(data.len() / 4), bzip2::Compression::best());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        Compression::Lz4 => {
+            // raw LZ4 blocks without a size prefix, as nfdump writes them
+            #[cfg(feature = "lz4-hc")]
+            if settings.lz4_hc_level > 0 {
+                let mode = lz4::block::CompressionMode::HIGHCOMPRESSION(settings.lz4_hc_level);
+                return lz4::block::compress(data, Some(mode), false);
+            }
+            Ok(lz4_flex::block::compress(data))
+        }
+        Compression::Zstd => {
+            let mut compressor = zstd::bulk::Compressor::new(settings.zstd_level)?;
+            if settings.zstd_window_log > 0 {
+                compressor.set_parameter(zstd::stream::raw::CParameter::WindowLog(settings.zstd_window_log))?;
+            }
+            compressor.compress(data)
+        }
+    }
+}
 
 pub enum Decompressor {
     Lzo(LzoDecompressor),
@@ -19,18 +160,71 @@ pub enum Decompressor {
 }
 
 impl Decompressor {
-    pub(crate) fn new(dtype: u8, data: Vec<u8>) -> Result<Self, Error> {
+    /// Creates a decompressor for one block. Block codecs (LZO, LZ4) decompress
+    /// into at most `limit` bytes, taking their output buffer from `pool`, and
+    /// fail with `NfdumpError::BlockTooLarge` for a block that expands beyond
+    /// it; stream codecs (bz2, zstd) stop once they produced `limit` bytes.
+    pub(crate) fn new(dtype: Compression, data: Vec<u8>, pool: &mut BufferPool, limit: usize) -> Result<Self, NfdumpError> {
         let decompressor = match dtype {
-            NFDUMP_COMPRESSION_TYPE_LZO => Decompressor::Lzo(LzoDecompressor::new(data)?),
-            NFDUMP_COMPRESSION_TYPE_LZ4 => Decompressor::Lz4(Lz4Decompressor::new(data)?),
-            NFDUMP_COMPRESSION_TYPE_BZ2 => Decompressor::Bz2(Bz2Decompressor::new(data)?),
-            NFDUMP_COMPRESSION_TYPE_ZSTD => Decompressor::Zstd(ZstdDecompressor::new(data)?),
-            NFDUMP_COMPRESSION_TYPE_PLAIN => Decompressor::Plain(PlainDecompressor::new(data)?),
-            _ => return Err(Error::new(io::ErrorKind::InvalidData, "Unsupported compression")),
+            Compression::Lzo => Decompressor::Lzo(LzoDecompressor::new(data, pool, limit)?),
+            Compression::Lz4 => Decompressor::Lz4(Lz4Decompressor::new(data, pool, limit)?),
+            Compression::Bz2 => Decompressor::Bz2(Bz2Decompressor::new(data, limit)?),
+            Compression::Zstd => Decompressor::Zstd(ZstdDecompressor::new(data, limit)?),
+            Compression::None => Decompressor::Plain(PlainDecompressor::new(data)?),
         };
 
         Ok(decompressor)
     }
+
+    /// Returns the buffers held by this decompressor to `pool` for the next block.
+    pub(crate) fn recycle(self, pool: &mut BufferPool) {
+        match self {
+            Decompressor::Lz4(d) => pool.give(d.d.into_inner()),
+            Decompressor::Bz2(d) => pool.give(d.d.into_inner().into_inner()),
+            Decompressor::Plain(d) => pool.give(d.d.into_inner()),
+            Decompressor::Lzo(d) => pool.give(d.d.into_inner()),
+            Decompressor::Zstd(d) => pool.give(d.d.finish().into_inner().into_inner()),
+        }
+    }
+
+    /// Returns `true` if a stream codec stopped because the block expands
+    /// beyond its limit.
+    pub(crate) fn exceeded(&self) -> bool {
+        match self {
+            Decompressor::Bz2(d) => d.limit.exceeded,
+            Decompressor::Zstd(d) => d.limit.exceeded,
+            _ => false,
+        }
+    }
+}
+
+/// `OutputLimit` caps the output of a stream codec, whose decoders would
+/// otherwise expand a block without bounds.
+struct OutputLimit {
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl OutputLimit {
+    fn new(limit: usize) -> OutputLimit {
+        OutputLimit { remaining: limit, exceeded: false }
+    }
+
+    fn read<R: Read>(&mut self, reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            // the limit is only exceeded if the stream has more to give
+            let mut probe = [0; 1];
+            if reader.read(&mut probe)? > 0 {
+                self.exceeded = true;
+                return Err(Error::new(ErrorKind::InvalidData, "decompressed block exceeds the maximum block size"));
+            }
+            return Ok(0);
+        }
+        let len = buf.len().min(self.remaining);
+        let n = reader.read(&mut buf[..len])?;
+        self.remaining -= n;
+        Ok(n)
+    }
 }
 
 impl Read for Decompressor {
@@ -47,19 +241,20 @@ impl Read for Decompressor {
 
 pub struct ZstdDecompressor<'a> {
     pub(crate) d: Box<ZstdDecoder<'a, BufReader<Cursor<Vec<u8>>>>>,
+    limit: OutputLimit,
 }
 
 impl ZstdDecompressor<'_> {
-    fn new(data: Vec<u8>) -> Result<Self, Error> {
+    fn new(data: Vec<u8>, limit: usize) -> Result<Self, Error> {
         let cursor = Cursor::new(data);
         let d = ZstdDecoder::new(cursor)?;
-        Ok(ZstdDecompressor { d: Box::new(d) })
+        Ok(ZstdDecompressor { d: Box::new(d), limit: OutputLimit::new(limit) })
     }
 }
 
 impl Read for ZstdDecompressor<'_> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        self.d.read(buf)
+        self.limit.read(&mut self.d, buf)
     }
 }
 
@@ -68,9 +263,26 @@ pub struct LzoDecompressor {
 }
 
 impl LzoDecompressor {
-    fn new(data: Vec<u8>) -> Result<Self, Error> {
-        let decompressed = minilzo::decompress(data.as_slice(), BUFSIZE).unwrap();
-        let d = Cursor::new(decompressed);
+    fn new(data: Vec<u8>, pool: &mut BufferPool, limit: usize) -> Result<Self, NfdumpError> {
+        let mut out = pool.take();
+        out.reserve(limit);
+        let mut size = limit as lzo_uint;
+        // SAFETY: `out` has room for `limit` bytes and the safe decoder
+        // writes at most `size` bytes, setting `size` to the number written
+        let r = unsafe {
+            lzo1x_decompress_safe(data.as_ptr(), data.len() as lzo_uint, out.as_mut_ptr(), &mut size, ptr::null_mut())
+        };
+        pool.give(data);
+        if r != LZO_E_OK {
+            pool.give(out);
+            if r == LZO_E_OUTPUT_OVERRUN {
+                return Err(block_too_large(limit));
+            }
+            return Err(Error::new(ErrorKind::InvalidData, "Lzo decompression failed").into());
+        }
+        // SAFETY: the decoder initialized the first `size` bytes
+        unsafe { out.set_len(size as usize) };
+        let d = Cursor::new(out);
         Ok(LzoDecompressor { d })
     }
 }
@@ -86,26 +298,78 @@ pub struct Lz4Decompressor {
 }
 
 impl Lz4Decompressor {
-    fn new(data: Vec<u8>) -> Result<Self, Error> {
-        let mut out: [u8; BUFSIZE] = [0; BUFSIZE];
-        let size = match lz4_flex::block::decompress_into(&data, &mut out) {
-            Ok(s) => s,
-            Err(_) => 0,
+    fn new(data: Vec<u8>, pool: &mut BufferPool, limit: usize) -> Result<Self, NfdumpError> {
+        let mut out = pool.take();
+        let size = match lz4_block_len(&data) {
+            Some(len) if len > limit => {
+                pool.give(data);
+                pool.give(out);
+                return Err(block_too_large(limit));
+            }
+            Some(len) => {
+                out.resize(len, 0);
+                lz4_flex::block::decompress_into(&data, &mut out).unwrap_or_default()
+            }
+            None => 0,
         };
+        pool.give(data);
 
         match size {
-            1.. => {
-                let trimmed_vec = out[..size].to_vec();
-                let d = Cursor::new(trimmed_vec);
+            1.. if size == out.len() => {
+                let d = Cursor::new(out);
                 Ok(Lz4Decompressor { d })
             },
             _ => {
-                Err(Error::new(ErrorKind::InvalidData, "Lz4 decompression failed"))
+                pool.give(out);
+                Err(Error::new(ErrorKind::InvalidData, "Lz4 decompression failed").into())
             },
         }
     }
 }
 
+// The reader adds the position of the block
+fn block_too_large(limit: usize) -> NfdumpError {
+    NfdumpError::BlockTooLarge { limit, context: ErrorContext::default() }
+}
+
+/// Returns the decompressed length of an LZ4 block by walking its sequences,
+/// or `None` if the block is malformed.
+fn lz4_block_len(data: &[u8]) -> Option<usize> {
+    // a length of 15 continues in the following bytes until one is below 255
+    fn extended(data: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+        if len == 15 {
+            loop {
+                let b = *data.get(*pos)?;
+                *pos += 1;
+                len = len.checked_add(b as usize)?;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        Some(len)
+    }
+
+    let mut pos = 0;
+    let mut len = 0usize;
+    while pos < data.len() {
+        let token = data[pos];
+        pos += 1;
+        let literals = extended(data, &mut pos, (token >> 4) as usize)?;
+        pos = pos.checked_add(literals).filter(|&p| p <= data.len())?;
+        len = len.checked_add(literals)?;
+        // the last sequence has no match
+        if pos == data.len() {
+            return Some(len);
+        }
+        // match offset
+        pos += 2;
+        let matched = extended(data, &mut pos, (token & 0x0f) as usize)?;
+        len = len.checked_add(matched + 4)?;
+    }
+    None
+}
+
 impl Read for Lz4Decompressor {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         self.d.read(buf)
@@ -114,18 +378,19 @@ impl Read for Lz4Decompressor {
 
 pub struct Bz2Decompressor {
     pub(crate) d: BzDecoder<Cursor<Vec<u8>>>,
+    limit: OutputLimit,
 }
 
 impl Bz2Decompressor {
-    pub(crate) fn new(data: Vec<u8>) -> Result<Self, Error> {
+    pub(crate) fn new(data: Vec<u8>, limit: usize) -> Result<Self, Error> {
         let cursor = Cursor::new(data);
-        Ok(Bz2Decompressor { d: BzDecoder::new(cursor) })
+        Ok(Bz2Decompressor { d: BzDecoder::new(cursor), limit: OutputLimit::new(limit) })
     }
 }
 
 impl Read for Bz2Decompressor {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.d.read(buf)
+        self.limit.read(&mut self.d, buf)
     }
 }
 
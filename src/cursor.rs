@@ -0,0 +1,141 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use crate::error::NfdumpError;
+use crate::NfFileReader;
+
+/// `SharedFile` is a handle to an open file that can be cloned cheaply into
+/// independent cursors. Every clone keeps its own position and reads with
+/// positioned reads, so clones can be used from different threads at the
+/// same time without reopening the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::NfFileReader;
+/// use nfdump::cursor::SharedFile;
+///
+/// let file = SharedFile::open("path_to_your_file").unwrap();
+/// let mut first = NfFileReader::new(file.clone()).unwrap();
+/// let mut second = NfFileReader::new(file.clone()).unwrap();
+/// first.set_block_range(0..10).unwrap();
+/// second.set_block_range(10..20).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedFile {
+    file: Arc<File>,
+    pos: u64,
+}
+
+impl SharedFile {
+    /// Opens the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SharedFile> {
+        Ok(SharedFile::new(File::open(path)?))
+    }
+
+    /// Creates a `SharedFile` positioned at the start of `file`.
+    pub fn new(file: File) -> SharedFile {
+        SharedFile {
+            file: Arc::new(file),
+            pos: 0,
+        }
+    }
+
+    /// Returns a new cursor over the same file, positioned at its start.
+    pub fn cursor(&self) -> SharedFile {
+        SharedFile {
+            file: self.file.clone(),
+            pos: 0,
+        }
+    }
+}
+
+impl From<File> for SharedFile {
+    fn from(file: File) -> SharedFile {
+        SharedFile::new(file)
+    }
+}
+
+impl Read for SharedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = read_at(&self.file, buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SharedFile {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let pos = match from {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(off) => self.pos.checked_add_signed(off),
+            SeekFrom::End(off) => self.file.metadata()?.len().checked_add_signed(off),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(self.pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// Splits `file` into up to `n` readers over consecutive, equally sized
+/// ranges of data blocks, to be read on separate threads.
+///
+/// Each reader starts with the extension maps, exporters and samplers of
+/// the blocks before its range, so records decode the same as in a single
+/// pass. The file is scanned once for this metadata, skipping over flow
+/// records without decoding them. Fewer than `n` readers are returned if
+/// the file has fewer data blocks.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::thread;
+/// use nfdump::cursor::{cursors, SharedFile};
+///
+/// let file = SharedFile::open("path_to_your_file").unwrap();
+/// let readers = cursors(&file, 4).unwrap();
+/// let counts: Vec<u64> = thread::scope(|scope| {
+///     let handles: Vec<_> = readers
+///         .into_iter()
+///         .map(|mut reader| scope.spawn(move || reader.count_records().unwrap()))
+///         .collect();
+///     handles.into_iter().map(|h| h.join().unwrap()).collect()
+/// });
+/// println!("{} flows", counts.iter().sum::<u64>());
+/// ```
+pub fn cursors(file: &SharedFile, n: usize) -> Result<Vec<NfFileReader<SharedFile>>, NfdumpError> {
+    let mut scanner = NfFileReader::new(file.cursor())?;
+    let offsets = scanner.block_offsets()?;
+    let blocks = offsets.len();
+    let n = n.clamp(1, blocks.max(1));
+
+    let mut readers = Vec::with_capacity(n);
+    let mut scanned = 0;
+    for i in 0..n {
+        let start = blocks * i / n;
+        let end = blocks * (i + 1) / n;
+        scanner.scan_metadata((start - scanned) as u32)?;
+        scanned = start;
+
+        let mut reader = NfFileReader::new(file.cursor())?;
+        reader.copy_metadata(&scanner);
+        let offset = offsets.get(start).copied().unwrap_or(0);
+        reader.start_at_block(start as u32, offset, (end - start) as u32)?;
+        readers.push(reader);
+    }
+    Ok(readers)
+}
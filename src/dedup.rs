@@ -0,0 +1,247 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use crate::flow::FlowRecord;
+use crate::record::RecordKind;
+
+/// `FlowKey` identifies a unidirectional flow independently of the exporter
+/// that reported it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src_addr: IpAddr,
+    pub dst_addr: IpAddr,
+    pub proto: u8,
+    pub src_port: u16,
+    pub dst_port: u16,
+}
+
+impl FlowKey {
+    /// Returns the key of a record, or `None` if it lacks addresses or
+    /// protocol.
+    pub fn from_record(record: &dyn FlowRecord) -> Option<FlowKey> {
+        Some(FlowKey {
+            src_addr: record.src_addr()?,
+            dst_addr: record.dst_addr()?,
+            proto: record.proto()?,
+            src_port: record.src_port().unwrap_or(0),
+            dst_port: record.dst_port().unwrap_or(0),
+        })
+    }
+
+    /// Returns a 64 bit hash of the key, stable across builds and
+    /// platforms. Like the key, it is unidirectional: keys that compare
+    /// equal have the same hash, and the two directions of a flow usually
+    /// differ.
+    pub fn hash64(&self) -> u64 {
+        let mut hasher = sha1_smol::Sha1::new();
+        for addr in [self.src_addr, self.dst_addr] {
+            match addr {
+                IpAddr::V4(a) => {
+                    hasher.update(&[4]);
+                    hasher.update(&a.octets());
+                }
+                IpAddr::V6(a) => {
+                    hasher.update(&[6]);
+                    hasher.update(&a.octets());
+                }
+            }
+        }
+        hasher.update(&[self.proto]);
+        hasher.update(&self.src_port.to_be_bytes());
+        hasher.update(&self.dst_port.to_be_bytes());
+        u64::from_be_bytes(hasher.digest().bytes()[..8].try_into().unwrap())
+    }
+}
+
+/// `Collapsed` is a flow with the duplicates reported by other exporters
+/// removed.
+#[derive(Debug)]
+pub struct Collapsed {
+    /// The record of the preferred exporter, or the first one seen.
+    pub record: RecordKind,
+    /// The `sysid`s of the exporters that reported the flow, in the order
+    /// their records arrived.
+    pub exporters: Vec<u16>,
+}
+
+impl Collapsed {
+    /// Returns the number of duplicate records dropped.
+    pub fn duplicates(&self) -> usize {
+        self.exporters.len() - 1
+    }
+}
+
+// Exporters whose reports of a flow may be duplicates of each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Group {
+    /// No paths are configured, any two exporters may see the same flow.
+    All,
+    Path(usize),
+    /// The exporter is on no configured path.
+    Alone(u16),
+}
+
+struct Pending {
+    record: RecordKind,
+    exporters: Vec<u16>,
+    rank: usize,
+    first_msec: u64,
+}
+
+/// `CrossExporterDedup` collapses the reports of one flow by several
+/// exporters along its path, so totals count each flow once.
+///
+/// Records are duplicates when they have the same `FlowKey`, come from
+/// different exporters and start within `window_msec` of each other. The
+/// key is unidirectional, so the two directions of a connection are
+/// separate flows.
+/// Topology hints narrow this down: with paths added, only exporters on a
+/// common path are considered, and the record of the exporter earliest on
+/// the path is kept. Without hints all exporters are considered and the
+/// first record seen is kept.
+///
+/// Records should arrive roughly ordered by start time, as they are in
+/// nfcapd files; a flow is emitted once records starting more than
+/// `window_msec` after it have been seen. Records without addresses and
+/// records of other kinds are not passed through.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::dedup::CrossExporterDedup;
+///
+/// let mut dedup = CrossExporterDedup::new(2000);
+/// // edge router 1, then core router 3
+/// dedup.add_path(&[1, 3]);
+///
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// let mut flows = Vec::new();
+/// while let Ok(record) = reader.read_record() {
+///     flows.extend(dedup.push(record));
+/// }
+/// flows.extend(dedup.finish());
+/// println!("{} flows, {} duplicates", flows.len(), dedup.duplicates());
+/// ```
+pub struct CrossExporterDedup {
+    window_msec: u64,
+    paths: HashMap<u16, (usize, usize)>,
+    npaths: usize,
+    pending: HashMap<(FlowKey, Group), Pending>,
+    order: VecDeque<(u64, FlowKey, Group)>,
+    watermark: u64,
+    duplicates: u64,
+}
+
+impl CrossExporterDedup {
+    /// Creates a deduplicator matching records starting within
+    /// `window_msec` of each other.
+    pub fn new(window_msec: u64) -> CrossExporterDedup {
+        CrossExporterDedup {
+            window_msec,
+            paths: HashMap::new(),
+            npaths: 0,
+            pending: HashMap::new(),
+            order: VecDeque::new(),
+            watermark: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// Adds a path as the `sysid`s of its exporters, the preferred one
+    /// first. An exporter is on at most one path; adding it again moves it.
+    pub fn add_path(&mut self, exporters: &[u16]) {
+        for (rank, sysid) in exporters.iter().enumerate() {
+            self.paths.insert(*sysid, (self.npaths, rank));
+        }
+        self.npaths += 1;
+    }
+
+    /// Returns the number of duplicate records dropped so far.
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// Adds a record and returns the flows that can no longer receive
+    /// duplicates.
+    pub fn push(&mut self, record: RecordKind) -> Vec<Collapsed> {
+        let Some(flow) = record.flow_record() else {
+            return Vec::new();
+        };
+        let Some(key) = FlowKey::from_record(flow) else {
+            return Vec::new();
+        };
+        let sysid = flow.exporter_sysid().unwrap_or(0);
+        let first_msec = flow.first_msec().unwrap_or(0);
+        let (group, rank) = match self.paths.get(&sysid) {
+            Some((path, rank)) => (Group::Path(*path), *rank),
+            None if self.npaths == 0 => (Group::All, 0),
+            None => (Group::Alone(sysid), 0),
+        };
+
+        let mut out = Vec::new();
+        if let Some(p) = self.pending.get_mut(&(key, group)) {
+            if p.exporters.contains(&sysid) || first_msec.abs_diff(p.first_msec) > self.window_msec {
+                // a later flow with the same key
+                let p = self.pending.remove(&(key, group)).unwrap();
+                out.push(Collapsed { record: p.record, exporters: p.exporters });
+            } else {
+                p.exporters.push(sysid);
+                if rank < p.rank {
+                    p.record = record;
+                    p.rank = rank;
+                }
+                self.duplicates += 1;
+                return self.expire(out);
+            }
+        }
+
+        self.pending.insert(
+            (key, group),
+            Pending {
+                record,
+                exporters: vec![sysid],
+                rank,
+                first_msec,
+            },
+        );
+        self.order.push_back((first_msec, key, group));
+        self.watermark = self.watermark.max(first_msec);
+        self.expire(out)
+    }
+
+    /// Returns the remaining flows.
+    pub fn finish(&mut self) -> Vec<Collapsed> {
+        let mut out = Vec::new();
+        for (first_msec, key, group) in self.order.drain(..) {
+            take_pending(&mut self.pending, first_msec, key, group, &mut out);
+        }
+        out
+    }
+
+    fn expire(&mut self, mut out: Vec<Collapsed>) -> Vec<Collapsed> {
+        while let Some(&(first_msec, key, group)) = self.order.front() {
+            if first_msec + self.window_msec >= self.watermark {
+                break;
+            }
+            self.order.pop_front();
+            take_pending(&mut self.pending, first_msec, key, group, &mut out);
+        }
+        out
+    }
+}
+
+// Moves a pending flow to `out`, unless it was already emitted and its key
+// reused by a later flow
+fn take_pending(
+    pending: &mut HashMap<(FlowKey, Group), Pending>,
+    first_msec: u64,
+    key: FlowKey,
+    group: Group,
+    out: &mut Vec<Collapsed>,
+) {
+    if pending.get(&(key, group)).is_some_and(|p| p.first_msec == first_msec) {
+        let p = pending.remove(&(key, group)).unwrap();
+        out.push(Collapsed { record: p.record, exporters: p.exporters });
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::vec;
+use crate::bloom::files_containing;
+use crate::error::NfdumpError;
+use crate::index::FileIndex;
+use crate::pushdown::Pushdown;
+use crate::record::RecordEnvelope;
+use crate::NfFileReader;
+
+/// `SourceTag` selects what `MultiReader` stores in `RecordEnvelope::source`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SourceTag {
+    /// The identification string of the input file.
+    Ident,
+    /// The name the input was added with, the path for `MultiReader::open`.
+    Name,
+}
+
+/// `MultiReader` reads several nfdump files one after another as a single
+/// stream of records, optionally tagging each record with its source.
+///
+/// With a time window set, or a pushdown with one, inputs with a
+/// `FileIndex` are pruned: files outside the window are dropped and data
+/// blocks outside it are skipped without being decompressed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::demux::{split_sources, MultiReader, SourceTag};
+///
+/// let mut reader = MultiReader::open(&["nfcapd.202401010000", "nfcapd.202401010005"]).unwrap();
+/// reader.set_source_tag(Some(SourceTag::Name));
+/// let records = reader.records().filter_map(Result::ok);
+/// for (source, records) in split_sources(records) {
+///     println!("{}: {} records", source, records.count());
+/// }
+/// ```
+pub struct MultiReader<R> {
+    inputs: VecDeque<Input<R>>,
+    tag: Option<SourceTag>,
+    cancel: Option<Arc<AtomicBool>>,
+    window: Option<(u64, u64)>,
+    pushdown: Pushdown,
+    feed: Option<InputFeed<R>>,
+}
+
+/// `InputFeed` supplies `MultiReader` with more inputs once it has read all
+/// it was given, as a name and a reader, or `None` to end the stream.
+pub type InputFeed<R> = Box<dyn FnMut() -> Result<Option<(String, NfFileReader<R>)>, NfdumpError> + Send>;
+
+struct Input<R> {
+    name: Arc<str>,
+    reader: NfFileReader<R>,
+    index: Option<Arc<FileIndex>>,
+}
+
+impl MultiReader<BufReader<File>> {
+    /// Opens the files at `paths`, named by their path, along with their
+    /// index sidecars where there are any.
+    pub fn open<P: AsRef<Path>>(paths: &[P]) -> Result<Self, NfdumpError> {
+        let mut ret = MultiReader::new();
+        for path in paths {
+            let path = path.as_ref();
+            let reader = NfFileReader::new(BufReader::new(File::open(path)?))?;
+            let name = path.display().to_string();
+            match FileIndex::open_sidecar(path)? {
+                Some(index) => ret.add_indexed_reader(&name, reader, Arc::new(index)),
+                None => ret.add_reader(&name, reader),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Opens the files of `paths` that may contain any of `addrs`, skipping
+    /// those whose Bloom filter sidecar rules all of them out. Files without
+    /// a sidecar are opened.
+    pub fn open_containing<P: AsRef<Path>>(paths: &[P], addrs: &[IpAddr]) -> Result<Self, NfdumpError> {
+        MultiReader::open(&files_containing(paths, addrs)?)
+    }
+}
+
+impl<R: Read + Seek> MultiReader<R> {
+    /// Creates a reader without inputs.
+    pub fn new() -> MultiReader<R> {
+        MultiReader {
+            inputs: VecDeque::new(),
+            tag: None,
+            cancel: None,
+            window: None,
+            pushdown: Pushdown::new(),
+            feed: None,
+        }
+    }
+
+    /// Appends an input, read after all inputs added before it.
+    pub fn add_reader(&mut self, name: &str, reader: NfFileReader<R>) {
+        self.push_input(Input { name: Arc::from(name), reader, index: None });
+    }
+
+    /// Appends an input with the index of its file. An index built from
+    /// another file is ignored.
+    pub fn add_indexed_reader(&mut self, name: &str, mut reader: NfFileReader<R>, index: Arc<FileIndex>) {
+        let index = index.matches(&mut reader).then_some(index);
+        self.push_input(Input { name: Arc::from(name), reader, index });
+    }
+
+    /// Sets a cancellation token for all inputs. Once it is set to `true`,
+    /// reads return `NfdumpError::Cancelled`.
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        for input in &mut self.inputs {
+            input.reader.set_cancel(cancel.clone());
+        }
+        self.cancel = Some(cancel);
+    }
+
+    /// Only returns flows overlapping the window from `start` to `end`, in
+    /// milliseconds. Indexed inputs entirely outside the window are
+    /// dropped, and their data blocks outside it skipped. Should be set
+    /// before reading.
+    pub fn set_time_window(&mut self, start: u64, end: u64) {
+        self.window = Some((start, end));
+        let inputs = std::mem::take(&mut self.inputs);
+        for input in inputs {
+            self.push_input(input);
+        }
+    }
+
+    /// Sets predicates for all inputs to check before decoding records, see
+    /// `NfFileReader::set_pushdown`. Its time window prunes indexed inputs
+    /// like `set_time_window`. Should be set before reading.
+    pub fn set_pushdown(&mut self, pushdown: Pushdown) {
+        self.pushdown = pushdown;
+        let inputs = std::mem::take(&mut self.inputs);
+        for input in inputs {
+            self.push_input(input);
+        }
+    }
+
+    /// Sets a feed called for the next input once all inputs are read, e.g.
+    /// to wait for the files a collector completes.
+    pub fn set_input_feed(&mut self, feed: InputFeed<R>) {
+        self.feed = Some(feed);
+    }
+
+    /// Sets what records are tagged with, or `None` (the default) to leave
+    /// `RecordEnvelope::source` empty.
+    pub fn set_source_tag(&mut self, tag: Option<SourceTag>) {
+        self.tag = tag;
+    }
+
+    /// Returns the name of the input currently being read.
+    pub fn current_source(&self) -> Option<&str> {
+        self.inputs.front().map(|input| &*input.name)
+    }
+
+    /// Reads the next record, moving on to the next input at the end of each
+    /// file. Returns `NfdumpError::EOF` once all inputs, including those of
+    /// the input feed, are exhausted.
+    pub fn read_record_envelope(&mut self) -> Result<RecordEnvelope, NfdumpError> {
+        loop {
+            if self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+                return Err(NfdumpError::Cancelled);
+            }
+            if self.inputs.is_empty() {
+                let Some(feed) = self.feed.as_mut() else {
+                    return Err(NfdumpError::EOF);
+                };
+                let Some((name, reader)) = feed()? else {
+                    return Err(NfdumpError::EOF);
+                };
+                self.add_reader(&name, reader);
+                continue;
+            }
+            let input = self.inputs.front_mut().unwrap();
+            match input.reader.read_record_envelope() {
+                Ok(mut env) => {
+                    env.source = match self.tag {
+                        Some(SourceTag::Ident) => Some(env.ident.clone()),
+                        Some(SourceTag::Name) => Some(input.name.clone()),
+                        None => None,
+                    };
+                    return Ok(env);
+                }
+                Err(NfdumpError::EOF) => {
+                    self.inputs.pop_front();
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns an iterator over the records of all inputs.
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records { reader: self }
+    }
+
+    fn push_input(&mut self, mut input: Input<R>) {
+        if let Some(cancel) = &self.cancel {
+            input.reader.set_cancel(cancel.clone());
+        }
+        let window = match (self.window, self.pushdown.time_window()) {
+            (Some((s1, e1)), Some((s2, e2))) => Some((s1.max(s2), e1.min(e2))),
+            (a, b) => a.or(b),
+        };
+        if let (Some((start, end)), Some(index)) = (window, &input.index) {
+            if !index.overlaps(start, end) {
+                return;
+            }
+            input.reader.set_pruned_blocks(index.prune(start, end));
+        }
+        if let Some((start, end)) = self.window {
+            input.reader.set_time_window(start, end);
+        }
+        input.reader.set_pushdown(self.pushdown.clone());
+        self.inputs.push_back(input);
+    }
+}
+
+impl<R: Read + Seek> Default for MultiReader<R> {
+    fn default() -> Self {
+        MultiReader::new()
+    }
+}
+
+/// Iterator over the records of a `MultiReader`, ending after the last input.
+pub struct Records<'a, R> {
+    reader: &'a mut MultiReader<R>,
+}
+
+impl<R: Read + Seek> Iterator for Records<'_, R> {
+    type Item = Result<RecordEnvelope, NfdumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.reader.read_record_envelope() {
+            Err(NfdumpError::EOF) => None,
+            r => Some(r),
+        }
+    }
+}
+
+/// Splits a combined stream of records into one iterator per source, in the
+/// order each source first appears.
+///
+/// Records are grouped by `RecordEnvelope::source`, or by their ident if no
+/// source tag is set. The whole stream is buffered.
+pub fn split_sources<I: IntoIterator<Item = RecordEnvelope>>(records: I) -> Vec<(Arc<str>, vec::IntoIter<RecordEnvelope>)> {
+    let mut index: HashMap<Arc<str>, usize> = HashMap::new();
+    let mut sources: Vec<(Arc<str>, Vec<RecordEnvelope>)> = Vec::new();
+    for env in records {
+        let key = env.source.clone().unwrap_or_else(|| env.ident.clone());
+        let idx = *index.entry(key.clone()).or_insert_with(|| {
+            sources.push((key, Vec::new()));
+            sources.len() - 1
+        });
+        sources[idx].1.push(env);
+    }
+    sources.into_iter().map(|(key, records)| (key, records.into_iter())).collect()
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+
+/// `FlowDirection` is the direction in which the exporter observed a flow on
+/// its interface (IPFIX element 61).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FlowDirection {
+    Ingress,
+    Egress,
+    /// An unassigned value.
+    Other(u8),
+}
+
+impl From<u8> for FlowDirection {
+    fn from(value: u8) -> FlowDirection {
+        match value {
+            0 => FlowDirection::Ingress,
+            1 => FlowDirection::Egress,
+            v => FlowDirection::Other(v),
+        }
+    }
+}
+
+impl From<FlowDirection> for u8 {
+    fn from(value: FlowDirection) -> u8 {
+        match value {
+            FlowDirection::Ingress => 0,
+            FlowDirection::Egress => 1,
+            FlowDirection::Other(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for FlowDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowDirection::Ingress => f.write_str("ingress"),
+            FlowDirection::Egress => f.write_str("egress"),
+            FlowDirection::Other(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// `BiFlowDirection` is the role of the source address of a bidirectional
+/// flow (IPFIX element 239).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BiFlowDirection {
+    /// The exporter did not determine the initiator.
+    Arbitrary,
+    /// The source initiated the flow.
+    Initiator,
+    /// The destination initiated the flow.
+    ReverseInitiator,
+    /// The source is on the inside of the observed perimeter.
+    Perimeter,
+    /// An unassigned value.
+    Other(u8),
+}
+
+impl From<u8> for BiFlowDirection {
+    fn from(value: u8) -> BiFlowDirection {
+        match value {
+            0 => BiFlowDirection::Arbitrary,
+            1 => BiFlowDirection::Initiator,
+            2 => BiFlowDirection::ReverseInitiator,
+            3 => BiFlowDirection::Perimeter,
+            v => BiFlowDirection::Other(v),
+        }
+    }
+}
+
+impl From<BiFlowDirection> for u8 {
+    fn from(value: BiFlowDirection) -> u8 {
+        match value {
+            BiFlowDirection::Arbitrary => 0,
+            BiFlowDirection::Initiator => 1,
+            BiFlowDirection::ReverseInitiator => 2,
+            BiFlowDirection::Perimeter => 3,
+            BiFlowDirection::Other(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for BiFlowDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BiFlowDirection::Arbitrary => f.write_str("arbitrary"),
+            BiFlowDirection::Initiator => f.write_str("initiator"),
+            BiFlowDirection::ReverseInitiator => f.write_str("reverse initiator"),
+            BiFlowDirection::Perimeter => f.write_str("perimeter"),
+            BiFlowDirection::Other(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// `DirectionFilter` selects records observed in one direction. Records
+/// without a direction are not selected.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::direction::{DirectionFilter, FlowDirection};
+///
+/// let ingress = DirectionFilter::new(FlowDirection::Ingress);
+/// let egress = DirectionFilter::parse("flowdir out").unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectionFilter {
+    direction: FlowDirection,
+}
+
+impl DirectionFilter {
+    /// Creates a filter selecting records observed in `direction`.
+    pub fn new(direction: FlowDirection) -> DirectionFilter {
+        DirectionFilter { direction }
+    }
+
+    /// Parses a filter expression: `in`, `out`, `ingress` or `egress`,
+    /// optionally preceded by `flowdir` as in nfdump.
+    pub fn parse(expr: &str) -> Result<DirectionFilter, NfdumpError> {
+        let tokens: Vec<&str> = expr.split_whitespace().collect();
+        let dir = match tokens.as_slice() {
+            [flowdir, dir] if flowdir.eq_ignore_ascii_case("flowdir") => dir,
+            [dir] => dir,
+            _ => return Err(NfdumpError::ParseError),
+        };
+        let direction = match dir.to_ascii_lowercase().as_str() {
+            "in" | "ingress" => FlowDirection::Ingress,
+            "out" | "egress" => FlowDirection::Egress,
+            _ => return Err(NfdumpError::ParseError),
+        };
+        Ok(DirectionFilter::new(direction))
+    }
+}
+
+impl Filter for DirectionFilter {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        record.direction() == Some(self.direction)
+    }
+}
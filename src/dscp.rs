@@ -0,0 +1,196 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+
+// registered code points and their names
+const NAMES: [(u8, &str); 23] = [
+    (0, "CS0"),
+    (1, "LE"),
+    (8, "CS1"),
+    (10, "AF11"),
+    (12, "AF12"),
+    (14, "AF13"),
+    (16, "CS2"),
+    (18, "AF21"),
+    (20, "AF22"),
+    (22, "AF23"),
+    (24, "CS3"),
+    (26, "AF31"),
+    (28, "AF32"),
+    (30, "AF33"),
+    (32, "CS4"),
+    (34, "AF41"),
+    (36, "AF42"),
+    (38, "AF43"),
+    (40, "CS5"),
+    (44, "VA"),
+    (46, "EF"),
+    (48, "CS6"),
+    (56, "CS7"),
+];
+
+/// `Dscp` is a Differentiated Services code point, the upper six bits of a
+/// ToS byte.
+///
+/// It is displayed by its name, like `EF` or `AF41`, if it has one, and
+/// parsed from a name or a number.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::dscp::Dscp;
+///
+/// let dscp = Dscp::from_tos(0xb8);
+/// assert_eq!(dscp, "ef".parse().unwrap());
+/// assert_eq!(dscp.value(), 46);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    /// Creates a `Dscp` from a code point, or returns `None` if it does not
+    /// fit in six bits.
+    pub fn new(value: u8) -> Option<Dscp> {
+        (value < 64).then_some(Dscp(value))
+    }
+
+    /// Returns the DSCP of a ToS byte.
+    pub fn from_tos(tos: u8) -> Dscp {
+        Dscp(tos >> 2)
+    }
+
+    /// Returns the code point.
+    pub fn value(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns the name of a registered code point.
+    pub fn name(&self) -> Option<&'static str> {
+        NAMES.iter().find(|(v, _)| *v == self.0).map(|(_, name)| *name)
+    }
+}
+
+impl fmt::Display for Dscp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for Dscp {
+    type Err = NfdumpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((value, _)) = NAMES.iter().find(|(_, name)| name.eq_ignore_ascii_case(s)) {
+            return Ok(Dscp(*value));
+        }
+        s.parse::<u8>()
+            .ok()
+            .and_then(Dscp::new)
+            .ok_or(NfdumpError::ParseError)
+    }
+}
+
+/// `Ecn` is the Explicit Congestion Notification field, the lower two bits
+/// of a ToS byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Ecn {
+    /// Not ECN-capable transport.
+    NotEct,
+    /// ECN-capable transport, ECT(1).
+    Ect1,
+    /// ECN-capable transport, ECT(0).
+    Ect0,
+    /// Congestion experienced.
+    Ce,
+}
+
+impl Ecn {
+    /// Returns the ECN field of a ToS byte.
+    pub fn from_tos(tos: u8) -> Ecn {
+        match tos & 0x03 {
+            0 => Ecn::NotEct,
+            1 => Ecn::Ect1,
+            2 => Ecn::Ect0,
+            _ => Ecn::Ce,
+        }
+    }
+}
+
+impl fmt::Display for Ecn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Ecn::NotEct => "Not-ECT",
+            Ecn::Ect1 => "ECT(1)",
+            Ecn::Ect0 => "ECT(0)",
+            Ecn::Ce => "CE",
+        })
+    }
+}
+
+/// `TosField` selects which ToS byte of a record a filter looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TosField {
+    Src,
+    Dst,
+    /// Either the source or the destination ToS.
+    SrcOrDst,
+}
+
+/// `DscpFilter` selects records whose DSCP in `field` is a given code point.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::dscp::DscpFilter;
+///
+/// let voice = DscpFilter::parse("dscp ef").unwrap();
+/// let video = DscpFilter::parse("dst dscp af41").unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DscpFilter {
+    field: TosField,
+    dscp: Dscp,
+}
+
+impl DscpFilter {
+    /// Creates a filter matching the DSCP of `field` against `dscp`.
+    pub fn new(field: TosField, dscp: Dscp) -> DscpFilter {
+        DscpFilter { field, dscp }
+    }
+
+    /// Parses a filter expression: `src dscp X`, `dst dscp X` or `dscp X`
+    /// for the source ToS, where `X` is a name like `ef` or a number.
+    pub fn parse(expr: &str) -> Result<DscpFilter, NfdumpError> {
+        let tokens: Vec<&str> = expr.split_whitespace().collect();
+        let (field, dscp) = match tokens.as_slice() {
+            [dir, kw, dscp] if kw.eq_ignore_ascii_case("dscp") => {
+                let field = match dir.to_ascii_lowercase().as_str() {
+                    "src" => TosField::Src,
+                    "dst" => TosField::Dst,
+                    _ => return Err(NfdumpError::ParseError),
+                };
+                (field, *dscp)
+            }
+            [kw, dscp] if kw.eq_ignore_ascii_case("dscp") => (TosField::Src, *dscp),
+            _ => return Err(NfdumpError::ParseError),
+        };
+        Ok(DscpFilter::new(field, dscp.parse()?))
+    }
+}
+
+impl Filter for DscpFilter {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        let dscps = match self.field {
+            TosField::Src => [record.src_dscp(), None],
+            TosField::Dst => [record.dst_dscp(), None],
+            TosField::SrcOrDst => [record.src_dscp(), record.dst_dscp()],
+        };
+        dscps.into_iter().flatten().any(|d| d == self.dscp)
+    }
+}
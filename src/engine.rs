@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// `EngineType` is the kind of flow switching engine in a NetFlow v5
+/// header, which routers with several engines use to tell their flows apart.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EngineType {
+    /// The route processor (engine type 0).
+    RouteProcessor,
+    /// A VIP or line card (engine type 1); the engine id is its slot.
+    LineCard,
+    /// Any other engine type.
+    Other(u8),
+}
+
+impl From<u8> for EngineType {
+    fn from(value: u8) -> EngineType {
+        match value {
+            0 => EngineType::RouteProcessor,
+            1 => EngineType::LineCard,
+            v => EngineType::Other(v),
+        }
+    }
+}
+
+impl From<EngineType> for u8 {
+    fn from(value: EngineType) -> u8 {
+        match value {
+            EngineType::RouteProcessor => 0,
+            EngineType::LineCard => 1,
+            EngineType::Other(v) => v,
+        }
+    }
+}
+
+/// `Engine` is the flow switching engine a record was exported by, taken
+/// from the `engine_type` and `engine_id` of a record header.
+///
+/// It is displayed as `engine_type/engine_id`, the way nfdump prints it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::engine::EngineType;
+/// use nfdump::record::RecordKind;
+///
+/// let file = File::open("path_to_your_file").unwrap();
+/// let mut reader = NfFileReader::new(file).unwrap();
+/// while let Ok(RecordKind::RecordV3(record)) = reader.read_record() {
+///     let engine = record.head.engine();
+///     if let Some(slot) = engine.slot() {
+///         println!("line card in slot {}: {}", slot, engine);
+///     }
+/// }
+/// ```
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Engine {
+    pub engine_type: u8,
+    pub engine_id: u8,
+}
+
+impl Engine {
+    /// Creates an `Engine` from the raw header values.
+    pub fn new(engine_type: u8, engine_id: u8) -> Engine {
+        Engine { engine_type, engine_id }
+    }
+
+    /// Reads the engine from the header of a NetFlow v5 packet, or returns
+    /// `None` if `packet` is not a v5 packet.
+    pub fn from_v5_header(packet: &[u8]) -> Option<Engine> {
+        if packet.len() < 24 || packet[0..2] != [0, 5] {
+            return None;
+        }
+        Some(Engine::new(packet[20], packet[21]))
+    }
+
+    /// Returns the NetFlow v5 meaning of the engine type.
+    pub fn kind(&self) -> EngineType {
+        EngineType::from(self.engine_type)
+    }
+
+    /// Returns the slot of the line card for line card engines.
+    pub fn slot(&self) -> Option<u8> {
+        match self.kind() {
+            EngineType::LineCard => Some(self.engine_id),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.engine_type, self.engine_id)
+    }
+}
@@ -2,6 +2,57 @@ use std::fmt::{Display, Formatter};
 use std::error::Error;
 use std::io;
 
+/// `ErrorContext` locates a failure within a file. Fields are `None` when
+/// they are unknown or do not apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// File offset of the data block.
+    pub file_offset: Option<u64>,
+    /// Index of the data block.
+    pub block: Option<u32>,
+    /// Index of the record within the block.
+    pub record: Option<u32>,
+    /// Offset of the record within the decompressed block.
+    pub record_offset: Option<u64>,
+    /// Type of the record.
+    pub record_type: Option<u16>,
+    /// Number of bytes needed.
+    pub expected: Option<usize>,
+    /// Number of bytes available.
+    pub available: Option<usize>,
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(block) = self.block {
+            parts.push(format!("block {}", block));
+        }
+        if let Some(offset) = self.file_offset {
+            parts.push(format!("file offset {}", offset));
+        }
+        if let Some(record) = self.record {
+            parts.push(format!("record {}", record));
+        }
+        if let Some(offset) = self.record_offset {
+            parts.push(format!("block offset {}", offset));
+        }
+        if let Some(rtype) = self.record_type {
+            parts.push(format!("record type {}", rtype));
+        }
+        match (self.expected, self.available) {
+            (Some(e), Some(a)) => parts.push(format!("expected {} bytes, {} available", e, a)),
+            (Some(e), None) => parts.push(format!("expected {} bytes", e)),
+            _ => {}
+        }
+        if parts.is_empty() {
+            write!(f, "unknown position")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum NfdumpError {
     EOF,
@@ -12,6 +63,17 @@ pub enum NfdumpError {
     UnsupportedVersion,
     UnsupportedCompression,
     UnexpectedExtension,
+    RecordCountMismatch { block: u32, expected: u32, found: u32 },
+    BlockCountMismatch { expected: u32, found: u32 },
+    /// A record could not be decoded; `context` tells where.
+    ParseErrorAt { context: ErrorContext },
+    /// The file ended before a block or record was complete.
+    UnexpectedEof { context: ErrorContext },
+    /// A data block, compressed or decompressed, exceeds the maximum block
+    /// size of `limit` bytes.
+    BlockTooLarge { limit: usize, context: ErrorContext },
+    /// The operation was stopped through its cancellation token.
+    Cancelled,
 }
 
 impl Display for NfdumpError {
@@ -25,6 +87,24 @@ impl Display for NfdumpError {
             NfdumpError::UnsupportedVersion => write!(f, "nfdump file version not supported (yet)"),
             NfdumpError::UnsupportedCompression => write!(f, "nfdump file compression not supported"),
             NfdumpError::UnexpectedExtension => write!(f, "unexpected extension"),
+            NfdumpError::RecordCountMismatch { block, expected, found } => write!(
+                f,
+                "block {} declares {} records but {} were found",
+                block, expected, found
+            ),
+            NfdumpError::BlockCountMismatch { expected, found } => write!(
+                f,
+                "file declares {} blocks but {} were found",
+                expected, found
+            ),
+            NfdumpError::ParseErrorAt { context } => write!(f, "parser error at {}", context),
+            NfdumpError::UnexpectedEof { context } => write!(f, "unexpected end of file at {}", context),
+            NfdumpError::BlockTooLarge { limit, context } => write!(
+                f,
+                "data block exceeds the maximum block size of {} bytes at {}",
+                limit, context
+            ),
+            NfdumpError::Cancelled => write!(f, "operation cancelled"),
         }
     }
 }
@@ -1,10 +1,16 @@
+use std::io::Cursor;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::block::{TYPE_EXPORTER_INFO, TYPE_EXPORTER_STAT, TYPE_SAMPLER};
 use crate::error::NfdumpError;
 use crate::record::NfFileRecordHeader;
 
 const AF_INET: u16 = 2;
-const AF_INET6: u16 = 10;
+// AF_INET6 is platform specific: Linux, NetBSD/OpenBSD, FreeBSD and macOS
+const AF_INET6: [u16; 4] = [10, 24, 28, 30];
+
+// exporter_info_record_t payload as written by nfdump 1.6 and 1.7
+const EXPORTER_INFO_SIZE: usize = 28;
 #[derive(Debug, Copy, Clone)]
 pub struct ExporterInfo {
     pub header: NfFileRecordHeader,
@@ -24,16 +30,97 @@ pub struct SamplerV0Record {
     pub exporter_sysid: u16,
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
+/// `SamplerRecord` describes a packet sampler of an exporter. The sampler
+/// selects `packet_interval` packets, then skips `space_interval` packets.
+#[derive(Debug, Copy, Clone)]
+pub struct SamplerRecord {
+    pub header: NfFileRecordHeader,
+    /// Selector id assigned by the exporting device.
+    pub id: i64,
+    pub packet_interval: u32,
+    pub space_interval: u32,
+    pub algorithm: u16,
+    pub exporter_sysid: u16,
+}
+
+impl ExporterInfo {
+    /// Serializes the exporter as an exporter info record, including its header.
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        let (sa_family, addr) = match self.address {
+            IpAddr::V4(a) => (AF_INET, u32::from(a) as u128),
+            IpAddr::V6(a) if AF_INET6.contains(&self.sa_family) => (self.sa_family, u128::from(a)),
+            IpAddr::V6(a) => (AF_INET6[0], u128::from(a)),
+        };
+        let mut data = Vec::with_capacity(EXPORTER_INFO_SIZE + 4);
+        data.write_u16::<LittleEndian>(TYPE_EXPORTER_INFO).unwrap();
+        data.write_u16::<LittleEndian>((EXPORTER_INFO_SIZE + 4) as u16).unwrap();
+        data.write_u32::<LittleEndian>(self.version).unwrap();
+        data.write_u64::<LittleEndian>((addr >> 64) as u64).unwrap();
+        data.write_u64::<LittleEndian>(addr as u64).unwrap();
+        data.write_u16::<LittleEndian>(sa_family).unwrap();
+        data.write_u16::<LittleEndian>(self.sysid).unwrap();
+        data.write_u32::<LittleEndian>(self.id).unwrap();
+        data
+    }
+}
+
+impl SamplerRecord {
+    /// Serializes the sampler as a sampler record, including its header.
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(24);
+        data.write_u16::<LittleEndian>(TYPE_SAMPLER).unwrap();
+        data.write_u16::<LittleEndian>(24).unwrap();
+        data.write_i64::<LittleEndian>(self.id).unwrap();
+        data.write_u32::<LittleEndian>(self.packet_interval).unwrap();
+        data.write_u32::<LittleEndian>(self.space_interval).unwrap();
+        data.write_u16::<LittleEndian>(self.algorithm).unwrap();
+        data.write_u16::<LittleEndian>(self.exporter_sysid).unwrap();
+        data
+    }
+
+    /// Returns the sampling rate, i.e. the factor by which packet and byte
+    /// counters of sampled flows must be multiplied to estimate actual traffic.
+    pub fn rate(&self) -> u64 {
+        if self.packet_interval == 0 {
+            return 1;
+        }
+        (self.packet_interval as u64 + self.space_interval as u64) / self.packet_interval as u64
+    }
+}
+
+/// Returns the sampler with `selector_id` among an exporter's samplers,
+/// falling back to the generic sampler (negative id).
+pub(crate) fn find_sampler(samplers: &[SamplerRecord], selector_id: i64) -> Option<&SamplerRecord> {
+    samplers
+        .iter()
+        .find(|s| s.id == selector_id)
+        .or_else(|| samplers.iter().filter(|s| s.id < 0).max_by_key(|s| s.id))
+}
+
+impl From<&SamplerV0Record> for SamplerRecord {
+    fn from(value: &SamplerV0Record) -> Self {
+        // legacy samplers select 1 out of `interval` packets
+        SamplerRecord {
+            header: value.header,
+            id: value.id as i64,
+            packet_interval: 1,
+            space_interval: value.interval.saturating_sub(1),
+            algorithm: value.algorithm,
+            exporter_sysid: value.exporter_sysid,
+        }
+    }
+}
+
+/// `ExporterStatsRecord` holds per-exporter statistics of a file.
+#[derive(Debug, Clone)]
 pub struct ExporterStatsRecord {
     pub header: NfFileRecordHeader,
     pub stat_count: u32,
     pub stat: Vec<ExporterStat>,
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
+/// `ExporterStat` counts what was received from one exporter.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub struct ExporterStat {
     pub sysid: u32,
     pub sequence_failure: u32,
@@ -41,14 +128,45 @@ pub struct ExporterStat {
     pub flows: u64,
 }
 
+impl ExporterStatsRecord {
+    /// Serializes the statistics as an exporter stat record, including its header.
+    pub(crate) fn to_bytes(stats: &[ExporterStat]) -> Vec<u8> {
+        let size = 8 + 24 * stats.len();
+        let mut data = Vec::with_capacity(size);
+        data.write_u16::<LittleEndian>(TYPE_EXPORTER_STAT).unwrap();
+        data.write_u16::<LittleEndian>(size as u16).unwrap();
+        data.write_u32::<LittleEndian>(stats.len() as u32).unwrap();
+        for stat in stats {
+            data.write_u32::<LittleEndian>(stat.sysid).unwrap();
+            data.write_u32::<LittleEndian>(stat.sequence_failure).unwrap();
+            data.write_u64::<LittleEndian>(stat.packets).unwrap();
+            data.write_u64::<LittleEndian>(stat.flows).unwrap();
+        }
+        data
+    }
+}
+
+/// Reads an exporter info record.
+///
+/// nfdump 1.6 and 1.7 write the same `exporter_info_record_t` layout. Records
+/// shorter than that layout are rejected rather than misparsed, longer ones
+/// decode the known prefix and ignore the rest. Addresses are decoded
+/// from nfdump's `ip_addr_t` for both IPv4 and IPv6 exporters, accepting the
+/// `AF_INET6` value of any platform the file was written on.
 pub fn read_exporter_record(
     header: NfFileRecordHeader,
     record_data: Vec<u8>,
 ) -> Result<ExporterInfo, NfdumpError> {
+    if record_data.len() < EXPORTER_INFO_SIZE {
+        return Err(NfdumpError::ParseError);
+    }
     let mut cursor = std::io::Cursor::new(&record_data);
 
     let version = cursor.read_u32::<LittleEndian>()?;
-    let addr = cursor.read_u128::<LittleEndian>()?;
+    // ip_addr_t holds two host order u64 words, an IPv4 address is the low
+    // half of the second one
+    let addr_hi = cursor.read_u64::<LittleEndian>()?;
+    let addr_lo = cursor.read_u64::<LittleEndian>()?;
     let sa_family = cursor.read_u16::<LittleEndian>()?;
     let sysid = cursor.read_u16::<LittleEndian>()?;
     let id = cursor.read_u32::<LittleEndian>()?;
@@ -58,9 +176,9 @@ pub fn read_exporter_record(
         version,
         address: {
             if sa_family == AF_INET {
-                IpAddr::from(Ipv4Addr::from((addr >> 64) as u32))
-            } else if sa_family == AF_INET6 {
-                IpAddr::from(Ipv6Addr::from(addr))
+                IpAddr::from(Ipv4Addr::from(addr_lo as u32))
+            } else if AF_INET6.contains(&sa_family) {
+                IpAddr::from(Ipv6Addr::from(((addr_hi as u128) << 64) | addr_lo as u128))
             } else {
                 return Err(NfdumpError::UnexpectedSAInExporter);
             }
@@ -86,8 +204,22 @@ pub fn read_samplerv0_record(
     })
 }
 
-// dead temporarily until implemented again
-#[allow(dead_code)]
+pub fn read_sampler_record(
+    header: NfFileRecordHeader,
+    record_data: Vec<u8>,
+) -> Result<SamplerRecord, NfdumpError> {
+    let mut cursor = std::io::Cursor::new(&record_data);
+
+    Ok(SamplerRecord {
+        header,
+        id: cursor.read_i64::<LittleEndian>()?,
+        packet_interval: cursor.read_u32::<LittleEndian>()?,
+        space_interval: cursor.read_u32::<LittleEndian>()?,
+        algorithm: cursor.read_u16::<LittleEndian>()?,
+        exporter_sysid: cursor.read_u16::<LittleEndian>()?,
+    })
+}
+
 pub fn read_exporter_stats_record(
     header: NfFileRecordHeader,
     record_data: Vec<u8>,
@@ -113,3 +245,35 @@ pub fn read_exporter_stats_record(
         stat,
     })
 }
+
+/// `InterfaceName` maps an interface index of an exporter to its name, as
+/// stored in interface name records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceName {
+    pub if_index: u32,
+    pub name: String,
+}
+
+/// Reads an interface name record: an array record header (element count
+/// and element size), followed by elements holding the interface index and a
+/// NUL terminated name.
+pub(crate) fn read_if_name_record(_header: NfFileRecordHeader, data: Vec<u8>) -> Result<Vec<InterfaceName>, NfdumpError> {
+    let mut cursor = Cursor::new(&data);
+    let num_elements = cursor.read_u16::<LittleEndian>()? as usize;
+    let element_size = cursor.read_u16::<LittleEndian>()? as usize;
+    if element_size < 4 || 4 + num_elements * element_size > data.len() {
+        return Err(NfdumpError::ParseError);
+    }
+
+    Ok(data[4..4 + num_elements * element_size]
+        .chunks_exact(element_size)
+        .map(|e| {
+            let name = &e[4..];
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            InterfaceName {
+                if_index: u32::from_le_bytes([e[0], e[1], e[2], e[3]]),
+                name: String::from_utf8_lossy(&name[..end]).into_owned(),
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,151 @@
+use std::net::IpAddr;
+use crate::flow::FlowRecord;
+use crate::pushdown::Pushdown;
+use crate::record::RecordKind;
+
+/// `Filter` decides whether a flow record is selected.
+///
+/// Filters work on any `FlowRecord` and can be combined with `All`, `Any`
+/// and `Not`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::filter::{AddrField, Filter};
+/// use nfdump::prefix::PrefixFilter;
+///
+/// let filter = PrefixFilter::new(AddrField::Src, ["10.0.0.0/8".parse().unwrap()]);
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     if filter.matches_kind(&record) {
+///         println!("{:?}", record);
+///     }
+/// }
+/// ```
+pub trait Filter {
+    /// Returns `true` if the record is selected.
+    fn matches(&self, record: &dyn FlowRecord) -> bool;
+
+    /// Returns `true` if the record is a flow record selected by the filter.
+    fn matches_kind(&self, record: &RecordKind) -> bool {
+        match record {
+            RecordKind::Record(r) => self.matches(r),
+            RecordKind::RecordV3(r) => self.matches(r),
+            _ => false,
+        }
+    }
+
+    /// Returns predicates every record selected by the filter satisfies,
+    /// for the reader to check before decoding records with
+    /// `NfFileReader::set_pushdown`. None by default.
+    fn pushdown(&self) -> Pushdown {
+        Pushdown::new()
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for Box<F> {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        (**self).matches(record)
+    }
+
+    fn matches_kind(&self, record: &RecordKind) -> bool {
+        (**self).matches_kind(record)
+    }
+
+    fn pushdown(&self) -> Pushdown {
+        (**self).pushdown()
+    }
+}
+
+impl<F: Filter + ?Sized> Filter for std::sync::Arc<F> {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        (**self).matches(record)
+    }
+
+    fn matches_kind(&self, record: &RecordKind) -> bool {
+        (**self).matches_kind(record)
+    }
+
+    fn pushdown(&self) -> Pushdown {
+        (**self).pushdown()
+    }
+}
+
+/// Selects records matched by every filter.
+pub struct All(pub Vec<Box<dyn Filter + Send + Sync>>);
+
+impl Filter for All {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        self.0.iter().all(|f| f.matches(record))
+    }
+
+    fn matches_kind(&self, record: &RecordKind) -> bool {
+        self.0.iter().all(|f| f.matches_kind(record))
+    }
+
+    fn pushdown(&self) -> Pushdown {
+        self.0.iter().fold(Pushdown::new(), |p, f| p.and(&f.pushdown()))
+    }
+}
+
+/// Selects records matched by at least one filter.
+pub struct Any(pub Vec<Box<dyn Filter + Send + Sync>>);
+
+impl Filter for Any {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        self.0.iter().any(|f| f.matches(record))
+    }
+}
+
+/// Selects records not matched by the inner filter.
+pub struct Not<F: Filter>(pub F);
+
+impl<F: Filter> Filter for Not<F> {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        !self.0.matches(record)
+    }
+}
+
+/// `FilterFn` turns a closure into a `Filter`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::filter::FilterFn;
+/// use nfdump::flow::FlowRecord;
+///
+/// let interface_7 = FilterFn(|r: &dyn FlowRecord| r.input() == Some(7));
+/// ```
+pub struct FilterFn<F>(pub F);
+
+impl<F: Fn(&dyn FlowRecord) -> bool> Filter for FilterFn<F> {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        (self.0)(record)
+    }
+}
+
+/// `AddrField` selects which address of a record a filter looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrField {
+    Src,
+    Dst,
+    /// Either the source or the destination address.
+    SrcOrDst,
+    NextHop,
+    BgpNextHop,
+}
+
+impl AddrField {
+    /// Returns the addresses of `record` selected by the field.
+    pub fn addrs(&self, record: &dyn FlowRecord) -> [Option<IpAddr>; 2] {
+        match self {
+            AddrField::Src => [record.src_addr(), None],
+            AddrField::Dst => [record.dst_addr(), None],
+            AddrField::SrcOrDst => [record.src_addr(), record.dst_addr()],
+            AddrField::NextHop => [record.next_hop(), None],
+            AddrField::BgpNextHop => [record.bgp_next_hop(), None],
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use std::net::IpAddr;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use crate::direction::{BiFlowDirection, FlowDirection};
+use crate::dscp::{Dscp, Ecn};
+use crate::engine::Engine;
+use crate::nfx_v3::RecordV3;
+use crate::tunnel::FlowLayer;
+use crate::record::Record;
+use crate::status::{FlowEndReason, ForwardingStatus};
+use crate::validate::{validate_record, Finding};
+
+/// `FlowRecord` gives uniform access to the fields shared by legacy common
+/// records and V3 records, so filters and reports can work on either.
+///
+/// Accessors return `None` when the record does not carry the field.
+pub trait FlowRecord {
+    /// Source address of the flow.
+    fn src_addr(&self) -> Option<IpAddr>;
+    /// Destination address of the flow.
+    fn dst_addr(&self) -> Option<IpAddr>;
+    /// IP next hop address.
+    fn next_hop(&self) -> Option<IpAddr>;
+    /// BGP next hop address.
+    fn bgp_next_hop(&self) -> Option<IpAddr>;
+    /// Source port, or the ICMP type and code for ICMP flows.
+    fn src_port(&self) -> Option<u16>;
+    /// Destination port.
+    fn dst_port(&self) -> Option<u16>;
+    /// IP protocol number.
+    fn proto(&self) -> Option<u8>;
+    /// ICMP type of an ICMP or ICMPv6 flow, carried in the high byte of the
+    /// destination port. `None` for other protocols.
+    fn icmp_type(&self) -> Option<u8> {
+        match self.proto()? {
+            1 | 58 => self.dst_port().map(|p| (p >> 8) as u8),
+            _ => None,
+        }
+    }
+    /// ICMP code of an ICMP or ICMPv6 flow, carried in the low byte of the
+    /// destination port. `None` for other protocols.
+    fn icmp_code(&self) -> Option<u8> {
+        match self.proto()? {
+            1 | 58 => self.dst_port().map(|p| (p & 0xff) as u8),
+            _ => None,
+        }
+    }
+    /// Cumulated TCP flags.
+    fn tcp_flags(&self) -> Option<u8>;
+    /// Forwarding status.
+    fn fwd_status(&self) -> Option<u8>;
+    /// Forwarding status, decoded.
+    fn forwarding_status(&self) -> Option<ForwardingStatus> {
+        self.fwd_status().map(ForwardingStatus::from)
+    }
+    /// Reason the exporter ended the flow, `None` if it did not say.
+    fn flow_end_reason(&self) -> Option<FlowEndReason> {
+        None
+    }
+    /// Direction in which the exporter observed the flow.
+    fn direction(&self) -> Option<FlowDirection> {
+        None
+    }
+    /// Role of the source address of a bidirectional flow.
+    fn bi_flow_direction(&self) -> Option<BiFlowDirection> {
+        None
+    }
+    /// Source type of service byte.
+    fn tos(&self) -> Option<u8>;
+    /// Destination type of service byte.
+    fn dst_tos(&self) -> Option<u8> {
+        None
+    }
+    /// DSCP of the source type of service byte.
+    fn src_dscp(&self) -> Option<Dscp> {
+        self.tos().map(Dscp::from_tos)
+    }
+    /// ECN bits of the source type of service byte.
+    fn src_ecn(&self) -> Option<Ecn> {
+        self.tos().map(Ecn::from_tos)
+    }
+    /// DSCP of the destination type of service byte.
+    fn dst_dscp(&self) -> Option<Dscp> {
+        self.dst_tos().map(Dscp::from_tos)
+    }
+    /// ECN bits of the destination type of service byte.
+    fn dst_ecn(&self) -> Option<Ecn> {
+        self.dst_tos().map(Ecn::from_tos)
+    }
+    /// First seen timestamp in milliseconds since the epoch.
+    fn first_msec(&self) -> Option<u64>;
+    /// Last seen timestamp in milliseconds since the epoch.
+    fn last_msec(&self) -> Option<u64>;
+    /// Packet count.
+    fn packets(&self) -> Option<u64>;
+    /// Byte count.
+    fn bytes(&self) -> Option<u64>;
+    /// Number of flows the record represents; aggregated records count
+    /// several flows.
+    fn flows(&self) -> u64 {
+        1
+    }
+    /// Packet count of the reverse direction of a bidirectional flow.
+    fn out_packets(&self) -> Option<u64> {
+        None
+    }
+    /// Byte count of the reverse direction of a bidirectional flow.
+    fn out_bytes(&self) -> Option<u64> {
+        None
+    }
+    /// Source autonomous system number.
+    fn src_as(&self) -> Option<u32>;
+    /// Destination autonomous system number.
+    fn dst_as(&self) -> Option<u32>;
+    /// SNMP input interface index.
+    fn input(&self) -> Option<u32>;
+    /// SNMP output interface index.
+    fn output(&self) -> Option<u32>;
+    /// Exporter system id the record was received from.
+    fn exporter_sysid(&self) -> Option<u16>;
+    /// Flow switching engine that exported the record.
+    fn engine(&self) -> Option<Engine> {
+        None
+    }
+
+    /// Checks the record for impossible values, such as a flow ending before
+    /// it starts or bytes without packets. Returns an empty list for a
+    /// plausible record.
+    fn validate(&self) -> Vec<Finding> {
+        validate_record(self)
+    }
+
+    /// Tunnel endpoints of an encapsulated flow.
+    fn tunnel_endpoints(&self) -> Option<FlowLayer> {
+        None
+    }
+
+    /// Returns the Community ID (version 1) of the flow, such as
+    /// `1:LQU9qZlK+B5F3KDmev6m5PMibrg=`, for correlating flows with Zeek or
+    /// Suricata logs. `seed` must match the seed used by the other tools,
+    /// usually 0.
+    ///
+    /// Returns `None` if the record lacks addresses or protocol.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::flow::FlowRecord;
+    /// use nfdump::record::RecordKind;
+    ///
+    /// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+    /// if let Ok(RecordKind::RecordV3(record)) = reader.read_record() {
+    ///     println!("{:?}", record.community_id(0));
+    /// }
+    /// ```
+    fn community_id(&self, seed: u16) -> Option<String> {
+        community_id(
+            seed,
+            self.src_addr()?,
+            self.dst_addr()?,
+            self.proto()?,
+            self.src_port().unwrap_or(0),
+            self.dst_port().unwrap_or(0),
+        )
+    }
+}
+
+const PROTO_ICMP: u8 = 1;
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+const PROTO_ICMP6: u8 = 58;
+const PROTO_SCTP: u8 = 132;
+
+/// Computes a version 1 Community ID. For ICMP flows the type and code are
+/// taken from the destination port, where NetFlow exporters store them.
+fn community_id(seed: u16, src: IpAddr, dst: IpAddr, proto: u8, src_port: u16, dst_port: u16) -> Option<String> {
+    let (mut src, mut dst) = (ip_bytes(src), ip_bytes(dst));
+    if src.len() != dst.len() {
+        return None;
+    }
+
+    let (mut sport, mut dport, one_way) = match proto {
+        PROTO_ICMP | PROTO_ICMP6 => {
+            let (icmp_type, icmp_code) = ((dst_port >> 8) as u8, dst_port as u8);
+            match icmp_counterpart(proto, icmp_type) {
+                Some(counterpart) => (icmp_type as u16, counterpart as u16, false),
+                None => (icmp_type as u16, icmp_code as u16, true),
+            }
+        }
+        _ => (src_port, dst_port, false),
+    };
+
+    if !(one_way || src < dst || (src == dst && sport < dport)) {
+        std::mem::swap(&mut src, &mut dst);
+        std::mem::swap(&mut sport, &mut dport);
+    }
+
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(&seed.to_be_bytes());
+    hasher.update(&src);
+    hasher.update(&dst);
+    hasher.update(&[proto, 0]);
+    if matches!(proto, PROTO_ICMP | PROTO_ICMP6 | PROTO_TCP | PROTO_UDP | PROTO_SCTP) {
+        hasher.update(&sport.to_be_bytes());
+        hasher.update(&dport.to_be_bytes());
+    }
+
+    Some(format!("1:{}", STANDARD.encode(hasher.digest().bytes())))
+}
+
+fn ip_bytes(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec(),
+    }
+}
+
+/// Returns the message type answering an ICMP type of a request/response
+/// pair, or `None` for one-way messages.
+fn icmp_counterpart(proto: u8, icmp_type: u8) -> Option<u8> {
+    let pairs: &[(u8, u8)] = if proto == PROTO_ICMP {
+        &[(8, 0), (13, 14), (15, 16), (10, 9), (17, 18)]
+    } else {
+        &[(128, 129), (130, 131), (133, 134), (135, 136), (139, 140), (144, 145)]
+    };
+    pairs.iter().find_map(|&(a, b)| match icmp_type {
+        t if t == a => Some(b),
+        t if t == b => Some(a),
+        _ => None,
+    })
+}
+
+impl FlowRecord for Record {
+    fn src_addr(&self) -> Option<IpAddr> {
+        Some(self.src_addr)
+    }
+
+    fn dst_addr(&self) -> Option<IpAddr> {
+        Some(self.dst_addr)
+    }
+
+    fn next_hop(&self) -> Option<IpAddr> {
+        None
+    }
+
+    fn bgp_next_hop(&self) -> Option<IpAddr> {
+        None
+    }
+
+    fn src_port(&self) -> Option<u16> {
+        Some(self.src_port)
+    }
+
+    fn dst_port(&self) -> Option<u16> {
+        Some(self.dst_port)
+    }
+
+    fn proto(&self) -> Option<u8> {
+        Some(self.prot)
+    }
+
+    fn tcp_flags(&self) -> Option<u8> {
+        Some(self.tcp_flags)
+    }
+
+    fn fwd_status(&self) -> Option<u8> {
+        Some(self.fwd_status)
+    }
+
+    fn tos(&self) -> Option<u8> {
+        Some(self.tos)
+    }
+
+    fn first_msec(&self) -> Option<u64> {
+        Some(self.first as u64 * 1000 + self.msec_first as u64)
+    }
+
+    fn last_msec(&self) -> Option<u64> {
+        Some(self.last as u64 * 1000 + self.msec_last as u64)
+    }
+
+    fn packets(&self) -> Option<u64> {
+        Some(self.packets)
+    }
+
+    fn bytes(&self) -> Option<u64> {
+        Some(self.bytes)
+    }
+
+    fn src_as(&self) -> Option<u32> {
+        self.src_as
+    }
+
+    fn dst_as(&self) -> Option<u32> {
+        self.dst_as
+    }
+
+    fn input(&self) -> Option<u32> {
+        self.input
+    }
+
+    fn output(&self) -> Option<u32> {
+        self.output
+    }
+
+    fn exporter_sysid(&self) -> Option<u16> {
+        Some(self.exporter_sysid)
+    }
+
+    fn flow_end_reason(&self) -> Option<FlowEndReason> {
+        Some(self.flow_end_reason).filter(|&r| r != 0).map(FlowEndReason::from)
+    }
+
+    fn bi_flow_direction(&self) -> Option<BiFlowDirection> {
+        Some(BiFlowDirection::from(self.bi_flow_dir))
+    }
+}
+
+impl FlowRecord for RecordV3 {
+    fn src_addr(&self) -> Option<IpAddr> {
+        match (&self.ipv4_flow, &self.ipv6_flow) {
+            (Some(e), _) => Some(IpAddr::V4(e.src_addr)),
+            (None, Some(e)) => Some(IpAddr::V6(e.src_addr)),
+            (None, None) => None,
+        }
+    }
+
+    fn dst_addr(&self) -> Option<IpAddr> {
+        match (&self.ipv4_flow, &self.ipv6_flow) {
+            (Some(e), _) => Some(IpAddr::V4(e.dst_addr)),
+            (None, Some(e)) => Some(IpAddr::V6(e.dst_addr)),
+            (None, None) => None,
+        }
+    }
+
+    fn next_hop(&self) -> Option<IpAddr> {
+        match (&self.ip_next_hop_ipv4, &self.ip_next_hop_ipv6) {
+            (Some(e), _) => Some(IpAddr::V4(e.ip)),
+            (None, Some(e)) => Some(IpAddr::V6(e.ip)),
+            (None, None) => None,
+        }
+    }
+
+    fn bgp_next_hop(&self) -> Option<IpAddr> {
+        match (&self.bgp_next_hop_ipv4, &self.bgp_next_hop_ipv6) {
+            (Some(e), _) => Some(IpAddr::V4(e.ip)),
+            (None, Some(e)) => Some(IpAddr::V6(e.ip)),
+            (None, None) => None,
+        }
+    }
+
+    fn src_port(&self) -> Option<u16> {
+        self.generic_flow.as_ref().map(|e| e.src_port)
+    }
+
+    fn dst_port(&self) -> Option<u16> {
+        self.generic_flow.as_ref().map(|e| e.dst_port)
+    }
+
+    fn proto(&self) -> Option<u8> {
+        self.generic_flow.as_ref().map(|e| e.proto)
+    }
+
+    fn tcp_flags(&self) -> Option<u8> {
+        self.generic_flow.as_ref().map(|e| e.tcp_flags)
+    }
+
+    fn fwd_status(&self) -> Option<u8> {
+        self.generic_flow.as_ref().map(|e| e.fwd_status)
+    }
+
+    fn tos(&self) -> Option<u8> {
+        self.generic_flow.as_ref().map(|e| e.src_tos)
+    }
+
+    fn first_msec(&self) -> Option<u64> {
+        self.generic_flow.as_ref().map(|e| e.msec_first)
+    }
+
+    fn last_msec(&self) -> Option<u64> {
+        self.generic_flow.as_ref().map(|e| e.msec_last)
+    }
+
+    fn packets(&self) -> Option<u64> {
+        self.generic_flow.as_ref().map(|e| e.in_packets)
+    }
+
+    fn bytes(&self) -> Option<u64> {
+        self.generic_flow.as_ref().map(|e| e.in_bytes)
+    }
+
+    fn flows(&self) -> u64 {
+        self.cnt_flow.as_ref().map_or(1, |c| c.flows.max(1))
+    }
+
+    fn out_packets(&self) -> Option<u64> {
+        self.cnt_flow.as_ref().map(|c| c.out_packets)
+    }
+
+    fn out_bytes(&self) -> Option<u64> {
+        self.cnt_flow.as_ref().map(|c| c.out_bytes)
+    }
+
+    fn src_as(&self) -> Option<u32> {
+        self.as_routing.as_ref().map(|e| e.src_as)
+    }
+
+    fn dst_as(&self) -> Option<u32> {
+        self.as_routing.as_ref().map(|e| e.dst_as)
+    }
+
+    fn input(&self) -> Option<u32> {
+        self.flow_misc.as_ref().map(|e| e.input)
+    }
+
+    fn output(&self) -> Option<u32> {
+        self.flow_misc.as_ref().map(|e| e.output)
+    }
+
+    fn exporter_sysid(&self) -> Option<u16> {
+        Some(self.head.exporter_id)
+    }
+
+    fn engine(&self) -> Option<Engine> {
+        Some(self.head.engine())
+    }
+
+    fn flow_end_reason(&self) -> Option<FlowEndReason> {
+        self.flow_misc
+            .as_ref()
+            .map(|e| e.flow_end_reason)
+            .filter(|&r| r != 0)
+            .map(FlowEndReason::from)
+    }
+
+    fn dst_tos(&self) -> Option<u8> {
+        self.flow_misc.as_ref().map(|e| e.dst_tos)
+    }
+
+    fn direction(&self) -> Option<FlowDirection> {
+        self.flow_misc.as_ref().map(|e| FlowDirection::from(e.dir))
+    }
+
+    fn bi_flow_direction(&self) -> Option<BiFlowDirection> {
+        self.flow_misc.as_ref().map(|e| BiFlowDirection::from(e.bi_flow_dir))
+    }
+
+    fn tunnel_endpoints(&self) -> Option<FlowLayer> {
+        self.tunnel().map(|t| t.outer)
+    }
+}
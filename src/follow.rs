@@ -0,0 +1,263 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use crate::error::NfdumpError;
+use crate::record::{RecordEnvelope, RecordKind};
+use crate::{NfFileHeader, NfFileReader};
+
+const CURRENT_PREFIX: &str = "nfcapd.current";
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `FollowReader` reads the file a collector is writing, like `tail -f`,
+/// returning records as their data blocks are written and waiting for more.
+///
+/// nfcapd writes to `nfcapd.current.<pid>` and leaves the header counting
+/// no blocks until it closes the file, writing the appendix and the final
+/// header before renaming it. The reader walks the block headers itself
+/// and only reads blocks written completely. A followed file ends once its
+/// header is final and all its data blocks are read.
+///
+/// Watching a directory follows the current file across rotations: after a
+/// file is closed, the next current file is opened. A file closed and
+/// replaced more than once while the reader was behind is missed; it can be
+/// read from its rotated name.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::follow::FollowReader;
+///
+/// let mut reader = FollowReader::watch("/var/cache/nfdump/router1");
+/// loop {
+///     let record = reader.read_record().unwrap();
+///     println!("{:?}", record);
+/// }
+/// ```
+pub struct FollowReader {
+    dir: Option<PathBuf>,
+    path: Option<PathBuf>,
+    file: Option<Followed>,
+    poll_interval: Duration,
+    cancel: Option<Arc<AtomicBool>>,
+    done: bool,
+}
+
+struct Followed {
+    path: PathBuf,
+    reader: NfFileReader<File>,
+    // second handle for walking the block headers without moving the reader
+    probe: File,
+    next_block: u64,
+    blocks: u32,
+    // number of data blocks once the writer closed the file
+    closed: Option<u32>,
+}
+
+impl FollowReader {
+    /// Follows the file at `path` until the collector closes it.
+    pub fn open<P: AsRef<Path>>(path: P) -> FollowReader {
+        FollowReader {
+            dir: None,
+            path: Some(path.as_ref().to_path_buf()),
+            file: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            cancel: None,
+            done: false,
+        }
+    }
+
+    /// Follows the `nfcapd.current.*` files in `dir`, moving on to the next
+    /// one each time the collector rotates its file. Reads never end.
+    pub fn watch<P: AsRef<Path>>(dir: P) -> FollowReader {
+        FollowReader {
+            dir: Some(dir.as_ref().to_path_buf()),
+            path: None,
+            file: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            cancel: None,
+            done: false,
+        }
+    }
+
+    /// Sets how long to wait between checks for new data, 1 second by
+    /// default.
+    pub fn set_poll_interval(&mut self, interval: Duration) {
+        self.poll_interval = interval;
+    }
+
+    /// Sets a cancellation token. Once it is set to `true`, reads return
+    /// `NfdumpError::Cancelled`, also while waiting for data.
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Returns the path of the file being followed.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|f| f.path.as_path())
+    }
+
+    /// Reads the next record, waiting until one is written. Returns
+    /// `NfdumpError::EOF` once a single followed file is closed and read.
+    pub fn read_record(&mut self) -> Result<RecordKind, NfdumpError> {
+        self.read_record_envelope().map(|env| env.record)
+    }
+
+    /// Reads the next record with its envelope, waiting until one is
+    /// written.
+    pub fn read_record_envelope(&mut self) -> Result<RecordEnvelope, NfdumpError> {
+        loop {
+            if self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+                return Err(NfdumpError::Cancelled);
+            }
+            if let Some(env) = self.poll_record_envelope()? {
+                return Ok(env);
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Reads the next record if one has been written, without waiting.
+    pub fn poll_record_envelope(&mut self) -> Result<Option<RecordEnvelope>, NfdumpError> {
+        loop {
+            if self.done {
+                return Err(NfdumpError::EOF);
+            }
+            if self.file.is_none() {
+                self.file = self.open_next()?;
+            }
+            let Some(file) = self.file.as_mut() else {
+                return Ok(None);
+            };
+            match file.reader.read_record_envelope() {
+                Ok(env) => return Ok(Some(env)),
+                Err(NfdumpError::EOF) => {}
+                Err(e) => return Err(e),
+            }
+            if file.grow()? > 0 {
+                continue;
+            }
+            if file.closed.is_none() {
+                return Ok(None);
+            }
+            self.file = None;
+            self.done = self.dir.is_none();
+        }
+    }
+
+    // Opens the file to follow next, or returns `None` if there is none yet
+    fn open_next(&mut self) -> Result<Option<Followed>, NfdumpError> {
+        let Some(dir) = &self.dir else {
+            let Some(path) = self.path.take() else {
+                return Ok(None);
+            };
+            let followed = Followed::open(&path)?;
+            if followed.is_none() {
+                self.path = Some(path);
+            }
+            return Ok(followed);
+        };
+
+        let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+        for dirent in fs::read_dir(dir)? {
+            let dirent = dirent?;
+            if !dirent.file_name().to_string_lossy().starts_with(CURRENT_PREFIX) {
+                continue;
+            }
+            let modified = dirent.metadata()?.modified()?;
+            if newest.as_ref().is_none_or(|(t, _)| modified > *t) {
+                newest = Some((modified, dirent.path()));
+            }
+        }
+        let Some((_, path)) = newest else {
+            return Ok(None);
+        };
+        match Followed::open(&path)? {
+            // a closed file is the previous one before its rename
+            Some(f) if f.closed.is_some() => Ok(None),
+            followed => Ok(followed),
+        }
+    }
+}
+
+impl Followed {
+    // Returns `None` if the header is not completely written yet
+    fn open(path: &Path) -> Result<Option<Followed>, NfdumpError> {
+        let probe = File::open(path)?;
+        let Some(header) = read_header(&probe)? else {
+            return Ok(None);
+        };
+        let reader = match NfFileReader::new(File::open(path)?) {
+            Ok(r) => r,
+            Err(NfdumpError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut followed = Followed {
+            path: path.to_path_buf(),
+            next_block: header.data_offset(),
+            closed: is_closed(&header).then(|| header.num_blocks()),
+            reader,
+            probe,
+            blocks: 0,
+        };
+        // the reader counts the blocks of a closed file itself
+        if let Some(n) = followed.closed {
+            followed.blocks = n;
+        }
+        Ok(Some(followed))
+    }
+
+    // Makes the data blocks written since the last call readable and
+    // returns their number
+    fn grow(&mut self) -> Result<u32, NfdumpError> {
+        if self.closed.is_none() {
+            // checked before walking the blocks, so appendix blocks written
+            // since are not taken for data blocks
+            if let Some(header) = read_header(&self.probe)? {
+                if is_closed(&header) {
+                    self.closed = Some(header.num_blocks());
+                }
+            }
+        }
+
+        let len = self.probe.metadata()?.len();
+        let limit = self.closed.unwrap_or(u32::MAX);
+        let mut count = 0;
+        let mut db_buf = [0; 12];
+        while self.blocks + count < limit && self.next_block + 12 <= len {
+            self.probe.seek(SeekFrom::Start(self.next_block))?;
+            self.probe.read_exact(&mut db_buf)?;
+            let size = u32::from_le_bytes([db_buf[4], db_buf[5], db_buf[6], db_buf[7]]) as u64;
+            if self.next_block + 12 + size > len {
+                break;
+            }
+            self.next_block += 12 + size;
+            count += 1;
+        }
+        self.blocks += count;
+        self.reader.follow_blocks(count);
+        Ok(count)
+    }
+}
+
+// Reads the header as it is on disk, or returns `None` if it is not
+// completely written yet
+fn read_header(mut file: &File) -> Result<Option<NfFileHeader>, NfdumpError> {
+    file.seek(SeekFrom::Start(0))?;
+    match NfFileReader::new(file) {
+        Ok(r) => Ok(Some(r.header)),
+        Err(NfdumpError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// nfcapd writes the block count, and the appendix of v2 files, on close
+fn is_closed(header: &NfFileHeader) -> bool {
+    match header {
+        NfFileHeader::V1(h) => h.num_blocks > 0,
+        NfFileHeader::V2(h) => h.num_blocks > 0 || h.off_appendix > 0,
+    }
+}
@@ -0,0 +1,155 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::annotation::{Annotations, Annotator};
+use crate::error::NfdumpError;
+use crate::filter::{AddrField, Filter};
+use crate::flow::FlowRecord;
+
+/// `GeoLookup` maps addresses to ISO 3166-1 alpha-2 country codes.
+pub trait GeoLookup: Send + Sync {
+    /// Returns the upper case country code of `addr`, if known.
+    fn country(&self, addr: IpAddr) -> Option<[u8; 2]>;
+}
+
+/// `MaxMindLookup` resolves countries from a MaxMind GeoIP2 or GeoLite2
+/// country or city database.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::geo::MaxMindLookup;
+///
+/// let lookup = MaxMindLookup::open("GeoLite2-Country.mmdb").unwrap();
+/// ```
+#[cfg(feature = "geoip")]
+pub struct MaxMindLookup {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+#[cfg(feature = "geoip")]
+impl MaxMindLookup {
+    /// Opens the database at `path`.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<MaxMindLookup, NfdumpError> {
+        let reader = maxminddb::Reader::open_readfile(path).map_err(|e| match e {
+            maxminddb::MaxMindDBError::IoError(e) => NfdumpError::IoError(std::io::Error::other(e)),
+            _ => NfdumpError::InvalidFile,
+        })?;
+        Ok(MaxMindLookup { reader })
+    }
+}
+
+#[cfg(feature = "geoip")]
+impl GeoLookup for MaxMindLookup {
+    fn country(&self, addr: IpAddr) -> Option<[u8; 2]> {
+        let country: maxminddb::geoip2::Country = self.reader.lookup(addr).ok()?;
+        parse_country(country.country?.iso_code?).ok()
+    }
+}
+
+/// `GeoFilter` selects records whose address in `field` is located in a
+/// given country, like nfdump's `src geo DE` filter.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use std::sync::Arc;
+/// use nfdump::geo::{GeoFilter, GeoLookup};
+///
+/// struct Everywhere;
+///
+/// impl GeoLookup for Everywhere {
+///     fn country(&self, _addr: IpAddr) -> Option<[u8; 2]> {
+///         Some(*b"CN")
+///     }
+/// }
+///
+/// let filter = GeoFilter::parse("dst geo CN", Arc::new(Everywhere)).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct GeoFilter {
+    field: AddrField,
+    country: [u8; 2],
+    lookup: Arc<dyn GeoLookup>,
+}
+
+impl GeoFilter {
+    /// Creates a filter matching the country of `field` against `country`,
+    /// a two letter country code.
+    pub fn new(field: AddrField, country: &str, lookup: Arc<dyn GeoLookup>) -> Result<GeoFilter, NfdumpError> {
+        Ok(GeoFilter {
+            field,
+            country: parse_country(country)?,
+            lookup,
+        })
+    }
+
+    /// Parses a filter expression in nfdump syntax: `src geo XX`,
+    /// `dst geo XX` or `geo XX` for either address.
+    pub fn parse(expr: &str, lookup: Arc<dyn GeoLookup>) -> Result<GeoFilter, NfdumpError> {
+        let tokens: Vec<&str> = expr.split_whitespace().collect();
+        let (field, country) = match tokens.as_slice() {
+            [dir, geo, country] if geo.eq_ignore_ascii_case("geo") => {
+                let field = match dir.to_ascii_lowercase().as_str() {
+                    "src" => AddrField::Src,
+                    "dst" => AddrField::Dst,
+                    _ => return Err(NfdumpError::ParseError),
+                };
+                (field, *country)
+            }
+            [geo, country] if geo.eq_ignore_ascii_case("geo") => (AddrField::SrcOrDst, *country),
+            _ => return Err(NfdumpError::ParseError),
+        };
+        GeoFilter::new(field, country, lookup)
+    }
+}
+
+impl Filter for GeoFilter {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        self.field
+            .addrs(record)
+            .into_iter()
+            .flatten()
+            .any(|a| self.lookup.country(a) == Some(self.country))
+    }
+}
+
+/// `Countries` is the annotation `GeoAnnotator` attaches to records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Countries {
+    /// The country code of the source address.
+    pub src: Option<[u8; 2]>,
+    /// The country code of the destination address.
+    pub dst: Option<[u8; 2]>,
+}
+
+/// `GeoAnnotator` attaches the `Countries` of a record's addresses.
+#[derive(Clone)]
+pub struct GeoAnnotator {
+    lookup: Arc<dyn GeoLookup>,
+}
+
+impl GeoAnnotator {
+    /// Creates an annotator resolving countries with `lookup`.
+    pub fn new(lookup: Arc<dyn GeoLookup>) -> GeoAnnotator {
+        GeoAnnotator { lookup }
+    }
+}
+
+impl Annotator for GeoAnnotator {
+    fn annotate(&self, record: &dyn FlowRecord, annotations: &mut Annotations) {
+        annotations.insert(Countries {
+            src: record.src_addr().and_then(|a| self.lookup.country(a)),
+            dst: record.dst_addr().and_then(|a| self.lookup.country(a)),
+        });
+    }
+}
+
+fn parse_country(code: &str) -> Result<[u8; 2], NfdumpError> {
+    match code.as_bytes() {
+        [a, b] if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+            Ok([a.to_ascii_uppercase(), b.to_ascii_uppercase()])
+        }
+        _ => Err(NfdumpError::ParseError),
+    }
+}
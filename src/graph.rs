@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use ipnet::IpNet;
+use crate::flow::FlowRecord;
+use crate::output::json_string;
+
+/// `GraphNodes` selects what a node of a `TalkerGraph` stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphNodes {
+    /// One node per address.
+    Hosts,
+    /// One node per subnet of the given IPv4 and IPv6 prefix lengths.
+    Subnets { v4_prefix: u8, v6_prefix: u8 },
+}
+
+/// `EdgeWeight` selects the counter used to weight edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeWeight {
+    Bytes,
+    Packets,
+    Flows,
+}
+
+/// Totals of an edge of a `TalkerGraph`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeCounters {
+    pub flows: u64,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl EdgeCounters {
+    fn weight(&self, weight: EdgeWeight) -> u64 {
+        match weight {
+            EdgeWeight::Bytes => self.bytes,
+            EdgeWeight::Packets => self.packets,
+            EdgeWeight::Flows => self.flows,
+        }
+    }
+}
+
+/// `TalkerGraph` collects who talks to whom: a directed graph from source
+/// to destination hosts or subnets, with edges carrying the traffic totals.
+/// The graph can be written as Graphviz DOT or GraphML.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::graph::{EdgeWeight, GraphNodes, TalkerGraph};
+/// use nfdump::record::RecordKind;
+///
+/// let mut graph = TalkerGraph::new(GraphNodes::Subnets { v4_prefix: 24, v6_prefix: 64 });
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     if let RecordKind::RecordV3(record) = record {
+///         graph.add(&record);
+///     }
+/// }
+/// graph.write_dot(&mut File::create("talkers.dot").unwrap(), EdgeWeight::Bytes).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TalkerGraph {
+    nodes: GraphNodes,
+    edges: HashMap<(IpNet, IpNet), EdgeCounters>,
+}
+
+impl TalkerGraph {
+    /// Creates an empty graph.
+    pub fn new(nodes: GraphNodes) -> TalkerGraph {
+        TalkerGraph {
+            nodes,
+            edges: HashMap::new(),
+        }
+    }
+
+    /// Adds a flow record; records without addresses are ignored.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        let (Some(src), Some(dst)) = (record.src_addr(), record.dst_addr()) else {
+            return;
+        };
+        let edge = self.edges.entry((self.node(src), self.node(dst))).or_default();
+        edge.flows += record.flows();
+        edge.packets += record.packets().unwrap_or(0);
+        edge.bytes += record.bytes().unwrap_or(0);
+    }
+
+    /// Returns the number of edges.
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Returns `true` if the graph has no edges.
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// Returns the edges with their totals, ordered by source and destination.
+    pub fn edges(&self) -> Vec<(IpNet, IpNet, EdgeCounters)> {
+        let sorted: BTreeMap<_, _> = self.edges.iter().collect();
+        sorted.into_iter().map(|(&(s, d), &c)| (s, d, c)).collect()
+    }
+
+    /// Writes the graph in Graphviz DOT format. Edge widths scale with
+    /// `weight`.
+    pub fn write_dot<W: Write>(&self, w: &mut W, weight: EdgeWeight) -> io::Result<()> {
+        let edges = self.edges();
+        let max = edges.iter().map(|(_, _, c)| c.weight(weight)).max().unwrap_or(0).max(1);
+
+        writeln!(w, "digraph talkers {{")?;
+        for node in self.node_list(&edges) {
+            writeln!(w, "    {} [label={}];", json_string(&node_name(&node)), json_string(&node_name(&node)))?;
+        }
+        for (src, dst, c) in &edges {
+            let penwidth = 1.0 + 9.0 * c.weight(weight) as f64 / max as f64;
+            writeln!(
+                w,
+                "    {} -> {} [label=\"{}\", penwidth={:.2}, flows={}, packets={}, bytes={}];",
+                json_string(&node_name(src)),
+                json_string(&node_name(dst)),
+                c.weight(weight),
+                penwidth,
+                c.flows,
+                c.packets,
+                c.bytes
+            )?;
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Writes the graph in GraphML format, with the totals as edge data and
+    /// `weight` as the edge weight.
+    pub fn write_graphml<W: Write>(&self, w: &mut W, weight: EdgeWeight) -> io::Result<()> {
+        let edges = self.edges();
+
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        for key in ["weight", "flows", "packets", "bytes"] {
+            writeln!(w, r#"  <key id="{0}" for="edge" attr.name="{0}" attr.type="long"/>"#, key)?;
+        }
+        writeln!(w, r#"  <graph id="talkers" edgedefault="directed">"#)?;
+        for node in self.node_list(&edges) {
+            writeln!(w, r#"    <node id="{}"/>"#, node_name(&node))?;
+        }
+        for (i, (src, dst, c)) in edges.iter().enumerate() {
+            writeln!(w, r#"    <edge id="e{}" source="{}" target="{}">"#, i, node_name(src), node_name(dst))?;
+            writeln!(w, r#"      <data key="weight">{}</data>"#, c.weight(weight))?;
+            writeln!(w, r#"      <data key="flows">{}</data>"#, c.flows)?;
+            writeln!(w, r#"      <data key="packets">{}</data>"#, c.packets)?;
+            writeln!(w, r#"      <data key="bytes">{}</data>"#, c.bytes)?;
+            writeln!(w, "    </edge>")?;
+        }
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")
+    }
+
+    fn node(&self, addr: IpAddr) -> IpNet {
+        let prefix = match (self.nodes, addr) {
+            (GraphNodes::Hosts, IpAddr::V4(_)) => 32,
+            (GraphNodes::Hosts, IpAddr::V6(_)) => 128,
+            (GraphNodes::Subnets { v4_prefix, .. }, IpAddr::V4(_)) => v4_prefix.min(32),
+            (GraphNodes::Subnets { v6_prefix, .. }, IpAddr::V6(_)) => v6_prefix.min(128),
+        };
+        IpNet::new(addr, prefix).unwrap().trunc()
+    }
+
+    fn node_list(&self, edges: &[(IpNet, IpNet, EdgeCounters)]) -> Vec<IpNet> {
+        let mut nodes: Vec<IpNet> = edges.iter().flat_map(|(s, d, _)| [*s, *d]).collect();
+        nodes.sort();
+        nodes.dedup();
+        nodes
+    }
+}
+
+fn node_name(net: &IpNet) -> String {
+    if net.prefix_len() == net.max_prefix_len() {
+        net.addr().to_string()
+    } else {
+        net.to_string()
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use crate::aggregate::Counters;
+use crate::error::NfdumpError;
+use crate::exporter::{ExporterInfo, ExporterStat, SamplerRecord};
+use crate::flow::FlowRecord;
+use crate::record::RecordKind;
+use crate::NfFileReader;
+
+/// `ExporterIssue` is a problem found in the data of an exporter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExporterIssue {
+    /// Records reference the exporter, but no exporter info describes it.
+    Unknown,
+    /// The exporter is described, but none of its records were seen.
+    Silent,
+    /// The collector saw gaps in the exporter's sequence numbers.
+    SequenceFailures(u32),
+    /// The collector's flow count differs from the records seen.
+    FlowCountMismatch { reported: u64, seen: u64 },
+}
+
+/// `ExporterSummary` combines what is known about one exporter.
+#[derive(Debug, Clone, Default)]
+pub struct ExporterSummary {
+    pub sysid: u16,
+    pub info: Option<ExporterInfo>,
+    /// Statistics kept by the collector.
+    pub stat: Option<ExporterStat>,
+    pub samplers: Vec<SamplerRecord>,
+    /// Totals and time coverage of the exporter's records.
+    pub records: Counters,
+}
+
+impl ExporterSummary {
+    /// Returns the problems found for the exporter, empty if it is healthy.
+    pub fn issues(&self) -> Vec<ExporterIssue> {
+        let mut issues = Vec::new();
+        if self.info.is_none() && self.records.flows > 0 {
+            issues.push(ExporterIssue::Unknown);
+        }
+        if self.info.is_some() && self.records.flows == 0 {
+            issues.push(ExporterIssue::Silent);
+        }
+        if let Some(stat) = &self.stat {
+            if stat.sequence_failure > 0 {
+                issues.push(ExporterIssue::SequenceFailures(stat.sequence_failure));
+            }
+            if self.records.flows > 0 && stat.flows != self.records.flows {
+                issues.push(ExporterIssue::FlowCountMismatch {
+                    reported: stat.flows,
+                    seen: self.records.flows,
+                });
+            }
+        }
+        issues
+    }
+}
+
+/// `ExporterHealth` summarizes each exporter of one or more files: its
+/// exporter info, the collector's statistics, its samplers, and the volume
+/// and time coverage of its records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::health::ExporterHealth;
+/// use nfdump::report::exporter_report;
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// let health = ExporterHealth::from_reader(&mut reader).unwrap();
+/// print!("{}", exporter_report(&health));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ExporterHealth {
+    exporters: BTreeMap<u16, ExporterSummary>,
+}
+
+impl ExporterHealth {
+    /// Creates an empty summary.
+    pub fn new() -> ExporterHealth {
+        ExporterHealth::default()
+    }
+
+    /// Reads all remaining records of `reader` and returns the summary of its
+    /// exporters.
+    pub fn from_reader<R: Read + Seek>(reader: &mut NfFileReader<R>) -> Result<ExporterHealth, NfdumpError> {
+        let mut health = ExporterHealth::new();
+        loop {
+            match reader.read_record() {
+                Ok(record) => health.add_kind(&record),
+                Err(NfdumpError::EOF) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        health.add_metadata(reader);
+        Ok(health)
+    }
+
+    fn summary(&mut self, sysid: u16) -> &mut ExporterSummary {
+        self.exporters.entry(sysid).or_insert_with(|| ExporterSummary {
+            sysid,
+            ..Default::default()
+        })
+    }
+
+    /// Adds a flow record to the counters of its exporter.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        if let Some(sysid) = record.exporter_sysid() {
+            self.summary(sysid).records.add(record);
+        }
+    }
+
+    /// Adds a flow record of either version; other records are ignored.
+    pub fn add_kind(&mut self, record: &RecordKind) {
+        match record {
+            RecordKind::Record(r) => self.add(r),
+            RecordKind::RecordV3(r) => self.add(r),
+            _ => {}
+        }
+    }
+
+    /// Takes the exporter infos, statistics and samplers seen by `reader`.
+    /// Call it after reading the records, since exporters may be described
+    /// anywhere in a file. Statistics of several files add up.
+    pub fn add_metadata<R: Read + Seek>(&mut self, reader: &NfFileReader<R>) {
+        for info in reader.exporters() {
+            self.summary(info.sysid).info = Some(*info);
+        }
+        for (&sysid, stat) in reader.exporter_stats() {
+            let summary = self.summary(sysid as u16);
+            let total = summary.stat.get_or_insert(ExporterStat { sysid, ..Default::default() });
+            total.sequence_failure += stat.sequence_failure;
+            total.packets += stat.packets;
+            total.flows += stat.flows;
+        }
+        for (&sysid, samplers) in reader.samplers() {
+            let summary = self.summary(sysid);
+            for sampler in samplers {
+                if !summary.samplers.iter().any(|s| s.id == sampler.id) {
+                    summary.samplers.push(*sampler);
+                }
+            }
+        }
+    }
+
+    /// Returns the summary of the exporter with `sysid`.
+    pub fn get(&self, sysid: u16) -> Option<&ExporterSummary> {
+        self.exporters.get(&sysid)
+    }
+
+    /// Returns all exporters ordered by sysid.
+    pub fn exporters(&self) -> impl Iterator<Item = &ExporterSummary> {
+        self.exporters.values()
+    }
+
+    /// Returns `true` if no exporter has issues.
+    pub fn is_healthy(&self) -> bool {
+        self.exporters().all(|e| e.issues().is_empty())
+    }
+}
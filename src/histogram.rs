@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::flow::FlowRecord;
+
+/// `PortHistogram` counts flows per port for one exporter and protocol.
+///
+/// Histograms are only computed in memory: nfdump reserves record types for
+/// them but defines no layout, so they are not written to files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortHistogram {
+    pub exporter_sysid: u16,
+    pub proto: u8,
+    /// `true` for destination ports, `false` for source ports.
+    pub dst: bool,
+    /// Flow count per port, sorted by port.
+    pub entries: Vec<(u16, u32)>,
+}
+
+/// `BppHistogram` counts flows per bytes-per-packet bin for one exporter
+/// and protocol.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BppHistogram {
+    pub exporter_sysid: u16,
+    pub proto: u8,
+    /// Width of each bin in bytes per packet.
+    pub bin_size: u16,
+    /// Flow count per bin; bin `i` covers `i * bin_size..(i + 1) * bin_size`
+    /// and the last bin also collects everything above.
+    pub bins: Vec<u32>,
+}
+
+/// `HistogramBuilder` computes port and bytes-per-packet histograms per
+/// exporter and protocol from a stream of flow records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::histogram::HistogramBuilder;
+/// use nfdump::record::RecordKind;
+///
+/// let mut histograms = HistogramBuilder::new(16, 1500);
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     if let RecordKind::RecordV3(record) = record {
+///         histograms.add(&record);
+///     }
+/// }
+/// let bpp = histograms.bpp_histograms();
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistogramBuilder {
+    bin_size: u16,
+    num_bins: usize,
+    src_ports: HashMap<(u16, u8), BTreeMap<u16, u32>>,
+    dst_ports: HashMap<(u16, u8), BTreeMap<u16, u32>>,
+    bpp: HashMap<(u16, u8), Vec<u32>>,
+}
+
+impl HistogramBuilder {
+    /// Creates a builder with bytes-per-packet bins of `bin_size` bytes up
+    /// to `max_bpp`; larger values fall into the last bin.
+    pub fn new(bin_size: u16, max_bpp: u32) -> HistogramBuilder {
+        let bin_size = bin_size.max(1);
+        HistogramBuilder {
+            bin_size,
+            num_bins: (max_bpp / bin_size as u32 + 1) as usize,
+            src_ports: HashMap::new(),
+            dst_ports: HashMap::new(),
+            bpp: HashMap::new(),
+        }
+    }
+
+    /// Adds a flow record. Ports are only counted for TCP and UDP flows.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        let key = (record.exporter_sysid().unwrap_or(0), record.proto().unwrap_or(0));
+
+        if matches!(key.1, 6 | 17) {
+            if let Some(port) = record.src_port() {
+                let count = self.src_ports.entry(key).or_default().entry(port).or_default();
+                *count = count.saturating_add(1);
+            }
+            if let Some(port) = record.dst_port() {
+                let count = self.dst_ports.entry(key).or_default().entry(port).or_default();
+                *count = count.saturating_add(1);
+            }
+        }
+
+        if let Some(bpp) = record.bytes().unwrap_or(0).checked_div(record.packets().unwrap_or(0)) {
+            let bin = ((bpp / self.bin_size as u64) as usize).min(self.num_bins - 1);
+            let bins = self.bpp.entry(key).or_insert_with(|| vec![0; self.num_bins]);
+            bins[bin] = bins[bin].saturating_add(1);
+        }
+    }
+
+    /// Returns the source and destination port histograms, ordered by
+    /// exporter and protocol.
+    pub fn port_histograms(&self) -> Vec<PortHistogram> {
+        let mut out: Vec<PortHistogram> = self
+            .src_ports
+            .iter()
+            .map(|(k, v)| (k, v, false))
+            .chain(self.dst_ports.iter().map(|(k, v)| (k, v, true)))
+            .map(|(&(exporter_sysid, proto), ports, dst)| PortHistogram {
+                exporter_sysid,
+                proto,
+                dst,
+                entries: ports.iter().map(|(p, c)| (*p, *c)).collect(),
+            })
+            .collect();
+        out.sort_by_key(|h| (h.exporter_sysid, h.proto, h.dst));
+        out
+    }
+
+    /// Returns the bytes-per-packet histograms, ordered by exporter and
+    /// protocol.
+    pub fn bpp_histograms(&self) -> Vec<BppHistogram> {
+        let mut out: Vec<BppHistogram> = self
+            .bpp
+            .iter()
+            .map(|(&(exporter_sysid, proto), bins)| BppHistogram {
+                exporter_sysid,
+                proto,
+                bin_size: self.bin_size,
+                bins: bins.clone(),
+            })
+            .collect();
+        out.sort_by_key(|h| (h.exporter_sysid, h.proto));
+        out
+    }
+}
@@ -0,0 +1,32 @@
+use crate::block::BlockFlags;
+use crate::error::NfdumpError;
+use crate::record::RecordKind;
+
+/// `BlockInfo` describes a data block when the reader starts on it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// Zero-based index of the data block.
+    pub index: u32,
+    /// File offset of the data block's header.
+    pub offset: u64,
+    /// Number of records declared in the block header.
+    pub num_records: u32,
+    /// Size of the (compressed) block data in bytes.
+    pub size: u32,
+    /// Block type.
+    pub id: u16,
+    /// Block flags.
+    pub flags: BlockFlags,
+}
+
+type BlockHook = Box<dyn FnMut(&BlockInfo) + Send>;
+type RecordHook = Box<dyn FnMut(&RecordKind) + Send>;
+type ErrorHook = Box<dyn FnMut(&NfdumpError) + Send>;
+
+/// `Hooks` holds the callbacks registered on a reader.
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) on_block_start: Option<BlockHook>,
+    pub(crate) on_record: Option<RecordHook>,
+    pub(crate) on_decode_error: Option<ErrorHook>,
+}
@@ -0,0 +1,145 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use crate::error::NfdumpError;
+
+const DEFAULT_CHUNK_SIZE: usize = 1048576;
+
+/// `HttpRangeReader` is a `Read + Seek` adaptor that fetches a remote file in
+/// chunks using HTTP range requests, so `NfFileReader` can open files served
+/// by a web archive without downloading them first.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::NfFileReader;
+/// use nfdump::http::HttpRangeReader;
+///
+/// let remote = HttpRangeReader::new("https://example.com/nfcapd.202401010000").unwrap();
+/// let mut reader = NfFileReader::new(remote).unwrap();
+/// ```
+pub struct HttpRangeReader {
+    agent: ureq::Agent,
+    url: String,
+    len: u64,
+    pos: u64,
+    chunk_size: usize,
+    buf: Vec<u8>,
+    buf_start: u64,
+}
+
+impl HttpRangeReader {
+    /// Creates a new `HttpRangeReader` for `url` using a 1 MiB read-ahead chunk.
+    pub fn new(url: &str) -> Result<Self, NfdumpError> {
+        Self::with_chunk_size(url, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Creates a new `HttpRangeReader` fetching `chunk_size` bytes per request.
+    ///
+    /// The file size is determined up front with a `HEAD` request, which must
+    /// return a `Content-Length`.
+    pub fn with_chunk_size(url: &str, chunk_size: usize) -> Result<Self, NfdumpError> {
+        let agent = ureq::AgentBuilder::new().build();
+        let response = agent.head(url).call().map_err(http_error)?;
+        let len = response
+            .header("Content-Length")
+            .and_then(|l| l.parse::<u64>().ok())
+            .ok_or_else(|| http_error("missing Content-Length"))?;
+
+        Ok(HttpRangeReader {
+            agent,
+            url: url.to_string(),
+            len,
+            pos: 0,
+            chunk_size: chunk_size.max(1),
+            buf: Vec::new(),
+            buf_start: 0,
+        })
+    }
+
+    /// Returns the size of the remote file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the remote file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn fetch(&mut self) -> io::Result<()> {
+        let end = (self.pos + self.chunk_size as u64).min(self.len) - 1;
+        let response = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", self.pos, end))
+            .call()
+            .map_err(http_error)?;
+
+        if response.status() != 206 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "server does not support range requests",
+            ));
+        }
+
+        self.buf.clear();
+        response.into_reader().read_to_end(&mut self.buf)?;
+        self.buf_start = self.pos;
+        Ok(())
+    }
+
+    fn buffered(&self) -> Option<&[u8]> {
+        let offset = self.pos.checked_sub(self.buf_start)? as usize;
+        if offset < self.buf.len() {
+            Some(&self.buf[offset..])
+        } else {
+            None
+        }
+    }
+}
+
+impl Read for HttpRangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.buffered().is_none() {
+            self.fetch()?;
+        }
+
+        let n = match self.buffered() {
+            Some(data) => {
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                n
+            }
+            None => 0,
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpRangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => self.len.checked_add_signed(p),
+            SeekFrom::Current(p) => self.pos.checked_add_signed(p),
+        };
+
+        match new_pos {
+            Some(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )),
+        }
+    }
+}
+
+fn http_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::other(e)
+}
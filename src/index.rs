@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use ipnet::IpNet;
+use crate::error::NfdumpError;
+use crate::hooks::BlockInfo;
+use crate::{modified_secs, sidecar_path, sidecar_stale, NfFileReader};
+
+const MAGIC: &[u8; 4] = b"NFIX";
+const VERSION: u16 = 2;
+const SIDECAR_EXTENSION: &str = "idx";
+const TOP_PREFIXES: usize = 16;
+const V4_PREFIX_LEN: u8 = 24;
+const V6_PREFIX_LEN: u8 = 48;
+
+/// `BlockPrune` is what a reader does with a data block outside its time
+/// window.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum BlockPrune {
+    Read,
+    /// Decompress the block for its metadata records only.
+    Scan,
+    /// Seek over the block.
+    Skip,
+}
+
+/// `BlockIndex` summarizes one data block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockIndex {
+    /// File offset of the block header.
+    pub offset: u64,
+    /// Number of records declared in the block header.
+    pub records: u32,
+    /// Number of flow records.
+    pub flows: u32,
+    /// Earliest first seen of the flows in milliseconds, 0 without flows.
+    pub first_msec: u64,
+    /// Latest last seen of the flows in milliseconds, 0 without flows.
+    pub last_msec: u64,
+    /// The block holds metadata records, like extension maps or exporters,
+    /// that later blocks may depend on.
+    pub metadata: bool,
+}
+
+impl BlockIndex {
+    /// Returns `true` if a flow of the block may overlap the window from
+    /// `start` to `end`.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.flows > 0 && self.first_msec <= end && self.last_msec >= start
+    }
+}
+
+/// `PrefixCount` is the traffic of one prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefixCount {
+    pub prefix: IpNet,
+    pub flows: u64,
+    pub bytes: u64,
+}
+
+/// `FileIndex` is a compact summary of an nfdump file: its time range and
+/// totals, the time range of each data block and the busiest source and
+/// destination prefixes (/24 for IPv4, /48 for IPv6).
+///
+/// Stored next to the file as a sidecar, it lets `MultiReader` skip files
+/// and data blocks outside a time window without reading them.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::demux::MultiReader;
+/// use nfdump::index::build_index;
+///
+/// // once, when the collector closes the file
+/// let index = build_index("nfcapd.202401010000").unwrap();
+/// for top in &index.top_src {
+///     println!("{} {} flows", top.prefix, top.flows);
+/// }
+///
+/// // sidecars are picked up when opening files
+/// let mut reader = MultiReader::open(&["nfcapd.202401010000", "nfcapd.202401010005"]).unwrap();
+/// reader.set_time_window(1704067260000, 1704067320000);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileIndex {
+    /// Creation time from the file header, 0 for v1 files. Together with
+    /// `num_blocks`, `file_size` and `modified` it ties the index to its
+    /// file.
+    pub created: u64,
+    /// Number of data blocks declared in the file header.
+    pub num_blocks: u32,
+    /// Size of the file in bytes.
+    pub file_size: u64,
+    /// Modification time of the file in seconds since the epoch, 0 if
+    /// unknown.
+    pub modified: u64,
+    /// Earliest first seen of the flows in milliseconds, 0 without flows.
+    pub first_msec: u64,
+    /// Latest last seen of the flows in milliseconds, 0 without flows.
+    pub last_msec: u64,
+    pub flows: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub blocks: Vec<BlockIndex>,
+    /// Source prefixes with the most flows, busiest first.
+    pub top_src: Vec<PrefixCount>,
+    /// Destination prefixes with the most flows, busiest first.
+    pub top_dst: Vec<PrefixCount>,
+}
+
+impl FileIndex {
+    /// Builds the index of the file of `reader` by reading all of it.
+    /// `modified` is left 0; `build_index` fills it in from the file.
+    pub fn build<R: Read + Seek>(mut reader: NfFileReader<R>) -> Result<FileIndex, NfdumpError> {
+        let block_infos = Arc::new(Mutex::new(Vec::new()));
+        let infos = block_infos.clone();
+        reader.set_on_block_start(move |info: &BlockInfo| infos.lock().unwrap().push(*info));
+
+        let mut index = FileIndex {
+            created: reader.header.created().unwrap_or(0),
+            num_blocks: reader.header.num_blocks(),
+            file_size: reader.file_size()?,
+            modified: 0,
+            first_msec: 0,
+            last_msec: 0,
+            flows: 0,
+            packets: 0,
+            bytes: 0,
+            blocks: Vec::new(),
+            top_src: Vec::new(),
+            top_dst: Vec::new(),
+        };
+        // records returned per block, flows per block and damaged blocks
+        let mut returned: HashMap<u32, u32> = HashMap::new();
+        let mut blocks: HashMap<u32, BlockIndex> = HashMap::new();
+        let mut damaged = Vec::new();
+        let mut src: HashMap<IpNet, (u64, u64)> = HashMap::new();
+        let mut dst: HashMap<IpNet, (u64, u64)> = HashMap::new();
+
+        loop {
+            let record = match reader.read_record() {
+                Ok(r) => r,
+                Err(NfdumpError::EOF) => break,
+                Err(NfdumpError::ParseErrorAt { context }) => {
+                    damaged.extend(context.block);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let Some(block) = reader.last_record_position().map(|p| p.block) else {
+                continue;
+            };
+            *returned.entry(block).or_default() += 1;
+            let Some(r) = record.flow_record() else {
+                continue;
+            };
+
+            let (first, last) = (r.first_msec().unwrap_or(0), r.last_msec().unwrap_or(0));
+            let bytes = r.bytes().unwrap_or(0);
+            let b = blocks.entry(block).or_insert(BlockIndex {
+                offset: 0,
+                records: 0,
+                flows: 0,
+                first_msec: first,
+                last_msec: last,
+                metadata: false,
+            });
+            b.flows += 1;
+            b.first_msec = b.first_msec.min(first);
+            b.last_msec = b.last_msec.max(last);
+            index.first_msec = if index.flows == 0 { first } else { index.first_msec.min(first) };
+            index.last_msec = index.last_msec.max(last);
+            index.flows += r.flows();
+            index.packets += r.packets().unwrap_or(0);
+            index.bytes += bytes;
+            for (addr, counts) in [(r.src_addr(), &mut src), (r.dst_addr(), &mut dst)] {
+                if let Some(prefix) = addr.map(prefix_of) {
+                    let c = counts.entry(prefix).or_default();
+                    c.0 += r.flows();
+                    c.1 += bytes;
+                }
+            }
+        }
+
+        for info in block_infos.lock().unwrap().iter() {
+            let mut b = blocks.remove(&info.index).unwrap_or(BlockIndex {
+                offset: 0,
+                records: 0,
+                flows: 0,
+                first_msec: 0,
+                last_msec: 0,
+                metadata: false,
+            });
+            b.offset = info.offset;
+            b.records = info.num_records;
+            b.metadata = damaged.contains(&info.index) || returned.get(&info.index).copied().unwrap_or(0) < info.num_records;
+            index.blocks.push(b);
+        }
+        index.top_src = top_prefixes(src);
+        index.top_dst = top_prefixes(dst);
+        Ok(index)
+    }
+
+    /// Returns `true` if the index was built from the file of `reader`.
+    pub fn matches<R: Read + Seek>(&self, reader: &mut NfFileReader<R>) -> bool {
+        self.created == reader.header.created().unwrap_or(0)
+            && self.num_blocks == reader.header.num_blocks()
+            && self.blocks.len() as u32 == self.num_blocks
+            && reader.file_size().ok() == Some(self.file_size)
+    }
+
+    /// Returns `true` if a flow of the file may overlap the window from
+    /// `start` to `end`.
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.flows > 0 && self.first_msec <= end && self.last_msec >= start
+    }
+
+    /// Sets the time window of `reader` and makes it skip the data blocks
+    /// without flows in the window, only scanning those with metadata
+    /// records. The reader should not have read any record yet.
+    ///
+    /// Returns `false`, leaving the reader unchanged, if the index was not
+    /// built from the reader's file.
+    pub fn apply<R: Read + Seek>(&self, reader: &mut NfFileReader<R>, start: u64, end: u64) -> bool {
+        if !self.matches(reader) {
+            return false;
+        }
+        reader.set_time_window(start, end);
+        reader.set_pruned_blocks(self.prune(start, end));
+        true
+    }
+
+    /// Returns what to do with each data block when reading flows in the
+    /// window from `start` to `end`.
+    pub(crate) fn prune(&self, start: u64, end: u64) -> Vec<BlockPrune> {
+        self.blocks
+            .iter()
+            .map(|b| match (b.overlaps(start, end), b.metadata) {
+                (true, _) => BlockPrune::Read,
+                (false, true) => BlockPrune::Scan,
+                (false, false) => BlockPrune::Skip,
+            })
+            .collect()
+    }
+
+    /// Writes the index in its sidecar format.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), NfdumpError> {
+        w.write_all(MAGIC)?;
+        w.write_u16::<LittleEndian>(VERSION)?;
+        w.write_u64::<LittleEndian>(self.created)?;
+        w.write_u32::<LittleEndian>(self.num_blocks)?;
+        w.write_u64::<LittleEndian>(self.file_size)?;
+        w.write_u64::<LittleEndian>(self.modified)?;
+        for v in [self.first_msec, self.last_msec, self.flows, self.packets, self.bytes] {
+            w.write_u64::<LittleEndian>(v)?;
+        }
+        w.write_u32::<LittleEndian>(self.blocks.len() as u32)?;
+        for b in &self.blocks {
+            w.write_u64::<LittleEndian>(b.offset)?;
+            w.write_u32::<LittleEndian>(b.records)?;
+            w.write_u32::<LittleEndian>(b.flows)?;
+            w.write_u64::<LittleEndian>(b.first_msec)?;
+            w.write_u64::<LittleEndian>(b.last_msec)?;
+            w.write_u8(b.metadata as u8)?;
+        }
+        for top in [&self.top_src, &self.top_dst] {
+            w.write_u16::<LittleEndian>(top.len() as u16)?;
+            for p in top.iter() {
+                let (family, addr) = match p.prefix.addr() {
+                    IpAddr::V4(a) => (4, a.to_ipv6_mapped().octets()),
+                    IpAddr::V6(a) => (6, a.octets()),
+                };
+                w.write_u8(family)?;
+                w.write_u8(p.prefix.prefix_len())?;
+                w.write_all(&addr)?;
+                w.write_u64::<LittleEndian>(p.flows)?;
+                w.write_u64::<LittleEndian>(p.bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by `write_to`.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<FileIndex, NfdumpError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(NfdumpError::ParseError);
+        }
+        if r.read_u16::<LittleEndian>()? != VERSION {
+            return Err(NfdumpError::UnsupportedVersion);
+        }
+        let created = r.read_u64::<LittleEndian>()?;
+        let num_blocks = r.read_u32::<LittleEndian>()?;
+        let file_size = r.read_u64::<LittleEndian>()?;
+        let modified = r.read_u64::<LittleEndian>()?;
+        let mut totals = [0; 5];
+        r.read_u64_into::<LittleEndian>(&mut totals)?;
+        let [first_msec, last_msec, flows, packets, bytes] = totals;
+
+        let nblocks = r.read_u32::<LittleEndian>()?;
+        let mut blocks = Vec::new();
+        for _ in 0..nblocks {
+            blocks.push(BlockIndex {
+                offset: r.read_u64::<LittleEndian>()?,
+                records: r.read_u32::<LittleEndian>()?,
+                flows: r.read_u32::<LittleEndian>()?,
+                first_msec: r.read_u64::<LittleEndian>()?,
+                last_msec: r.read_u64::<LittleEndian>()?,
+                metadata: r.read_u8()? != 0,
+            });
+        }
+
+        let mut tops = [Vec::new(), Vec::new()];
+        for top in &mut tops {
+            for _ in 0..r.read_u16::<LittleEndian>()? {
+                let family = r.read_u8()?;
+                let len = r.read_u8()?;
+                let mut addr = [0; 16];
+                r.read_exact(&mut addr)?;
+                let addr = match family {
+                    4 => IpAddr::V4(Ipv6Addr::from(addr).to_ipv4_mapped().ok_or(NfdumpError::ParseError)?),
+                    6 => IpAddr::V6(Ipv6Addr::from(addr)),
+                    _ => return Err(NfdumpError::ParseError),
+                };
+                top.push(PrefixCount {
+                    prefix: IpNet::new(addr, len).map_err(|_| NfdumpError::ParseError)?,
+                    flows: r.read_u64::<LittleEndian>()?,
+                    bytes: r.read_u64::<LittleEndian>()?,
+                });
+            }
+        }
+        let [top_src, top_dst] = tops;
+
+        Ok(FileIndex {
+            created,
+            num_blocks,
+            file_size,
+            modified,
+            first_msec,
+            last_msec,
+            flows,
+            packets,
+            bytes,
+            blocks,
+            top_src,
+            top_dst,
+        })
+    }
+
+    /// Writes the index to the sidecar of the nfdump file at `path`.
+    pub fn write_sidecar<P: AsRef<Path>>(&self, path: P) -> Result<(), NfdumpError> {
+        let mut w = BufWriter::new(File::create(sidecar_path(path, SIDECAR_EXTENSION))?);
+        self.write_to(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Reads the sidecar of the nfdump file at `path`, or returns `None` if
+    /// there is none or it is stale: written by another version of the
+    /// format, or for a file of another size or modification time.
+    pub fn open_sidecar<P: AsRef<Path>>(path: P) -> Result<Option<FileIndex>, NfdumpError> {
+        let path = path.as_ref();
+        let index = match File::open(sidecar_path(path, SIDECAR_EXTENSION)) {
+            Ok(f) => match FileIndex::read_from(&mut BufReader::new(f)) {
+                Ok(index) => index,
+                Err(NfdumpError::UnsupportedVersion) => return Ok(None),
+                Err(e) => return Err(e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let stale = sidecar_stale(path, index.file_size, index.modified)?;
+        Ok((!stale).then_some(index))
+    }
+}
+
+/// Builds the index of the nfdump file at `path` and writes it to its
+/// sidecar.
+pub fn build_index<P: AsRef<Path>>(path: P) -> Result<FileIndex, NfdumpError> {
+    let path = path.as_ref();
+    let reader = NfFileReader::new(BufReader::new(File::open(path)?))?;
+    let mut index = FileIndex::build(reader)?;
+    index.modified = modified_secs(&fs::metadata(path)?);
+    index.write_sidecar(path)?;
+    Ok(index)
+}
+
+fn prefix_of(addr: IpAddr) -> IpNet {
+    let len = match addr {
+        IpAddr::V4(_) => V4_PREFIX_LEN,
+        IpAddr::V6(_) => V6_PREFIX_LEN,
+    };
+    IpNet::new(addr, len).unwrap().trunc()
+}
+
+fn top_prefixes(counts: HashMap<IpNet, (u64, u64)>) -> Vec<PrefixCount> {
+    let mut top: Vec<PrefixCount> = counts
+        .into_iter()
+        .map(|(prefix, (flows, bytes))| PrefixCount { prefix, flows, bytes })
+        .collect();
+    top.sort_by(|a, b| b.flows.cmp(&a.flows).then(b.bytes.cmp(&a.bytes)).then(a.prefix.cmp(&b.prefix)));
+    top.truncate(TOP_PREFIXES);
+    top
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::Path;
+use crate::compress::Compression;
+use crate::error::NfdumpError;
+use crate::exporter::{ExporterInfo, ExporterStat, SamplerRecord};
+use crate::output::json_string;
+use crate::{NfFileHeader, NfFileReader, StatRecord};
+
+/// `NfFileInfo` summarizes an nfdump file from its header, appendix and
+/// metadata records, like `nfdump -I`. Flow records are not decoded, which
+/// makes it cheap enough for cataloging large archives.
+#[derive(Debug, Clone)]
+pub struct NfFileInfo {
+    /// File layout version (1 or 2).
+    pub version: u16,
+    /// Identification string with trailing NUL bytes removed.
+    pub ident: String,
+    /// Compression of the data blocks, derived from the header flags for v1
+    /// files.
+    pub compression: Compression,
+    /// Encryption of the data blocks from the v2 header, 0 for none. Always
+    /// 0 for v1 files.
+    pub encryption: u8,
+    /// Header flags (v1 files only).
+    pub flags: Option<u32>,
+    /// nfdump version that wrote the file (v2 files only).
+    pub nf_version: Option<u32>,
+    /// Creation time in seconds since the epoch (v2 files only).
+    pub created: Option<u64>,
+    /// Block buffer size the file was written with (v2 files only).
+    pub block_size: Option<u32>,
+    /// Number of data blocks, excluding the appendix.
+    pub num_blocks: u32,
+    /// Number of appendix blocks (v2 files only).
+    pub appendix_blocks: u16,
+    /// File offset of the appendix, 0 if there is none (v2 files only).
+    pub off_appendix: Option<u64>,
+    /// Size of the file, if read from a path.
+    pub file_size: Option<u64>,
+    pub stat_record: StatRecord,
+    /// Exporters recorded in the file.
+    pub exporters: Vec<ExporterInfo>,
+    /// Exporter statistics recorded in the file, by sysid.
+    pub exporter_stats: Vec<ExporterStat>,
+    /// Samplers recorded in the file, by exporter sysid and id.
+    pub samplers: Vec<SamplerRecord>,
+    /// Inconsistencies found in the header and metadata.
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// `Anomaly` is an inconsistency `NfFileInfo` finds in a file's header and
+/// metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// A v2 file without appendix, typically one the collector has not
+    /// closed yet.
+    NoAppendix,
+    /// The appendix starts at or beyond the end of the file.
+    AppendixBeyondEnd { offset: u64, file_size: u64 },
+    /// The stat record counts no flows although there are data blocks.
+    EmptyStatRecord,
+    /// The stat record's first seen time is after its last seen time.
+    TimesReversed { first_seen: u64, last_seen: u64 },
+    /// The per-protocol flow counts of the stat record do not add up to
+    /// the total.
+    ProtocolCountsMismatch { flows: u64, sum: u64 },
+    /// The collector lost export packets.
+    SequenceFailures(u64),
+    /// Several exporters share a sysid, so records can not be attributed to
+    /// one of them.
+    DuplicateExporter(u16),
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::NoAppendix => write!(f, "no appendix"),
+            Anomaly::AppendixBeyondEnd { offset, file_size } => {
+                write!(f, "appendix at {} beyond the end of the file at {}", offset, file_size)
+            }
+            Anomaly::EmptyStatRecord => write!(f, "empty stat record"),
+            Anomaly::TimesReversed { first_seen, last_seen } => {
+                write!(f, "first seen {} after last seen {}", first_seen, last_seen)
+            }
+            Anomaly::ProtocolCountsMismatch { flows, sum } => {
+                write!(f, "{} flows but {} flows by protocol", flows, sum)
+            }
+            Anomaly::SequenceFailures(n) => write!(f, "{} sequence failures", n),
+            Anomaly::DuplicateExporter(sysid) => write!(f, "duplicate exporter sysid {}", sysid),
+        }
+    }
+}
+
+impl NfFileInfo {
+    /// Reads the summary of the file at `path`.
+    ///
+    /// nfcapd writes exporter and sampler records into the data blocks, so
+    /// every data block is decompressed and scanned for them; flow records
+    /// are passed over.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use nfdump::info::NfFileInfo;
+    ///
+    /// let info = NfFileInfo::read("path_to_your_file").unwrap();
+    /// println!("{}: {} blocks", info.ident, info.num_blocks);
+    /// for anomaly in &info.anomalies {
+    ///     println!("warning: {}", anomaly);
+    /// }
+    /// ```
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<NfFileInfo, NfdumpError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut reader = NfFileReader::new(BufReader::new(file))?;
+        reader.scan_metadata(reader.header.num_blocks())?;
+        let mut info = NfFileInfo::from_reader(&reader);
+        info.file_size = Some(file_size);
+        if let Some(offset) = info.off_appendix.filter(|o| *o > 0 && *o >= file_size) {
+            info.anomalies.insert(0, Anomaly::AppendixBeyondEnd { offset, file_size });
+        }
+        Ok(info)
+    }
+
+    /// Builds the summary from a reader. Exporters and samplers only
+    /// reflect the metadata the reader has read so far: for a freshly
+    /// opened reader, the appendix of a v2 file.
+    pub fn from_reader<R: Read + Seek>(reader: &NfFileReader<R>) -> NfFileInfo {
+        let ident = reader.get_ident();
+        let end = ident.iter().position(|&b| b == 0).unwrap_or(ident.len());
+
+        let mut exporter_stats: Vec<ExporterStat> = reader.exporter_stats().values().copied().collect();
+        exporter_stats.sort_by_key(|s| s.sysid);
+        let mut samplers: Vec<SamplerRecord> = reader.samplers().values().flatten().copied().collect();
+        samplers.sort_by_key(|s| (s.exporter_sysid, s.id));
+
+        let (flags, nf_version, encryption, off_appendix) = match &reader.header {
+            NfFileHeader::V1(h) => (Some(h.flags), None, 0, None),
+            NfFileHeader::V2(h) => (None, Some(h.nf_version), h.encryption, Some(h.off_appendix)),
+        };
+
+        let mut info = NfFileInfo {
+            version: reader.header.version(),
+            ident: String::from_utf8_lossy(&ident[..end]).into_owned(),
+            // the reader rejects files with an unknown codec
+            compression: reader.header.compression().unwrap_or_default(),
+            encryption,
+            flags,
+            nf_version,
+            created: reader.header.created(),
+            block_size: reader.header.block_size(),
+            num_blocks: reader.header.num_blocks(),
+            appendix_blocks: reader.header.appendix_blocks().unwrap_or(0),
+            off_appendix,
+            file_size: None,
+            stat_record: reader.stat_record.clone(),
+            exporters: reader.exporters().to_vec(),
+            exporter_stats,
+            samplers,
+            anomalies: Vec::new(),
+        };
+        info.anomalies = info.find_anomalies();
+        info
+    }
+
+    fn find_anomalies(&self) -> Vec<Anomaly> {
+        let mut anomalies = Vec::new();
+        if self.off_appendix == Some(0) {
+            anomalies.push(Anomaly::NoAppendix);
+        }
+
+        let stats = self.stat_record.normalized();
+        if stats.flows == 0 && self.num_blocks > 0 && self.off_appendix != Some(0) {
+            anomalies.push(Anomaly::EmptyStatRecord);
+        }
+        if stats.first_seen > stats.last_seen {
+            anomalies.push(Anomaly::TimesReversed {
+                first_seen: stats.first_seen,
+                last_seen: stats.last_seen,
+            });
+        }
+        let sum = stats.flows_tcp
+            .saturating_add(stats.flows_udp)
+            .saturating_add(stats.flows_icmp)
+            .saturating_add(stats.flows_other);
+        if sum != stats.flows {
+            anomalies.push(Anomaly::ProtocolCountsMismatch { flows: stats.flows, sum });
+        }
+        if stats.sequence_failure > 0 {
+            anomalies.push(Anomaly::SequenceFailures(stats.sequence_failure));
+        }
+
+        let mut sysids: Vec<u16> = self.exporters.iter().map(|e| e.sysid).collect();
+        sysids.sort_unstable();
+        for pair in sysids.windows(2) {
+            if pair[0] == pair[1] && anomalies.last() != Some(&Anomaly::DuplicateExporter(pair[0])) {
+                anomalies.push(Anomaly::DuplicateExporter(pair[0]));
+            }
+        }
+        anomalies
+    }
+
+    /// Writes the summary as a JSON object, for inventory tooling.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::io;
+    /// use nfdump::info::NfFileInfo;
+    ///
+    /// let info = NfFileInfo::read("path_to_your_file").unwrap();
+    /// info.write_json_to(&mut io::stdout()).unwrap();
+    /// ```
+    pub fn write_json_to<W: Write>(&self, w: &mut W) -> Result<(), NfdumpError> {
+        write!(
+            w,
+            "{{\"version\":{},\"ident\":{},\"compression\":\"{}\",\"encryption\":{}",
+            self.version,
+            json_string(&self.ident),
+            self.compression,
+            self.encryption
+        )?;
+        let optional = [
+            ("flags", self.flags.map(u64::from)),
+            ("nf_version", self.nf_version.map(u64::from)),
+            ("created", self.created),
+            ("block_size", self.block_size.map(u64::from)),
+            ("off_appendix", self.off_appendix),
+            ("file_size", self.file_size),
+        ];
+        for (name, value) in optional {
+            match value {
+                Some(v) => write!(w, ",\"{}\":{}", name, v)?,
+                None => write!(w, ",\"{}\":null", name)?,
+            }
+        }
+        write!(w, ",\"num_blocks\":{},\"appendix_blocks\":{}", self.num_blocks, self.appendix_blocks)?;
+
+        let s = self.stat_record.normalized();
+        write!(
+            w,
+            ",\"stat\":{{\"flows\":{},\"bytes\":{},\"packets\":{},\
+             \"flows_tcp\":{},\"flows_udp\":{},\"flows_icmp\":{},\"flows_other\":{},\
+             \"bytes_tcp\":{},\"bytes_udp\":{},\"bytes_icmp\":{},\"bytes_other\":{},\
+             \"packets_tcp\":{},\"packets_udp\":{},\"packets_icmp\":{},\"packets_other\":{},\
+             \"first_seen\":{},\"last_seen\":{},\"sequence_failure\":{}}}",
+            s.flows,
+            s.bytes,
+            s.packets,
+            s.flows_tcp,
+            s.flows_udp,
+            s.flows_icmp,
+            s.flows_other,
+            s.bytes_tcp,
+            s.bytes_udp,
+            s.bytes_icmp,
+            s.bytes_other,
+            s.packets_tcp,
+            s.packets_udp,
+            s.packets_icmp,
+            s.packets_other,
+            s.first_seen,
+            s.last_seen,
+            s.sequence_failure
+        )?;
+
+        w.write_all(b",\"exporters\":[")?;
+        for (i, e) in self.exporters.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            write!(
+                w,
+                "{{\"sysid\":{},\"id\":{},\"version\":{},\"address\":\"{}\"}}",
+                e.sysid, e.id, e.version, e.address
+            )?;
+        }
+        w.write_all(b"],\"exporter_stats\":[")?;
+        for (i, s) in self.exporter_stats.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            write!(
+                w,
+                "{{\"sysid\":{},\"sequence_failure\":{},\"packets\":{},\"flows\":{}}}",
+                s.sysid, s.sequence_failure, s.packets, s.flows
+            )?;
+        }
+        w.write_all(b"],\"samplers\":[")?;
+        for (i, s) in self.samplers.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            write!(
+                w,
+                "{{\"exporter_sysid\":{},\"id\":{},\"algorithm\":{},\"packet_interval\":{},\"space_interval\":{}}}",
+                s.exporter_sysid, s.id, s.algorithm, s.packet_interval, s.space_interval
+            )?;
+        }
+        w.write_all(b"],\"anomalies\":[")?;
+        for (i, a) in self.anomalies.iter().enumerate() {
+            if i > 0 {
+                w.write_all(b",")?;
+            }
+            w.write_all(json_string(&a.to_string()).as_bytes())?;
+        }
+        w.write_all(b"]}")?;
+        Ok(())
+    }
+
+    /// Returns the summary as a JSON object.
+    pub fn to_json(&self) -> String {
+        let mut out = Vec::new();
+        // writing to a Vec can not fail
+        _ = self.write_json_to(&mut out);
+        String::from_utf8(out).unwrap_or_default()
+    }
+}
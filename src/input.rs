@@ -0,0 +1,118 @@
+use std::io::{self, IoSliceMut, Read, Seek, SeekFrom};
+
+pub(crate) const DEFAULT_READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// `BufferedInput` buffers the reads of an `NfFileReader` and tracks the
+/// position itself, so block and record headers and position checks do not
+/// each cost a system call.
+///
+/// Reads larger than the buffer go straight into the caller's buffer with a
+/// vectored read that fills the internal buffer from the same call, which
+/// picks up the header of the next data block along with a block's data.
+pub(crate) struct BufferedInput<R> {
+    inner: R,
+    buf: Vec<u8>,
+    // unread part of `buf`
+    start: usize,
+    end: usize,
+    // position of `inner`, i.e. of the end of the buffered data
+    pos: u64,
+}
+
+impl<R: Read + Seek> BufferedInput<R> {
+    pub(crate) fn new(mut inner: R, capacity: usize) -> io::Result<BufferedInput<R>> {
+        let pos = inner.stream_position()?;
+        Ok(BufferedInput {
+            inner,
+            buf: vec![0; capacity],
+            start: 0,
+            end: 0,
+            pos,
+        })
+    }
+
+    /// Changes the buffer size, dropping buffered data. A size of 0 disables
+    /// buffering.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        let pos = self.stream_position()?;
+        self.discard(pos)?;
+        self.buf = vec![0; capacity];
+        Ok(())
+    }
+
+    fn discard(&mut self, pos: u64) -> io::Result<()> {
+        if pos != self.pos {
+            self.inner.seek(SeekFrom::Start(pos))?;
+            self.pos = pos;
+        }
+        self.start = 0;
+        self.end = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for BufferedInput<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.start < self.end {
+            let n = out.len().min(self.end - self.start);
+            out[..n].copy_from_slice(&self.buf[self.start..self.start + n]);
+            self.start += n;
+            return Ok(n);
+        }
+
+        self.start = 0;
+        self.end = 0;
+        if out.len() >= self.buf.len() {
+            let n = match self.buf.is_empty() {
+                true => self.inner.read(out)?,
+                false => self.inner.read_vectored(&mut [IoSliceMut::new(out), IoSliceMut::new(&mut self.buf)])?,
+            };
+            self.pos += n as u64;
+            if n > out.len() {
+                self.end = n - out.len();
+                return Ok(out.len());
+            }
+            return Ok(n);
+        }
+
+        let n = self.inner.read(&mut self.buf)?;
+        self.pos += n as u64;
+        self.end = n;
+        let n = out.len().min(n);
+        out[..n].copy_from_slice(&self.buf[..n]);
+        self.start = n;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for BufferedInput<R> {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let target = match from {
+            SeekFrom::Start(p) => p,
+            SeekFrom::Current(off) => self
+                .stream_position()?
+                .checked_add_signed(off)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative position"))?,
+            SeekFrom::End(_) => {
+                let pos = self.inner.seek(from)?;
+                self.pos = pos;
+                self.start = 0;
+                self.end = 0;
+                return Ok(pos);
+            }
+        };
+
+        // stay within the buffer if the target is buffered
+        let buffered_from = self.pos - self.end as u64;
+        if target >= buffered_from && target <= self.pos {
+            self.start = (target - buffered_from) as usize;
+            return Ok(target);
+        }
+        self.discard(target)?;
+        Ok(target)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos - (self.end - self.start) as u64)
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use ipnet::IpNet;
+use crate::annotation::{Annotations, Annotator};
+use crate::error::NfdumpError;
+use crate::filter::{AddrField, Filter};
+use crate::flow::FlowRecord;
+use crate::prefix::PrefixMap;
+use crate::record::RecordKind;
+
+/// `IpList` is a named list of addresses and prefixes, like a blocklist
+/// from a threat intelligence feed.
+///
+/// Lists are loaded from plain text, one address or prefix per line, or
+/// from a MISP CSV export. Lines that hold no address are skipped and
+/// counted, since feeds often carry headers or comments in their own style.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::intel::IpList;
+///
+/// let drop = IpList::open("spamhaus-drop", "drop.txt").unwrap();
+/// println!("{}: {} entries, {} lines skipped", drop.name(), drop.len(), drop.skipped());
+/// ```
+#[derive(Clone)]
+pub struct IpList {
+    name: Arc<str>,
+    entries: PrefixMap<()>,
+    skipped: usize,
+}
+
+impl IpList {
+    /// Creates an empty list.
+    pub fn new(name: &str) -> IpList {
+        IpList {
+            name: name.into(),
+            entries: PrefixMap::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Opens a plain text list.
+    pub fn open<P: AsRef<Path>>(name: &str, path: P) -> Result<IpList, NfdumpError> {
+        IpList::from_text(name, BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a plain text list: one address or prefix per line, followed by
+    /// anything. Comments start with `#` or `;`.
+    pub fn from_text<R: BufRead>(name: &str, reader: R) -> Result<IpList, NfdumpError> {
+        let mut list = IpList::new(name);
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.split(['#', ';']).next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let token = line.split(|c: char| c.is_whitespace() || c == ',').next().unwrap_or("");
+            match parse_entry(token) {
+                Some(net) => list.insert(net),
+                None => list.skipped += 1,
+            }
+        }
+        Ok(list)
+    }
+
+    /// Reads a MISP CSV export. The header line must name the `type` and
+    /// `value` columns; the addresses of `ip-src`, `ip-dst` and composite
+    /// types like `ip-dst|port` or `domain|ip` are loaded, other attributes
+    /// are ignored.
+    pub fn from_misp_csv<R: BufRead>(name: &str, reader: R) -> Result<IpList, NfdumpError> {
+        let mut lines = reader.lines();
+        let header = match lines.next() {
+            Some(line) => split_csv(&line?),
+            None => return Ok(IpList::new(name)),
+        };
+        let column = |name: &str| header.iter().position(|h| h == name).ok_or(NfdumpError::ParseError);
+        let (type_col, value_col) = (column("type")?, column("value")?);
+
+        let mut list = IpList::new(name);
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = split_csv(&line);
+            let (Some(ty), Some(value)) = (fields.get(type_col), fields.get(value_col)) else {
+                list.skipped += 1;
+                continue;
+            };
+            // composite values are separated like their types: `ip-dst|port`
+            // has a value like `192.0.2.1|443`
+            let ip = ty
+                .split('|')
+                .zip(value.split('|'))
+                .find(|(t, _)| t.starts_with("ip"))
+                .map(|(_, v)| v);
+            match ip {
+                Some(v) => match parse_entry(v) {
+                    Some(net) => list.insert(net),
+                    None => list.skipped += 1,
+                },
+                None => continue,
+            }
+        }
+        Ok(list)
+    }
+
+    /// Adds an address or prefix to the list.
+    pub fn insert(&mut self, net: IpNet) {
+        self.entries.insert(net, ());
+    }
+
+    /// Returns the name of the list.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the most specific entry containing `addr`.
+    pub fn lookup(&self, addr: IpAddr) -> Option<IpNet> {
+        self.entries.longest_match(addr).map(|(net, _)| net)
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the list has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of lines skipped while loading.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// `IntelMatch` records that an address of a flow is on a list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntelMatch {
+    /// The name of the list.
+    pub list: Arc<str>,
+    /// The entry of the list that matched.
+    pub entry: IpNet,
+    /// `AddrField::Src` or `AddrField::Dst`, the address that matched.
+    pub field: AddrField,
+    pub addr: IpAddr,
+}
+
+impl fmt::Display for IntelMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = match self.field {
+            AddrField::Src => "src",
+            AddrField::Dst => "dst",
+            _ => "addr",
+        };
+        write!(f, "{} {} in {} ({})", field, self.addr, self.list, self.entry)
+    }
+}
+
+/// `Tagged` is a record together with the lists its addresses are on.
+#[derive(Debug)]
+pub struct Tagged {
+    pub record: RecordKind,
+    pub matches: Vec<IntelMatch>,
+}
+
+/// `ThreatIntel` matches the addresses of records against a set of lists.
+///
+/// As a `Filter` it selects records with at least one match; `tag` attaches
+/// the matches to a record instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::intel::{IpList, ThreatIntel};
+///
+/// let mut intel = ThreatIntel::new();
+/// intel.add_list(IpList::open("drop", "drop.txt").unwrap());
+///
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     let tagged = intel.tag(record);
+///     for m in &tagged.matches {
+///         println!("{}", m);
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ThreatIntel {
+    lists: Vec<IpList>,
+    field: AddrField,
+}
+
+impl ThreatIntel {
+    /// Creates a matcher without lists, looking at source and destination
+    /// addresses.
+    pub fn new() -> ThreatIntel {
+        ThreatIntel {
+            lists: Vec::new(),
+            field: AddrField::SrcOrDst,
+        }
+    }
+
+    /// Adds a list to match against.
+    pub fn add_list(&mut self, list: IpList) {
+        self.lists.push(list);
+    }
+
+    /// Returns the lists.
+    pub fn lists(&self) -> &[IpList] {
+        &self.lists
+    }
+
+    /// Sets the addresses to look at: `AddrField::Src`, `AddrField::Dst` or
+    /// `AddrField::SrcOrDst`, the default.
+    pub fn set_field(&mut self, field: AddrField) {
+        self.field = field;
+    }
+
+    /// Returns the matches of the addresses of `record` on all lists.
+    pub fn lookup(&self, record: &dyn FlowRecord) -> Vec<IntelMatch> {
+        let mut matches = Vec::new();
+        for (field, addr) in self.addrs(record) {
+            for list in &self.lists {
+                if let Some(entry) = list.lookup(addr) {
+                    matches.push(IntelMatch {
+                        list: list.name.clone(),
+                        entry,
+                        field,
+                        addr,
+                    });
+                }
+            }
+        }
+        matches
+    }
+
+    /// Attaches the matches of a flow record to it. Other kinds of records
+    /// get no matches.
+    pub fn tag(&self, record: RecordKind) -> Tagged {
+        let matches = record.flow_record().map(|r| self.lookup(r)).unwrap_or_default();
+        Tagged { record, matches }
+    }
+
+    fn addrs(&self, record: &dyn FlowRecord) -> impl Iterator<Item = (AddrField, IpAddr)> {
+        let (src, dst) = match self.field {
+            AddrField::Src => (record.src_addr(), None),
+            AddrField::Dst => (None, record.dst_addr()),
+            _ => (record.src_addr(), record.dst_addr()),
+        };
+        [(AddrField::Src, src), (AddrField::Dst, dst)]
+            .into_iter()
+            .filter_map(|(field, addr)| addr.map(|a| (field, a)))
+    }
+}
+
+impl Default for ThreatIntel {
+    fn default() -> Self {
+        ThreatIntel::new()
+    }
+}
+
+/// Attaches the matches of a record as a `Vec<IntelMatch>`, if there are any.
+impl Annotator for ThreatIntel {
+    fn annotate(&self, record: &dyn FlowRecord, annotations: &mut Annotations) {
+        let matches = self.lookup(record);
+        if !matches.is_empty() {
+            annotations.insert(matches);
+        }
+    }
+}
+
+impl Filter for ThreatIntel {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        self.addrs(record)
+            .any(|(_, addr)| self.lists.iter().any(|l| l.lookup(addr).is_some()))
+    }
+}
+
+fn parse_entry(s: &str) -> Option<IpNet> {
+    let s = s.trim();
+    s.parse::<IpNet>()
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+// Splits a CSV line, removing the quotes around fields
+fn split_csv(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use crate::aggregate::{Counters, OrderBy};
+use crate::flow::FlowRecord;
+use crate::record::RecordKind;
+
+/// `InterfaceCounters` holds the traffic that entered and left through one
+/// interface.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    /// Flows with the interface as input interface.
+    pub input: Counters,
+    /// Flows with the interface as output interface.
+    pub output: Counters,
+}
+
+impl InterfaceCounters {
+    /// Returns the byte asymmetry between input and output, from -1.0 (only
+    /// output) to 1.0 (only input), or 0.0 for an idle interface.
+    pub fn asymmetry(&self) -> f64 {
+        let (i, o) = (self.input.bytes as f64, self.output.bytes as f64);
+        if i + o == 0.0 {
+            0.0
+        } else {
+            (i - o) / (i + o)
+        }
+    }
+
+    /// Returns the bytes through the interface in both directions.
+    pub fn total_bytes(&self) -> u64 {
+        self.input.bytes + self.output.bytes
+    }
+}
+
+/// `InterfaceSummary` accounts flows, packets and bytes per SNMP interface
+/// index, in both directions.
+///
+/// Interface indexes are only unique per exporter; summarize the records of
+/// a single exporter to avoid mixing interfaces of different routers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::interface::InterfaceSummary;
+/// use nfdump::output::OutputOptions;
+/// use nfdump::report::interface_report;
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// let mut summary = InterfaceSummary::new();
+/// while let Ok(record) = reader.read_record() {
+///     summary.add_kind(&record);
+/// }
+/// let mut options = OutputOptions::new();
+/// options.set_interface_names(reader.interface_names().clone());
+/// print!("{}", interface_report(&summary, &options));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceSummary {
+    interfaces: HashMap<u32, InterfaceCounters>,
+    totals: Counters,
+}
+
+impl InterfaceSummary {
+    /// Creates an empty summary.
+    pub fn new() -> InterfaceSummary {
+        InterfaceSummary::default()
+    }
+
+    /// Adds a flow record to its input and output interfaces. Records
+    /// without interface information only count towards the totals.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        self.totals.add(record);
+        if let Some(input) = record.input() {
+            self.interfaces.entry(input).or_default().input.add(record);
+        }
+        if let Some(output) = record.output() {
+            self.interfaces.entry(output).or_default().output.add(record);
+        }
+    }
+
+    /// Adds a flow record of either version; other records are ignored.
+    pub fn add_kind(&mut self, record: &RecordKind) {
+        match record {
+            RecordKind::Record(r) => self.add(r),
+            RecordKind::RecordV3(r) => self.add(r),
+            _ => {}
+        }
+    }
+
+    /// Returns the totals over all records added.
+    pub fn totals(&self) -> &Counters {
+        &self.totals
+    }
+
+    /// Returns the counters of interface `if_index`.
+    pub fn get(&self, if_index: u32) -> Option<&InterfaceCounters> {
+        self.interfaces.get(&if_index)
+    }
+
+    /// Returns the number of interfaces seen.
+    pub fn len(&self) -> usize {
+        self.interfaces.len()
+    }
+
+    /// Returns `true` if no interface was seen.
+    pub fn is_empty(&self) -> bool {
+        self.interfaces.is_empty()
+    }
+
+    /// Returns all interfaces ranked by the sum of their input and output
+    /// counters, largest first. Ties are ordered by interface index.
+    pub fn interfaces(&self, order: OrderBy) -> Vec<(u32, InterfaceCounters)> {
+        let mut interfaces: Vec<_> = self.interfaces.iter().map(|(i, c)| (*i, *c)).collect();
+        let value = |c: &InterfaceCounters| order.value(&c.input) + order.value(&c.output);
+        interfaces.sort_unstable_by(|a, b| value(&b.1).cmp(&value(&a.1)).then(a.0.cmp(&b.0)));
+        interfaces
+    }
+}
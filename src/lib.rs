@@ -1,23 +1,102 @@
+pub mod aggregate;
+pub mod annotation;
+pub mod anonymize;
+pub mod asn;
+pub mod bogon;
 mod block;
+pub mod bloom;
+pub mod catalog;
+pub mod collector;
+#[cfg(any(unix, windows))]
+pub mod cursor;
 mod compress;
+pub mod dedup;
+pub mod demux;
+pub mod direction;
+pub mod distribution;
+pub mod dscp;
+pub mod engine;
 pub mod error;
-mod exporter;
+pub mod exporter;
+pub mod filter;
+pub mod flow;
+pub mod follow;
+pub mod geo;
+pub mod graph;
+pub mod health;
+pub mod hooks;
+mod input;
+pub mod histogram;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod index;
+pub mod info;
+pub mod intel;
+pub mod interface;
+pub mod mac;
+pub mod matrix;
+#[cfg(feature = "nats")]
+pub mod nats;
 mod nffilev1;
 mod nffilev2;
 mod nfx;
 pub mod record;
+pub mod report;
+pub mod schema;
+#[cfg(feature = "silk")]
+pub mod silk;
+pub mod sort;
+pub mod split;
 pub mod nfx_v3;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod output;
+pub mod parallel;
+pub mod port;
+pub mod prefix;
+pub mod profile;
+pub mod projection;
+pub mod pushdown;
+pub mod stats;
+pub mod status;
+pub mod storage;
+pub mod template;
+#[cfg(feature = "testgen")]
+pub mod testgen;
+pub mod transcode;
+pub mod tunnel;
+pub mod validate;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod writer;
 
+use crate::annotation::Annotations;
 use crate::block::{DataBlock, DataBlockHeader};
-use crate::compress::{Decompressor, NFDUMP_COMPRESSION_TYPE_BZ2, NFDUMP_COMPRESSION_TYPE_LZ4, NFDUMP_COMPRESSION_TYPE_LZO, NFDUMP_COMPRESSION_TYPE_PLAIN, NFDUMP_COMPRESSION_TYPE_ZSTD};
-use crate::error::NfdumpError;
-use crate::exporter::ExporterInfo;
+use crate::compress::{BufferPool, Decompressor, DEFAULT_MAX_BLOCK_SIZE, MAX_HEADER_BLOCK_SIZE};
+use crate::error::{ErrorContext, NfdumpError};
+use crate::exporter::{find_sampler, ExporterInfo, ExporterStat, ExporterStatsRecord, InterfaceName, SamplerRecord};
+use crate::flow::FlowRecord;
+use crate::hooks::{BlockInfo, Hooks};
+use crate::index::BlockPrune;
+use crate::input::{BufferedInput, DEFAULT_READ_BUFFER_SIZE};
 use crate::nffilev1::{NfFileHeaderV1, StatRecordV1};
 use crate::nffilev2::{NfFileHeaderV2, StatRecordV2};
-use crate::record::{RecordKind};
+use crate::nfx_v3::RecordV3;
+use crate::projection::Projection;
+use crate::pushdown::Pushdown;
+use crate::record::{RecordEnvelope, RecordKind, RecordPosition};
+use crate::stats::{BlockMismatch, IoStats, ReadStats};
 use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
 use std::default::Default;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+pub use crate::block::BlockFlags;
+pub use crate::compress::Compression;
 
 const NFFILE_V1_HEADER_SIZE: usize = 140;
 const NFFILE_V2_HEADER_SIZE: usize = 40;
@@ -29,12 +108,181 @@ pub enum NfFileHeader {
     V2(NfFileHeaderV2),
 }
 
-#[derive(Debug)]
+impl NfFileHeader {
+    /// Returns the file layout version (1 or 2).
+    pub fn version(&self) -> u16 {
+        match self {
+            NfFileHeader::V1(h) => h.version,
+            NfFileHeader::V2(h) => h.version,
+        }
+    }
+
+    /// Returns the number of data blocks, excluding the appendix.
+    pub fn num_blocks(&self) -> u32 {
+        match self {
+            NfFileHeader::V1(h) => h.num_blocks,
+            NfFileHeader::V2(h) => h.num_blocks,
+        }
+    }
+
+    /// Returns the file offset of the first data block.
+    pub(crate) fn data_offset(&self) -> u64 {
+        match self {
+            NfFileHeader::V1(_) => (NFFILE_V1_HEADER_SIZE + NFFILE_V1_STAT_RECORD_SIZE) as u64,
+            NfFileHeader::V2(_) => NFFILE_V2_HEADER_SIZE as u64,
+        }
+    }
+
+    /// Returns the compression of the data blocks. For v1 files it is derived
+    /// from the header flags.
+    ///
+    /// Fails with `NfdumpError::UnsupportedCompression` if a v2 header names
+    /// an unknown codec.
+    pub fn compression(&self) -> Result<Compression, NfdumpError> {
+        match self {
+            NfFileHeader::V1(h) => Ok(match h.flags & 0x19 {
+                0x01 => Compression::Lzo,
+                0x08 => Compression::Bz2,
+                0x10 => Compression::Lz4,
+                _ => Compression::None,
+            }),
+            NfFileHeader::V2(h) => Compression::try_from(h.compression),
+        }
+    }
+
+    /// Returns the creation time in seconds since the epoch. Only v2 files
+    /// record it.
+    pub fn created(&self) -> Option<u64> {
+        match self {
+            NfFileHeader::V1(_) => None,
+            NfFileHeader::V2(h) => Some(h.created),
+        }
+    }
+
+    /// Returns the number of appendix blocks. Only v2 files have an appendix.
+    pub fn appendix_blocks(&self) -> Option<u16> {
+        match self {
+            NfFileHeader::V1(_) => None,
+            NfFileHeader::V2(h) => Some(h.appendix_blocks),
+        }
+    }
+
+    /// Returns the raw identification string, including any NUL padding.
+    pub fn ident(&self) -> &[u8] {
+        match self {
+            NfFileHeader::V1(h) => &h.ident,
+            NfFileHeader::V2(h) => &h.ident,
+        }
+    }
+
+    /// Returns the block buffer size the file was written with. Only v2
+    /// files record it.
+    pub fn block_size(&self) -> Option<u32> {
+        match self {
+            NfFileHeader::V1(_) => None,
+            NfFileHeader::V2(h) => Some(h.block_size),
+        }
+    }
+
+    /// Returns the default maximum block size of a reader: the block size
+    /// from the header, kept between 5 and 64 MiB, or 5 MiB for v1 files.
+    pub(crate) fn max_block_size(&self) -> usize {
+        match self.block_size() {
+            Some(size) if size > 0 => (size as usize).clamp(DEFAULT_MAX_BLOCK_SIZE, MAX_HEADER_BLOCK_SIZE),
+            _ => DEFAULT_MAX_BLOCK_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum StatRecord {
     V1(StatRecordV1),
     V2(StatRecordV2),
 }
 
+impl StatRecord {
+    /// Returns the stat record's counters in a version-independent form.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let reader = NfFileReader::new(file).unwrap();
+    /// let stats = reader.stat_record.normalized();
+    /// println!("{} flows between {} and {}", stats.flows, stats.first_seen, stats.last_seen);
+    /// ```
+    pub fn normalized(&self) -> FlowStats {
+        match self {
+            StatRecord::V1(s) => FlowStats::from(s),
+            StatRecord::V2(s) => FlowStats::from(s),
+        }
+    }
+}
+
+/// `FlowStats` holds the counters of a stat record independent of the file
+/// version. All counters are `u64` and the first and last seen timestamps
+/// are in milliseconds since the epoch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlowStats {
+    pub flows: u64,
+    pub bytes: u64,
+    pub packets: u64,
+
+    pub flows_tcp: u64,
+    pub flows_udp: u64,
+    pub flows_icmp: u64,
+    pub flows_other: u64,
+
+    pub bytes_tcp: u64,
+    pub bytes_udp: u64,
+    pub bytes_icmp: u64,
+    pub bytes_other: u64,
+
+    pub packets_tcp: u64,
+    pub packets_udp: u64,
+    pub packets_icmp: u64,
+    pub packets_other: u64,
+
+    pub first_seen: u64,
+    pub last_seen: u64,
+
+    pub sequence_failure: u64,
+}
+
+impl From<&StatRecordV1> for FlowStats {
+    fn from(value: &StatRecordV1) -> FlowStats {
+        FlowStats::from(&StatRecordV2::from(value))
+    }
+}
+
+impl From<&StatRecordV2> for FlowStats {
+    fn from(value: &StatRecordV2) -> FlowStats {
+        FlowStats {
+            flows: value.flows,
+            bytes: value.bytes,
+            packets: value.packets,
+            flows_tcp: value.flows_tcp,
+            flows_udp: value.flows_udp,
+            flows_icmp: value.flows_icmp,
+            flows_other: value.flows_other,
+            bytes_tcp: value.bytes_tcp,
+            bytes_udp: value.bytes_udp,
+            bytes_icmp: value.bytes_icmp,
+            bytes_other: value.bytes_other,
+            packets_tcp: value.packets_tcp,
+            packets_udp: value.packets_udp,
+            packets_icmp: value.packets_icmp,
+            packets_other: value.packets_other,
+            first_seen: value.first_seen,
+            last_seen: value.last_seen,
+            sequence_failure: value.sequence_failure,
+        }
+    }
+}
+
 /// `NfFileReader` reads nfdump files and provides methods to access the data.
 ///
 /// # Examples
@@ -47,13 +295,51 @@ pub enum StatRecord {
 /// let mut reader = NfFileReader::new(file).unwrap();
 /// ```
 pub struct NfFileReader<R> {
-    reader: R,
+    reader: BufferedInput<R>,
     pub header: NfFileHeader,
     pub stat_record: StatRecord,
     data_block: Option<DataBlock>,
     remaining_blocks: u32,
-    extensions: Vec<u16>,
+    // extension maps by map id
+    extensions: HashMap<u16, Vec<u16>>,
     exporters: Vec<ExporterInfo>,
+    exporters_by_sysid: HashMap<u16, Arc<ExporterInfo>>,
+    exporter_stats: HashMap<u32, ExporterStat>,
+    ident: Option<Arc<str>>,
+    samplers: HashMap<u16, Vec<SamplerRecord>>,
+    interface_names: HashMap<u32, String>,
+    buffers: BufferPool,
+    max_block_size: usize,
+    blocks_read: u32,
+    block_offset: u64,
+    last_position: Option<RecordPosition>,
+    stats: ReadStats,
+    io_stats: Option<IoStats>,
+    strict: bool,
+    truncated: bool,
+    // where the file was found to be truncated, reported once the data
+    // before it has been returned
+    truncation: Option<ErrorContext>,
+    time_window: Option<(u64, u64)>,
+    // the stat record rules out the time windows, kept up to date by
+    // `update_outside_window`
+    outside_window: bool,
+    limit: Option<u64>,
+    skip: u64,
+    skipped: u64,
+    records_returned: u64,
+    // error hit by `read_records` after it collected records, returned by
+    // the next read
+    pending_error: Option<NfdumpError>,
+    // why the appendix could not be read completely
+    appendix_error: Option<NfdumpError>,
+    return_unknown: bool,
+    cancel: Option<Arc<AtomicBool>>,
+    hooks: Hooks,
+    // what to do with each data block, by index; blocks past the end are read
+    pruned_blocks: Vec<BlockPrune>,
+    projection: Projection,
+    pushdown: Pushdown,
 }
 
 impl<R: Read + Seek> NfFileReader<R> {
@@ -68,7 +354,8 @@ impl<R: Read + Seek> NfFileReader<R> {
     /// let file = File::open("path_to_your_file").unwrap();
     /// let mut reader = NfFileReader::new(file).unwrap();
     /// ```
-    pub fn new(mut reader: R) -> Result<Self, NfdumpError> {
+    pub fn new(reader: R) -> Result<Self, NfdumpError> {
+        let mut reader = BufferedInput::new(reader, DEFAULT_READ_BUFFER_SIZE)?;
         let magic = reader.read_u16::<LittleEndian>()?;
         if magic != 0xa50c {
             return Err(NfdumpError::InvalidFile);
@@ -88,6 +375,7 @@ impl<R: Read + Seek> NfFileReader<R> {
             }
             _ => return Err(NfdumpError::UnsupportedVersion),
         };
+        header.compression()?;
 
         let stat_record = match version {
             0x0001 => {
@@ -109,17 +397,59 @@ impl<R: Read + Seek> NfFileReader<R> {
             NfFileHeader::V2(h) => h.num_blocks,
         };
 
+        let max_block_size = header.max_block_size();
+
         let mut ret = Self {
             reader,
             header,
             stat_record,
             data_block: None,
             remaining_blocks,
-            extensions: Vec::new(),
+            extensions: HashMap::new(),
             exporters: Vec::new(),
+            exporters_by_sysid: HashMap::new(),
+            exporter_stats: HashMap::new(),
+            ident: None,
+            samplers: HashMap::new(),
+            interface_names: HashMap::new(),
+            buffers: BufferPool::default(),
+            max_block_size,
+            blocks_read: 0,
+            block_offset: 0,
+            last_position: None,
+            stats: ReadStats {
+                blocks_expected: remaining_blocks,
+                ..Default::default()
+            },
+            io_stats: None,
+            strict: false,
+            truncated: false,
+            truncation: None,
+            time_window: None,
+            outside_window: false,
+            limit: None,
+            skip: 0,
+            skipped: 0,
+            records_returned: 0,
+            pending_error: None,
+            appendix_error: None,
+            return_unknown: false,
+            cancel: None,
+            hooks: Hooks::default(),
+            pruned_blocks: Vec::new(),
+            projection: Projection::all(),
+            pushdown: Pushdown::new(),
         };
 
-        _ = ret.read_appendix();
+        ret.read_appendix()?;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            version,
+            compression = %ret.header.compression().unwrap_or_default(),
+            blocks = remaining_blocks,
+            "opened nfdump file"
+        );
 
         Ok(ret)
     }
@@ -137,151 +467,1424 @@ impl<R: Read + Seek> NfFileReader<R> {
     /// println!("{:?}", reader.get_ident());
     /// ```
     pub fn get_ident(&self) -> Vec<u8> {
-        match &self.header {
-            NfFileHeader::V1(h) => h.ident.to_vec(),
-            NfFileHeader::V2(h) => h.ident.to_vec(),
+        self.header.ident().to_vec()
+    }
+
+    /// Returns the exporters seen so far, either in the appendix or in the data
+    /// blocks read up to this point.
+    pub fn exporters(&self) -> &[ExporterInfo] {
+        &self.exporters
+    }
+
+    fn add_exporter(&mut self, exporter: ExporterInfo) {
+        self.exporters_by_sysid.insert(exporter.sysid, Arc::new(exporter));
+        self.exporters.push(exporter);
+    }
+
+    /// Returns the exporter statistics seen so far, keyed by the `sysid` of
+    /// the exporter. Later statistics of an exporter replace earlier ones.
+    pub fn exporter_stats(&self) -> &HashMap<u32, ExporterStat> {
+        &self.exporter_stats
+    }
+
+    fn add_exporter_stats(&mut self, stats: ExporterStatsRecord) {
+        for stat in stats.stat {
+            self.exporter_stats.insert(stat.sysid, stat);
         }
     }
 
-    fn read_appendix(&mut self) -> Result<(), NfdumpError> {
-        if let NfFileHeader::V2(header) = &self.header {
-            self.reader.seek(SeekFrom::Start(header.off_appendix))?;
-            for _ in 0..header.appendix_blocks {
-                self.read_data_block()?;
-                while let Some(r) = self.data_block.as_mut().unwrap().read_record(&self.extensions) {
-                    match r {
-                        RecordKind::Ident(i) => {
-                            if let NfFileHeader::V2(header) = &mut self.header {
-                                header.ident = i;
-                            }
-                        }
-                        RecordKind::Stat(s) => {
-                            self.stat_record = StatRecord::V2(s);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            self.reader.seek(SeekFrom::Start(NFFILE_V2_HEADER_SIZE as u64))?;
+    /// Returns the samplers seen so far, keyed by the `sysid` of the exporter
+    /// they belong to. Legacy sampler records are converted to the current
+    /// representation.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// while reader.read_record().is_ok() {}
+    /// for (sysid, samplers) in reader.samplers() {
+    ///     println!("exporter {}: {:?}", sysid, samplers);
+    /// }
+    /// ```
+    pub fn samplers(&self) -> &HashMap<u16, Vec<SamplerRecord>> {
+        &self.samplers
+    }
+
+    /// Returns the sampler with `selector_id` of the exporter with
+    /// `exporter_sysid`, falling back to the exporter's generic sampler
+    /// (negative id) when no sampler matches the selector.
+    pub fn sampler_for(&self, exporter_sysid: u16, selector_id: i64) -> Option<&SamplerRecord> {
+        find_sampler(self.samplers.get(&exporter_sysid)?, selector_id)
+    }
+
+    /// Resolves the sampler a V3 record was sampled with from its
+    /// `sampler_info` extension.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::record::RecordKind;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// if let Ok(RecordKind::RecordV3(r)) = reader.read_record() {
+    ///     let rate = reader.resolve_sampler(&r).map(|s| s.rate()).unwrap_or(1);
+    ///     let bytes = r.generic_flow.map(|g| g.in_bytes).unwrap_or(0);
+    ///     println!("estimated bytes: {}", bytes * rate);
+    /// }
+    /// ```
+    pub fn resolve_sampler(&self, record: &RecordV3) -> Option<&SamplerRecord> {
+        let info = record.sampler_info.as_ref()?;
+        self.sampler_for(info.sysid, info.selector_id as i64)
+    }
+
+    fn add_sampler(&mut self, sampler: SamplerRecord) {
+        let samplers = self.samplers.entry(sampler.exporter_sysid).or_default();
+        match samplers.iter_mut().find(|s| s.id == sampler.id) {
+            Some(s) => *s = sampler,
+            None => samplers.push(sampler),
         }
-        self.data_block = None;
-        Ok(())
     }
 
-    fn _read_record(&mut self) -> Result<RecordKind, NfdumpError> {
-        if self.data_block.is_none() && self.remaining_blocks > 0 {
+    /// Returns the interface names seen so far, keyed by interface index.
+    ///
+    /// Names come from interface name records in the data blocks or the
+    /// appendix and can be passed to `OutputOptions` to render interface
+    /// names instead of numbers.
+    pub fn interface_names(&self) -> &HashMap<u32, String> {
+        &self.interface_names
+    }
+
+    fn add_interface_names(&mut self, names: Vec<InterfaceName>) {
+        for n in names {
+            self.interface_names.insert(n.if_index, n.name);
+        }
+    }
+
+    /// Returns the position of the record most recently returned by
+    /// `read_record`, or `None` if no record has been read yet.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// if reader.read_record().is_ok() {
+    ///     let pos = reader.last_record_position().unwrap();
+    ///     println!("block {} at {}, record offset {}", pos.block, pos.block_offset, pos.record_offset);
+    /// }
+    /// ```
+    pub fn last_record_position(&self) -> Option<RecordPosition> {
+        self.last_position
+    }
+
+    /// Counts the flow records remaining in the file without decoding them.
+    ///
+    /// Data blocks are still decompressed, but only record headers are parsed
+    /// and record bodies are skipped, which is much faster than reading every
+    /// record with `read_record`. Like `read_record`, this consumes the reader.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// println!("{} flows", reader.count_records().unwrap());
+    /// ```
+    pub fn count_records(&mut self) -> Result<u64, NfdumpError> {
+        let mut count = match self.data_block.as_mut() {
+            Some(block) => block.count_records(),
+            None => 0,
+        };
+        self.finish_data_block()?;
+
+        while self.remaining_blocks > 0 {
             if let NfFileHeader::V2(h) = &self.header {
-                if self.reader.seek(SeekFrom::Current(0)).unwrap() >= h.off_appendix {
-                    return Err(NfdumpError::EOF);
+                if self.reader.stream_position()? >= h.off_appendix {
+                    break;
                 }
             }
+            self.check_cancelled()?;
             self.read_data_block()?;
             self.remaining_blocks -= 1;
-        } else if self.data_block.is_none() && self.remaining_blocks == 0 {
-            return Err(NfdumpError::EOF);
+            count += self.data_block.as_mut().unwrap().count_records();
+            self.finish_data_block()?;
         }
+        self.check_block_count()?;
 
-        let record = self.data_block.as_mut().unwrap().read_record(&self.extensions);
-        if record.is_none() {
-            self.data_block = None;
-            return self._read_record();
-        }
-        record.ok_or(NfdumpError::EOF)
+        Ok(count)
     }
 
-    /// Reads the next record from the file.
-    ///
-    /// Returns one of the following:
+    /// Moves the reader back to the first data block, so a file can be read
+    /// again without reopening it, e.g. to compute statistics first and
+    /// extract matching records second.
     ///
-    /// * `RecordKind::Record` - A flow record typically found in a V1 file.
-    /// * `RecordKind::RecordV3` - A flow record typically found in a V2 file (V3 signifies it's from a DataBlock Type 3).
+    /// Metadata gathered from data blocks (extension maps, exporters,
+    /// samplers, interface names) is cleared and rebuilt from the appendix
+    /// and as blocks are read again. `stats()` restarts, while `io_stats()`
+    /// keeps counting. `set_skip` and `set_limit` apply to the next pass as
+    /// well.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use std::fs::File;
     /// use nfdump::NfFileReader;
-    /// use nfdump::record::RecordKind;
     ///
     /// let file = File::open("path_to_your_file").unwrap();
     /// let mut reader = NfFileReader::new(file).unwrap();
-    /// match reader.read_record() {
-    ///    Ok(rec) => match rec {
-    ///        RecordKind::Record(r) => println!("{:?}", r),
-    ///        RecordKind::RecordV3(r) => println!("{:?}", r),
-    ///        _ => {}},
-    ///    Err(e) => println!("{:?}", e),
+    /// let count = reader.count_records().unwrap();
+    /// reader.rewind().unwrap();
+    /// while let Ok(record) = reader.read_record() {
+    ///     println!("{:?} of {} records", record, count);
     /// }
     /// ```
-    pub fn read_record(&mut self) -> Result<RecordKind, NfdumpError> {
-        while let Ok(r) = self._read_record() {
-            match r {
-                RecordKind::ExtensionMap(e) => self.extensions = e.ex_id.clone(),
-                RecordKind::ExporterInfo(e) => self.exporters.push(e.clone()),
-                RecordKind::Record(_) | RecordKind::RecordV3(_) => return Ok(r),
-                RecordKind::None if self.remaining_blocks > 0 => {
-                    self.read_data_block()?;
-                    self.remaining_blocks -= 1;
-                    continue;
-                }
-                RecordKind::None => return Err(NfdumpError::EOF),
-                _ => continue,
+    pub fn rewind(&mut self) -> Result<(), NfdumpError> {
+        self.release_data_block();
+        self.remaining_blocks = self.header.num_blocks();
+        self.extensions.clear();
+        self.exporters.clear();
+        self.exporters_by_sysid.clear();
+        self.exporter_stats.clear();
+        self.ident = None;
+        self.samplers.clear();
+        self.interface_names.clear();
+        self.blocks_read = 0;
+        self.block_offset = 0;
+        self.last_position = None;
+        self.stats = ReadStats {
+            blocks_expected: self.remaining_blocks,
+            ..Default::default()
+        };
+        self.truncated = false;
+        self.truncation = None;
+        self.skipped = 0;
+        self.records_returned = 0;
+        self.pending_error = None;
+
+        match self.header {
+            NfFileHeader::V1(_) => {
+                let start = NFFILE_V1_HEADER_SIZE + NFFILE_V1_STAT_RECORD_SIZE;
+                self.reader.seek(SeekFrom::Start(start as u64))?;
+            }
+            NfFileHeader::V2(_) => {
+                // seeks back to the first data block, even if the appendix
+                // is damaged
+                self.read_appendix()?;
+                self.reader.seek(SeekFrom::Start(NFFILE_V2_HEADER_SIZE as u64))?;
             }
         }
-        Err(NfdumpError::EOF)
+        Ok(())
     }
 
-    fn read_data_block(&mut self) -> Result<(), NfdumpError> {
-        let mut db_buf = vec![0; 12];
-        self.reader.read_exact(&mut db_buf)?;
+    /// Returns the size of the underlying file. The reader's position is
+    /// left unchanged.
+    pub(crate) fn file_size(&mut self) -> Result<u64, NfdumpError> {
+        let pos = self.reader.stream_position()?;
+        let size = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(pos))?;
+        Ok(size)
+    }
 
-        let mut cursor = std::io::Cursor::new(db_buf);
+    /// Returns the file offsets of the data blocks, found by walking the
+    /// block headers without reading block data. The reader's position is
+    /// left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// for (i, offset) in reader.block_offsets().unwrap().iter().enumerate() {
+    ///     println!("block {} at {}", i, offset);
+    /// }
+    /// ```
+    pub fn block_offsets(&mut self) -> Result<Vec<u64>, NfdumpError> {
+        let pos = self.reader.stream_position()?;
+        let (mut offset, end) = match &self.header {
+            NfFileHeader::V1(_) => ((NFFILE_V1_HEADER_SIZE + NFFILE_V1_STAT_RECORD_SIZE) as u64, u64::MAX),
+            NfFileHeader::V2(h) => (NFFILE_V2_HEADER_SIZE as u64, h.off_appendix),
+        };
 
-        let num_records = cursor.read_u32::<LittleEndian>()?;
-        let size = cursor.read_u32::<LittleEndian>()?;
-        let id = cursor.read_u16::<LittleEndian>()?;
-        let flags = cursor.read_u16::<LittleEndian>()?;
+        let mut offsets = Vec::new();
+        let mut db_buf = [0; 12];
+        while offsets.len() < self.header.num_blocks() as usize && offset < end {
+            self.reader.seek(SeekFrom::Start(offset))?;
+            if read_full(&mut self.reader, &mut db_buf)? < db_buf.len() {
+                break;
+            }
+            offsets.push(offset);
+            let size = u32::from_le_bytes([db_buf[4], db_buf[5], db_buf[6], db_buf[7]]);
+            offset += 12 + size as u64;
+        }
+
+        self.reader.seek(SeekFrom::Start(pos))?;
+        Ok(offsets)
+    }
 
-        let mut data = vec![0; size as usize];
-        self.reader.read_exact(&mut data)?;
+    /// Restricts the reader to the data blocks with indices in `blocks`, so
+    /// several readers of one file can each scan a part of it, e.g. on
+    /// different threads.
+    ///
+    /// The reader is rewound and the blocks before the range are scanned for
+    /// extension maps, exporters and samplers, skipping over flow records
+    /// without decoding them. To split a file into many ranges, prefer
+    /// `cursor::cursors`, which scans the file only once.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_block_range(10..20).unwrap();
+    /// while let Ok(record) = reader.read_record() {
+    ///     println!("{:?}", record);
+    /// }
+    /// ```
+    pub fn set_block_range(&mut self, blocks: Range<u32>) -> Result<(), NfdumpError> {
+        let offsets = self.block_offsets()?;
+        let end = (blocks.end as usize).min(offsets.len());
+        let start = (blocks.start as usize).min(end);
+        self.rewind()?;
+        self.scan_metadata(start as u32)?;
+        let offset = offsets.get(start).copied().unwrap_or(self.reader.stream_position()?);
+        self.start_at_block(start as u32, offset, (end - start) as u32)
+    }
 
-        let decompressor = NfFileReader::<R>::select_decompressor(&self.header, data)?;
+    /// Reads the next `blocks` data blocks for their metadata only.
+    pub(crate) fn scan_metadata(&mut self, blocks: u32) -> Result<(), NfdumpError> {
+        self.release_data_block();
+        for _ in 0..blocks.min(self.remaining_blocks) {
+            self.check_cancelled()?;
+            self.read_data_block()?;
+            self.remaining_blocks -= 1;
+            for record in self.data_block.as_mut().unwrap().read_metadata() {
+                self.add_metadata(record);
+            }
+            self.release_data_block();
+        }
+        Ok(())
+    }
 
-        let db_header = DataBlockHeader {
-            num_records,
-            size,
-            id,
-            flags,
+    /// Positions the reader at the data block `index` found at `offset`,
+    /// reading `count` blocks from there.
+    pub(crate) fn start_at_block(&mut self, index: u32, offset: u64, count: u32) -> Result<(), NfdumpError> {
+        self.release_data_block();
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.remaining_blocks = count;
+        self.blocks_read = index;
+        self.block_offset = 0;
+        self.last_position = None;
+        // block indices stay relative to the start of the file
+        self.stats = ReadStats {
+            blocks_expected: index + count,
+            blocks_read: index,
+            ..Default::default()
         };
+        self.truncated = false;
+        self.truncation = None;
+        Ok(())
+    }
 
-        self.data_block = Some(DataBlock::new(db_header, decompressor));
+    /// Sets what to do with each data block by index: read it, only scan it
+    /// for metadata, or skip it.
+    pub(crate) fn set_pruned_blocks(&mut self, pruned: Vec<BlockPrune>) {
+        self.pruned_blocks = pruned;
+    }
 
-        Ok(())
+    /// Makes `count` more data blocks readable, for a file still being
+    /// written whose header does not count them yet.
+    pub(crate) fn follow_blocks(&mut self, count: u32) {
+        self.remaining_blocks += count;
+        self.stats.blocks_expected += count;
     }
 
-    fn select_decompressor(
-        header: &NfFileHeader,
-        data: Vec<u8>,
-    ) -> Result<Box<Decompressor>, NfdumpError> {
-        match header {
-            NfFileHeader::V1(h) => {
-                let decompressor: Box<Decompressor> = match h.flags & 0x19 {
-                    0x01 => Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_LZO, data)?),
-                    0x08 => Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_BZ2, data)?),
-                    0x10 => Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_LZ4, data)?),
-                    _ => Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_PLAIN, data)?),
-                };
-
-                Ok(decompressor)
-            }
-            NfFileHeader::V2(h) => match h.compression {
-                0 => Ok(Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_PLAIN, data)?)),
-                1 => Ok(Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_LZO, data)?)),
-                2 => Ok(Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_BZ2, data)?)),
-                3 => Ok(Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_LZ4, data)?)),
-                4 => Ok(Box::new(Decompressor::new(NFDUMP_COMPRESSION_TYPE_ZSTD, data)?)),
-                _ => Err(NfdumpError::UnsupportedCompression),
-            },
+    /// Replaces the metadata gathered from data blocks with that of `other`.
+    pub(crate) fn copy_metadata<S>(&mut self, other: &NfFileReader<S>) {
+        self.extensions = other.extensions.clone();
+        self.exporters = other.exporters.clone();
+        self.exporters_by_sysid = other.exporters_by_sysid.clone();
+        self.exporter_stats = other.exporter_stats.clone();
+        self.samplers = other.samplers.clone();
+        self.interface_names = other.interface_names.clone();
+    }
+
+    /// Returns the block and record counts observed so far, compared against
+    /// the counts declared in the file and block headers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// while reader.read_record().is_ok() {}
+    /// if !reader.stats().is_consistent() {
+    ///     println!("file is damaged: {:?}", reader.stats());
+    /// }
+    /// ```
+    pub fn stats(&self) -> &ReadStats {
+        &self.stats
+    }
+
+    /// Enables or disables collecting `IoStats`. Collection is off by
+    /// default; enabling it resets the counters.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_io_stats(true);
+    /// while reader.read_record().is_ok() {}
+    /// let stats = reader.io_stats().unwrap();
+    /// println!("{} bytes read, {} decompressed", stats.bytes_read, stats.bytes_decompressed);
+    /// ```
+    pub fn set_io_stats(&mut self, enabled: bool) {
+        self.io_stats = enabled.then(IoStats::default);
+    }
+
+    /// Returns the I/O and decoding counters collected so far, or `None` if
+    /// collection is disabled.
+    pub fn io_stats(&self) -> Option<&IoStats> {
+        self.io_stats.as_ref()
+    }
+
+    /// Sets a cancellation token. Once it is set to `true`, reads return
+    /// `NfdumpError::Cancelled`, which also aborts scans driven by the
+    /// reader, such as `count_records`, `ExporterHealth::from_reader` or
+    /// `Validator::validate`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use nfdump::NfFileReader;
+    ///
+    /// let cancel = Arc::new(AtomicBool::new(false));
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_cancel(cancel.clone());
+    ///
+    /// // from another thread, e.g. a GUI's stop button
+    /// cancel.store(true, Ordering::Relaxed);
+    /// assert!(reader.read_record().is_err());
+    /// ```
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Sets the V3 record extensions to decode. Elements outside of the
+    /// projection are skipped over and left `None`, which saves decoding
+    /// work when only a few fields are needed. Every element is decoded by
+    /// default.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::projection::Projection;
+    /// use nfdump::record::RecordKind;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_projection(Projection::from_fields(&["src_addr", "dst_addr", "in_bytes"]).unwrap());
+    /// while let Ok(RecordKind::RecordV3(record)) = reader.read_record() {
+    ///     assert!(record.mac_address.is_none());
+    /// }
+    /// ```
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// Sets predicates to check before decoding records. Records ruled out
+    /// are skipped without being decoded where possible, and with a time
+    /// window the file is not read at all if its stat record rules the
+    /// window out. See `Pushdown` for what is checked when.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::filter::{AddrField, All, Filter};
+    /// use nfdump::prefix::PrefixFilter;
+    /// use nfdump::pushdown::Pushdown;
+    ///
+    /// let mut window = Pushdown::new();
+    /// window.set_time_window(1704067200000, 1704067500000);
+    /// let filter = All(vec![
+    ///     Box::new(window),
+    ///     Box::new(PrefixFilter::new(AddrField::Src, ["10.0.0.0/8".parse().unwrap()])),
+    /// ]);
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_pushdown(filter.pushdown());
+    /// while let Ok(record) = reader.read_record() {
+    ///     if filter.matches_kind(&record) {
+    ///         println!("{:?}", record);
+    ///     }
+    /// }
+    /// ```
+    pub fn set_pushdown(&mut self, pushdown: Pushdown) {
+        self.pushdown = pushdown;
+        self.update_outside_window();
+    }
+
+    fn check_cancelled(&self) -> Result<(), NfdumpError> {
+        match &self.cancel {
+            Some(c) if c.load(Ordering::Relaxed) => Err(NfdumpError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Registers a callback run when the reader starts on a data block,
+    /// before the block is decompressed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_on_block_start(|block| {
+    ///     println!("block {}: {} records, {} bytes", block.index, block.num_records, block.size);
+    /// });
+    /// while reader.read_record().is_ok() {}
+    /// ```
+    pub fn set_on_block_start<F: FnMut(&BlockInfo) + Send + 'static>(&mut self, hook: F) {
+        self.hooks.on_block_start = Some(Box::new(hook));
+    }
+
+    /// Registers a callback run for every record `read_record` returns.
+    pub fn set_on_record<F: FnMut(&RecordKind) + Send + 'static>(&mut self, hook: F) {
+        self.hooks.on_record = Some(Box::new(hook));
+    }
+
+    /// Registers a callback run for every error `read_record` returns, other
+    /// than `NfdumpError::EOF`.
+    pub fn set_on_decode_error<F: FnMut(&NfdumpError) + Send + 'static>(&mut self, hook: F) {
+        self.hooks.on_decode_error = Some(Box::new(hook));
+    }
+
+    /// Enables strict mode, in which a mismatch between declared and decoded
+    /// block or record counts is returned as an error instead of only being
+    /// reported through `stats()`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Returns `true` if the file ended in the middle of a data block, as
+    /// files from crashed collectors often do.
+    ///
+    /// The records of the truncated block that could still be decoded are
+    /// returned by `read_record` before it reports
+    /// `NfdumpError::UnexpectedEof`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// while reader.read_record().is_ok() {}
+    /// if reader.is_truncated() {
+    ///     println!("file is truncated");
+    /// }
+    /// ```
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Returns the error that stopped reading the appendix of a v2 file, or
+    /// `None` if it was read completely.
+    ///
+    /// A damaged appendix does not keep the data blocks from being read; the
+    /// ident, stat record and exporters found before the damage are kept.
+    pub fn appendix_error(&self) -> Option<&NfdumpError> {
+        self.appendix_error.as_ref()
+    }
+
+    /// Sets the memory ceiling for a single data block, in bytes.
+    ///
+    /// Both the compressed block and its decompressed contents must fit within
+    /// this size; larger blocks fail with `NfdumpError::BlockTooLarge` instead
+    /// of growing the buffers further, and the next read continues with the
+    /// following block. The default is the block size from the v2 file
+    /// header, at least 5 MiB, which fits any block written by nfdump, and at
+    /// most 64 MiB. Block buffers are reused between blocks, so a reader holds
+    /// roughly twice this amount at most.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_max_block_size(1024 * 1024);
+    /// ```
+    pub fn set_max_block_size(&mut self, size: usize) {
+        self.max_block_size = size;
+    }
+
+    /// Sets the size of the read buffer in front of the underlying reader, in
+    /// bytes. The default is 64 KiB; larger buffers mean fewer reads, which
+    /// helps on network file systems. A size of 0 disables buffering, e.g.
+    /// when the reader is already buffered.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_read_buffer_size(1024 * 1024).unwrap();
+    /// ```
+    pub fn set_read_buffer_size(&mut self, size: usize) -> Result<(), NfdumpError> {
+        Ok(self.reader.set_capacity(size)?)
+    }
+
+    /// Restricts `read_record` to flows overlapping the time window from
+    /// `start` to `end`, both in milliseconds since the epoch.
+    ///
+    /// Records without timestamps are dropped. If the stat record shows that
+    /// no flow of the file falls into the window, no data block is read at
+    /// all. Otherwise every data block is still decompressed, as block
+    /// headers carry no time range; `index::FileIndex::apply` sets the
+    /// window and skips the blocks a file index rules out.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_time_window(1704067200000, 1704067500000);
+    /// ```
+    pub fn set_time_window(&mut self, start: u64, end: u64) {
+        self.time_window = Some((start, end));
+        self.update_outside_window();
+    }
+
+    /// Stops `read_record` after `limit` flow records, like `nfdump -c`.
+    ///
+    /// Once the limit is reached `read_record` returns `NfdumpError::EOF`
+    /// without reading any further blocks. Records dropped by the time window
+    /// or `set_skip` do not count towards the limit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_limit(100);
+    /// ```
+    pub fn set_limit(&mut self, limit: u64) {
+        self.limit = Some(limit);
+    }
+
+    /// Drops the first `skip` flow records `read_record` would return.
+    pub fn set_skip(&mut self, skip: u64) {
+        self.skip = skip;
+    }
+
+    /// Makes `read_record` return records this crate does not decode as
+    /// `RecordKind::Unimplemented`, with their raw body, instead of passing
+    /// over them. Off by default.
+    ///
+    /// These are not flow records: they are returned regardless of the time
+    /// window and do not count towards `set_skip` or `set_limit`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::record::RecordKind;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// reader.set_return_unknown(true);
+    /// while let Ok(record) = reader.read_record() {
+    ///     if let RecordKind::Unimplemented { rtype, data } = record {
+    ///         println!("record type {}: {} bytes", rtype, data.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn set_return_unknown(&mut self, return_unknown: bool) {
+        self.return_unknown = return_unknown;
+    }
+
+    /// Records whether the stat record rules out any flow of the file
+    /// falling into the time window.
+    fn update_outside_window(&mut self) {
+        let stats = self.stat_record.normalized();
+        let (first, last) = (stats.first_seen, stats.last_seen);
+        // an empty stat record carries no time range
+        let outside = |(start, end): (u64, u64)| first != 0 && last != 0 && (last < start || first > end);
+        self.outside_window = self.time_window.is_some_and(outside) || self.pushdown.time_window().is_some_and(outside);
+    }
+
+    fn in_time_window(&self, record: &dyn FlowRecord) -> bool {
+        let Some((start, end)) = self.time_window else {
+            return true;
+        };
+        match (record.first_msec(), record.last_msec()) {
+            (Some(first), Some(last)) => first <= end && last >= start,
+            _ => false,
+        }
+    }
+
+    fn read_appendix(&mut self) -> Result<(), NfdumpError> {
+        let NfFileHeader::V2(header) = &self.header else {
+            return Ok(());
+        };
+        let (off_appendix, appendix_blocks) = (header.off_appendix, header.appendix_blocks);
+        let remaining_blocks = self.remaining_blocks;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("appendix", offset = off_appendix, blocks = appendix_blocks).entered();
+        let result = self.read_appendix_blocks(off_appendix, appendix_blocks);
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            tracing::warn!(error = %e, "damaged appendix");
+        }
+        self.appendix_error = result.err();
+        self.update_outside_window();
+
+        // a damaged appendix must not affect reading the data blocks
+        self.release_data_block();
+        self.remaining_blocks = remaining_blocks;
+        self.truncated = false;
+        self.truncation = None;
+        // appendix blocks are not data blocks
+        self.blocks_read = 0;
+        self.stats.blocks_read = 0;
+        self.stats.records_expected = 0;
+        self.reader.seek(SeekFrom::Start(NFFILE_V2_HEADER_SIZE as u64))?;
+        Ok(())
+    }
+
+    fn read_appendix_blocks(&mut self, off_appendix: u64, appendix_blocks: u16) -> Result<(), NfdumpError> {
+        self.reader.seek(SeekFrom::Start(off_appendix))?;
+        for _ in 0..appendix_blocks {
+            self.read_data_block()?;
+            while let Some(r) = self.data_block.as_mut().unwrap().read_record(&self.extensions, &Projection::all(), &Pushdown::new())? {
+                match r {
+                    RecordKind::Ident(i) => {
+                        if let NfFileHeader::V2(header) = &mut self.header {
+                            header.ident = i;
+                        }
+                    }
+                    RecordKind::Stat(s) => {
+                        self.stat_record = StatRecord::V2(s);
+                    }
+                    RecordKind::ExporterInfo(e) => self.add_exporter(e),
+                    RecordKind::ExporterStats(s) => self.add_exporter_stats(s),
+                    RecordKind::Sampler(s) => self.add_sampler(s),
+                    RecordKind::SamplerV0(s) => self.add_sampler(SamplerRecord::from(&s)),
+                    RecordKind::IfName(n) => self.add_interface_names(n),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn _read_record(&mut self) -> Result<RecordKind, NfdumpError> {
+        if self.data_block.is_none() && self.remaining_blocks > 0 {
+            if let NfFileHeader::V2(h) = &self.header {
+                // files still being written have no appendix yet
+                if h.off_appendix > 0 && self.reader.stream_position()? >= h.off_appendix {
+                    self.check_block_count()?;
+                    return Err(NfdumpError::EOF);
+                }
+            }
+            self.advance_block()?;
+            if self.data_block.is_none() {
+                // the remaining blocks were pruned
+                self.check_block_count()?;
+                return Err(NfdumpError::EOF);
+            }
+        } else if self.data_block.is_none() && self.remaining_blocks == 0 {
+            self.check_block_count()?;
+            return Err(NfdumpError::EOF);
+        }
+
+        let block = self.data_block.as_mut().unwrap();
+        let record = block.read_record(&self.extensions, &self.projection, &self.pushdown);
+        self.last_position = Some(RecordPosition {
+            block: self.blocks_read - 1,
+            block_offset: self.block_offset,
+            record_offset: block.last_record_offset,
+        });
+        match record {
+            Ok(Some(r)) => {
+                if let Some(io) = self.io_stats.as_mut() {
+                    *io.records_by_type.entry(block.last_record_type).or_default() += 1;
+                }
+                Ok(r)
+            }
+            Ok(None) => {
+                let finished = self.finish_data_block();
+                if let Some(context) = self.truncation.take() {
+                    self.remaining_blocks = 0;
+                    return Err(NfdumpError::UnexpectedEof { context });
+                }
+                finished?;
+                self._read_record()
+            }
+            Err(NfdumpError::ParseErrorAt { mut context }) => {
+                context.block = Some(self.blocks_read - 1);
+                context.file_offset = Some(self.block_offset);
+                Err(NfdumpError::ParseErrorAt { context })
+            }
+            Err(NfdumpError::UnexpectedEof { mut context }) => {
+                // the rest of the block is unusable
+                self.truncation = None;
+                self.finish_data_block().ok();
+                context.block = Some(self.blocks_read - 1);
+                context.file_offset = Some(self.block_offset);
+                Err(NfdumpError::UnexpectedEof { context })
+            }
+            Err(NfdumpError::BlockTooLarge { limit, mut context }) => {
+                // skip the rest of the block, the next call continues with
+                // the following one
+                self.finish_data_block().ok();
+                context.block = Some(self.blocks_read - 1);
+                context.file_offset = Some(self.block_offset);
+                Err(NfdumpError::BlockTooLarge { limit, context })
+            }
+            Err(NfdumpError::IoError(e)) => {
+                // the decompressor failed, the rest of the block is unusable
+                self.finish_data_block().ok();
+                if let Some(context) = self.truncation.take() {
+                    self.remaining_blocks = 0;
+                    return Err(NfdumpError::UnexpectedEof { context });
+                }
+                Err(NfdumpError::IoError(e))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the next record from the file.
+    ///
+    /// Returns one of the following:
+    ///
+    /// * `RecordKind::Record` - A flow record typically found in a V1 file.
+    /// * `RecordKind::RecordV3` - A flow record typically found in a V2 file (V3 signifies it's from a DataBlock Type 3).
+    /// * `RecordKind::Unimplemented` - A record this crate does not decode, with its raw body, if enabled with `set_return_unknown`.
+    ///
+    /// `NfdumpError::EOF` is only returned once a correctly terminated file
+    /// has been read completely. A file ending inside a block or record yields
+    /// `NfdumpError::UnexpectedEof` after the records before the damage. A
+    /// record that cannot be decoded yields `NfdumpError::ParseErrorAt`
+    /// describing where it is; the next call continues with the following
+    /// record. Corrupt compressed data yields the decompressor's
+    /// `NfdumpError::IoError`; the next call continues with the following
+    /// block.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::record::RecordKind;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// match reader.read_record() {
+    ///    Ok(rec) => match rec {
+    ///        RecordKind::Record(r) => println!("{:?}", r),
+    ///        RecordKind::RecordV3(r) => println!("{:?}", r),
+    ///        _ => {}},
+    ///    Err(e) => println!("{:?}", e),
+    /// }
+    /// ```
+    pub fn read_record(&mut self) -> Result<RecordKind, NfdumpError> {
+        if let Some(e) = self.pending_error.take() {
+            return Err(e);
+        }
+        self.check_cancelled()?;
+        if self.outside_window || self.limit.is_some_and(|l| self.records_returned >= l) {
+            return Err(NfdumpError::EOF);
+        }
+        match self.next_record() {
+            Ok(r) => {
+                if let Some(hook) = self.hooks.on_record.as_mut() {
+                    hook(&r);
+                }
+                Ok(r)
+            }
+            Err(NfdumpError::EOF) => Err(NfdumpError::EOF),
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %e, "reading record failed");
+                if let Some(io) = self.io_stats.as_mut() {
+                    io.decode_errors += 1;
+                }
+                if let Some(hook) = self.hooks.on_decode_error.as_mut() {
+                    hook(&e);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the next record for `read_record`, applying the reader's
+    /// filters and keeping track of metadata records.
+    fn next_record(&mut self) -> Result<RecordKind, NfdumpError> {
+        loop {
+            let r = self._read_record()?;
+            match r {
+                RecordKind::ExtensionMap(_)
+                | RecordKind::ExporterInfo(_)
+                | RecordKind::ExporterStats(_)
+                | RecordKind::Sampler(_)
+                | RecordKind::SamplerV0(_)
+                | RecordKind::IfName(_) => self.add_metadata(r),
+                RecordKind::Record(ref rec) if !self.in_time_window(rec) => continue,
+                RecordKind::RecordV3(ref rec) if !self.in_time_window(rec) => continue,
+                RecordKind::Record(_) | RecordKind::RecordV3(_) if self.skipped < self.skip => self.skipped += 1,
+                RecordKind::Record(_) | RecordKind::RecordV3(_) => {
+                    self.records_returned += 1;
+                    return Ok(r);
+                }
+                RecordKind::Unimplemented { .. } if self.return_unknown => return Ok(r),
+                RecordKind::None if self.remaining_blocks > 0 => {
+                    self.advance_block()?;
+                    continue;
+                }
+                RecordKind::None => return Err(NfdumpError::EOF),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Keeps track of a metadata record read from a data block.
+    fn add_metadata(&mut self, record: RecordKind) {
+        match record {
+            RecordKind::ExtensionMap(e) => {
+                self.extensions.insert(e.map_id, e.ex_id);
+            }
+            RecordKind::ExporterInfo(e) => self.add_exporter(e),
+            RecordKind::ExporterStats(s) => self.add_exporter_stats(s),
+            RecordKind::Sampler(s) => self.add_sampler(s),
+            RecordKind::SamplerV0(s) => self.add_sampler(SamplerRecord::from(&s)),
+            RecordKind::IfName(n) => self.add_interface_names(n),
+            _ => {}
+        }
+    }
+
+    /// Reads up to `max` records like `read_record` and appends them to
+    /// `out`, returning how many were appended. Returns `Ok(0)` once the
+    /// file has been read completely.
+    ///
+    /// An error after some records were collected is returned by the next
+    /// call instead, so no record is lost.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// let mut batch = Vec::with_capacity(1024);
+    /// while reader.read_records(&mut batch, 1024).unwrap() > 0 {
+    ///     println!("{} records", batch.len());
+    ///     batch.clear();
+    /// }
+    /// ```
+    pub fn read_records(&mut self, out: &mut Vec<RecordKind>, max: usize) -> Result<usize, NfdumpError> {
+        let mut n = 0;
+        while n < max {
+            match self.read_record() {
+                Ok(r) => {
+                    out.push(r);
+                    n += 1;
+                }
+                Err(NfdumpError::EOF) => break,
+                Err(e) if n == 0 => return Err(e),
+                Err(e) => {
+                    self.pending_error = Some(e);
+                    break;
+                }
+            }
+        }
+        Ok(n)
+    }
+
+    /// Validates the record count of a fully read data block, then releases it.
+    fn finish_data_block(&mut self) -> Result<(), NfdumpError> {
+        let Some(block) = self.data_block.as_ref() else {
+            return Ok(());
+        };
+
+        let expected = block.num_records();
+        let found = block.records_read;
+        self.stats.records_read += found as u64;
+        self.release_data_block();
+
+        if expected != found {
+            let mismatch = BlockMismatch {
+                block: self.blocks_read - 1,
+                expected,
+                found,
+            };
+            self.stats.block_mismatches.push(mismatch);
+            if self.strict {
+                return Err(NfdumpError::RecordCountMismatch {
+                    block: mismatch.block,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the number of data blocks read once the end of the file is reached.
+    fn check_block_count(&self) -> Result<(), NfdumpError> {
+        let expected = self.stats.blocks_expected;
+        if self.strict && self.stats.blocks_read != expected {
+            return Err(NfdumpError::BlockCountMismatch {
+                expected,
+                found: self.stats.blocks_read,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads the next flow record like `read_record`, wrapped in an envelope
+    /// with the record's resolved exporter and the file's ident.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    ///
+    /// let file = File::open("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::new(file).unwrap();
+    /// while let Ok(env) = reader.read_record_envelope() {
+    ///     if let Some(exporter) = &env.exporter {
+    ///         println!("{}: {:?} from {}", env.ident, env.record, exporter.address);
+    ///     }
+    /// }
+    /// ```
+    pub fn read_record_envelope(&mut self) -> Result<RecordEnvelope, NfdumpError> {
+        let record = self.read_record()?;
+        let exporter = record
+            .exporter_sysid()
+            .and_then(|sysid| self.exporters_by_sysid.get(&sysid).cloned());
+        let ident = self.ident.get_or_insert_with(|| {
+            let ident = match &self.header {
+                NfFileHeader::V1(h) => &h.ident[..],
+                NfFileHeader::V2(h) => &h.ident[..],
+            };
+            let end = ident.iter().position(|&b| b == 0).unwrap_or(ident.len());
+            Arc::from(String::from_utf8_lossy(&ident[..end]))
+        });
+
+        Ok(RecordEnvelope {
+            record,
+            exporter,
+            ident: ident.clone(),
+            source: None,
+            annotations: Annotations::new(),
+        })
+    }
+
+    /// Reads the next data block and returns its record count, flags and
+    /// decompressed contents, or `None` after the last data block.
+    pub(crate) fn read_raw_block(&mut self) -> Result<Option<(u32, BlockFlags, Vec<u8>)>, NfdumpError> {
+        self.release_data_block();
+        self.check_cancelled()?;
+        if self.remaining_blocks == 0 {
+            return Ok(None);
+        }
+        if let NfFileHeader::V2(h) = &self.header {
+            if self.reader.stream_position()? >= h.off_appendix {
+                return Ok(None);
+            }
+        }
+        self.read_data_block()?;
+        self.remaining_blocks -= 1;
+
+        let block = self.data_block.as_mut().unwrap();
+        let (num_records, flags) = (block.num_records(), block._header.flags);
+        let mut data = Vec::new();
+        block.decoder.read_to_end(&mut data)?;
+        self.release_data_block();
+
+        Ok(Some((num_records, flags, data)))
+    }
+
+    /// Reads the decompressed appendix blocks of a v2 file, leaving the
+    /// reader's position unchanged.
+    pub(crate) fn read_raw_appendix(&mut self) -> Result<Vec<(u32, Vec<u8>)>, NfdumpError> {
+        let NfFileHeader::V2(header) = &self.header else {
+            return Ok(Vec::new());
+        };
+        let (off_appendix, appendix_blocks) = (header.off_appendix, header.appendix_blocks);
+
+        self.release_data_block();
+        let pos = self.reader.stream_position()?;
+        let blocks_read = self.blocks_read;
+        self.reader.seek(SeekFrom::Start(off_appendix))?;
+
+        let mut blocks = Vec::new();
+        for _ in 0..appendix_blocks {
+            self.read_data_block()?;
+            let block = self.data_block.as_mut().unwrap();
+            let num_records = block.num_records();
+            let mut data = Vec::new();
+            block.decoder.read_to_end(&mut data)?;
+            blocks.push((num_records, data));
+            self.release_data_block();
+        }
+
+        self.reader.seek(SeekFrom::Start(pos))?;
+        self.blocks_read = blocks_read;
+        self.stats.blocks_read = blocks_read;
+        Ok(blocks)
+    }
+
+    fn release_data_block(&mut self) {
+        if let Some(block) = self.data_block.take() {
+            if let Some(io) = self.io_stats.as_mut() {
+                io.bytes_decompressed += block.offset;
+                io.records_pruned += block.records_pruned as u64;
+                io.peak_decompressed_size = io.peak_decompressed_size.max(block.offset as usize);
+            }
+            block.recycle(&mut self.buffers);
+        }
+    }
+
+    /// Reads the next data block, scanning pruned blocks for metadata or
+    /// skipping them. Leaves no block loaded if all remaining blocks are
+    /// pruned.
+    fn advance_block(&mut self) -> Result<(), NfdumpError> {
+        while self.remaining_blocks > 0 {
+            let prune = self.pruned_blocks.get(self.blocks_read as usize).copied();
+            match prune.unwrap_or(BlockPrune::Read) {
+                BlockPrune::Read => {
+                    self.read_data_block()?;
+                    self.remaining_blocks -= 1;
+                    return Ok(());
+                }
+                BlockPrune::Scan => {
+                    self.read_data_block()?;
+                    self.remaining_blocks -= 1;
+                    for record in self.data_block.as_mut().unwrap().read_metadata() {
+                        self.add_metadata(record);
+                    }
+                    self.finish_data_block()?;
+                }
+                BlockPrune::Skip => {
+                    self.skip_data_block()?;
+                    self.remaining_blocks -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Seeks over the next data block without reading its data.
+    fn skip_data_block(&mut self) -> Result<(), NfdumpError> {
+        self.release_data_block();
+        self.block_offset = self.reader.stream_position()?;
+        self.blocks_read += 1;
+
+        let mut db_buf = [0; 12];
+        let n = read_full(&mut self.reader, &mut db_buf)?;
+        if n < db_buf.len() {
+            self.truncated = true;
+            self.remaining_blocks = 0;
+            return Err(NfdumpError::UnexpectedEof {
+                context: ErrorContext {
+                    file_offset: Some(self.block_offset),
+                    block: Some(self.blocks_read - 1),
+                    expected: Some(db_buf.len()),
+                    available: Some(n),
+                    ..Default::default()
+                },
+            });
+        }
+        let size = u32::from_le_bytes([db_buf[4], db_buf[5], db_buf[6], db_buf[7]]);
+        self.reader.seek(SeekFrom::Current(size as i64))?;
+        self.stats.blocks_read = self.blocks_read;
+        Ok(())
+    }
+
+    fn read_data_block(&mut self) -> Result<(), NfdumpError> {
+        self.release_data_block();
+        self.block_offset = self.reader.stream_position()?;
+        self.blocks_read += 1;
+
+        let mut db_buf = vec![0; 12];
+        let n = read_full(&mut self.reader, &mut db_buf)?;
+        if n < db_buf.len() {
+            self.truncated = true;
+            self.remaining_blocks = 0;
+            return Err(NfdumpError::UnexpectedEof {
+                context: ErrorContext {
+                    file_offset: Some(self.block_offset),
+                    block: Some(self.blocks_read - 1),
+                    expected: Some(db_buf.len()),
+                    available: Some(n),
+                    ..Default::default()
+                },
+            });
+        }
+
+        let mut cursor = std::io::Cursor::new(db_buf);
+
+        let num_records = cursor.read_u32::<LittleEndian>()?;
+        let size = cursor.read_u32::<LittleEndian>()?;
+        let id = cursor.read_u16::<LittleEndian>()?;
+        let flags = BlockFlags::from_bits(cursor.read_u16::<LittleEndian>()?);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "data_block",
+            index = self.blocks_read - 1,
+            offset = self.block_offset,
+            num_records,
+            size,
+        )
+        .entered();
+
+        if let Some(hook) = self.hooks.on_block_start.as_mut() {
+            hook(&BlockInfo {
+                index: self.blocks_read - 1,
+                offset: self.block_offset,
+                num_records,
+                size,
+                id,
+                flags,
+            });
+        }
+
+        if size as usize > self.max_block_size {
+            // pass over the block, so the next read continues with the
+            // following one
+            self.reader.seek(SeekFrom::Current(size as i64))?;
+            self.remaining_blocks = self.remaining_blocks.saturating_sub(1);
+            self.stats.blocks_read = self.blocks_read;
+            return Err(NfdumpError::BlockTooLarge {
+                limit: self.max_block_size,
+                context: ErrorContext {
+                    file_offset: Some(self.block_offset),
+                    block: Some(self.blocks_read - 1),
+                    expected: Some(size as usize),
+                    ..Default::default()
+                },
+            });
+        }
+
+        let mut data = self.buffers.take();
+        data.resize(size as usize, 0);
+        let n = read_full(&mut self.reader, &mut data)?;
+        if let Some(io) = self.io_stats.as_mut() {
+            // block header and data
+            io.bytes_read += 12 + n as u64;
+            io.peak_block_size = io.peak_block_size.max(n);
+        }
+        if n < data.len() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(expected = data.len(), available = n, "truncated data block");
+            // keep what is there; stream codecs can still yield leading records
+            self.truncated = true;
+            self.truncation = Some(ErrorContext {
+                file_offset: Some(self.block_offset),
+                block: Some(self.blocks_read - 1),
+                expected: Some(data.len()),
+                available: Some(n),
+                ..Default::default()
+            });
+            data.truncate(n);
+        }
+
+        self.stats.blocks_read = self.blocks_read;
+        self.stats.records_expected += num_records as u64;
+
+        let decompressor = match NfFileReader::<R>::select_decompressor(
+            &self.header,
+            flags,
+            data,
+            &mut self.buffers,
+            self.max_block_size,
+        ) {
+            Ok(d) => d,
+            Err(_) if self.truncation.is_some() => {
+                self.remaining_blocks = 0;
+                let context = self.truncation.take().unwrap();
+                return Err(NfdumpError::UnexpectedEof { context });
+            }
+            Err(NfdumpError::BlockTooLarge { limit, .. }) => {
+                return Err(NfdumpError::BlockTooLarge {
+                    limit,
+                    context: ErrorContext {
+                        file_offset: Some(self.block_offset),
+                        block: Some(self.blocks_read - 1),
+                        ..Default::default()
+                    },
+                });
+            }
+            Err(e) => return Err(e),
+        };
+
+        let db_header = DataBlockHeader {
+            num_records,
+            size,
+            id,
+            flags,
+        };
+
+        self.data_block = Some(DataBlock::new(db_header, decompressor, &mut self.buffers, self.max_block_size));
+
+        Ok(())
+    }
+
+    fn select_decompressor(
+        header: &NfFileHeader,
+        flags: BlockFlags,
+        data: Vec<u8>,
+        pool: &mut BufferPool,
+        limit: usize,
+    ) -> Result<Decompressor, NfdumpError> {
+        let compression = flags.compression(header.compression()?);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("decompress", %compression, size = data.len()).entered();
+        Decompressor::new(compression, data, pool, limit)
+    }
+}
+
+impl NfFileReader<Cursor<Vec<u8>>> {
+    /// Creates a new `NfFileReader` over a file held in memory, e.g. one
+    /// received over the network.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use nfdump::NfFileReader;
+    ///
+    /// let bytes = std::fs::read("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::from_bytes(bytes).unwrap();
+    /// while let Ok(record) = reader.read_record() {
+    ///     println!("{:?}", record);
+    /// }
+    /// ```
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, NfdumpError> {
+        let mut reader = NfFileReader::new(Cursor::new(bytes))?;
+        // reads from memory gain nothing from buffering
+        reader.set_read_buffer_size(0)?;
+        Ok(reader)
+    }
+}
+
+impl<'a> NfFileReader<Cursor<&'a [u8]>> {
+    /// Creates a new `NfFileReader` over a file held in memory, borrowing
+    /// the bytes instead of taking ownership of them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use nfdump::NfFileReader;
+    ///
+    /// let bytes = std::fs::read("path_to_your_file").unwrap();
+    /// let mut reader = NfFileReader::from_slice(&bytes).unwrap();
+    /// println!("{} flows", reader.count_records().unwrap());
+    /// ```
+    pub fn from_slice(bytes: &'a [u8]) -> Result<Self, NfdumpError> {
+        let mut reader = NfFileReader::new(Cursor::new(bytes))?;
+        reader.set_read_buffer_size(0)?;
+        Ok(reader)
+    }
+}
+
+/// Returns the path of a sidecar of the nfdump file at `path`: the path with
+/// `.` and `extension` appended.
+pub(crate) fn sidecar_path<P: AsRef<Path>>(path: P, extension: &str) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Returns the modification time of a file in seconds since the epoch, 0 if
+/// unknown.
+pub(crate) fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Returns `true` if the nfdump file at `path` no longer has the size and
+/// modification time its sidecar was built for. A `modified` of 0 is not
+/// checked.
+pub(crate) fn sidecar_stale(path: &Path, file_size: u64, modified: u64) -> Result<bool, NfdumpError> {
+    let metadata = std::fs::metadata(path)?;
+    Ok(file_size != metadata.len() || (modified != 0 && modified != modified_secs(&metadata)))
+}
+
+/// Reads into `buf` until it is full or the reader is exhausted, returning the
+/// number of bytes read. Unlike `read_exact`, a short read is not an error.
+pub(crate) fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
         }
     }
+    Ok(n)
 }
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::error::NfdumpError;
+
+/// `MacAddr` is an Ethernet MAC address.
+///
+/// It is displayed as six colon separated, lowercase hex octets, like nfdump
+/// prints it, and parsed from octets separated by `:` or `-`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::mac::MacAddr;
+///
+/// let mac: MacAddr = "00-1B-21-3a-4c-5d".parse().unwrap();
+/// assert_eq!(mac.octets(), [0x00, 0x1b, 0x21, 0x3a, 0x4c, 0x5d]);
+/// assert_eq!(mac.to_string(), "00:1b:21:3a:4c:5d");
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// Creates a `MacAddr` from its six octets.
+    pub const fn new(octets: [u8; 6]) -> MacAddr {
+        MacAddr(octets)
+    }
+
+    /// Returns the six octets of the address.
+    pub const fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// Returns `true` for the all-zero address, which nfdump writes when the
+    /// exporter did not send the field.
+    pub fn is_unspecified(&self) -> bool {
+        self.0 == [0; 6]
+    }
+
+    /// Returns `true` for the broadcast address `ff:ff:ff:ff:ff:ff`.
+    pub fn is_broadcast(&self) -> bool {
+        self.0 == [0xff; 6]
+    }
+
+    /// Returns `true` if the group bit of the first octet is set.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Returns the address as the low 48 bits of a `u64`, first octet most
+    /// significant, the way nfdump stores it in a record.
+    pub(crate) fn to_u64(self) -> u64 {
+        self.0.iter().fold(0, |acc, &b| acc << 8 | b as u64)
+    }
+
+    /// Creates a `MacAddr` from the low 48 bits of `value`.
+    pub(crate) fn from_u64(value: u64) -> MacAddr {
+        let b = value.to_be_bytes();
+        MacAddr([b[2], b[3], b[4], b[5], b[6], b[7]])
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> MacAddr {
+        MacAddr(octets)
+    }
+}
+
+impl From<MacAddr> for [u8; 6] {
+    fn from(mac: MacAddr) -> [u8; 6] {
+        mac.0
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+impl fmt::Debug for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MacAddr({})", self)
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = NfdumpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut octets = [0; 6];
+        let mut parts = s.trim().split([':', '-']);
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or(NfdumpError::ParseError)?;
+            if part.is_empty() || part.len() > 2 {
+                return Err(NfdumpError::ParseError);
+            }
+            *octet = u8::from_str_radix(part, 16).map_err(|_| NfdumpError::ParseError)?;
+        }
+        if parts.next().is_some() {
+            return Err(NfdumpError::ParseError);
+        }
+        Ok(MacAddr(octets))
+    }
+}
+
+impl TryFrom<&str> for MacAddr {
+    type Error = NfdumpError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
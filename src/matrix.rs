@@ -0,0 +1,181 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fmt::Write;
+use std::net::IpAddr;
+use ipnet::IpNet;
+use crate::aggregate::{Counters, OrderBy};
+use crate::flow::FlowRecord;
+use crate::record::RecordKind;
+
+/// `Bucketing` selects how `TrafficMatrix` groups the sources and
+/// destinations of flows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucketing {
+    /// By autonomous system. Flows without AS information count for AS 0,
+    /// like in nfdump.
+    As,
+    /// By subnet, truncating IPv4 and IPv6 addresses to the given prefix
+    /// lengths.
+    Subnet { v4_prefix: u8, v6_prefix: u8 },
+}
+
+/// `Bucket` is a row or column of a `TrafficMatrix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Bucket {
+    As(u32),
+    Net(IpNet),
+}
+
+impl fmt::Display for Bucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bucket::As(asn) => write!(f, "AS{}", asn),
+            Bucket::Net(net) => write!(f, "{}", net),
+        }
+    }
+}
+
+/// `TrafficMatrix` accumulates flows, packets and bytes between pairs of
+/// source and destination buckets, for peering and capacity planning
+/// analysis.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::aggregate::OrderBy;
+/// use nfdump::matrix::{Bucketing, TrafficMatrix};
+///
+/// let mut matrix = TrafficMatrix::new(Bucketing::As);
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     matrix.add_kind(&record);
+/// }
+/// print!("{}", matrix.to_matrix_csv(OrderBy::Bytes));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrafficMatrix {
+    bucketing: Bucketing,
+    cells: HashMap<(Bucket, Bucket), Counters>,
+    totals: Counters,
+}
+
+impl TrafficMatrix {
+    /// Creates an empty matrix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a subnet prefix length exceeds the address length.
+    pub fn new(bucketing: Bucketing) -> TrafficMatrix {
+        if let Bucketing::Subnet { v4_prefix, v6_prefix } = bucketing {
+            assert!(v4_prefix <= 32 && v6_prefix <= 128, "invalid prefix length");
+        }
+        TrafficMatrix {
+            bucketing,
+            cells: HashMap::new(),
+            totals: Counters::default(),
+        }
+    }
+
+    /// Returns how flows are grouped.
+    pub fn bucketing(&self) -> Bucketing {
+        self.bucketing
+    }
+
+    /// Returns the bucket of an address or AS number.
+    fn bucket(&self, addr: Option<IpAddr>, asn: Option<u32>) -> Option<Bucket> {
+        match self.bucketing {
+            Bucketing::As => Some(Bucket::As(asn.unwrap_or(0))),
+            Bucketing::Subnet { v4_prefix, v6_prefix } => {
+                let addr = addr?;
+                let len = if addr.is_ipv4() { v4_prefix } else { v6_prefix };
+                IpNet::new(addr, len).ok().map(|net| Bucket::Net(net.trunc()))
+            }
+        }
+    }
+
+    /// Adds a flow record. Records without the addresses needed for subnet
+    /// buckets only count towards the totals.
+    pub fn add(&mut self, record: &dyn FlowRecord) {
+        self.totals.add(record);
+        let src = self.bucket(record.src_addr(), record.src_as());
+        let dst = self.bucket(record.dst_addr(), record.dst_as());
+        if let (Some(src), Some(dst)) = (src, dst) {
+            self.cells.entry((src, dst)).or_default().add(record);
+        }
+    }
+
+    /// Adds a flow record of either version; other records are ignored.
+    pub fn add_kind(&mut self, record: &RecordKind) {
+        match record {
+            RecordKind::Record(r) => self.add(r),
+            RecordKind::RecordV3(r) => self.add(r),
+            _ => {}
+        }
+    }
+
+    /// Returns the totals over all records added.
+    pub fn totals(&self) -> &Counters {
+        &self.totals
+    }
+
+    /// Returns the counters of the cell from `src` to `dst`.
+    pub fn get(&self, src: Bucket, dst: Bucket) -> Option<&Counters> {
+        self.cells.get(&(src, dst))
+    }
+
+    /// Returns the number of non-empty cells.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Returns `true` if no cell has traffic.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Returns the non-empty cells ordered by source and destination.
+    pub fn cells(&self) -> Vec<(Bucket, Bucket, Counters)> {
+        let mut cells: Vec<_> = self.cells.iter().map(|(&(s, d), c)| (s, d, *c)).collect();
+        cells.sort_unstable_by_key(|c| (c.0, c.1));
+        cells
+    }
+
+    /// Returns every bucket seen as a source or destination, in order. These
+    /// are the rows and columns of the N×N matrix.
+    pub fn buckets(&self) -> Vec<Bucket> {
+        let set: BTreeSet<Bucket> = self.cells.keys().flat_map(|&(s, d)| [s, d]).collect();
+        set.into_iter().collect()
+    }
+
+    /// Renders the non-empty cells as CSV, one line per source and
+    /// destination pair.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("src,dst,flows,packets,bytes\n");
+        for (src, dst, c) in self.cells() {
+            _ = writeln!(out, "{},{},{},{},{}", src, dst, c.flows, c.packets, c.bytes);
+        }
+        out
+    }
+
+    /// Renders the N×N matrix of one counter as CSV, with sources as rows
+    /// and destinations as columns. Empty cells are 0.
+    pub fn to_matrix_csv(&self, counter: OrderBy) -> String {
+        let buckets = self.buckets();
+        let mut out = String::from("src\\dst");
+        for b in &buckets {
+            _ = write!(out, ",{}", b);
+        }
+        out.push('\n');
+        for src in &buckets {
+            _ = write!(out, "{}", src);
+            for dst in &buckets {
+                let value = self.get(*src, *dst).map_or(0, |c| counter.value(c));
+                _ = write!(out, ",{}", value);
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
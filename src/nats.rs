@@ -0,0 +1,288 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+use crate::output::{json_string, Formatter, OutputFormat, OutputOptions};
+use crate::record::RecordKind;
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `NatsPublisher` streams flow records to NATS JetStream.
+///
+/// Records are rendered with a `Formatter`, JSON by default, and published
+/// to a subject built from a template, in which `{exporter}` is replaced by
+/// the exporter `sysid` and `{proto}` by the protocol number. Records are
+/// buffered and sent in batches of `batch_size`; a batch is complete when
+/// JetStream has acknowledged every message of it, so a stream must be
+/// configured for the subjects.
+///
+/// The connection is opened on the first flush. It speaks the plain text
+/// NATS protocol; TLS is not supported.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::nats::NatsPublisher;
+///
+/// let mut publisher = NatsPublisher::new("localhost:4222", "flows.{exporter}");
+/// publisher.set_batch_size(500);
+///
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     publisher.publish_kind(&record).unwrap();
+/// }
+/// publisher.flush().unwrap();
+/// ```
+pub struct NatsPublisher {
+    addr: String,
+    subject: String,
+    formatter: Formatter,
+    filter: Option<Box<dyn Filter + Send + Sync>>,
+    batch_size: usize,
+    ack_timeout: Duration,
+    user: Option<(String, String)>,
+    token: Option<String>,
+    conn: Option<Connection>,
+    batch: Vec<(String, String)>,
+    published: u64,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    inbox: String,
+}
+
+impl NatsPublisher {
+    /// Creates a publisher for the NATS server at `addr`, e.g.
+    /// `localhost:4222`, publishing to the subject template `subject`.
+    pub fn new(addr: &str, subject: &str) -> NatsPublisher {
+        NatsPublisher {
+            addr: addr.to_string(),
+            subject: subject.to_string(),
+            formatter: Formatter::new(OutputFormat::Json, OutputOptions::new()),
+            filter: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            user: None,
+            token: None,
+            conn: None,
+            batch: Vec::new(),
+            published: 0,
+        }
+    }
+
+    /// Sets the formatter the message payloads are rendered with.
+    pub fn set_formatter(&mut self, formatter: Formatter) {
+        self.formatter = formatter;
+    }
+
+    /// Only publishes records selected by `filter`.
+    pub fn set_filter(&mut self, filter: Box<dyn Filter + Send + Sync>) {
+        self.filter = Some(filter);
+    }
+
+    /// Sets the number of records sent per batch, 100 by default.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.max(1);
+    }
+
+    /// Sets how long to wait for the acknowledgements of a batch, 5 seconds
+    /// by default.
+    pub fn set_ack_timeout(&mut self, timeout: Duration) {
+        self.ack_timeout = timeout;
+    }
+
+    /// Authenticates with a user name and password.
+    pub fn set_user_password(&mut self, user: &str, password: &str) {
+        self.user = Some((user.to_string(), password.to_string()));
+    }
+
+    /// Authenticates with a token.
+    pub fn set_token(&mut self, token: &str) {
+        self.token = Some(token.to_string());
+    }
+
+    /// Returns the number of records acknowledged by JetStream so far.
+    pub fn published(&self) -> u64 {
+        self.published
+    }
+
+    /// Adds a record to the batch, sending the batch when it is full.
+    /// Records not selected by the filter are skipped.
+    pub fn publish(&mut self, record: &dyn FlowRecord) -> Result<(), NfdumpError> {
+        if let Some(filter) = &self.filter {
+            if !filter.matches(record) {
+                return Ok(());
+            }
+        }
+        let subject = self
+            .subject
+            .replace("{exporter}", &record.exporter_sysid().unwrap_or(0).to_string())
+            .replace("{proto}", &record.proto().unwrap_or(0).to_string());
+        self.batch.push((subject, self.formatter.format(record)));
+        if self.batch.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Adds a flow record to the batch; other kinds of records are ignored.
+    pub fn publish_kind(&mut self, record: &RecordKind) -> Result<(), NfdumpError> {
+        match record.flow_record() {
+            Some(r) => self.publish(r),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends the buffered records and waits for JetStream to acknowledge
+    /// them.
+    ///
+    /// On error the batch is dropped and the connection closed; it is
+    /// reopened by the next flush.
+    pub fn flush(&mut self) -> Result<(), NfdumpError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::take(&mut self.batch);
+        let result = self.send(&batch);
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    fn send(&mut self, batch: &[(String, String)]) -> Result<(), NfdumpError> {
+        if self.conn.is_none() {
+            self.conn = Some(self.connect()?);
+        }
+        let conn = self.conn.as_mut().unwrap();
+
+        let mut out = Vec::new();
+        for (i, (subject, payload)) in batch.iter().enumerate() {
+            write!(out, "PUB {} {}.{} {}\r\n", subject, conn.inbox, i, payload.len())?;
+            out.extend_from_slice(payload.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+        conn.writer.write_all(&out)?;
+
+        let mut acked = 0;
+        while acked < batch.len() {
+            let ack = conn.read_message()?;
+            if let Some(err) = ack_error(&ack) {
+                return Err(io::Error::other(format!("JetStream publish failed: {}", err)).into());
+            }
+            acked += 1;
+        }
+        self.published += acked as u64;
+        Ok(())
+    }
+
+    fn connect(&self) -> Result<Connection, NfdumpError> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(self.ack_timeout))?;
+        let mut conn = Connection {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            inbox: inbox(),
+        };
+
+        let info = conn.read_line()?;
+        if !info.starts_with("INFO ") {
+            return Err(io::Error::other(format!("unexpected greeting: {}", info)).into());
+        }
+
+        let mut options = format!(
+            "{{\"verbose\":false,\"pedantic\":false,\"headers\":true,\"no_responders\":true,\"name\":\"nfdump\",\"lang\":\"rust\",\"version\":{}",
+            json_string(env!("CARGO_PKG_VERSION"))
+        );
+        if let Some((user, password)) = &self.user {
+            options.push_str(&format!(",\"user\":{},\"pass\":{}", json_string(user), json_string(password)));
+        }
+        if let Some(token) = &self.token {
+            options.push_str(&format!(",\"auth_token\":{}", json_string(token)));
+        }
+        options.push('}');
+        write!(conn.writer, "CONNECT {}\r\nSUB {}.* 1\r\nPING\r\n", options, conn.inbox)?;
+
+        loop {
+            match conn.read_line()?.as_str() {
+                "PONG" => return Ok(conn),
+                "+OK" => {}
+                line => return Err(io::Error::other(format!("connect failed: {}", line)).into()),
+            }
+        }
+    }
+}
+
+impl Connection {
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    fn read_payload(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut payload = vec![0; len + 2];
+        io::Read::read_exact(&mut self.reader, &mut payload)?;
+        payload.truncate(len);
+        Ok(payload)
+    }
+
+    // Reads until the next message on the inbox, answering pings. Returns
+    // the headers, if any, followed by the payload.
+    fn read_message(&mut self) -> io::Result<String> {
+        loop {
+            let line = self.read_line()?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let len = match fields.as_slice() {
+                ["PING"] => {
+                    self.writer.write_all(b"PONG\r\n")?;
+                    continue;
+                }
+                ["+OK"] | ["PONG"] => continue,
+                ["INFO", ..] => continue,
+                ["MSG", .., len] | ["HMSG", .., len] => len.parse().map_err(|_| bad_line(&line))?,
+                _ if line.starts_with("-ERR") => return Err(io::Error::other(line)),
+                _ => return Err(bad_line(&line)),
+            };
+            let payload = self.read_payload(len)?;
+            return Ok(String::from_utf8_lossy(&payload).into_owned());
+        }
+    }
+}
+
+fn bad_line(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected protocol line: {}", line))
+}
+
+// A unique inbox prefix for the acknowledgements of one connection
+fn inbox() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("_INBOX.nfdump{:x}{:x}", std::process::id(), nanos)
+}
+
+// Returns the error of an acknowledgement: a status header like
+// `NATS/1.0 503` for no responders, or an `"error"` member in the JSON
+fn ack_error(ack: &str) -> Option<String> {
+    if let Some(status) = ack.strip_prefix("NATS/1.0") {
+        let status = status.lines().next().unwrap_or("").trim();
+        if status.starts_with("503") {
+            return Some("no stream for subject".to_string());
+        }
+        if !status.is_empty() {
+            return Some(status.to_string());
+        }
+    }
+    let start = ack.find("\"error\"")?;
+    let rest = &ack[start..];
+    let end = rest.find('}').map(|i| i + 1).unwrap_or(rest.len());
+    Some(rest[..end].to_string())
+}
@@ -10,7 +10,7 @@ pub struct NfFileHeaderV1 {
 }
 
 /// `StatRecordV1` represents a stat record.
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct StatRecordV1 {
     pub flows: u64,
     pub bytes: u64,
@@ -1,4 +1,7 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::block::TYPE_STAT;
+use crate::flow::FlowRecord;
+use crate::nffilev1::StatRecordV1;
 
 pub struct NfFileHeaderV2 {
     pub magic: u16,
@@ -15,7 +18,7 @@ pub struct NfFileHeaderV2 {
     pub ident: Vec<u8>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct StatRecordV2 {
     pub flows: u64,
     pub bytes: u64,
@@ -58,8 +61,15 @@ impl From<Vec<u8>> for NfFileHeaderV2 {
     }
 }
 
+// body size of the stat record written by nfdump 1.7 and later, with all
+// counters and millisecond timestamps as u64
+const STAT_RECORD_SIZE: usize = 144;
+
 impl From<Vec<u8>> for StatRecordV2 {
-    fn from(value: Vec<u8>) -> StatRecordV2 {
+    /// Parses a stat record body. A record shorter than the current layout
+    /// leaves the missing counters at zero.
+    fn from(mut value: Vec<u8>) -> StatRecordV2 {
+        value.resize(value.len().max(STAT_RECORD_SIZE), 0);
         let mut cursor = std::io::Cursor::new(&value);
 
         StatRecordV2 {
@@ -83,4 +93,77 @@ impl From<Vec<u8>> for StatRecordV2 {
             sequence_failure: cursor.read_u64::<LittleEndian>().unwrap(),
         }
     }
-}
\ No newline at end of file
+}
+impl From<&StatRecordV1> for StatRecordV2 {
+    fn from(value: &StatRecordV1) -> StatRecordV2 {
+        StatRecordV2 {
+            flows: value.flows,
+            bytes: value.bytes,
+            packets: value.packets,
+            flows_tcp: value.flows_tcp,
+            flows_udp: value.flows_udp,
+            flows_icmp: value.flows_icmp,
+            flows_other: value.flows_other,
+            bytes_tcp: value.bytes_tcp,
+            bytes_udp: value.bytes_udp,
+            bytes_icmp: value.bytes_icmp,
+            bytes_other: value.bytes_other,
+            packets_tcp: value.packets_tcp,
+            packets_udp: value.packets_udp,
+            packets_icmp: value.packets_icmp,
+            packets_other: value.packets_other,
+            // v2 keeps the first and last timestamps in milliseconds
+            first_seen: value.first_seen as u64 * 1000 + value.msec_first as u64,
+            last_seen: value.last_seen as u64 * 1000 + value.msec_last as u64,
+            sequence_failure: value.sequence_failure as u64,
+        }
+    }
+}
+
+impl StatRecordV2 {
+    /// Adds a flow record to the counters.
+    pub(crate) fn add_record(&mut self, record: &dyn FlowRecord) {
+        let flows = record.flows();
+        let packets = record.packets().unwrap_or(0);
+        let bytes = record.bytes().unwrap_or(0);
+
+        self.flows += flows;
+        self.packets += packets;
+        self.bytes += bytes;
+        let (f, p, b) = match record.proto() {
+            Some(6) => (&mut self.flows_tcp, &mut self.packets_tcp, &mut self.bytes_tcp),
+            Some(17) => (&mut self.flows_udp, &mut self.packets_udp, &mut self.bytes_udp),
+            Some(1) | Some(58) => (&mut self.flows_icmp, &mut self.packets_icmp, &mut self.bytes_icmp),
+            _ => (&mut self.flows_other, &mut self.packets_other, &mut self.bytes_other),
+        };
+        *f += flows;
+        *p += packets;
+        *b += bytes;
+
+        if let (Some(first), Some(last)) = (record.first_msec(), record.last_msec()) {
+            if first != 0 && (self.first_seen == 0 || first < self.first_seen) {
+                self.first_seen = first;
+            }
+            self.last_seen = self.last_seen.max(last);
+        }
+    }
+
+    /// Serializes the stat record as an appendix record, including its header.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let fields = [
+            self.flows, self.bytes, self.packets,
+            self.flows_tcp, self.flows_udp, self.flows_icmp, self.flows_other,
+            self.bytes_tcp, self.bytes_udp, self.bytes_icmp, self.bytes_other,
+            self.packets_tcp, self.packets_udp, self.packets_icmp, self.packets_other,
+            self.first_seen, self.last_seen, self.sequence_failure,
+        ];
+
+        let mut buf = Vec::with_capacity(4 + fields.len() * 8);
+        buf.write_u16::<LittleEndian>(TYPE_STAT).unwrap();
+        buf.write_u16::<LittleEndian>((4 + fields.len() * 8) as u16).unwrap();
+        for f in fields {
+            buf.write_u64::<LittleEndian>(f).unwrap();
+        }
+        buf
+    }
+}
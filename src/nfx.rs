@@ -1,4 +1,5 @@
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::block::TYPE_EXTENSION_MAP;
 use crate::error::NfdumpError;
 use crate::record::NfFileRecordHeader;
 
@@ -33,3 +34,29 @@ pub fn read_extension_map(
         ex_id,
     })
 }
+
+/// Returns the size of an extension's data within a common record.
+pub(crate) fn extension_size(id: u16) -> u16 {
+    match id {
+        // input/output interfaces and AS numbers, 2 or 4 bytes each
+        4 | 6 => 4,
+        5 | 7 => 8,
+        _ => 0,
+    }
+}
+
+/// Serializes an extension map record, including its header.
+pub(crate) fn extension_map_bytes(map_id: u16, ex_id: &[u16]) -> Vec<u8> {
+    // the id list is terminated by 0 and padded to 4 bytes
+    let size = (8 + 2 * (ex_id.len() + 1)).next_multiple_of(4);
+    let mut data = Vec::with_capacity(size);
+    data.write_u16::<LittleEndian>(TYPE_EXTENSION_MAP).unwrap();
+    data.write_u16::<LittleEndian>(size as u16).unwrap();
+    data.write_u16::<LittleEndian>(map_id).unwrap();
+    data.write_u16::<LittleEndian>(ex_id.iter().map(|&id| extension_size(id)).sum()).unwrap();
+    for &id in ex_id {
+        data.write_u16::<LittleEndian>(id).unwrap();
+    }
+    data.resize(size, 0);
+    data
+}
@@ -2,42 +2,48 @@
 // except some of the extensions which are not implemented yet
 #![allow(dead_code)]
 
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 use std::net::{Ipv4Addr, Ipv6Addr};
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::block::TYPE_RECORD_V3;
+use crate::engine::Engine;
 use crate::error::NfdumpError;
+use crate::mac::MacAddr;
+use crate::projection::Projection;
 use crate::record::NfFileRecordHeader;
 
-use eui48::MacAddress;
-
-const EXT_NULL: u16 = 0x0;
-const EXT_GENERIC_FLOW: u16 = 0x1;
-const EXT_IPV4_FLOW: u16 = 0x2;
-const EXT_IPV6_FLOW: u16 = 0x3;
-const EXT_FLOW_MISC: u16 = 0x4;
-const EXT_CNT_FLOW: u16 = 0x5;
-const EXT_VLAN_FLOW: u16 = 0x6;
-const EXT_AS_ROUTING: u16 = 0x7;
-const EXT_BGP_NEXT_HOP_V4: u16 = 0x8;
-const EXT_BGP_NEXT_HOP_V6: u16 = 0x9;
-const EXT_IP_NEXT_HOP_V4: u16 = 0xa;
-const EXT_IP_NEXT_HOP_V6: u16 = 0xb;
-const EXT_IP_RECEIVED_V4: u16 = 0xc;
-const EXT_IP_RECEIVED_V6: u16 = 0xd;
-const EXT_SAMPLER_INFO: u16 = 0x12;
-const EXT_IN_PAYLOAD: u16 = 0x1d;
-const EXT_NSEL_X_LATE_IPV4: u16 = 0x14;
-const EXT_NSEL_X_LATE_IPV6: u16 = 0x15;
-const EXT_NSEL_X_LATE_PORT: u16 = 0x16;
-
-const EXT_MAC_ADDR: u16 = 0xf;
-const EXT_LAYER2: u16 = 0x26;
-const EXT_MPLS: u16 = 0xe;
-const EXT_TUN_V4: u16 = 0x1f;
-const EXT_TUN_V6: u16 = 0x20;
-
-
-#[derive(Debug)]
+pub(crate) const EXT_NULL: u16 = 0x0;
+pub(crate) const EXT_GENERIC_FLOW: u16 = 0x1;
+pub(crate) const EXT_IPV4_FLOW: u16 = 0x2;
+pub(crate) const EXT_IPV6_FLOW: u16 = 0x3;
+pub(crate) const EXT_FLOW_MISC: u16 = 0x4;
+pub(crate) const EXT_CNT_FLOW: u16 = 0x5;
+pub(crate) const EXT_VLAN_FLOW: u16 = 0x6;
+pub(crate) const EXT_AS_ROUTING: u16 = 0x7;
+pub(crate) const EXT_BGP_NEXT_HOP_V4: u16 = 0x8;
+pub(crate) const EXT_BGP_NEXT_HOP_V6: u16 = 0x9;
+pub(crate) const EXT_IP_NEXT_HOP_V4: u16 = 0xa;
+pub(crate) const EXT_IP_NEXT_HOP_V6: u16 = 0xb;
+pub(crate) const EXT_IP_RECEIVED_V4: u16 = 0xc;
+pub(crate) const EXT_IP_RECEIVED_V6: u16 = 0xd;
+pub(crate) const EXT_SAMPLER_INFO: u16 = 0x12;
+pub(crate) const EXT_IN_PAYLOAD: u16 = 0x1d;
+pub(crate) const EXT_NSEL_X_LATE_IPV4: u16 = 0x14;
+pub(crate) const EXT_NSEL_X_LATE_IPV6: u16 = 0x15;
+pub(crate) const EXT_NSEL_X_LATE_PORT: u16 = 0x16;
+
+pub(crate) const EXT_MAC_ADDR: u16 = 0xf;
+pub(crate) const EXT_LAYER2: u16 = 0x26;
+pub(crate) const EXT_MPLS: u16 = 0xe;
+pub(crate) const EXT_TUN_V4: u16 = 0x1f;
+pub(crate) const EXT_TUN_V6: u16 = 0x20;
+
+// record header plus the V3 header fields
+const RECORD_V3_HEADER_SIZE: usize = 12;
+
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct RecordHeaderV3 {
     pub header: NfFileRecordHeader,
     pub num_elements: u16,
@@ -48,7 +54,15 @@ pub struct RecordHeaderV3 {
     pub nf_version: u8,
 }
 
+impl RecordHeaderV3 {
+    /// Returns the flow switching engine the record was exported by.
+    pub fn engine(&self) -> Engine {
+        Engine::new(self.engine_type, self.engine_id)
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExGenericFlow {
     pub msec_first: u64,
     pub msec_last: u64,
@@ -64,18 +78,21 @@ pub struct ExGenericFlow {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExIpv4Flow {
     pub src_addr: Ipv4Addr,
     pub dst_addr: Ipv4Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExIpv6Flow {
     pub src_addr: Ipv6Addr,
     pub dst_addr: Ipv6Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExFlowMisc {
     pub input: u32,
     pub output: u32,
@@ -90,6 +107,7 @@ pub struct ExFlowMisc {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExCntFlow {
     pub flows: u64,
     pub out_packets: u64,
@@ -97,18 +115,21 @@ pub struct ExCntFlow {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExVlan {
     pub src_vlan: u32,
     pub dst_vlan: u32,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExAsRouting {
     pub src_as: u32,
     pub dst_as: u32,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExSamplerInfo {
     pub selector_id: u64,
     pub sysid: u16,
@@ -116,57 +137,76 @@ pub struct ExSamplerInfo {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExNselXLatePort {
     pub src_port: u16,
     pub dst_port: u16,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExBgpNextHopIpv4 {
     pub ip: Ipv4Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExBgpNextHopIpv6 {
     pub ip: Ipv6Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExIpNextHopIpv4 {
     pub ip: Ipv4Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExIpNextHopIpv6 {
     pub ip: Ipv6Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExIpReceivedIpv4 {
     pub ip: Ipv4Addr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExIpReceivedIpv6 {
     pub ip: Ipv6Addr,
 }
 
 pub type ExInPayload = Vec<u8>;
 
-pub type ExNselXLateIpv4 = [u32; 10];
+#[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ExNselXLateIpv4 {
+    pub src_addr: Ipv4Addr,
+    pub dst_addr: Ipv4Addr,
+}
 
-pub type ExNselXLateIpv6 = [u128; 10];
+#[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ExNselXLateIpv6 {
+    pub src_addr: Ipv6Addr,
+    pub dst_addr: Ipv6Addr,
+}
 
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExMacAddress {
-    pub in_src_mac: MacAddress,
-    pub out_dst_mac: MacAddress,
-    pub in_dst_mac: MacAddress,
-    pub out_src_mac: MacAddress,
+    pub in_src_mac: MacAddr,
+    pub out_dst_mac: MacAddr,
+    pub in_dst_mac: MacAddr,
+    pub out_src_mac: MacAddr,
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExLayer2 {
     pub vlan_id: u16,
     pub customer_vlan_id: u16,
@@ -182,6 +222,7 @@ pub struct ExLayer2 {
 
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExMPLS {
     pub mpls_label_1:  u32,
     pub mpls_label_2:  u32,
@@ -197,6 +238,7 @@ pub struct ExMPLS {
 
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExTunIpv4 {
     pub src_addr: Ipv4Addr,
     pub dst_addr: Ipv4Addr,
@@ -205,6 +247,7 @@ pub struct ExTunIpv4 {
 
 
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct ExTunIpv6 {
     pub src_addr: Ipv6Addr,
     pub dst_addr: Ipv6Addr,
@@ -213,7 +256,8 @@ pub struct ExTunIpv6 {
 
 
 /// `Record` represents a flow record.
-#[derive(Debug)]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct RecordV3 {
     pub head: RecordHeaderV3,
     pub generic_flow: Option<ExGenericFlow>,
@@ -225,6 +269,8 @@ pub struct RecordV3 {
     pub as_routing: Option<ExAsRouting>,
     pub sampler_info: Option<ExSamplerInfo>,
     pub nsel_xlate_port: Option<ExNselXLatePort>,
+    pub nsel_xlate_ipv4: Option<ExNselXLateIpv4>,
+    pub nsel_xlate_ipv6: Option<ExNselXLateIpv6>,
     pub bgp_next_hop_ipv4: Option<ExBgpNextHopIpv4>,
     pub bgp_next_hop_ipv6: Option<ExBgpNextHopIpv6>,
     pub ip_next_hop_ipv4: Option<ExIpNextHopIpv4>,
@@ -236,12 +282,28 @@ pub struct RecordV3 {
     pub layer2: Option<ExLayer2>,
     pub mpls: Option<ExMPLS>,
     pub tun_ipv4: Option<ExTunIpv4>,
-    pub tun_ipv6: Option<ExTunIpv6>
+    pub tun_ipv6: Option<ExTunIpv6>,
+    /// Elements this crate does not decode, as extension id and element
+    /// data without the element header, in file order. `to_bytes` writes
+    /// them back unchanged.
+    pub unknown: Vec<(u16, Vec<u8>)>,
 }
 
 impl RecordV3 {
     pub fn new(header: NfFileRecordHeader, data: Vec<u8>) -> Result<RecordV3, NfdumpError> {
-        let mut cursor = Cursor::new(&data);
+        RecordV3::from_slice(header, &data)
+    }
+
+    /// Decodes a record from its body, borrowing element data from `data`
+    /// instead of copying it.
+    pub fn from_slice(header: NfFileRecordHeader, data: &[u8]) -> Result<RecordV3, NfdumpError> {
+        RecordV3::from_slice_projected(header, data, &Projection::all())
+    }
+
+    /// Decodes a record from its body like `from_slice`, skipping the
+    /// elements outside of `projection`.
+    pub(crate) fn from_slice_projected(header: NfFileRecordHeader, data: &[u8], projection: &Projection) -> Result<RecordV3, NfdumpError> {
+        let mut cursor = Cursor::new(data);
 
         let v3_header = RecordHeaderV3 {
             header,
@@ -264,6 +326,8 @@ impl RecordV3 {
             as_routing: None,
             sampler_info: None,
             nsel_xlate_port: None,
+            nsel_xlate_ipv4: None,
+            nsel_xlate_ipv6: None,
             bgp_next_hop_ipv4: None,
             bgp_next_hop_ipv6: None,
             ip_next_hop_ipv4: None,
@@ -275,7 +339,8 @@ impl RecordV3 {
             layer2: None,
             mpls: None,
             tun_ipv4: None,
-            tun_ipv6: None
+            tun_ipv6: None,
+            unknown: Vec::new(),
         };
 
         let mut cnt = 0;
@@ -286,10 +351,17 @@ impl RecordV3 {
             let ext = cursor.read_u16::<LittleEndian>()?;
             let size = cursor.read_u16::<LittleEndian>()? as usize;
 
-            // Read extension data into a separate buffer
-            let mut ext_data = vec![0; size - 4];
-            cursor.read_exact(&mut ext_data)?;
-            let mut ext_cursor = Cursor::new(&ext_data);
+            // element data, without the element header
+            let start = cursor.position() as usize;
+            let ext_data = size
+                .checked_sub(4)
+                .and_then(|len| data.get(start..start + len))
+                .ok_or(NfdumpError::ParseError)?;
+            cursor.set_position((start + ext_data.len()) as u64);
+            if !projection.contains_id(ext) {
+                continue;
+            }
+            let mut ext_cursor = Cursor::new(ext_data);
 
             match ext {
                 EXT_GENERIC_FLOW => {
@@ -365,6 +437,18 @@ impl RecordV3 {
                         dst_port: ext_cursor.read_u16::<LittleEndian>()?,
                     });
                 }
+                EXT_NSEL_X_LATE_IPV4 => {
+                    record.nsel_xlate_ipv4 = Some(ExNselXLateIpv4 {
+                        src_addr: Ipv4Addr::from(ext_cursor.read_u32::<LittleEndian>()?),
+                        dst_addr: Ipv4Addr::from(ext_cursor.read_u32::<LittleEndian>()?),
+                    });
+                }
+                EXT_NSEL_X_LATE_IPV6 => {
+                    record.nsel_xlate_ipv6 = Some(ExNselXLateIpv6 {
+                        src_addr: Ipv6Addr::from(ext_cursor.read_u128::<LittleEndian>()?),
+                        dst_addr: Ipv6Addr::from(ext_cursor.read_u128::<LittleEndian>()?),
+                    });
+                }
                 EXT_BGP_NEXT_HOP_V4 => {
                     record.bgp_next_hop_ipv4 = Some(ExBgpNextHopIpv4 {
                         ip: Ipv4Addr::from(ext_cursor.read_u32::<LittleEndian>()?),
@@ -396,16 +480,14 @@ impl RecordV3 {
                     });
                 }
                 EXT_IN_PAYLOAD => {
-                    let mut payload = vec![0; record.head.header.size as usize - 4];
-                    ext_cursor.read_exact(&mut payload)?;
-                    record.in_payload = Some(payload);
+                    record.in_payload = Some(ext_data.to_vec());
                 }
                 EXT_MAC_ADDR => {
                     record.mac_address = Some(ExMacAddress {
-                        in_src_mac: _mac_from_u64(ext_cursor.read_u64::<LittleEndian>()?),
-                        out_dst_mac: _mac_from_u64(ext_cursor.read_u64::<LittleEndian>()?),
-                        in_dst_mac: _mac_from_u64(ext_cursor.read_u64::<LittleEndian>()?),
-                        out_src_mac: _mac_from_u64(ext_cursor.read_u64::<LittleEndian>()?),
+                        in_src_mac: MacAddr::from_u64(ext_cursor.read_u64::<LittleEndian>()?),
+                        out_dst_mac: MacAddr::from_u64(ext_cursor.read_u64::<LittleEndian>()?),
+                        in_dst_mac: MacAddr::from_u64(ext_cursor.read_u64::<LittleEndian>()?),
+                        out_src_mac: MacAddr::from_u64(ext_cursor.read_u64::<LittleEndian>()?),
                     });
                 }
                 EXT_LAYER2 => {
@@ -450,25 +532,200 @@ impl RecordV3 {
                         proto: ext_cursor.read_u8()?,
                     });
                 }
-                _ => {}
+                _ => record.unknown.push((ext, ext_data.to_vec())),
             }
 
         }
 
-        return Ok(record);
+        Ok(record)
     }
-}
 
+    /// Serializes the record including its record header, recomputing the
+    /// element count and record size from the extensions present.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, NfdumpError> {
+        let mut elements: Vec<u8> = Vec::new();
+        let mut num_elements: u16 = 0;
+
+        let mut element = |id: u16, data: Vec<u8>| -> Result<(), NfdumpError> {
+            let size = u16::try_from(data.len() + 4).map_err(|_| NfdumpError::ParseError)?;
+            elements.write_u16::<LittleEndian>(id)?;
+            elements.write_u16::<LittleEndian>(size)?;
+            elements.extend_from_slice(&data);
+            num_elements += 1;
+            Ok(())
+        };
 
-fn _mac_from_u64(value: u64) -> MacAddress {
-    let bytes = [
-        (value >> 40 & 0xFF) as u8,
-        (value >> 32 & 0xFF) as u8,
-        (value >> 24 & 0xFF) as u8,
-        (value >> 16 & 0xFF) as u8,
-        (value >> 8  & 0xFF) as u8,
-        (value       & 0xFF) as u8,
-    ];
+        if let Some(e) = &self.generic_flow {
+            let mut d = Vec::with_capacity(48);
+            d.write_u64::<LittleEndian>(e.msec_first)?;
+            d.write_u64::<LittleEndian>(e.msec_last)?;
+            d.write_u64::<LittleEndian>(e.msec_received)?;
+            d.write_u64::<LittleEndian>(e.in_packets)?;
+            d.write_u64::<LittleEndian>(e.in_bytes)?;
+            d.write_u16::<LittleEndian>(e.src_port)?;
+            d.write_u16::<LittleEndian>(e.dst_port)?;
+            d.write_u8(e.proto)?;
+            d.write_u8(e.tcp_flags)?;
+            d.write_u8(e.fwd_status)?;
+            d.write_u8(e.src_tos)?;
+            element(EXT_GENERIC_FLOW, d)?;
+        }
+        if let Some(e) = &self.ipv4_flow {
+            let mut d = Vec::with_capacity(8);
+            d.write_u32::<LittleEndian>(u32::from(e.src_addr))?;
+            d.write_u32::<LittleEndian>(u32::from(e.dst_addr))?;
+            element(EXT_IPV4_FLOW, d)?;
+        }
+        if let Some(e) = &self.ipv6_flow {
+            let mut d = Vec::with_capacity(32);
+            d.write_u128::<LittleEndian>(u128::from(e.src_addr))?;
+            d.write_u128::<LittleEndian>(u128::from(e.dst_addr))?;
+            element(EXT_IPV6_FLOW, d)?;
+        }
+        if let Some(e) = &self.flow_misc {
+            let mut d = Vec::with_capacity(16);
+            d.write_u32::<LittleEndian>(e.input)?;
+            d.write_u32::<LittleEndian>(e.output)?;
+            d.write_u8(e.src_mask)?;
+            d.write_u8(e.dst_mask)?;
+            d.write_u8(e.dir)?;
+            d.write_u8(e.dst_tos)?;
+            d.write_u8(e.bi_flow_dir)?;
+            d.write_u8(e.flow_end_reason)?;
+            d.write_u8(e.rev_tcp_flags)?;
+            d.write_u8(e.fill)?;
+            element(EXT_FLOW_MISC, d)?;
+        }
+        if let Some(e) = &self.cnt_flow {
+            let mut d = Vec::with_capacity(24);
+            d.write_u64::<LittleEndian>(e.flows)?;
+            d.write_u64::<LittleEndian>(e.out_packets)?;
+            d.write_u64::<LittleEndian>(e.out_bytes)?;
+            element(EXT_CNT_FLOW, d)?;
+        }
+        if let Some(e) = &self.vlan {
+            let mut d = Vec::with_capacity(8);
+            d.write_u32::<LittleEndian>(e.src_vlan)?;
+            d.write_u32::<LittleEndian>(e.dst_vlan)?;
+            element(EXT_VLAN_FLOW, d)?;
+        }
+        if let Some(e) = &self.as_routing {
+            let mut d = Vec::with_capacity(8);
+            d.write_u32::<LittleEndian>(e.src_as)?;
+            d.write_u32::<LittleEndian>(e.dst_as)?;
+            element(EXT_AS_ROUTING, d)?;
+        }
+        if let Some(e) = &self.bgp_next_hop_ipv4 {
+            element(EXT_BGP_NEXT_HOP_V4, u32::from(e.ip).to_le_bytes().to_vec())?;
+        }
+        if let Some(e) = &self.bgp_next_hop_ipv6 {
+            element(EXT_BGP_NEXT_HOP_V6, u128::from(e.ip).to_le_bytes().to_vec())?;
+        }
+        if let Some(e) = &self.ip_next_hop_ipv4 {
+            element(EXT_IP_NEXT_HOP_V4, u32::from(e.ip).to_le_bytes().to_vec())?;
+        }
+        if let Some(e) = &self.ip_next_hop_ipv6 {
+            element(EXT_IP_NEXT_HOP_V6, u128::from(e.ip).to_le_bytes().to_vec())?;
+        }
+        if let Some(e) = &self.ip_received_ipv4 {
+            element(EXT_IP_RECEIVED_V4, u32::from(e.ip).to_le_bytes().to_vec())?;
+        }
+        if let Some(e) = &self.ip_received_ipv6 {
+            element(EXT_IP_RECEIVED_V6, u128::from(e.ip).to_le_bytes().to_vec())?;
+        }
+        if let Some(e) = &self.mpls {
+            let mut d = Vec::with_capacity(40);
+            for label in [
+                e.mpls_label_1, e.mpls_label_2, e.mpls_label_3, e.mpls_label_4, e.mpls_label_5,
+                e.mpls_label_6, e.mpls_label_7, e.mpls_label_8, e.mpls_label_9, e.mpls_label_10,
+            ] {
+                d.write_u32::<LittleEndian>(label)?;
+            }
+            element(EXT_MPLS, d)?;
+        }
+        if let Some(e) = &self.mac_address {
+            let mut d = Vec::with_capacity(32);
+            d.write_u64::<LittleEndian>(e.in_src_mac.to_u64())?;
+            d.write_u64::<LittleEndian>(e.out_dst_mac.to_u64())?;
+            d.write_u64::<LittleEndian>(e.in_dst_mac.to_u64())?;
+            d.write_u64::<LittleEndian>(e.out_src_mac.to_u64())?;
+            element(EXT_MAC_ADDR, d)?;
+        }
+        if let Some(e) = &self.sampler_info {
+            let mut d = Vec::with_capacity(12);
+            d.write_u64::<LittleEndian>(e.selector_id)?;
+            d.write_u16::<LittleEndian>(e.sysid)?;
+            d.write_u16::<LittleEndian>(e.align)?;
+            element(EXT_SAMPLER_INFO, d)?;
+        }
+        if let Some(e) = &self.nsel_xlate_port {
+            let mut d = Vec::with_capacity(4);
+            d.write_u16::<LittleEndian>(e.src_port)?;
+            d.write_u16::<LittleEndian>(e.dst_port)?;
+            element(EXT_NSEL_X_LATE_PORT, d)?;
+        }
+        if let Some(e) = &self.nsel_xlate_ipv4 {
+            let mut d = Vec::with_capacity(8);
+            d.write_u32::<LittleEndian>(u32::from(e.src_addr))?;
+            d.write_u32::<LittleEndian>(u32::from(e.dst_addr))?;
+            element(EXT_NSEL_X_LATE_IPV4, d)?;
+        }
+        if let Some(e) = &self.nsel_xlate_ipv6 {
+            let mut d = Vec::with_capacity(32);
+            d.write_u128::<LittleEndian>(u128::from(e.src_addr))?;
+            d.write_u128::<LittleEndian>(u128::from(e.dst_addr))?;
+            element(EXT_NSEL_X_LATE_IPV6, d)?;
+        }
+        if let Some(e) = &self.in_payload {
+            element(EXT_IN_PAYLOAD, e.clone())?;
+        }
+        if let Some(e) = &self.tun_ipv4 {
+            let mut d = Vec::with_capacity(12);
+            d.write_u32::<LittleEndian>(u32::from(e.src_addr))?;
+            d.write_u32::<LittleEndian>(u32::from(e.dst_addr))?;
+            d.write_u8(e.proto)?;
+            d.extend_from_slice(&[0; 3]);
+            element(EXT_TUN_V4, d)?;
+        }
+        if let Some(e) = &self.tun_ipv6 {
+            let mut d = Vec::with_capacity(36);
+            d.write_u128::<LittleEndian>(u128::from(e.src_addr))?;
+            d.write_u128::<LittleEndian>(u128::from(e.dst_addr))?;
+            d.write_u8(e.proto)?;
+            d.extend_from_slice(&[0; 3]);
+            element(EXT_TUN_V6, d)?;
+        }
+        if let Some(e) = &self.layer2 {
+            let mut d = Vec::with_capacity(28);
+            d.write_u16::<LittleEndian>(e.vlan_id)?;
+            d.write_u16::<LittleEndian>(e.customer_vlan_id)?;
+            d.write_u16::<LittleEndian>(e.post_vlan_id)?;
+            d.write_u16::<LittleEndian>(e.post_customer_vlan_id)?;
+            d.write_u32::<LittleEndian>(e.ingress)?;
+            d.write_u32::<LittleEndian>(e.egress)?;
+            d.write_u64::<LittleEndian>(e.vx_lan)?;
+            d.write_u16::<LittleEndian>(e.ether_type)?;
+            d.write_u8(e.ip_version)?;
+            d.write_u8(e.fill)?;
+            element(EXT_LAYER2, d)?;
+        }
+        for (id, data) in &self.unknown {
+            element(*id, data.clone())?;
+        }
 
-    MacAddress::new(bytes)
-}
\ No newline at end of file
+        let size = u16::try_from(elements.len() + RECORD_V3_HEADER_SIZE)
+            .map_err(|_| NfdumpError::ParseError)?;
+        let mut out = Vec::with_capacity(size as usize);
+        out.write_u16::<LittleEndian>(TYPE_RECORD_V3)?;
+        out.write_u16::<LittleEndian>(size)?;
+        out.write_u16::<LittleEndian>(num_elements)?;
+        out.write_u8(self.head.engine_type)?;
+        out.write_u8(self.head.engine_id)?;
+        out.write_u16::<LittleEndian>(self.head.exporter_id)?;
+        out.write_u8(self.head.flags)?;
+        out.write_u8(self.head.nf_version)?;
+        out.extend_from_slice(&elements);
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,226 @@
+use std::io;
+use std::net::IpAddr;
+use crate::aggregate::{Aggregator, Counters, OrderBy, StatElement, StatKey};
+use crate::error::NfdumpError;
+use crate::flow::FlowRecord;
+use crate::output::json_string;
+use crate::report::proto_name;
+
+// OTLP `AggregationTemporality` and `SeverityNumber` values
+const TEMPORALITY_DELTA: u8 = 1;
+const SEVERITY_INFO: u8 = 9;
+
+/// `OtlpExporter` sends flow data to an OpenTelemetry collector with the
+/// OTLP/HTTP JSON protocol: interval aggregates as metrics and, optionally,
+/// flow records as logs.
+///
+/// Each group of an `Aggregator` becomes a data point of the delta sums
+/// `nfdump.flows`, `nfdump.packets` and `nfdump.bytes`, with the group's key
+/// as attribute. Flow records become log records with the
+/// `source.address`, `destination.port`, ... attributes of the OpenTelemetry
+/// semantic conventions.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::aggregate::{Aggregator, StatElement};
+/// use nfdump::otel::OtlpExporter;
+///
+/// let mut exporter = OtlpExporter::new("http://localhost:4318");
+/// exporter.set_service_name("edge-router-1");
+///
+/// let mut agg = Aggregator::new(StatElement::DstPort);
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     agg.add_kind(&record);
+/// }
+/// exporter.export_metrics(&agg, 1704067200000, 1704067500000).unwrap();
+/// ```
+pub struct OtlpExporter {
+    agent: ureq::Agent,
+    endpoint: String,
+    service_name: String,
+    headers: Vec<(String, String)>,
+}
+
+impl OtlpExporter {
+    /// Creates an exporter sending to the collector at `endpoint`, e.g.
+    /// `http://localhost:4318`. Metrics are posted to `/v1/metrics` and logs
+    /// to `/v1/logs` below it.
+    pub fn new(endpoint: &str) -> OtlpExporter {
+        OtlpExporter {
+            agent: ureq::AgentBuilder::new().build(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            service_name: "nfdump".to_string(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Sets the `service.name` resource attribute, `nfdump` by default.
+    pub fn set_service_name(&mut self, name: &str) {
+        self.service_name = name.to_string();
+    }
+
+    /// Adds a header sent with every request, e.g. for authentication.
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Sends the groups of `agg` as metrics of the interval from `start_msec`
+    /// to `end_msec`, in milliseconds since the epoch.
+    pub fn export_metrics(&self, agg: &Aggregator, start_msec: u64, end_msec: u64) -> Result<(), NfdumpError> {
+        self.post("/v1/metrics", &self.metrics_json(agg, start_msec, end_msec))
+    }
+
+    /// Sends flow records as logs.
+    pub fn export_logs(&self, records: &[&dyn FlowRecord]) -> Result<(), NfdumpError> {
+        self.post("/v1/logs", &self.logs_json(records))
+    }
+
+    /// Returns the `ExportMetricsServiceRequest` JSON `export_metrics` sends.
+    pub fn metrics_json(&self, agg: &Aggregator, start_msec: u64, end_msec: u64) -> String {
+        let groups = agg.top(agg.len(), OrderBy::Flows);
+        let metric = |name: &str, unit: &str, value: fn(&Counters) -> u64| {
+            let points: Vec<String> = groups
+                .iter()
+                .map(|(key, counters)| {
+                    format!(
+                        "{{\"attributes\":[{}],\"startTimeUnixNano\":\"{}\",\"timeUnixNano\":\"{}\",\"asInt\":\"{}\"}}",
+                        key_attribute(agg.element(), key),
+                        start_msec * 1_000_000,
+                        end_msec * 1_000_000,
+                        value(counters)
+                    )
+                })
+                .collect();
+            format!(
+                "{{\"name\":{},\"unit\":{},\"sum\":{{\"aggregationTemporality\":{},\"isMonotonic\":true,\"dataPoints\":[{}]}}}}",
+                json_string(name),
+                json_string(unit),
+                TEMPORALITY_DELTA,
+                points.join(",")
+            )
+        };
+        let metrics = [
+            metric("nfdump.flows", "{flow}", |c| c.flows),
+            metric("nfdump.packets", "{packet}", |c| c.packets),
+            metric("nfdump.bytes", "By", |c| c.bytes),
+        ];
+        format!(
+            "{{\"resourceMetrics\":[{{{},\"scopeMetrics\":[{{{},\"metrics\":[{}]}}]}}]}}",
+            self.resource(),
+            scope(),
+            metrics.join(",")
+        )
+    }
+
+    /// Returns the `ExportLogsServiceRequest` JSON `export_logs` sends.
+    pub fn logs_json(&self, records: &[&dyn FlowRecord]) -> String {
+        let logs: Vec<String> = records.iter().map(|r| log_record(*r)).collect();
+        format!(
+            "{{\"resourceLogs\":[{{{},\"scopeLogs\":[{{{},\"logRecords\":[{}]}}]}}]}}",
+            self.resource(),
+            scope(),
+            logs.join(",")
+        )
+    }
+
+    fn resource(&self) -> String {
+        format!(
+            "\"resource\":{{\"attributes\":[{}]}}",
+            string_attribute("service.name", &self.service_name)
+        )
+    }
+
+    fn post(&self, path: &str, body: &str) -> Result<(), NfdumpError> {
+        let mut request = self
+            .agent
+            .post(&format!("{}{}", self.endpoint, path))
+            .set("Content-Type", "application/json");
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        request.send_string(body).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+fn scope() -> String {
+    format!(
+        "\"scope\":{{\"name\":\"nfdump\",\"version\":{}}}",
+        json_string(env!("CARGO_PKG_VERSION"))
+    )
+}
+
+fn string_attribute(key: &str, value: &str) -> String {
+    format!("{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}", json_string(key), json_string(value))
+}
+
+fn int_attribute(key: &str, value: u64) -> String {
+    format!("{{\"key\":{},\"value\":{{\"intValue\":\"{}\"}}}}", json_string(key), value)
+}
+
+fn key_attribute(element: StatElement, key: &StatKey) -> String {
+    let name = match element {
+        StatElement::SrcIp => "source.address",
+        StatElement::DstIp => "destination.address",
+        StatElement::Ip => "network.address",
+        StatElement::SrcPort => "source.port",
+        StatElement::DstPort => "destination.port",
+        StatElement::Port => "network.port",
+        StatElement::Proto => "network.transport",
+    };
+    match key {
+        StatKey::Ip(ip) => string_attribute(name, &ip.to_string()),
+        StatKey::Port(port) => int_attribute(name, *port as u64),
+        StatKey::Proto(proto) => string_attribute(name, &proto_name(*proto).to_lowercase()),
+    }
+}
+
+fn log_record(r: &dyn FlowRecord) -> String {
+    let mut attributes = Vec::new();
+    let addr = |a: Option<IpAddr>| a.map(|a| a.to_string()).unwrap_or_default();
+    if let Some(a) = r.src_addr() {
+        attributes.push(string_attribute("source.address", &a.to_string()));
+    }
+    if let Some(p) = r.src_port() {
+        attributes.push(int_attribute("source.port", p as u64));
+    }
+    if let Some(a) = r.dst_addr() {
+        attributes.push(string_attribute("destination.address", &a.to_string()));
+    }
+    if let Some(p) = r.dst_port() {
+        attributes.push(int_attribute("destination.port", p as u64));
+    }
+    if let Some(p) = r.proto() {
+        attributes.push(string_attribute("network.transport", &proto_name(p).to_lowercase()));
+    }
+    if let Some(packets) = r.packets() {
+        attributes.push(int_attribute("nfdump.packets", packets));
+    }
+    if let Some(bytes) = r.bytes() {
+        attributes.push(int_attribute("nfdump.bytes", bytes));
+    }
+    attributes.push(int_attribute("nfdump.flows", r.flows()));
+    if let Some(sysid) = r.exporter_sysid() {
+        attributes.push(int_attribute("nfdump.exporter_sysid", sysid as u64));
+    }
+
+    let body = format!(
+        "{} {}:{} -> {}:{}",
+        proto_name(r.proto().unwrap_or(0)),
+        addr(r.src_addr()),
+        r.src_port().unwrap_or(0),
+        addr(r.dst_addr()),
+        r.dst_port().unwrap_or(0)
+    );
+    format!(
+        "{{\"timeUnixNano\":\"{}\",\"severityNumber\":{},\"severityText\":\"INFO\",\"body\":{{\"stringValue\":{}}},\"attributes\":[{}]}}",
+        r.first_msec().unwrap_or(0) * 1_000_000,
+        SEVERITY_INFO,
+        json_string(&body),
+        attributes.join(",")
+    )
+}
@@ -0,0 +1,713 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+use crate::flow::FlowRecord;
+use crate::report::proto_name;
+
+/// `OutputFormat` selects how `Formatter` renders records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One line per flow, like `nfdump -o line`.
+    Line,
+    /// The line format with TCP flags and ToS, like `nfdump -o long`.
+    Long,
+    /// The long format with packets per second, bits per second and bytes
+    /// per packet, like `nfdump -o extended`.
+    Extended,
+    /// Comma separated values with a header line.
+    Csv,
+    /// One JSON object per flow.
+    Json,
+    /// Zeek `conn.log` in its tab separated format, with the log header.
+    ZeekConn,
+    /// Zeek `conn.log` with one JSON object per flow, as written by Zeek's
+    /// JSON logging.
+    ZeekConnJson,
+    /// Suricata EVE JSON events with `event_type` `netflow`.
+    SuricataEve,
+    /// ArcSight Common Event Format messages, for syslog based SIEMs.
+    Cef,
+    /// IBM QRadar Log Event Extended Format 2.0 messages, tab delimited.
+    Leef,
+}
+
+/// `OutputOptions` controls details of the rendered output.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    interface_names: HashMap<u32, String>,
+    numeric_interfaces: bool,
+}
+
+impl OutputOptions {
+    /// Creates the default options.
+    pub fn new() -> OutputOptions {
+        OutputOptions::default()
+    }
+
+    /// Sets the names interfaces are rendered with, keyed by interface
+    /// index, such as the map returned by `NfFileReader::interface_names`.
+    /// Interfaces without a name are rendered as numbers.
+    pub fn set_interface_names(&mut self, names: HashMap<u32, String>) {
+        self.interface_names = names;
+    }
+
+    /// Renders interfaces as numbers even if their names are known.
+    pub fn set_numeric_interfaces(&mut self, numeric: bool) {
+        self.numeric_interfaces = numeric;
+    }
+
+    /// Returns the rendering of interface `if_index`.
+    pub fn interface(&self, if_index: u32) -> String {
+        match self.interface_names.get(&if_index) {
+            Some(name) if !self.numeric_interfaces => name.clone(),
+            _ => if_index.to_string(),
+        }
+    }
+}
+
+/// `Formatter` renders flow records as text.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::output::{Formatter, OutputFormat, OutputOptions};
+/// use nfdump::record::RecordKind;
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// let mut options = OutputOptions::new();
+/// options.set_interface_names(reader.interface_names().clone());
+/// let formatter = Formatter::new(OutputFormat::Csv, options);
+///
+/// if let Some(header) = formatter.header() {
+///     println!("{}", header);
+/// }
+/// while let Ok(RecordKind::RecordV3(record)) = reader.read_record() {
+///     println!("{}", formatter.format(&record));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Formatter {
+    format: OutputFormat,
+    options: OutputOptions,
+}
+
+impl Formatter {
+    /// Creates a formatter for `format`.
+    pub fn new(format: OutputFormat, options: OutputOptions) -> Formatter {
+        Formatter { format, options }
+    }
+
+    /// Returns the options of the formatter.
+    pub fn options(&self) -> &OutputOptions {
+        &self.options
+    }
+
+    /// Returns the options of the formatter for modification, for example to
+    /// add interface names learned while reading.
+    pub fn options_mut(&mut self) -> &mut OutputOptions {
+        &mut self.options
+    }
+
+    /// Returns the header line of the format, if it has one.
+    pub fn header(&self) -> Option<String> {
+        match self.format {
+            OutputFormat::Line => Some(format!(
+                "{:<23} {:>9} {:<5} {:>21}    {:>21} {:>8} {:>8} {:>5}",
+                "Date first seen", "Duration", "Proto", "Src IP Addr:Port", "Dst IP Addr:Port", "Packets", "Bytes", "Flows"
+            )),
+            OutputFormat::Long => Some(format!(
+                "{:<23} {:>9} {:<5} {:>21}    {:>21} {:<8} {:>3} {:>8} {:>8} {:>5}",
+                "Date first seen", "Duration", "Proto", "Src IP Addr:Port", "Dst IP Addr:Port", "Flags", "Tos", "Packets",
+                "Bytes", "Flows"
+            )),
+            OutputFormat::Extended => Some(format!(
+                "{:<23} {:>9} {:<5} {:>21}    {:>21} {:<8} {:>3} {:>8} {:>8} {:>8} {:>8} {:>6} {:>5}",
+                "Date first seen", "Duration", "Proto", "Src IP Addr:Port", "Dst IP Addr:Port", "Flags", "Tos", "Packets",
+                "Bytes", "pps", "bps", "Bpp", "Flows"
+            )),
+            OutputFormat::Csv => Some("ts,te,td,sa,da,sp,dp,pr,flg,fwd,stos,ipkt,ibyt,fl,in,out,sas,das,exid".to_string()),
+            OutputFormat::ZeekConn => {
+                let names: Vec<_> = ZEEK_CONN_FIELDS.iter().map(|(name, _)| *name).collect();
+                let types: Vec<_> = ZEEK_CONN_FIELDS.iter().map(|(_, ty)| *ty).collect();
+                Some(format!(
+                    "#separator \\x09\n#set_separator\t,\n#empty_field\t(empty)\n#unset_field\t-\n#path\tconn\n#fields\t{}\n#types\t{}",
+                    names.join("\t"),
+                    types.join("\t")
+                ))
+            }
+            OutputFormat::Json
+            | OutputFormat::ZeekConnJson
+            | OutputFormat::SuricataEve
+            | OutputFormat::Cef
+            | OutputFormat::Leef => None,
+        }
+    }
+
+    /// Renders a record, without a trailing newline.
+    pub fn format(&self, record: &dyn FlowRecord) -> String {
+        match self.format {
+            OutputFormat::Line | OutputFormat::Long | OutputFormat::Extended => self.format_line(record),
+            OutputFormat::Csv => self.format_csv(record),
+            OutputFormat::Json => self.format_json(record),
+            OutputFormat::ZeekConn => zeek_conn(record)
+                .into_iter()
+                .map(|v| v.unwrap_or_else(|| "-".to_string()))
+                .collect::<Vec<_>>()
+                .join("\t"),
+            OutputFormat::ZeekConnJson => {
+                let fields = ZEEK_CONN_FIELDS.iter().zip(zeek_conn(record)).filter_map(|((name, ty), v)| {
+                    let v = v?;
+                    Some(match *ty {
+                        "string" | "addr" | "enum" => format!("\"{}\":{}", name, json_string(&v)),
+                        _ => format!("\"{}\":{}", name, v),
+                    })
+                });
+                format!("{{{}}}", fields.collect::<Vec<_>>().join(","))
+            }
+            OutputFormat::SuricataEve => suricata_netflow(record),
+            OutputFormat::Cef => self.format_cef(record),
+            OutputFormat::Leef => self.format_leef(record),
+        }
+    }
+
+    fn format_line(&self, r: &dyn FlowRecord) -> String {
+        let first = r.first_msec().unwrap_or(0);
+        let duration = r.last_msec().unwrap_or(0).saturating_sub(first);
+        // like nfdump, ICMP flows show their type and code as the
+        // destination port
+        let (src_port, dst_port) = match (r.icmp_type(), r.icmp_code()) {
+            (Some(t), Some(c)) => ("0".to_string(), format!("{}.{}", t, c)),
+            _ => (r.src_port().unwrap_or(0).to_string(), r.dst_port().unwrap_or(0).to_string()),
+        };
+        let mut line = format!(
+            "{:<23} {:>5}.{:03} {:<5} {:>21} -> {:>21}",
+            format_msec(first),
+            duration / 1000,
+            duration % 1000,
+            proto_name(r.proto().unwrap_or(0)),
+            addr_port(r.src_addr(), &src_port),
+            addr_port(r.dst_addr(), &dst_port),
+        );
+        let packets = r.packets().unwrap_or(0);
+        let bytes = r.bytes().unwrap_or(0);
+        if self.format != OutputFormat::Line {
+            _ = write!(line, " {:<8} {:>3}", tcp_flags(r.tcp_flags().unwrap_or(0)), r.tos().unwrap_or(0));
+        }
+        _ = write!(line, " {:>8} {:>8}", packets, bytes);
+        if self.format == OutputFormat::Extended {
+            let pps = packets.saturating_mul(1000).checked_div(duration).unwrap_or(0);
+            let bps = bytes.saturating_mul(8000).checked_div(duration).unwrap_or(0);
+            let bpp = bytes.checked_div(packets).unwrap_or(0);
+            _ = write!(line, " {:>8} {:>8} {:>6}", pps, bps, bpp);
+        }
+        _ = write!(line, " {:>5}", r.flows());
+        line
+    }
+
+    fn format_csv(&self, r: &dyn FlowRecord) -> String {
+        let first = r.first_msec().unwrap_or(0);
+        let last = r.last_msec().unwrap_or(0);
+        let iface = |i: Option<u32>| i.map(|i| csv_field(&self.options.interface(i))).unwrap_or_default();
+        format!(
+            "{},{},{}.{:03},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            format_msec(first),
+            format_msec(last),
+            last.saturating_sub(first) / 1000,
+            last.saturating_sub(first) % 1000,
+            opt(r.src_addr()),
+            opt(r.dst_addr()),
+            opt(r.src_port()),
+            opt(r.dst_port()),
+            proto_name(r.proto().unwrap_or(0)),
+            tcp_flags(r.tcp_flags().unwrap_or(0)),
+            opt(r.fwd_status()),
+            opt(r.tos()),
+            opt(r.packets()),
+            opt(r.bytes()),
+            r.flows(),
+            iface(r.input()),
+            iface(r.output()),
+            opt(r.src_as()),
+            opt(r.dst_as()),
+            opt(r.exporter_sysid())
+        )
+    }
+
+    fn format_json(&self, r: &dyn FlowRecord) -> String {
+        let mut out = String::from("{");
+        let mut field = |key: &str, value: String| {
+            if out.len() > 1 {
+                out.push_str(", ");
+            }
+            _ = write!(out, "\"{}\": {}", key, value);
+        };
+
+        if let Some(sysid) = r.exporter_sysid() {
+            field("export_sysid", sysid.to_string());
+        }
+        if let Some(first) = r.first_msec() {
+            field("t_first", json_string(&format_msec_with(first, 'T')));
+        }
+        if let Some(last) = r.last_msec() {
+            field("t_last", json_string(&format_msec_with(last, 'T')));
+        }
+        if let Some(proto) = r.proto() {
+            field("proto", proto.to_string());
+        }
+        for (key, addr) in [("src", r.src_addr()), ("dst", r.dst_addr())] {
+            match addr {
+                Some(IpAddr::V4(a)) => field(&format!("{}4_addr", key), json_string(&a.to_string())),
+                Some(IpAddr::V6(a)) => field(&format!("{}6_addr", key), json_string(&a.to_string())),
+                None => {}
+            }
+        }
+        if let Some(port) = r.src_port() {
+            field("src_port", port.to_string());
+        }
+        if let Some(port) = r.dst_port() {
+            field("dst_port", port.to_string());
+        }
+        if let (Some(icmp_type), Some(icmp_code)) = (r.icmp_type(), r.icmp_code()) {
+            field("icmp_type", icmp_type.to_string());
+            field("icmp_code", icmp_code.to_string());
+        }
+        if let Some(flags) = r.tcp_flags() {
+            field("tcp_flags", json_string(&tcp_flags(flags)));
+        }
+        if let Some(tos) = r.tos() {
+            field("src_tos", tos.to_string());
+        }
+        if let Some(packets) = r.packets() {
+            field("in_packets", packets.to_string());
+        }
+        if let Some(bytes) = r.bytes() {
+            field("in_bytes", bytes.to_string());
+        }
+        field("flows", r.flows().to_string());
+        if let Some(input) = r.input() {
+            field("input_snmp", self.json_interface(input));
+        }
+        if let Some(output) = r.output() {
+            field("output_snmp", self.json_interface(output));
+        }
+        if let Some(asn) = r.src_as() {
+            field("src_as", asn.to_string());
+        }
+        if let Some(asn) = r.dst_as() {
+            field("dst_as", asn.to_string());
+        }
+        if let Some(ip) = r.next_hop() {
+            field("ip_nexthop", json_string(&ip.to_string()));
+        }
+        if let Some(ip) = r.bgp_next_hop() {
+            field("bgp_nexthop", json_string(&ip.to_string()));
+        }
+        if let Some(reason) = r.flow_end_reason() {
+            field("end_reason", json_string(&reason.to_string()));
+        }
+
+        out.push('}');
+        out
+    }
+
+    /// Renders a record as a CEF message. The extension holds the flow with
+    /// the standard CEF keys; packet counts use the custom number fields.
+    fn format_cef(&self, r: &dyn FlowRecord) -> String {
+        let mut ext = Vec::new();
+        let mut field = |key: &str, value: String| ext.push(format!("{}={}", key, cef_value(&value)));
+        if let Some(first) = r.first_msec() {
+            field("start", first.to_string());
+        }
+        if let Some(last) = r.last_msec() {
+            field("end", last.to_string());
+        }
+        if let Some(addr) = r.src_addr() {
+            field(if addr.is_ipv4() { "src" } else { "c6a2" }, addr.to_string());
+        }
+        if let Some(port) = r.src_port() {
+            field("spt", port.to_string());
+        }
+        if let Some(addr) = r.dst_addr() {
+            field(if addr.is_ipv4() { "dst" } else { "c6a3" }, addr.to_string());
+        }
+        if let Some(port) = r.dst_port() {
+            field("dpt", port.to_string());
+        }
+        if let Some(proto) = r.proto() {
+            field("proto", proto_name(proto));
+        }
+        if let Some(bytes) = r.bytes() {
+            field("in", bytes.to_string());
+        }
+        if let Some(bytes) = r.out_bytes() {
+            field("out", bytes.to_string());
+        }
+        if let Some(packets) = r.packets() {
+            field("cn1", packets.to_string());
+            field("cn1Label", "packets".to_string());
+        }
+        field("cn2", r.flows().to_string());
+        field("cn2Label", "flows".to_string());
+        if let Some(input) = r.input() {
+            field("deviceInboundInterface", self.options.interface(input));
+        }
+        if let Some(output) = r.output() {
+            field("deviceOutboundInterface", self.options.interface(output));
+        }
+        if let Some(flags) = r.tcp_flags().filter(|_| r.proto() == Some(6)) {
+            field("cs1", tcp_flags(flags));
+            field("cs1Label", "tcpFlags".to_string());
+        }
+        format!(
+            "CEF:0|nfdump|nfdump|{}|flow|Network flow|1|{}",
+            cef_header(env!("CARGO_PKG_VERSION")),
+            ext.join(" ")
+        )
+    }
+
+    /// Renders a record as a LEEF 2.0 message with tab delimited attributes.
+    fn format_leef(&self, r: &dyn FlowRecord) -> String {
+        let mut attrs = Vec::new();
+        let mut field = |key: &str, value: String| attrs.push(format!("{}={}", key, leef_value(&value)));
+        if let Some(first) = r.first_msec() {
+            field("devTime", format!("{} UTC", format_msec(first)));
+            field("devTimeFormat", "yyyy-MM-dd HH:mm:ss.SSS z".to_string());
+        }
+        if let Some(addr) = r.src_addr() {
+            field("src", addr.to_string());
+        }
+        if let Some(port) = r.src_port() {
+            field("srcPort", port.to_string());
+        }
+        if let Some(addr) = r.dst_addr() {
+            field("dst", addr.to_string());
+        }
+        if let Some(port) = r.dst_port() {
+            field("dstPort", port.to_string());
+        }
+        if let Some(proto) = r.proto() {
+            field("proto", proto_name(proto));
+        }
+        if let Some(packets) = r.packets() {
+            field("srcPackets", packets.to_string());
+        }
+        if let Some(bytes) = r.bytes() {
+            field("srcBytes", bytes.to_string());
+        }
+        if let Some(packets) = r.out_packets() {
+            field("dstPackets", packets.to_string());
+        }
+        if let Some(bytes) = r.out_bytes() {
+            field("dstBytes", bytes.to_string());
+        }
+        if let (Some(first), Some(last)) = (r.first_msec(), r.last_msec()) {
+            field("duration", last.saturating_sub(first).to_string());
+        }
+        field("flows", r.flows().to_string());
+        if let Some(input) = r.input() {
+            field("inInterface", self.options.interface(input));
+        }
+        if let Some(output) = r.output() {
+            field("outInterface", self.options.interface(output));
+        }
+        if let Some(flags) = r.tcp_flags().filter(|_| r.proto() == Some(6)) {
+            field("tcpFlags", tcp_flags(flags));
+        }
+        format!(
+            "LEEF:2.0|nfdump|nfdump|{}|flow|x09|{}",
+            leef_value(env!("CARGO_PKG_VERSION")),
+            attrs.join("\t")
+        )
+    }
+
+    fn json_interface(&self, if_index: u32) -> String {
+        let rendered = self.options.interface(if_index);
+        if rendered.parse::<u32>().is_ok() {
+            rendered
+        } else {
+            json_string(&rendered)
+        }
+    }
+}
+
+/// Names and Zeek types of the `conn.log` columns.
+const ZEEK_CONN_FIELDS: [(&str, &str); 21] = [
+    ("ts", "time"),
+    ("uid", "string"),
+    ("id.orig_h", "addr"),
+    ("id.orig_p", "port"),
+    ("id.resp_h", "addr"),
+    ("id.resp_p", "port"),
+    ("proto", "enum"),
+    ("service", "string"),
+    ("duration", "interval"),
+    ("orig_bytes", "count"),
+    ("resp_bytes", "count"),
+    ("conn_state", "string"),
+    ("local_orig", "bool"),
+    ("local_resp", "bool"),
+    ("missed_bytes", "count"),
+    ("history", "string"),
+    ("orig_pkts", "count"),
+    ("orig_ip_bytes", "count"),
+    ("resp_pkts", "count"),
+    ("resp_ip_bytes", "count"),
+    ("tunnel_parents", "set[string]"),
+];
+
+/// Returns the `conn.log` columns of a record, `None` for unset ones.
+///
+/// Flow records count IP bytes, which fill both `orig_bytes` and
+/// `orig_ip_bytes`; the responder columns are only set for bidirectional
+/// flows. For ICMP the ports carry the type and code, like Zeek does.
+fn zeek_conn(r: &dyn FlowRecord) -> Vec<Option<String>> {
+    let first = r.first_msec().unwrap_or(0);
+    let duration = r.last_msec().unwrap_or(first).saturating_sub(first);
+    let seconds = |msec: u64| format!("{}.{:06}", msec / 1000, msec % 1000 * 1000);
+    let proto = r.proto().unwrap_or(0);
+    let (orig_p, resp_p) = match (r.icmp_type(), r.icmp_code()) {
+        (Some(t), Some(c)) => (Some(t as u16), Some(c as u16)),
+        _ => (r.src_port(), r.dst_port()),
+    };
+    let proto_name = match proto {
+        1 | 58 => "icmp",
+        6 => "tcp",
+        17 => "udp",
+        _ => "unknown_transport",
+    };
+
+    vec![
+        Some(seconds(first)),
+        Some(zeek_uid(r)),
+        r.src_addr().map(|a| a.to_string()),
+        orig_p.map(|p| p.to_string()),
+        r.dst_addr().map(|a| a.to_string()),
+        resp_p.map(|p| p.to_string()),
+        Some(proto_name.to_string()),
+        None,
+        Some(seconds(duration)),
+        r.bytes().map(|b| b.to_string()),
+        r.out_bytes().map(|b| b.to_string()),
+        None,
+        None,
+        None,
+        None,
+        None,
+        r.packets().map(|p| p.to_string()),
+        r.bytes().map(|b| b.to_string()),
+        r.out_packets().map(|p| p.to_string()),
+        r.out_bytes().map(|b| b.to_string()),
+        None,
+    ]
+}
+
+/// Hashes the flow's key, start time and exporter, so the ids derived from
+/// it are the same every time a flow is rendered.
+fn flow_digest(r: &dyn FlowRecord) -> [u8; 20] {
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(&r.first_msec().unwrap_or(0).to_be_bytes());
+    hasher.update(r.community_id(0).unwrap_or_default().as_bytes());
+    hasher.update(&r.exporter_sysid().unwrap_or(0).to_be_bytes());
+    hasher.digest().bytes()
+}
+
+/// Derives a Zeek style connection uid from the flow.
+fn zeek_uid(r: &dyn FlowRecord) -> String {
+    const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+    let digest = flow_digest(r);
+    let mut n = u128::from_be_bytes(digest[..16].try_into().unwrap());
+    let mut uid = String::from("C");
+    for _ in 0..17 {
+        uid.push(BASE62[(n % 62) as usize] as char);
+        n /= 62;
+    }
+    uid
+}
+
+/// Renders a record as a Suricata EVE `netflow` event. Like Suricata's own
+/// netflow events it describes a single direction of the flow.
+fn suricata_netflow(r: &dyn FlowRecord) -> String {
+    // EVE timestamps have microseconds and a UTC offset
+    let timestamp = |msec: u64| json_string(&format!("{}000+0000", format_msec_with(msec, 'T')));
+    let first = r.first_msec().unwrap_or(0);
+    let last = r.last_msec().unwrap_or(first).max(first);
+    let proto = r.proto().unwrap_or(0);
+
+    let mut out = String::from("{");
+    let mut field = |key: &str, value: String| {
+        if out.len() > 1 {
+            out.push(',');
+        }
+        _ = write!(out, "\"{}\":{}", key, value);
+    };
+
+    field("timestamp", timestamp(first));
+    // flow ids stay below 2^53 so JSON consumers keep them exact
+    let digest = flow_digest(r);
+    let flow_id = u64::from_be_bytes(digest[..8].try_into().unwrap()) & ((1 << 51) - 1);
+    field("flow_id", flow_id.to_string());
+    field("event_type", json_string("netflow"));
+    if let Some(addr) = r.src_addr() {
+        field("src_ip", json_string(&addr.to_string()));
+    }
+    if matches!(proto, 6 | 17 | 132) {
+        if let Some(port) = r.src_port() {
+            field("src_port", port.to_string());
+        }
+    }
+    if let Some(addr) = r.dst_addr() {
+        field("dest_ip", json_string(&addr.to_string()));
+    }
+    if matches!(proto, 6 | 17 | 132) {
+        if let Some(port) = r.dst_port() {
+            field("dest_port", port.to_string());
+        }
+    }
+    let proto_name = match proto {
+        1 => "ICMP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        58 => "IPv6-ICMP".to_string(),
+        132 => "SCTP".to_string(),
+        p => p.to_string(),
+    };
+    field("proto", json_string(&proto_name));
+    if let (Some(icmp_type), Some(icmp_code)) = (r.icmp_type(), r.icmp_code()) {
+        field("icmp_type", icmp_type.to_string());
+        field("icmp_code", icmp_code.to_string());
+    }
+
+    field(
+        "netflow",
+        format!(
+            "{{\"pkts\":{},\"bytes\":{},\"start\":{},\"end\":{},\"age\":{}}}",
+            r.packets().unwrap_or(0),
+            r.bytes().unwrap_or(0),
+            timestamp(first),
+            timestamp(last),
+            (last - first) / 1000
+        ),
+    );
+
+    if proto == 6 {
+        let flags = r.tcp_flags().unwrap_or(0);
+        let mut tcp = format!("{{\"tcp_flags\":\"{:02x}\"", flags);
+        let names = ["fin", "syn", "rst", "psh", "ack", "urg", "ecn", "cwr"];
+        for (i, name) in names.iter().enumerate() {
+            if flags & (1 << i) != 0 {
+                _ = write!(tcp, ",\"{}\":true", name);
+            }
+        }
+        tcp.push('}');
+        field("tcp", tcp);
+    }
+
+    out.push('}');
+    out
+}
+
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn addr_port(addr: Option<IpAddr>, port: &str) -> String {
+    match addr {
+        Some(IpAddr::V4(a)) => format!("{}:{}", a, port),
+        Some(IpAddr::V6(a)) => format!("{}.{}", a, port),
+        None => format!("-:{}", port),
+    }
+}
+
+/// Renders TCP flags the way nfdump does, one letter per set flag and `.`
+/// for unset flags, in the order CWR, ECE, URG, ACK, PSH, RST, SYN, FIN.
+pub(crate) fn tcp_flags(flags: u8) -> String {
+    "CEUAPRSF"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if flags & (0x80 >> i) != 0 { c } else { '.' })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes a CEF header field.
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Escapes a CEF extension value.
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Removes the LEEF delimiters from a value, which LEEF can not escape.
+fn leef_value(value: &str) -> String {
+    value.replace(['\t', '|', '\n', '\r'], " ")
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => _ = write!(out, "\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Formats milliseconds since the epoch as `YYYY-MM-DD hh:mm:ss.mmm` in UTC.
+pub(crate) fn format_msec(msec: u64) -> String {
+    format_msec_with(msec, ' ')
+}
+
+/// Formats milliseconds since the epoch in UTC, with `sep` between date and
+/// time.
+pub(crate) fn format_msec_with(msec: u64, sep: char) -> String {
+    let secs = msec / 1000;
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // civil date from days since 1970-01-01
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        sep,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        msec % 1000
+    )
+}
@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::error::NfdumpError;
+use crate::record::RecordKind;
+use crate::NfFileReader;
+
+/// Reads the files at `paths` on a pool of threads, one file at a time per
+/// thread, and folds the records of each file into a partial result with
+/// `per_record`. The partial results are merged with `reduce` in the order
+/// of `paths`.
+///
+/// The pool has as many threads as the machine has cores, but no more than
+/// there are files. If a file can not be read, the remaining files are
+/// skipped and the error of the first failing file in `paths` is returned.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::flow::FlowRecord;
+/// use nfdump::parallel::process_files_parallel;
+/// use nfdump::record::RecordKind;
+///
+/// let bytes = process_files_parallel(
+///     &["nfcapd.1", "nfcapd.2", "nfcapd.3"],
+///     |bytes: &mut u64, record| match record {
+///         RecordKind::Record(r) => *bytes += r.bytes().unwrap_or(0),
+///         RecordKind::RecordV3(r) => *bytes += r.bytes().unwrap_or(0),
+///         _ => {}
+///     },
+///     |a, b| a + b,
+/// ).unwrap();
+/// println!("{} bytes", bytes);
+/// ```
+pub fn process_files_parallel<P, T, F, G>(paths: &[P], per_record: F, reduce: G) -> Result<T, NfdumpError>
+where
+    P: AsRef<Path> + Sync,
+    T: Default + Send,
+    F: Fn(&mut T, RecordKind) + Sync,
+    G: Fn(T, T) -> T,
+{
+    process_files_parallel_with_cancel(paths, Arc::new(AtomicBool::new(false)), per_record, reduce)
+}
+
+/// Like `process_files_parallel`, but stops all threads with
+/// `NfdumpError::Cancelled` once `cancel` is set to `true`.
+pub fn process_files_parallel_with_cancel<P, T, F, G>(
+    paths: &[P],
+    cancel: Arc<AtomicBool>,
+    per_record: F,
+    reduce: G,
+) -> Result<T, NfdumpError>
+where
+    P: AsRef<Path> + Sync,
+    T: Default + Send,
+    F: Fn(&mut T, RecordKind) + Sync,
+    G: Fn(T, T) -> T,
+{
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(paths.len());
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results: Vec<Mutex<Option<Result<T, NfdumpError>>>> = paths.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= paths.len() || failed.load(Ordering::Relaxed) || cancel.load(Ordering::Relaxed) {
+                    break;
+                }
+                let result = process_file(paths[i].as_ref(), &cancel, &per_record);
+                if result.is_err() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    let mut total = T::default();
+    for result in results {
+        match result.into_inner().unwrap() {
+            Some(Ok(partial)) => total = reduce(total, partial),
+            Some(Err(e)) => return Err(e),
+            // skipped after another file failed or the scan was cancelled
+            None if cancel.load(Ordering::Relaxed) => return Err(NfdumpError::Cancelled),
+            None => {}
+        }
+    }
+    Ok(total)
+}
+
+fn process_file<T, F>(path: &Path, cancel: &Arc<AtomicBool>, per_record: &F) -> Result<T, NfdumpError>
+where
+    T: Default,
+    F: Fn(&mut T, RecordKind),
+{
+    let mut partial = T::default();
+    let mut reader = NfFileReader::new(File::open(path)?)?;
+    reader.set_cancel(cancel.clone());
+    loop {
+        match reader.read_record() {
+            Ok(record) => per_record(&mut partial, record),
+            Err(NfdumpError::EOF) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(partial)
+}
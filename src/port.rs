@@ -0,0 +1,159 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+
+/// `PortSet` is a set of ports stored as a bitmap, so lookups take constant
+/// time however many ports and ranges it holds.
+///
+/// Sets are parsed from comma separated ports and inclusive ranges.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::port::PortSet;
+///
+/// let set: PortSet = "80,443,8000-8100".parse().unwrap();
+/// assert!(set.contains(8080));
+/// ```
+#[derive(Clone)]
+pub struct PortSet {
+    bits: Box<[u64; 1024]>,
+}
+
+impl PortSet {
+    /// Creates an empty set.
+    pub fn new() -> PortSet {
+        PortSet { bits: Box::new([0; 1024]) }
+    }
+
+    /// Adds a port to the set.
+    pub fn insert(&mut self, port: u16) {
+        self.bits[port as usize / 64] |= 1 << (port % 64);
+    }
+
+    /// Adds the inclusive range `start..=end` to the set.
+    pub fn insert_range(&mut self, start: u16, end: u16) {
+        for port in start..=end {
+            self.insert(port);
+        }
+    }
+
+    /// Returns `true` if the set contains `port`.
+    pub fn contains(&self, port: u16) -> bool {
+        self.bits[port as usize / 64] & (1 << (port % 64)) != 0
+    }
+
+    /// Returns the number of ports in the set.
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Returns `true` if the set contains no ports.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&b| b == 0)
+    }
+
+    /// Returns the ports of the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..=u16::MAX).filter(|&p| self.contains(p))
+    }
+}
+
+impl Default for PortSet {
+    fn default() -> Self {
+        PortSet::new()
+    }
+}
+
+impl fmt::Debug for PortSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl FromStr for PortSet {
+    type Err = NfdumpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = PortSet::new();
+        for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            match item.split_once('-') {
+                Some((start, end)) => {
+                    let start = start.trim().parse::<u16>().map_err(|_| NfdumpError::ParseError)?;
+                    let end = end.trim().parse::<u16>().map_err(|_| NfdumpError::ParseError)?;
+                    if start > end {
+                        return Err(NfdumpError::ParseError);
+                    }
+                    set.insert_range(start, end);
+                }
+                None => set.insert(item.parse::<u16>().map_err(|_| NfdumpError::ParseError)?),
+            }
+        }
+        Ok(set)
+    }
+}
+
+impl TryFrom<&str> for PortSet {
+    type Error = NfdumpError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl FromIterator<u16> for PortSet {
+    fn from_iter<I: IntoIterator<Item = u16>>(iter: I) -> Self {
+        let mut set = PortSet::new();
+        iter.into_iter().for_each(|p| set.insert(p));
+        set
+    }
+}
+
+/// `PortField` selects which port of a record a filter looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortField {
+    Src,
+    Dst,
+    /// Either the source or the destination port.
+    SrcOrDst,
+}
+
+/// `PortFilter` selects records whose port in `field` is in a `PortSet`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::port::{PortField, PortFilter};
+///
+/// let filter = PortFilter::new(PortField::Dst, "80,443".parse().unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct PortFilter {
+    field: PortField,
+    set: PortSet,
+}
+
+impl PortFilter {
+    /// Creates a filter matching `field` against `set`.
+    pub fn new(field: PortField, set: PortSet) -> PortFilter {
+        PortFilter { field, set }
+    }
+
+    /// Returns the ports of the filter.
+    pub fn set(&self) -> &PortSet {
+        &self.set
+    }
+}
+
+impl Filter for PortFilter {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        let ports = match self.field {
+            PortField::Src => [record.src_port(), None],
+            PortField::Dst => [record.dst_port(), None],
+            PortField::SrcOrDst => [record.src_port(), record.dst_port()],
+        };
+        ports.into_iter().flatten().any(|p| self.set.contains(p))
+    }
+}
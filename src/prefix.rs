@@ -0,0 +1,333 @@
+use std::net::IpAddr;
+use ipnet::IpNet;
+use crate::filter::{AddrField, Filter};
+use crate::flow::FlowRecord;
+
+const NO_CHILD: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    children: [u32; 2],
+    terminal: bool,
+}
+
+/// Binary trie over address bits, most significant bit first.
+#[derive(Clone)]
+struct Trie {
+    nodes: Vec<Node>,
+}
+
+impl Trie {
+    fn new() -> Trie {
+        Trie {
+            nodes: vec![Node { children: [NO_CHILD; 2], terminal: false }],
+        }
+    }
+
+    fn insert(&mut self, addr: u128, prefix_len: u8, width: u8) {
+        let mut node = 0;
+        for i in 0..prefix_len {
+            if self.nodes[node].terminal {
+                // a shorter prefix already covers this one
+                return;
+            }
+            let bit = ((addr >> (width - 1 - i)) & 1) as usize;
+            if self.nodes[node].children[bit] == NO_CHILD {
+                self.nodes.push(Node { children: [NO_CHILD; 2], terminal: false });
+                self.nodes[node].children[bit] = (self.nodes.len() - 1) as u32;
+            }
+            node = self.nodes[node].children[bit] as usize;
+        }
+        self.nodes[node].terminal = true;
+        self.nodes[node].children = [NO_CHILD; 2];
+    }
+
+    fn contains(&self, addr: u128, width: u8) -> bool {
+        let mut node = 0;
+        for i in 0..width {
+            if self.nodes[node].terminal {
+                return true;
+            }
+            let bit = ((addr >> (width - 1 - i)) & 1) as usize;
+            match self.nodes[node].children[bit] {
+                NO_CHILD => return false,
+                next => node = next as usize,
+            }
+        }
+        self.nodes[node].terminal
+    }
+}
+
+/// `PrefixSet` is a set of IPv4 and IPv6 prefixes with lookups in time
+/// proportional to the address length, independent of the number of
+/// prefixes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::prefix::PrefixSet;
+///
+/// let set: PrefixSet = ["10.0.0.0/8", "2001:db8::/32"]
+///     .iter()
+///     .map(|p| p.parse().unwrap())
+///     .collect();
+/// assert!(set.contains("10.1.2.3".parse().unwrap()));
+/// ```
+#[derive(Clone)]
+pub struct PrefixSet {
+    v4: Trie,
+    v6: Trie,
+    len: usize,
+}
+
+impl PrefixSet {
+    /// Creates an empty set.
+    pub fn new() -> PrefixSet {
+        PrefixSet {
+            v4: Trie::new(),
+            v6: Trie::new(),
+            len: 0,
+        }
+    }
+
+    /// Adds a prefix to the set. Host bits below the prefix length are ignored.
+    pub fn insert(&mut self, net: IpNet) {
+        match net {
+            IpNet::V4(n) => self.v4.insert(u32::from(n.network()) as u128, n.prefix_len(), 32),
+            IpNet::V6(n) => self.v6.insert(u128::from(n.network()), n.prefix_len(), 128),
+        }
+        self.len += 1;
+    }
+
+    /// Returns `true` if any prefix in the set contains `addr`.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(a) => self.v4.contains(u32::from(a) as u128, 32),
+            IpAddr::V6(a) => self.v6.contains(u128::from(a), 128),
+        }
+    }
+
+    /// Returns the number of prefixes inserted.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no prefix was inserted.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for PrefixSet {
+    fn default() -> Self {
+        PrefixSet::new()
+    }
+}
+
+impl From<IpNet> for PrefixSet {
+    fn from(net: IpNet) -> Self {
+        let mut set = PrefixSet::new();
+        set.insert(net);
+        set
+    }
+}
+
+impl FromIterator<IpNet> for PrefixSet {
+    fn from_iter<I: IntoIterator<Item = IpNet>>(iter: I) -> Self {
+        let mut set = PrefixSet::new();
+        iter.into_iter().for_each(|n| set.insert(n));
+        set
+    }
+}
+
+impl Extend<IpNet> for PrefixSet {
+    fn extend<I: IntoIterator<Item = IpNet>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|n| self.insert(n));
+    }
+}
+
+#[derive(Clone, Copy)]
+struct MapNode {
+    children: [u32; 2],
+    value: u32,
+}
+
+/// Binary trie over address bits mapping prefixes to the index of their value.
+#[derive(Clone)]
+struct MapTrie {
+    nodes: Vec<MapNode>,
+}
+
+impl MapTrie {
+    fn new() -> MapTrie {
+        MapTrie {
+            nodes: vec![MapNode { children: [NO_CHILD; 2], value: NO_CHILD }],
+        }
+    }
+
+    // Returns the value slot of the prefix, creating its nodes as needed.
+    fn slot(&mut self, addr: u128, prefix_len: u8, width: u8) -> &mut u32 {
+        let mut node = 0;
+        for i in 0..prefix_len {
+            let bit = ((addr >> (width - 1 - i)) & 1) as usize;
+            if self.nodes[node].children[bit] == NO_CHILD {
+                self.nodes.push(MapNode { children: [NO_CHILD; 2], value: NO_CHILD });
+                self.nodes[node].children[bit] = (self.nodes.len() - 1) as u32;
+            }
+            node = self.nodes[node].children[bit] as usize;
+        }
+        &mut self.nodes[node].value
+    }
+
+    fn longest_match(&self, addr: u128, width: u8) -> Option<u32> {
+        let mut node = 0;
+        let mut found = None;
+        for i in 0..=width {
+            if self.nodes[node].value != NO_CHILD {
+                found = Some(self.nodes[node].value);
+            }
+            if i == width {
+                break;
+            }
+            let bit = ((addr >> (width - 1 - i)) & 1) as usize;
+            match self.nodes[node].children[bit] {
+                NO_CHILD => break,
+                next => node = next as usize,
+            }
+        }
+        found
+    }
+}
+
+/// `PrefixMap` maps IPv4 and IPv6 prefixes to values, with longest prefix
+/// match lookups in time proportional to the address length.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::prefix::PrefixMap;
+///
+/// let mut map = PrefixMap::new();
+/// map.insert("10.0.0.0/8".parse().unwrap(), "internal");
+/// map.insert("10.1.0.0/16".parse().unwrap(), "lab");
+/// let (net, name) = map.longest_match("10.1.2.3".parse().unwrap()).unwrap();
+/// assert_eq!(*name, "lab");
+/// assert_eq!(net.to_string(), "10.1.0.0/16");
+/// ```
+#[derive(Clone)]
+pub struct PrefixMap<V> {
+    v4: MapTrie,
+    v6: MapTrie,
+    entries: Vec<(IpNet, V)>,
+}
+
+impl<V> PrefixMap<V> {
+    /// Creates an empty map.
+    pub fn new() -> PrefixMap<V> {
+        PrefixMap {
+            v4: MapTrie::new(),
+            v6: MapTrie::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Maps a prefix to `value`, returning the previous value of the same
+    /// prefix. Host bits below the prefix length are ignored.
+    pub fn insert(&mut self, net: IpNet, value: V) -> Option<V> {
+        let net = net.trunc();
+        let slot = match net {
+            IpNet::V4(n) => self.v4.slot(u32::from(n.network()) as u128, n.prefix_len(), 32),
+            IpNet::V6(n) => self.v6.slot(u128::from(n.network()), n.prefix_len(), 128),
+        };
+        if *slot != NO_CHILD {
+            return Some(std::mem::replace(&mut self.entries[*slot as usize].1, value));
+        }
+        *slot = self.entries.len() as u32;
+        self.entries.push((net, value));
+        None
+    }
+
+    /// Returns the most specific prefix containing `addr` and its value.
+    pub fn longest_match(&self, addr: IpAddr) -> Option<(IpNet, &V)> {
+        let index = match addr {
+            IpAddr::V4(a) => self.v4.longest_match(u32::from(a) as u128, 32),
+            IpAddr::V6(a) => self.v6.longest_match(u128::from(a), 128),
+        }?;
+        let (net, value) = &self.entries[index as usize];
+        Some((*net, value))
+    }
+
+    /// Returns the value of the most specific prefix containing `addr`.
+    pub fn get(&self, addr: IpAddr) -> Option<&V> {
+        self.longest_match(addr).map(|(_, v)| v)
+    }
+
+    /// Returns the prefixes and their values in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (IpNet, &V)> {
+        self.entries.iter().map(|(net, value)| (*net, value))
+    }
+
+    /// Returns the number of prefixes in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<V> Default for PrefixMap<V> {
+    fn default() -> Self {
+        PrefixMap::new()
+    }
+}
+
+impl<V> FromIterator<(IpNet, V)> for PrefixMap<V> {
+    fn from_iter<I: IntoIterator<Item = (IpNet, V)>>(iter: I) -> Self {
+        let mut map = PrefixMap::new();
+        iter.into_iter().for_each(|(n, v)| {
+            map.insert(n, v);
+        });
+        map
+    }
+}
+
+/// `PrefixFilter` selects records whose address in `field` lies in a
+/// `PrefixSet`.
+#[derive(Clone)]
+pub struct PrefixFilter {
+    field: AddrField,
+    set: PrefixSet,
+}
+
+impl PrefixFilter {
+    /// Creates a filter matching `field` against the given prefixes.
+    pub fn new<I: IntoIterator<Item = IpNet>>(field: AddrField, prefixes: I) -> PrefixFilter {
+        PrefixFilter {
+            field,
+            set: prefixes.into_iter().collect(),
+        }
+    }
+
+    /// Creates a filter matching `field` against an existing set.
+    pub fn with_set(field: AddrField, set: PrefixSet) -> PrefixFilter {
+        PrefixFilter { field, set }
+    }
+
+    /// Returns the prefixes of the filter.
+    pub fn set(&self) -> &PrefixSet {
+        &self.set
+    }
+}
+
+impl Filter for PrefixFilter {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        self.field
+            .addrs(record)
+            .into_iter()
+            .flatten()
+            .any(|a| self.set.contains(a))
+    }
+}
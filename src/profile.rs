@@ -0,0 +1,148 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use crate::compress::Compression;
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::split::Splitter;
+use crate::writer::NfFileWriter;
+use crate::{NfFileReader, StatRecord};
+
+struct Channel {
+    name: String,
+    filter: Arc<dyn Filter + Send + Sync>,
+    dir: PathBuf,
+}
+
+/// `ChannelResult` describes the file a channel wrote for one source file.
+#[derive(Debug, Clone)]
+pub struct ChannelResult {
+    /// Name of the channel.
+    pub name: String,
+    /// Path of the written file.
+    pub path: PathBuf,
+    /// Statistics of the records written to the file.
+    pub stat_record: StatRecord,
+}
+
+/// `Profile` is a set of named channels, each with its own filter and output
+/// directory, like an NfSen profile.
+///
+/// Each source file is read once and its records are written to every
+/// channel whose filter matches them. Channel files are named after the
+/// source file, so `nfcapd.202401010000` becomes
+/// `<channel dir>/nfcapd.202401010000`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::Compression;
+/// use nfdump::filter::AddrField;
+/// use nfdump::port::{PortField, PortFilter};
+/// use nfdump::prefix::PrefixFilter;
+/// use nfdump::profile::Profile;
+///
+/// let mut profile = Profile::new(Compression::Zstd);
+/// profile.add_channel("web", PortFilter::new(PortField::Dst, "80,443".parse().unwrap()), "profiles/live/web");
+/// profile.add_channel("lan", PrefixFilter::new(AddrField::SrcOrDst, ["10.0.0.0/8".parse().unwrap()]), "profiles/live/lan");
+///
+/// for channel in profile.process("nfcapd.202401010000").unwrap() {
+///     println!("{}: {:?}", channel.name, channel.stat_record);
+/// }
+/// ```
+pub struct Profile {
+    channels: Vec<Channel>,
+    compression: Compression,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Profile {
+    /// Creates a profile without channels, writing files with the given
+    /// compression.
+    pub fn new(compression: Compression) -> Profile {
+        Profile {
+            channels: Vec::new(),
+            compression,
+            cancel: None,
+        }
+    }
+
+    /// Adds a channel named `name` writing the records matched by `filter`
+    /// to `dir`. The directory is created when the first file is processed.
+    pub fn add_channel<F, P>(&mut self, name: &str, filter: F, dir: P)
+    where
+        F: Filter + Send + Sync + 'static,
+        P: AsRef<Path>,
+    {
+        self.channels.push(Channel {
+            name: name.to_string(),
+            filter: Arc::new(filter),
+            dir: dir.as_ref().to_path_buf(),
+        });
+    }
+
+    /// Returns the names of the channels.
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(|c| c.name.as_str())
+    }
+
+    /// Sets a cancellation token. Once it is set to `true`, `process` stops
+    /// with `NfdumpError::Cancelled`, leaving the channel files incomplete.
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Processes the file at `src`, writing one file per channel, and returns
+    /// the results in channel order.
+    pub fn process<P: AsRef<Path>>(&self, src: P) -> Result<Vec<ChannelResult>, NfdumpError> {
+        let src = src.as_ref();
+        let file_name = src.file_name().ok_or(NfdumpError::InvalidFile)?;
+        let mut reader = NfFileReader::new(BufReader::new(File::open(src)?))?;
+        if let Some(cancel) = &self.cancel {
+            reader.set_cancel(cancel.clone());
+        }
+        let ident = reader.get_ident();
+        let end = ident.iter().position(|&b| b == 0).unwrap_or(ident.len());
+        let ident = String::from_utf8_lossy(&ident[..end]).into_owned();
+
+        let mut splitter = Splitter::new();
+        let mut paths = Vec::with_capacity(self.channels.len());
+        for channel in &self.channels {
+            fs::create_dir_all(&channel.dir)?;
+            let path = channel.dir.join(file_name);
+            let mut writer = NfFileWriter::new(BufWriter::new(File::create(&path)?), self.compression)?;
+            writer.set_ident(&ident);
+            splitter.add_output(Arc::clone(&channel.filter), writer);
+            paths.push(path);
+        }
+
+        loop {
+            match reader.read_record() {
+                Ok(record) => {
+                    splitter.write_record_kind(&record)?;
+                }
+                Err(NfdumpError::EOF) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let stat_records: Vec<StatRecord> = (0..splitter.len())
+            .map(|i| splitter.writer(i).unwrap().stat_record())
+            .collect();
+        splitter.finish()?;
+
+        Ok(self
+            .channels
+            .iter()
+            .zip(paths)
+            .zip(stat_records)
+            .map(|((channel, path), stat_record)| ChannelResult {
+                name: channel.name.clone(),
+                path,
+                stat_record,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt;
+use crate::error::NfdumpError;
+use crate::nfx_v3::*;
+
+/// `Extension` is an element of V3 records that can be projected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Extension {
+    GenericFlow,
+    Ipv4Flow,
+    Ipv6Flow,
+    FlowMisc,
+    CntFlow,
+    Vlan,
+    AsRouting,
+    BgpNextHopV4,
+    BgpNextHopV6,
+    IpNextHopV4,
+    IpNextHopV6,
+    IpReceivedV4,
+    IpReceivedV6,
+    SamplerInfo,
+    NselXlatePort,
+    NselXlateIpv4,
+    NselXlateIpv6,
+    InPayload,
+    MacAddr,
+    Layer2,
+    Mpls,
+    TunV4,
+    TunV6,
+}
+
+impl Extension {
+    /// All extensions this crate decodes.
+    pub const ALL: [Extension; 23] = [
+        Extension::GenericFlow,
+        Extension::Ipv4Flow,
+        Extension::Ipv6Flow,
+        Extension::FlowMisc,
+        Extension::CntFlow,
+        Extension::Vlan,
+        Extension::AsRouting,
+        Extension::BgpNextHopV4,
+        Extension::BgpNextHopV6,
+        Extension::IpNextHopV4,
+        Extension::IpNextHopV6,
+        Extension::IpReceivedV4,
+        Extension::IpReceivedV6,
+        Extension::SamplerInfo,
+        Extension::NselXlatePort,
+        Extension::NselXlateIpv4,
+        Extension::NselXlateIpv6,
+        Extension::InPayload,
+        Extension::MacAddr,
+        Extension::Layer2,
+        Extension::Mpls,
+        Extension::TunV4,
+        Extension::TunV6,
+    ];
+
+    /// Returns the element id of the extension in V3 records.
+    pub fn id(&self) -> u16 {
+        match self {
+            Extension::GenericFlow => EXT_GENERIC_FLOW,
+            Extension::Ipv4Flow => EXT_IPV4_FLOW,
+            Extension::Ipv6Flow => EXT_IPV6_FLOW,
+            Extension::FlowMisc => EXT_FLOW_MISC,
+            Extension::CntFlow => EXT_CNT_FLOW,
+            Extension::Vlan => EXT_VLAN_FLOW,
+            Extension::AsRouting => EXT_AS_ROUTING,
+            Extension::BgpNextHopV4 => EXT_BGP_NEXT_HOP_V4,
+            Extension::BgpNextHopV6 => EXT_BGP_NEXT_HOP_V6,
+            Extension::IpNextHopV4 => EXT_IP_NEXT_HOP_V4,
+            Extension::IpNextHopV6 => EXT_IP_NEXT_HOP_V6,
+            Extension::IpReceivedV4 => EXT_IP_RECEIVED_V4,
+            Extension::IpReceivedV6 => EXT_IP_RECEIVED_V6,
+            Extension::SamplerInfo => EXT_SAMPLER_INFO,
+            Extension::NselXlatePort => EXT_NSEL_X_LATE_PORT,
+            Extension::NselXlateIpv4 => EXT_NSEL_X_LATE_IPV4,
+            Extension::NselXlateIpv6 => EXT_NSEL_X_LATE_IPV6,
+            Extension::InPayload => EXT_IN_PAYLOAD,
+            Extension::MacAddr => EXT_MAC_ADDR,
+            Extension::Layer2 => EXT_LAYER2,
+            Extension::Mpls => EXT_MPLS,
+            Extension::TunV4 => EXT_TUN_V4,
+            Extension::TunV6 => EXT_TUN_V6,
+        }
+    }
+
+    // Bit of the extension in a projection mask
+    fn bit(&self) -> u64 {
+        1 << (*self as u32)
+    }
+
+    fn from_id(id: u16) -> Option<Extension> {
+        Extension::ALL.iter().copied().find(|e| e.id() == id)
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// `Projection` is the set of V3 record extensions to decode. Elements
+/// outside of it are skipped without being decoded, and left `None` in the
+/// record; the record header is always decoded.
+///
+/// Records read with a projection are meant for reading only: writing them
+/// back drops the skipped elements.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::projection::{Extension, Projection};
+///
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// let projection: Projection = [Extension::GenericFlow, Extension::Ipv4Flow].into_iter().collect();
+/// reader.set_projection(projection);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Projection {
+    mask: u64,
+    unknown: bool,
+}
+
+impl Projection {
+    /// Returns a projection decoding only the record header.
+    pub fn new() -> Projection {
+        Projection { mask: 0, unknown: false }
+    }
+
+    /// Returns a projection decoding every element, the reader's default.
+    pub fn all() -> Projection {
+        Projection {
+            mask: Extension::ALL.iter().fold(0, |mask, e| mask | e.bit()),
+            unknown: true,
+        }
+    }
+
+    /// Returns the projection decoding the extensions carrying the named
+    /// fields, as listed by `schema::fields`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use nfdump::projection::{Extension, Projection};
+    ///
+    /// let projection = Projection::from_fields(&["t_first", "src_addr", "in_bytes"]).unwrap();
+    /// assert!(projection.contains(Extension::GenericFlow));
+    /// assert!(projection.contains(Extension::Ipv6Flow));
+    /// assert!(!projection.contains(Extension::FlowMisc));
+    /// ```
+    pub fn from_fields(names: &[&str]) -> Result<Projection, NfdumpError> {
+        let mut projection = Projection::new();
+        for name in names {
+            let extensions = field_extensions(name).ok_or(NfdumpError::ParseError)?;
+            for e in extensions {
+                projection.insert(*e);
+            }
+        }
+        Ok(projection)
+    }
+
+    /// Adds `extension` to the projection.
+    pub fn insert(&mut self, extension: Extension) {
+        self.mask |= extension.bit();
+    }
+
+    /// Returns `true` if the projection decodes `extension`.
+    pub fn contains(&self, extension: Extension) -> bool {
+        self.mask & extension.bit() != 0
+    }
+
+    /// Sets whether to keep the elements this crate does not decode in
+    /// `RecordV3::unknown`. Only `all` keeps them by default.
+    pub fn set_unknown(&mut self, unknown: bool) {
+        self.unknown = unknown;
+    }
+
+    pub(crate) fn contains_id(&self, id: u16) -> bool {
+        match Extension::from_id(id) {
+            Some(e) => self.contains(e),
+            None => self.unknown,
+        }
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection::all()
+    }
+}
+
+impl FromIterator<Extension> for Projection {
+    fn from_iter<I: IntoIterator<Item = Extension>>(iter: I) -> Projection {
+        let mut projection = Projection::new();
+        for e in iter {
+            projection.insert(e);
+        }
+        projection
+    }
+}
+
+// Extensions a V3 record carries a schema field in
+fn field_extensions(name: &str) -> Option<&'static [Extension]> {
+    use Extension::*;
+    Some(match name {
+        "export_sysid" => &[],
+        "t_first" | "t_last" | "t_received" | "proto" | "src_port" | "dst_port" | "icmp_type" | "icmp_code"
+        | "tcp_flags" | "fwd_status" | "src_tos" | "in_packets" | "in_bytes" => &[GenericFlow],
+        "src_addr" | "dst_addr" => &[Ipv4Flow, Ipv6Flow],
+        "out_packets" | "out_bytes" | "flows" => &[CntFlow],
+        "rev_tcp_flags" | "dst_tos" | "input_snmp" | "output_snmp" | "src_mask" | "dst_mask" | "direction"
+        | "biflow_dir" | "end_reason" => &[FlowMisc],
+        "src_as" | "dst_as" => &[AsRouting],
+        "ip_nexthop" => &[IpNextHopV4, IpNextHopV6],
+        "bgp_nexthop" => &[BgpNextHopV4, BgpNextHopV6],
+        "ip_router" => &[IpReceivedV4, IpReceivedV6],
+        "src_vlan" | "dst_vlan" => &[Vlan],
+        "in_src_mac" | "out_dst_mac" | "in_dst_mac" | "out_src_mac" => &[MacAddr],
+        "vlan_id" | "customer_vlan_id" | "post_vlan_id" | "post_customer_vlan_id" | "ingress" | "egress" | "vxlan"
+        | "ether_type" => &[Layer2],
+        "src_xlt_port" | "dst_xlt_port" => &[NselXlatePort],
+        "src_xlt_addr" | "dst_xlt_addr" => &[NselXlateIpv4, NselXlateIpv6],
+        "sampler_id" => &[SamplerInfo],
+        "tun_src_addr" | "tun_dst_addr" | "tun_proto" => &[TunV4, TunV6],
+        "in_payload" => &[InPayload],
+        _ if name.starts_with("mpls_label_") && crate::schema::field(name).is_some() => &[Mpls],
+        _ => return None,
+    })
+}
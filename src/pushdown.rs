@@ -0,0 +1,214 @@
+use crate::block::{TYPE_COMMON_RECORD, TYPE_RECORD_V3};
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+use crate::nfx_v3::{RecordV3, EXT_GENERIC_FLOW};
+use crate::projection::Extension;
+use crate::record::RecordKind;
+
+// V3 record header fields after the record header
+const V3_HEADER_SIZE: usize = 8;
+
+/// `Pushdown` holds cheap predicates a reader evaluates before decoding
+/// records: a time window, the record types to return and the extensions
+/// V3 records must carry.
+///
+/// Record types are checked on the record header, and V3 records are
+/// checked by scanning their element headers, so records ruled out are
+/// never decoded. With a time window, files whose stat record rules out
+/// the window are not read, and `MultiReader` skips the data blocks of
+/// indexed files outside the window without decompressing them.
+///
+/// Metadata records, like extension maps and exporters, are always read.
+/// Legacy common records are checked for the time window after decoding,
+/// and not for extensions.
+///
+/// A `Pushdown` is also a `Filter`, so it can be combined with other
+/// filters; `Filter::pushdown` collects the predicates of a filter that
+/// can be pushed down.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::projection::Extension;
+/// use nfdump::pushdown::Pushdown;
+///
+/// let mut pushdown = Pushdown::new();
+/// pushdown.set_time_window(1704067200000, 1704067500000);
+/// pushdown.require(Extension::Ipv6Flow);
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// reader.set_pushdown(pushdown);
+/// while let Ok(record) = reader.read_record() {
+///     println!("{:?}", record);
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pushdown {
+    time: Option<(u64, u64)>,
+    record_types: Option<Vec<u16>>,
+    required: Vec<Extension>,
+}
+
+impl Pushdown {
+    /// Returns a pushdown without predicates, keeping every record.
+    pub fn new() -> Pushdown {
+        Pushdown::default()
+    }
+
+    /// Only keeps flows overlapping the window from `start` to `end`, both
+    /// in milliseconds since the epoch. Flows without timestamps are
+    /// dropped.
+    pub fn set_time_window(&mut self, start: u64, end: u64) {
+        self.time = Some((start, end));
+    }
+
+    /// Only keeps records of the given types, as counted in
+    /// `IoStats::records_by_type`, e.g. `0x000b` for V3 records.
+    pub fn set_record_types(&mut self, types: &[u16]) {
+        self.record_types = Some(types.to_vec());
+    }
+
+    /// Only keeps V3 records carrying `extension`.
+    pub fn require(&mut self, extension: Extension) {
+        if !self.required.contains(&extension) {
+            self.required.push(extension);
+        }
+    }
+
+    /// Returns the time window, if any.
+    pub fn time_window(&self) -> Option<(u64, u64)> {
+        self.time
+    }
+
+    /// Returns `true` if the pushdown has no predicates.
+    pub fn is_empty(&self) -> bool {
+        self.time.is_none() && self.record_types.is_none() && self.required.is_empty()
+    }
+
+    /// Returns the pushdown keeping the records both `self` and `other`
+    /// keep.
+    pub fn and(mut self, other: &Pushdown) -> Pushdown {
+        self.time = match (self.time, other.time) {
+            (Some((s1, e1)), Some((s2, e2))) => Some((s1.max(s2), e1.min(e2))),
+            (a, b) => a.or(b),
+        };
+        self.record_types = match (self.record_types, &other.record_types) {
+            (Some(a), Some(b)) => Some(a.into_iter().filter(|t| b.contains(t)).collect()),
+            (a, b) => a.or_else(|| b.clone()),
+        };
+        for e in &other.required {
+            self.require(*e);
+        }
+        self
+    }
+
+    pub(crate) fn keeps_type(&self, rtype: u16) -> bool {
+        self.record_types.as_ref().is_none_or(|t| t.contains(&rtype))
+    }
+
+    // Returns `true` if the times of a flow fall into the window
+    fn keeps_times(&self, first: Option<u64>, last: Option<u64>) -> bool {
+        let Some((start, end)) = self.time else {
+            return true;
+        };
+        match (first, last) {
+            (Some(first), Some(last)) => first <= end && last >= start,
+            _ => false,
+        }
+    }
+
+    /// Checks a V3 record body by its element headers and the times of its
+    /// generic flow element, without decoding it. Malformed bodies are kept
+    /// for the decoder to report.
+    pub(crate) fn keeps_v3(&self, data: &[u8]) -> bool {
+        if self.time.is_none() && self.required.is_empty() {
+            return true;
+        }
+        let Some(num_elements) = data.get(..2).map(|b| u16::from_le_bytes([b[0], b[1]])) else {
+            return true;
+        };
+        let mut times = (None, None);
+        // element ids seen, all known ids are below 64
+        let mut seen: u64 = 0;
+        let mut offset = V3_HEADER_SIZE;
+        for _ in 0..num_elements {
+            let Some(h) = data.get(offset..offset + 4) else {
+                return true;
+            };
+            let id = u16::from_le_bytes([h[0], h[1]]);
+            let size = u16::from_le_bytes([h[2], h[3]]) as usize;
+            if size < 4 || offset + size > data.len() {
+                return true;
+            }
+            if id == EXT_GENERIC_FLOW && size >= 20 {
+                let first = &data[offset + 4..offset + 12];
+                let last = &data[offset + 12..offset + 20];
+                times = (
+                    Some(u64::from_le_bytes(first.try_into().unwrap())),
+                    Some(u64::from_le_bytes(last.try_into().unwrap())),
+                );
+            }
+            if id < 64 {
+                seen |= 1 << id;
+            }
+            offset += size;
+        }
+        self.required.iter().all(|e| seen & (1 << e.id()) != 0) && self.keeps_times(times.0, times.1)
+    }
+
+    /// Checks a decoded legacy record for the time window.
+    pub(crate) fn keeps_flow(&self, record: &dyn FlowRecord) -> bool {
+        self.keeps_times(record.first_msec(), record.last_msec())
+    }
+}
+
+impl Filter for Pushdown {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        self.keeps_flow(record)
+    }
+
+    fn matches_kind(&self, record: &RecordKind) -> bool {
+        match record {
+            RecordKind::Record(r) => self.keeps_type(TYPE_COMMON_RECORD) && self.keeps_flow(r),
+            RecordKind::RecordV3(r) => {
+                self.keeps_type(TYPE_RECORD_V3) && self.keeps_flow(r) && self.required.iter().all(|e| carries(r, *e))
+            }
+            _ => false,
+        }
+    }
+
+    fn pushdown(&self) -> Pushdown {
+        self.clone()
+    }
+}
+
+// Returns `true` if a decoded record carries `extension`
+fn carries(record: &RecordV3, extension: Extension) -> bool {
+    match extension {
+        Extension::GenericFlow => record.generic_flow.is_some(),
+        Extension::Ipv4Flow => record.ipv4_flow.is_some(),
+        Extension::Ipv6Flow => record.ipv6_flow.is_some(),
+        Extension::FlowMisc => record.flow_misc.is_some(),
+        Extension::CntFlow => record.cnt_flow.is_some(),
+        Extension::Vlan => record.vlan.is_some(),
+        Extension::AsRouting => record.as_routing.is_some(),
+        Extension::BgpNextHopV4 => record.bgp_next_hop_ipv4.is_some(),
+        Extension::BgpNextHopV6 => record.bgp_next_hop_ipv6.is_some(),
+        Extension::IpNextHopV4 => record.ip_next_hop_ipv4.is_some(),
+        Extension::IpNextHopV6 => record.ip_next_hop_ipv6.is_some(),
+        Extension::IpReceivedV4 => record.ip_received_ipv4.is_some(),
+        Extension::IpReceivedV6 => record.ip_received_ipv6.is_some(),
+        Extension::SamplerInfo => record.sampler_info.is_some(),
+        Extension::NselXlatePort => record.nsel_xlate_port.is_some(),
+        Extension::NselXlateIpv4 => record.nsel_xlate_ipv4.is_some(),
+        Extension::NselXlateIpv6 => record.nsel_xlate_ipv6.is_some(),
+        Extension::InPayload => record.in_payload.is_some(),
+        Extension::MacAddr => record.mac_address.is_some(),
+        Extension::Layer2 => record.layer2.is_some(),
+        Extension::Mpls => record.mpls.is_some(),
+        Extension::TunV4 => record.tun_ipv4.is_some(),
+        Extension::TunV6 => record.tun_ipv6.is_some(),
+    }
+}
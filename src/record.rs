@@ -1,34 +1,97 @@
 use std::io::{Cursor, Error};
+use std::sync::Arc;
+use crate::annotation::Annotations;
 use crate::error::NfdumpError;
-use byteorder::{LittleEndian, ReadBytesExt};
+use crate::flow::FlowRecord;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use crate::exporter::{ExporterInfo, SamplerV0Record};
+use crate::exporter::{InterfaceName, ExporterInfo, ExporterStatsRecord, SamplerRecord, SamplerV0Record};
 use crate::nffilev2::StatRecordV2;
-use crate::nfx::ExtensionMap;
-use crate::nfx_v3::RecordV3;
+use crate::block::TYPE_COMMON_RECORD;
+use crate::nfx::{extension_size, ExtensionMap};
+use crate::nfx_v3::{ExAsRouting, ExFlowMisc, ExGenericFlow, ExIpv4Flow, ExIpv6Flow, RecordHeaderV3, RecordV3};
 
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum RecordKind {
     ExtensionMap(ExtensionMap),
+    IfName(Vec<InterfaceName>),
     ExporterInfo(ExporterInfo),
+    ExporterStats(ExporterStatsRecord),
     SamplerV0(SamplerV0Record),
+    Sampler(SamplerRecord),
     Record(Record),
     RecordV3(RecordV3),
     Ident(Vec<u8>),
     Stat(StatRecordV2),
-    Unimplemented,
+    /// A record this crate does not decode, with its record type and body
+    /// (without the record header), so it can be copied unchanged.
+    Unimplemented { rtype: u16, data: Vec<u8> },
     None,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct NfFileRecordHeader {
     pub rtype: u16,
     pub size: u16,
 }
 
+impl RecordKind {
+    /// Returns the `sysid` of the exporter a flow record was received from.
+    pub fn exporter_sysid(&self) -> Option<u16> {
+        match self {
+            RecordKind::Record(r) => Some(r.exporter_sysid),
+            RecordKind::RecordV3(r) => Some(r.head.exporter_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the record as a `FlowRecord` if it is a flow record.
+    pub fn flow_record(&self) -> Option<&dyn FlowRecord> {
+        match self {
+            RecordKind::Record(r) => Some(r),
+            RecordKind::RecordV3(r) => Some(r),
+            _ => None,
+        }
+    }
+}
+
+/// `RecordEnvelope` carries a flow record together with the metadata needed to
+/// interpret it, so consumers don't need access to the reader.
+#[derive(Debug)]
+pub struct RecordEnvelope {
+    pub record: RecordKind,
+    /// The exporter the record was received from, if it is known.
+    pub exporter: Option<Arc<ExporterInfo>>,
+    /// Identification string of the file the record was read from.
+    pub ident: Arc<str>,
+    /// Source tag set by `MultiReader` when reading several inputs, `None`
+    /// otherwise.
+    pub source: Option<Arc<str>>,
+    /// Metadata attached by enrichment stages.
+    pub annotations: Annotations,
+}
+
+/// `RecordPosition` locates a record within an nfdump file.
+///
+/// Records live inside (possibly compressed) data blocks, so a position is the
+/// block's index and file offset plus the record's offset within the block's
+/// decompressed contents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RecordPosition {
+    /// Zero-based index of the data block holding the record.
+    pub block: u32,
+    /// File offset of the data block's header.
+    pub block_offset: u64,
+    /// Offset of the record header within the decompressed block.
+    pub record_offset: u64,
+}
+
 /// `Record` represents a flow record.
 #[derive(Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Record {
     pub head: NfFileRecordHeader,
     pub flags: u16,
@@ -56,12 +119,164 @@ pub struct Record {
     pub dst_as: Option<u32>,
 }
 
+impl From<&Record> for RecordV3 {
+    /// Translates a legacy common record into the V3 layout used by v2 files.
+    fn from(r: &Record) -> RecordV3 {
+        let mut v3 = RecordV3 {
+            head: RecordHeaderV3 {
+                exporter_id: r.exporter_sysid,
+                ..Default::default()
+            },
+            generic_flow: Some(ExGenericFlow {
+                msec_first: r.first as u64 * 1000 + r.msec_first as u64,
+                msec_last: r.last as u64 * 1000 + r.msec_last as u64,
+                msec_received: 0,
+                in_packets: r.packets,
+                in_bytes: r.bytes,
+                src_port: r.src_port,
+                dst_port: r.dst_port,
+                proto: r.prot,
+                tcp_flags: r.tcp_flags,
+                fwd_status: r.fwd_status,
+                src_tos: r.tos,
+            }),
+            ..Default::default()
+        };
+
+        match (r.src_addr, r.dst_addr) {
+            (IpAddr::V4(src_addr), IpAddr::V4(dst_addr)) => {
+                v3.ipv4_flow = Some(ExIpv4Flow { src_addr, dst_addr });
+            }
+            (src, dst) => {
+                v3.ipv6_flow = Some(ExIpv6Flow {
+                    src_addr: to_ipv6(src),
+                    dst_addr: to_ipv6(dst),
+                });
+            }
+        }
+
+        if r.input.is_some() || r.output.is_some() || r.bi_flow_dir != 0 || r.flow_end_reason != 0 {
+            v3.flow_misc = Some(ExFlowMisc {
+                input: r.input.unwrap_or(0),
+                output: r.output.unwrap_or(0),
+                src_mask: 0,
+                dst_mask: 0,
+                dir: 0,
+                dst_tos: 0,
+                bi_flow_dir: r.bi_flow_dir,
+                flow_end_reason: r.flow_end_reason,
+                rev_tcp_flags: 0,
+                fill: 0,
+            });
+        }
+
+        if r.src_as.is_some() || r.dst_as.is_some() {
+            v3.as_routing = Some(ExAsRouting {
+                src_as: r.src_as.unwrap_or(0),
+                dst_as: r.dst_as.unwrap_or(0),
+            });
+        }
+
+        v3
+    }
+}
+
+impl Record {
+    /// Returns the ids of the extensions needed to store the record's optional
+    /// fields, in the order they appear in the record.
+    pub(crate) fn extension_ids(&self) -> Vec<u16> {
+        self.extensions().map(|(id, _, _)| id).collect()
+    }
+
+    /// Returns the id and values of each extension needed by the record.
+    fn extensions(&self) -> impl Iterator<Item = (u16, u32, u32)> {
+        [(4, self.input, self.output), (6, self.src_as, self.dst_as)]
+            .into_iter()
+            .filter(|(_, a, b)| a.is_some() || b.is_some())
+            .map(|(id, a, b)| {
+                let (a, b) = (a.unwrap_or(0), b.unwrap_or(0));
+                // the 4 byte variant of an extension has the next id
+                let wide = a > u16::MAX as u32 || b > u16::MAX as u32;
+                (id + wide as u16, a, b)
+            })
+    }
+
+    /// Serializes the record as a common record referencing extension map
+    /// `map_id`, which must list the ids returned by `extension_ids`.
+    ///
+    /// The record header, `flags` and `ext_map` are derived from the record's
+    /// contents rather than taken from the record.
+    pub(crate) fn to_bytes(&self, map_id: u16) -> Vec<u8> {
+        let v6 = self.src_addr.is_ipv6() || self.dst_addr.is_ipv6();
+        let wide = self.packets > u32::MAX as u64 || self.bytes > u32::MAX as u64;
+        let flags = (v6 as u16) | if wide { 0x06 } else { 0 };
+        let size = 32
+            + if v6 { 32 } else { 8 }
+            + if wide { 16 } else { 8 }
+            + self.extensions().map(|(id, _, _)| extension_size(id) as usize).sum::<usize>();
+
+        let mut data = Vec::with_capacity(size);
+        data.write_u16::<LittleEndian>(TYPE_COMMON_RECORD).unwrap();
+        data.write_u16::<LittleEndian>(size as u16).unwrap();
+        data.write_u16::<LittleEndian>(flags).unwrap();
+        data.write_u16::<LittleEndian>(map_id).unwrap();
+        data.write_u16::<LittleEndian>(self.msec_first).unwrap();
+        data.write_u16::<LittleEndian>(self.msec_last).unwrap();
+        data.write_u32::<LittleEndian>(self.first).unwrap();
+        data.write_u32::<LittleEndian>(self.last).unwrap();
+        data.extend_from_slice(&[self.fwd_status, self.tcp_flags, self.prot, self.tos]);
+        data.write_u16::<LittleEndian>(self.src_port).unwrap();
+        data.write_u16::<LittleEndian>(self.dst_port).unwrap();
+        data.write_u16::<LittleEndian>(self.exporter_sysid).unwrap();
+        data.extend_from_slice(&[self.bi_flow_dir, self.flow_end_reason]);
+        for addr in [self.src_addr, self.dst_addr] {
+            match addr {
+                IpAddr::V4(a) if !v6 => data.write_u32::<LittleEndian>(a.into()).unwrap(),
+                a => data.write_u128::<LittleEndian>(to_ipv6(a).into()).unwrap(),
+            }
+        }
+        for value in [self.packets, self.bytes] {
+            if wide {
+                data.write_u64::<LittleEndian>(value).unwrap();
+            } else {
+                data.write_u32::<LittleEndian>(value as u32).unwrap();
+            }
+        }
+        for (id, a, b) in self.extensions() {
+            if extension_size(id) == 4 {
+                data.write_u16::<LittleEndian>(a as u16).unwrap();
+                data.write_u16::<LittleEndian>(b as u16).unwrap();
+            } else {
+                data.write_u32::<LittleEndian>(a).unwrap();
+                data.write_u32::<LittleEndian>(b).unwrap();
+            }
+        }
+        data
+    }
+}
+
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(a) => a.to_ipv6_mapped(),
+        IpAddr::V6(a) => a,
+    }
+}
+
 pub fn new_record(
     header: NfFileRecordHeader,
     data: Vec<u8>,
-    ext: &Vec<u16>,
+    ext: &[u16],
+) -> Result<Record, NfdumpError> {
+    parse_record(header, &data, ext)
+}
+
+/// Decodes a common record from its body.
+pub(crate) fn parse_record(
+    header: NfFileRecordHeader,
+    data: &[u8],
+    ext: &[u16],
 ) -> Result<Record, NfdumpError> {
-    let mut cursor = Cursor::new(&data);
+    let mut cursor = Cursor::new(data);
     let flags = cursor.read_u16::<LittleEndian>()?;
 
     Ok(Record {
@@ -93,7 +308,7 @@ pub fn new_record(
     })
 }
 
-fn read_addr(cur: &mut Cursor<&Vec<u8>>, flags: u16) -> Result<IpAddr, Error> {
+fn read_addr(cur: &mut Cursor<&[u8]>, flags: u16) -> Result<IpAddr, Error> {
     if flags & 0x01 == 0 {
         Ok(IpAddr::from(Ipv4Addr::from(cur.read_u32::<LittleEndian>()?)))
     } else {
@@ -101,7 +316,7 @@ fn read_addr(cur: &mut Cursor<&Vec<u8>>, flags: u16) -> Result<IpAddr, Error> {
     }
 }
 
-fn read_pkt_or_byt(cur: &mut Cursor<&Vec<u8>>, flags: u16) -> Result<u64, Error> {
+fn read_pkt_or_byt(cur: &mut Cursor<&[u8]>, flags: u16) -> Result<u64, Error> {
     if flags & 0x02 == 0 {
         Ok(cur.read_u32::<LittleEndian>()? as u64)
     } else {
@@ -109,7 +324,7 @@ fn read_pkt_or_byt(cur: &mut Cursor<&Vec<u8>>, flags: u16) -> Result<u64, Error>
     }
 }
 
-fn read_ext(cur: &mut Cursor<&Vec<u8>>, emap: &Vec<u16>, ext: u16) -> Result<u32, Error> {
+fn read_ext(cur: &mut Cursor<&[u8]>, emap: &[u16], ext: u16) -> Result<u32, Error> {
     if emap.contains(&ext) {
         Ok(cur.read_u16::<LittleEndian>()? as u32)
     } else if emap.contains(&(ext + 1)) {
@@ -0,0 +1,227 @@
+use std::fmt::Write;
+use crate::aggregate::{Aggregator, Counters, OrderBy, StatKey};
+use crate::health::{ExporterHealth, ExporterIssue};
+use crate::interface::InterfaceSummary;
+use crate::output::{format_msec, OutputOptions};
+
+/// Renders the top `n` groups of an aggregation as a text table in the
+/// layout of `nfdump -s`, followed by nfdump's summary line.
+///
+/// Timestamps are printed in UTC.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::aggregate::{Aggregator, OrderBy, StatElement};
+/// use nfdump::report::top_n;
+///
+/// let mut agg = Aggregator::new(StatElement::DstPort);
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     agg.add_kind(&record);
+/// }
+/// print!("{}", top_n(&agg, 10, OrderBy::Bytes));
+/// ```
+pub fn top_n(agg: &Aggregator, n: usize, order: OrderBy) -> String {
+    let totals = agg.totals();
+    let mut out = String::new();
+
+    _ = writeln!(out, "Top {} {} ordered by {}:", n, agg.element().title(), order.name());
+    _ = writeln!(
+        out,
+        "{:<23} {:>12} {:<5} {:>39} {:>14} {:>14} {:>14} {:>8} {:>8} {:>5}",
+        "Date first seen",
+        "Duration",
+        "Proto",
+        agg.element().title(),
+        "Flows(%)",
+        "Packets(%)",
+        "Bytes(%)",
+        "pps",
+        "bps",
+        "bpp"
+    );
+
+    for (key, c) in agg.top(n, order) {
+        let value = match key {
+            StatKey::Ip(ip) => ip.to_string(),
+            StatKey::Port(port) => port.to_string(),
+            StatKey::Proto(proto) => proto_name(proto),
+        };
+        let proto = match c.proto {
+            Some(p) => proto_name(p),
+            None => "any".to_string(),
+        };
+        let (pps, bps, bpp) = rates(&c);
+        _ = writeln!(
+            out,
+            "{:<23} {:>12} {:<5} {:>39} {:>14} {:>14} {:>14} {:>8} {:>8} {:>5}",
+            format_msec(c.first_msec),
+            format_duration(c.duration_msec()),
+            proto,
+            value,
+            with_percent(c.flows, totals.flows),
+            with_percent(c.packets, totals.packets),
+            with_percent(c.bytes, totals.bytes),
+            format_number(pps),
+            format_number(bps),
+            bpp
+        );
+    }
+
+    _ = writeln!(out);
+    out.push_str(&summary(totals));
+    out
+}
+
+/// Renders the interfaces of a summary as a text table ranked by bytes, with
+/// interface names resolved through `options`.
+///
+/// The asymmetry column is the share of bytes received minus the share sent,
+/// from -1.00 (only output) to 1.00 (only input).
+pub fn interface_report(interfaces: &InterfaceSummary, options: &OutputOptions) -> String {
+    let mut out = String::new();
+    _ = writeln!(
+        out,
+        "{:<16} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>9}",
+        "Interface", "In flows", "In pkts", "In bytes", "Out flows", "Out pkts", "Out bytes", "Asymmetry"
+    );
+    for (if_index, c) in interfaces.interfaces(OrderBy::Bytes) {
+        _ = writeln!(
+            out,
+            "{:<16} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>9.2}",
+            options.interface(if_index),
+            format_number(c.input.flows),
+            format_number(c.input.packets),
+            format_number(c.input.bytes),
+            format_number(c.output.flows),
+            format_number(c.output.packets),
+            format_number(c.output.bytes),
+            c.asymmetry()
+        );
+    }
+
+    _ = writeln!(out);
+    out.push_str(&summary(interfaces.totals()));
+    out
+}
+
+/// Renders one block per exporter with its address, the collector's
+/// statistics, samplers, record totals, time coverage and issues.
+pub fn exporter_report(health: &ExporterHealth) -> String {
+    let mut out = String::new();
+    for e in health.exporters() {
+        match &e.info {
+            Some(info) => _ = writeln!(out, "Exporter {}: {} (NetFlow v{}, id {})", e.sysid, info.address, info.version, info.id),
+            None => _ = writeln!(out, "Exporter {}: unknown", e.sysid),
+        }
+        if let Some(stat) = &e.stat {
+            _ = writeln!(
+                out,
+                "  Collector: {} flows, {} packets, {} sequence failures",
+                stat.flows, stat.packets, stat.sequence_failure
+            );
+        }
+        for s in &e.samplers {
+            _ = writeln!(
+                out,
+                "  Sampler {}: algorithm {}, packet interval {}, space interval {}",
+                s.id, s.algorithm, s.packet_interval, s.space_interval
+            );
+        }
+        let c = &e.records;
+        _ = writeln!(
+            out,
+            "  Records: {} flows, {} packets, {} bytes",
+            c.flows,
+            format_number(c.packets),
+            format_number(c.bytes)
+        );
+        if c.flows > 0 {
+            _ = writeln!(out, "  Coverage: {} - {}", format_msec(c.first_msec), format_msec(c.last_msec));
+        }
+        for issue in e.issues() {
+            let text = match issue {
+                ExporterIssue::Unknown => "records without exporter info".to_string(),
+                ExporterIssue::Silent => "no records".to_string(),
+                ExporterIssue::SequenceFailures(n) => format!("{} sequence failures", n),
+                ExporterIssue::FlowCountMismatch { reported, seen } => {
+                    format!("collector reported {} flows, {} seen", reported, seen)
+                }
+            };
+            _ = writeln!(out, "  Issue: {}", text);
+        }
+    }
+    out
+}
+
+/// Renders nfdump's summary line for a set of totals.
+pub fn summary(totals: &Counters) -> String {
+    let (pps, bps, bpp) = rates(totals);
+    format!(
+        "Summary: total flows: {}, total bytes: {}, total packets: {}, avg bps: {}, avg pps: {}, avg bpp: {}\n",
+        totals.flows,
+        format_number(totals.bytes),
+        format_number(totals.packets),
+        format_number(bps),
+        pps,
+        bpp
+    )
+}
+
+/// Returns the name nfdump prints for an IP protocol number.
+pub fn proto_name(proto: u8) -> String {
+    match proto {
+        1 => "ICMP".to_string(),
+        2 => "IGMP".to_string(),
+        4 => "IPIP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        41 => "IPv6".to_string(),
+        47 => "GRE".to_string(),
+        50 => "ESP".to_string(),
+        51 => "AH".to_string(),
+        58 => "ICMP6".to_string(),
+        89 => "OSPF".to_string(),
+        103 => "PIM".to_string(),
+        132 => "SCTP".to_string(),
+        p => p.to_string(),
+    }
+}
+
+/// Formats a number scaled like nfdump: `1.2 M`, `3.4 G`.
+pub fn format_number(n: u64) -> String {
+    const K: f64 = 1000.0;
+    let f = n as f64;
+    if f >= K * K * K * K {
+        format!("{:.1} T", f / (K * K * K * K))
+    } else if f >= K * K * K {
+        format!("{:.1} G", f / (K * K * K))
+    } else if f >= K * K {
+        format!("{:.1} M", f / (K * K))
+    } else {
+        n.to_string()
+    }
+}
+
+fn with_percent(value: u64, total: u64) -> String {
+    let percent = if total == 0 { 0.0 } else { value as f64 * 100.0 / total as f64 };
+    format!("{}({:4.1})", format_number(value), percent)
+}
+
+/// Returns packets per second, bits per second and bytes per packet.
+fn rates(c: &Counters) -> (u64, u64, u64) {
+    let duration = c.duration_msec() as u128;
+    let per_sec = |v: u128| v.checked_div(duration).map_or(0, |r| u64::try_from(r).unwrap_or(u64::MAX));
+    let pps = per_sec(c.packets as u128 * 1000);
+    let bps = per_sec(c.bytes as u128 * 8000);
+    let bpp = c.bytes.checked_div(c.packets).unwrap_or(0);
+    (pps, bps, bpp)
+}
+
+fn format_duration(msec: u64) -> String {
+    let secs = msec / 1000;
+    format!("{:02}:{:02}:{:02}.{:03}", secs / 3600, secs / 60 % 60, secs % 60, msec % 1000)
+}
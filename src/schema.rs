@@ -0,0 +1,324 @@
+use std::fmt;
+use std::net::IpAddr;
+use crate::flow::FlowRecord;
+use crate::mac::MacAddr;
+use crate::nfx_v3::RecordV3;
+use crate::record::RecordKind;
+
+/// `FieldType` is the type of the values of a record field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    /// IPv4 or IPv6 address.
+    Addr,
+    U8,
+    U16,
+    U32,
+    U64,
+    /// Milliseconds since the epoch.
+    Timestamp,
+    Mac,
+    /// Raw bytes, like a payload sample.
+    Bytes,
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldType::Addr => "addr",
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::Timestamp => "timestamp",
+            FieldType::Mac => "mac",
+            FieldType::Bytes => "bytes",
+        })
+    }
+}
+
+/// `Value` is the value of a record field.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    Addr(IpAddr),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Timestamp(u64),
+    Mac(MacAddr),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// Returns the type of the value.
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            Value::Addr(_) => FieldType::Addr,
+            Value::U8(_) => FieldType::U8,
+            Value::U16(_) => FieldType::U16,
+            Value::U32(_) => FieldType::U32,
+            Value::U64(_) => FieldType::U64,
+            Value::Timestamp(_) => FieldType::Timestamp,
+            Value::Mac(_) => FieldType::Mac,
+            Value::Bytes(_) => FieldType::Bytes,
+        }
+    }
+
+    /// Returns the value as an unsigned integer, for integer and timestamp
+    /// values.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::U8(v) => Some(v as u64),
+            Value::U16(v) => Some(v as u64),
+            Value::U32(v) => Some(v as u64),
+            Value::U64(v) | Value::Timestamp(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Timestamps are displayed in milliseconds and bytes in hex.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Addr(a) => write!(f, "{}", a),
+            Value::U8(v) => write!(f, "{}", v),
+            Value::U16(v) => write!(f, "{}", v),
+            Value::U32(v) => write!(f, "{}", v),
+            Value::U64(v) | Value::Timestamp(v) => write!(f, "{}", v),
+            Value::Mac(m) => write!(f, "{}", m),
+            Value::Bytes(b) => b.iter().try_for_each(|b| write!(f, "{:02x}", b)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Getter {
+    /// A field of every flow record.
+    Flow(fn(&dyn FlowRecord) -> Option<Value>),
+    /// A field only V3 records carry.
+    V3(fn(&RecordV3) -> Option<Value>),
+}
+
+/// `Field` describes a field flow records may carry.
+#[derive(Clone, Copy)]
+pub struct Field {
+    /// Name of the field, as in nfdump's JSON output where it has one.
+    pub name: &'static str,
+    pub field_type: FieldType,
+    pub description: &'static str,
+    getter: Getter,
+}
+
+impl Field {
+    const fn flow(
+        name: &'static str,
+        field_type: FieldType,
+        description: &'static str,
+        get: fn(&dyn FlowRecord) -> Option<Value>,
+    ) -> Field {
+        Field { name, field_type, description, getter: Getter::Flow(get) }
+    }
+
+    const fn v3(
+        name: &'static str,
+        field_type: FieldType,
+        description: &'static str,
+        get: fn(&RecordV3) -> Option<Value>,
+    ) -> Field {
+        Field { name, field_type, description, getter: Getter::V3(get) }
+    }
+
+    /// Returns `true` if legacy common records can carry the field, not
+    /// only V3 records.
+    pub fn is_common(&self) -> bool {
+        matches!(self.getter, Getter::Flow(_))
+    }
+
+    /// Returns the value of the field in `record`, or `None` if the record
+    /// does not carry it.
+    pub fn value(&self, record: &RecordKind) -> Option<Value> {
+        match (self.getter, record) {
+            (Getter::Flow(get), _) => get(record.flow_record()?),
+            (Getter::V3(get), RecordKind::RecordV3(r)) => get(r),
+            (Getter::V3(_), _) => None,
+        }
+    }
+
+    /// Returns `true` if `record` carries the field.
+    pub fn is_present(&self, record: &RecordKind) -> bool {
+        self.value(record).is_some()
+    }
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Field")
+            .field("name", &self.name)
+            .field("field_type", &self.field_type)
+            .field("common", &self.is_common())
+            .finish()
+    }
+}
+
+static FIELDS: &[Field] = &[
+    Field::flow("export_sysid", FieldType::U16, "Exporter system id", |r| r.exporter_sysid().map(Value::U16)),
+    Field::flow("t_first", FieldType::Timestamp, "First seen", |r| r.first_msec().map(Value::Timestamp)),
+    Field::flow("t_last", FieldType::Timestamp, "Last seen", |r| r.last_msec().map(Value::Timestamp)),
+    Field::v3("t_received", FieldType::Timestamp, "Time the collector received the flow", |r| {
+        r.generic_flow.as_ref().map(|g| Value::Timestamp(g.msec_received))
+    }),
+    Field::flow("proto", FieldType::U8, "IP protocol", |r| r.proto().map(Value::U8)),
+    Field::flow("src_addr", FieldType::Addr, "Source address", |r| r.src_addr().map(Value::Addr)),
+    Field::flow("dst_addr", FieldType::Addr, "Destination address", |r| r.dst_addr().map(Value::Addr)),
+    Field::flow("src_port", FieldType::U16, "Source port", |r| r.src_port().map(Value::U16)),
+    Field::flow("dst_port", FieldType::U16, "Destination port", |r| r.dst_port().map(Value::U16)),
+    Field::flow("icmp_type", FieldType::U8, "ICMP type", |r| r.icmp_type().map(Value::U8)),
+    Field::flow("icmp_code", FieldType::U8, "ICMP code", |r| r.icmp_code().map(Value::U8)),
+    Field::flow("tcp_flags", FieldType::U8, "Cumulated TCP flags", |r| r.tcp_flags().map(Value::U8)),
+    Field::v3("rev_tcp_flags", FieldType::U8, "Cumulated TCP flags of the reverse direction", |r| {
+        r.flow_misc.as_ref().map(|m| Value::U8(m.rev_tcp_flags))
+    }),
+    Field::flow("fwd_status", FieldType::U8, "Forwarding status", |r| r.fwd_status().map(Value::U8)),
+    Field::flow("src_tos", FieldType::U8, "Source type of service", |r| r.tos().map(Value::U8)),
+    Field::flow("dst_tos", FieldType::U8, "Destination type of service", |r| r.dst_tos().map(Value::U8)),
+    Field::flow("in_packets", FieldType::U64, "Packets", |r| r.packets().map(Value::U64)),
+    Field::flow("in_bytes", FieldType::U64, "Bytes", |r| r.bytes().map(Value::U64)),
+    Field::flow("out_packets", FieldType::U64, "Packets of the reverse direction", |r| r.out_packets().map(Value::U64)),
+    Field::flow("out_bytes", FieldType::U64, "Bytes of the reverse direction", |r| r.out_bytes().map(Value::U64)),
+    Field::flow("flows", FieldType::U64, "Number of flows the record represents", |r| Some(Value::U64(r.flows()))),
+    Field::flow("input_snmp", FieldType::U32, "Input interface index", |r| r.input().map(Value::U32)),
+    Field::flow("output_snmp", FieldType::U32, "Output interface index", |r| r.output().map(Value::U32)),
+    Field::flow("src_as", FieldType::U32, "Source AS", |r| r.src_as().map(Value::U32)),
+    Field::flow("dst_as", FieldType::U32, "Destination AS", |r| r.dst_as().map(Value::U32)),
+    Field::v3("src_mask", FieldType::U8, "Source prefix length", |r| r.flow_misc.as_ref().map(|m| Value::U8(m.src_mask))),
+    Field::v3("dst_mask", FieldType::U8, "Destination prefix length", |r| r.flow_misc.as_ref().map(|m| Value::U8(m.dst_mask))),
+    Field::v3("direction", FieldType::U8, "Flow direction, 0 ingress and 1 egress", |r| {
+        r.flow_misc.as_ref().map(|m| Value::U8(m.dir))
+    }),
+    Field::v3("biflow_dir", FieldType::U8, "Bidirectional flow direction", |r| {
+        r.flow_misc.as_ref().map(|m| Value::U8(m.bi_flow_dir))
+    }),
+    Field::v3("end_reason", FieldType::U8, "Flow end reason", |r| r.flow_misc.as_ref().map(|m| Value::U8(m.flow_end_reason))),
+    Field::flow("ip_nexthop", FieldType::Addr, "IP next hop", |r| r.next_hop().map(Value::Addr)),
+    Field::flow("bgp_nexthop", FieldType::Addr, "BGP next hop", |r| r.bgp_next_hop().map(Value::Addr)),
+    Field::v3("ip_router", FieldType::Addr, "Address of the exporting router", |r| {
+        match (&r.ip_received_ipv4, &r.ip_received_ipv6) {
+            (Some(e), _) => Some(Value::Addr(IpAddr::V4(e.ip))),
+            (_, Some(e)) => Some(Value::Addr(IpAddr::V6(e.ip))),
+            _ => None,
+        }
+    }),
+    Field::v3("src_vlan", FieldType::U32, "Source VLAN", |r| r.vlan.as_ref().map(|v| Value::U32(v.src_vlan))),
+    Field::v3("dst_vlan", FieldType::U32, "Destination VLAN", |r| r.vlan.as_ref().map(|v| Value::U32(v.dst_vlan))),
+    Field::v3("in_src_mac", FieldType::Mac, "Incoming source MAC address", |r| r.mac_address.as_ref().map(|m| Value::Mac(m.in_src_mac))),
+    Field::v3("out_dst_mac", FieldType::Mac, "Outgoing destination MAC address", |r| {
+        r.mac_address.as_ref().map(|m| Value::Mac(m.out_dst_mac))
+    }),
+    Field::v3("in_dst_mac", FieldType::Mac, "Incoming destination MAC address", |r| {
+        r.mac_address.as_ref().map(|m| Value::Mac(m.in_dst_mac))
+    }),
+    Field::v3("out_src_mac", FieldType::Mac, "Outgoing source MAC address", |r| {
+        r.mac_address.as_ref().map(|m| Value::Mac(m.out_src_mac))
+    }),
+    Field::v3("vlan_id", FieldType::U16, "Layer 2 VLAN id", |r| r.layer2.as_ref().map(|l| Value::U16(l.vlan_id))),
+    Field::v3("customer_vlan_id", FieldType::U16, "Layer 2 customer VLAN id", |r| {
+        r.layer2.as_ref().map(|l| Value::U16(l.customer_vlan_id))
+    }),
+    Field::v3("post_vlan_id", FieldType::U16, "Layer 2 VLAN id after the exporter", |r| {
+        r.layer2.as_ref().map(|l| Value::U16(l.post_vlan_id))
+    }),
+    Field::v3("post_customer_vlan_id", FieldType::U16, "Layer 2 customer VLAN id after the exporter", |r| {
+        r.layer2.as_ref().map(|l| Value::U16(l.post_customer_vlan_id))
+    }),
+    Field::v3("ingress", FieldType::U32, "Layer 2 ingress interface", |r| r.layer2.as_ref().map(|l| Value::U32(l.ingress))),
+    Field::v3("egress", FieldType::U32, "Layer 2 egress interface", |r| r.layer2.as_ref().map(|l| Value::U32(l.egress))),
+    Field::v3("vxlan", FieldType::U64, "VXLAN network identifier", |r| r.layer2.as_ref().map(|l| Value::U64(l.vx_lan))),
+    Field::v3("ether_type", FieldType::U16, "Ethernet type", |r| r.layer2.as_ref().map(|l| Value::U16(l.ether_type))),
+    Field::v3("mpls_label_1", FieldType::U32, "MPLS label 1", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_1))),
+    Field::v3("mpls_label_2", FieldType::U32, "MPLS label 2", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_2))),
+    Field::v3("mpls_label_3", FieldType::U32, "MPLS label 3", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_3))),
+    Field::v3("mpls_label_4", FieldType::U32, "MPLS label 4", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_4))),
+    Field::v3("mpls_label_5", FieldType::U32, "MPLS label 5", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_5))),
+    Field::v3("mpls_label_6", FieldType::U32, "MPLS label 6", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_6))),
+    Field::v3("mpls_label_7", FieldType::U32, "MPLS label 7", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_7))),
+    Field::v3("mpls_label_8", FieldType::U32, "MPLS label 8", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_8))),
+    Field::v3("mpls_label_9", FieldType::U32, "MPLS label 9", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_9))),
+    Field::v3("mpls_label_10", FieldType::U32, "MPLS label 10", |r| r.mpls.as_ref().map(|m| Value::U32(m.mpls_label_10))),
+    Field::v3("src_xlt_addr", FieldType::Addr, "NAT translated source address", |r| {
+        match (&r.nsel_xlate_ipv4, &r.nsel_xlate_ipv6) {
+            (Some(x), _) => Some(Value::Addr(IpAddr::V4(x.src_addr))),
+            (_, Some(x)) => Some(Value::Addr(IpAddr::V6(x.src_addr))),
+            _ => None,
+        }
+    }),
+    Field::v3("dst_xlt_addr", FieldType::Addr, "NAT translated destination address", |r| {
+        match (&r.nsel_xlate_ipv4, &r.nsel_xlate_ipv6) {
+            (Some(x), _) => Some(Value::Addr(IpAddr::V4(x.dst_addr))),
+            (_, Some(x)) => Some(Value::Addr(IpAddr::V6(x.dst_addr))),
+            _ => None,
+        }
+    }),
+    Field::v3("src_xlt_port", FieldType::U16, "NAT translated source port", |r| {
+        r.nsel_xlate_port.as_ref().map(|p| Value::U16(p.src_port))
+    }),
+    Field::v3("dst_xlt_port", FieldType::U16, "NAT translated destination port", |r| {
+        r.nsel_xlate_port.as_ref().map(|p| Value::U16(p.dst_port))
+    }),
+    Field::v3("sampler_id", FieldType::U64, "Sampler selector id", |r| r.sampler_info.as_ref().map(|s| Value::U64(s.selector_id))),
+    Field::v3("tun_src_addr", FieldType::Addr, "Tunnel source address", |r| {
+        match (&r.tun_ipv4, &r.tun_ipv6) {
+            (Some(t), _) => Some(Value::Addr(IpAddr::V4(t.src_addr))),
+            (_, Some(t)) => Some(Value::Addr(IpAddr::V6(t.src_addr))),
+            _ => None,
+        }
+    }),
+    Field::v3("tun_dst_addr", FieldType::Addr, "Tunnel destination address", |r| {
+        match (&r.tun_ipv4, &r.tun_ipv6) {
+            (Some(t), _) => Some(Value::Addr(IpAddr::V4(t.dst_addr))),
+            (_, Some(t)) => Some(Value::Addr(IpAddr::V6(t.dst_addr))),
+            _ => None,
+        }
+    }),
+    Field::v3("tun_proto", FieldType::U8, "Tunnel protocol", |r| {
+        match (&r.tun_ipv4, &r.tun_ipv6) {
+            (Some(t), _) => Some(Value::U8(t.proto)),
+            (_, Some(t)) => Some(Value::U8(t.proto)),
+            _ => None,
+        }
+    }),
+    Field::v3("in_payload", FieldType::Bytes, "Sample of the first packet's payload", |r| r.in_payload.clone().map(Value::Bytes)),
+];
+
+/// Returns all fields flow records can carry, in a stable order suitable
+/// for table columns.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::schema;
+///
+/// let mut reader = NfFileReader::new(File::open("nfcapd.202401010000").unwrap()).unwrap();
+/// let record = reader.read_record().unwrap();
+/// for field in schema::fields() {
+///     match field.value(&record) {
+///         Some(value) => println!("{} ({}): {}", field.name, field.field_type, value),
+///         None => println!("{} ({}): -", field.name, field.field_type),
+///     }
+/// }
+/// ```
+pub fn fields() -> &'static [Field] {
+    FIELDS
+}
+
+/// Returns the field named `name`.
+pub fn field(name: &str) -> Option<&'static Field> {
+    FIELDS.iter().find(|f| f.name == name)
+}
+
+/// Returns the fields `record` carries with their values.
+pub fn values(record: &RecordKind) -> Vec<(&'static Field, Value)> {
+    FIELDS.iter().filter_map(|f| Some((f, f.value(record)?))).collect()
+}
@@ -0,0 +1,529 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::compress::Compression;
+use crate::error::{ErrorContext, NfdumpError};
+use crate::flow::FlowRecord;
+use crate::read_full;
+use crate::nfx_v3::{ExFlowMisc, ExGenericFlow, ExIpNextHopIpv4, ExIpNextHopIpv6, ExIpv4Flow, ExIpv6Flow, RecordHeaderV3, RecordV3};
+
+const SILK_MAGIC: u32 = 0xdeadbeef;
+// first file version with header entries, used by SiLK 1.0 and later
+const SILK_FILE_VERSION: u8 = 16;
+
+// release written into the headers of new files
+const SILK_VERSION: u32 = 3_190_000;
+
+const FT_RWIPV6ROUTING: u8 = 0x0c;
+const FT_RWIPV6ROUTING_SIZE: usize = 88;
+const FT_RWGENERIC: u8 = 0x16;
+
+const SK_COMPMETHOD_NONE: u8 = 0;
+const SK_COMPMETHOD_LZO1X: u8 = 2;
+
+// set in the TCP state byte of IPv6 records
+const SK_TCPSTATE_IPV6: u8 = 0x80;
+
+// uncompressed size of the blocks of compressed files
+const SILK_BLOCK_SIZE: usize = 65536;
+// largest uncompressed block SiLK reads, and the LZO bound of its
+// compressed size
+const SILK_MAX_BLOCK_SIZE: usize = 1048576;
+const SILK_MAX_COMPRESSED_SIZE: usize = SILK_MAX_BLOCK_SIZE + SILK_MAX_BLOCK_SIZE / 16 + 64 + 3;
+
+/// `SilkHeader` is the generic header of a SiLK flow file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SilkHeader {
+    /// `true` if record fields are stored in big-endian byte order.
+    pub big_endian: bool,
+    /// Record format id, such as `0x0c` for FT_RWIPV6ROUTING.
+    pub file_format: u8,
+    /// Header layout version.
+    pub file_version: u8,
+    /// Compression method (0 none, 1 zlib, 2 LZO, 3 snappy).
+    pub compression: u8,
+    /// Version of the SiLK release that wrote the file.
+    pub silk_version: u32,
+    /// Size of each record in bytes.
+    pub record_size: u16,
+    /// Version of the record format.
+    pub record_version: u16,
+}
+
+/// Fields shared by the supported SiLK record formats.
+struct SilkFlow {
+    start: u64,
+    elapsed: u32,
+    src_port: u16,
+    dst_port: u16,
+    proto: u8,
+    sensor: u16,
+    tcp_flags: u8,
+    input: u16,
+    output: u16,
+    packets: u32,
+    bytes: u32,
+    src_addr: IpAddr,
+    dst_addr: IpAddr,
+    next_hop: IpAddr,
+}
+
+impl SilkFlow {
+    fn into_record(self) -> RecordV3 {
+        let mut v3 = RecordV3 {
+            head: RecordHeaderV3 {
+                exporter_id: self.sensor,
+                ..Default::default()
+            },
+            generic_flow: Some(ExGenericFlow {
+                msec_first: self.start,
+                msec_last: self.start + self.elapsed as u64,
+                msec_received: 0,
+                in_packets: self.packets as u64,
+                in_bytes: self.bytes as u64,
+                src_port: self.src_port,
+                dst_port: self.dst_port,
+                proto: self.proto,
+                tcp_flags: self.tcp_flags,
+                fwd_status: 0,
+                src_tos: 0,
+            }),
+            flow_misc: Some(ExFlowMisc {
+                input: self.input as u32,
+                output: self.output as u32,
+                src_mask: 0,
+                dst_mask: 0,
+                dir: 0,
+                dst_tos: 0,
+                bi_flow_dir: 0,
+                flow_end_reason: 0,
+                rev_tcp_flags: 0,
+                fill: 0,
+            }),
+            ..Default::default()
+        };
+
+        match (self.src_addr, self.dst_addr) {
+            (IpAddr::V4(src_addr), IpAddr::V4(dst_addr)) => {
+                v3.ipv4_flow = Some(ExIpv4Flow { src_addr, dst_addr });
+            }
+            (src, dst) => {
+                v3.ipv6_flow = Some(ExIpv6Flow {
+                    src_addr: to_ipv6(src),
+                    dst_addr: to_ipv6(dst),
+                });
+            }
+        }
+
+        match self.next_hop {
+            IpAddr::V4(ip) if !ip.is_unspecified() => v3.ip_next_hop_ipv4 = Some(ExIpNextHopIpv4 { ip }),
+            IpAddr::V6(ip) if !ip.is_unspecified() => v3.ip_next_hop_ipv6 = Some(ExIpNextHopIpv6 { ip }),
+            _ => {}
+        }
+
+        v3
+    }
+}
+
+fn to_ipv6(addr: IpAddr) -> Ipv6Addr {
+    match addr {
+        IpAddr::V4(a) => a.to_ipv6_mapped(),
+        IpAddr::V6(a) => a,
+    }
+}
+
+/// Reads integers of a record in the file's byte order.
+struct Fields<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Fields<'_> {
+    fn bytes<const N: usize>(&self, off: usize) -> [u8; N] {
+        self.data[off..off + N].try_into().unwrap()
+    }
+
+    fn u16(&self, off: usize) -> u16 {
+        let b = self.bytes(off);
+        if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }
+    }
+
+    fn u32(&self, off: usize) -> u32 {
+        let b = self.bytes(off);
+        if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+    }
+
+    fn u64(&self, off: usize) -> u64 {
+        let b = self.bytes(off);
+        if self.big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) }
+    }
+
+    /// Reads an address stored in network byte order as 16 bytes, holding an
+    /// IPv4-mapped address unless `v6` is set.
+    fn addr(&self, off: usize, v6: bool) -> IpAddr {
+        let b: [u8; 16] = self.bytes(off);
+        if v6 {
+            IpAddr::V6(Ipv6Addr::from(b))
+        } else {
+            IpAddr::V4(Ipv4Addr::new(b[12], b[13], b[14], b[15]))
+        }
+    }
+}
+
+/// Decodes an FT_RWIPV6ROUTING version 1 record.
+fn parse_ipv6_routing(f: &Fields) -> SilkFlow {
+    let v6 = f.data[23] & SK_TCPSTATE_IPV6 != 0;
+    SilkFlow {
+        start: f.u64(0),
+        elapsed: f.u32(8),
+        src_port: f.u16(12),
+        dst_port: f.u16(14),
+        proto: f.data[16],
+        sensor: f.u16(18),
+        tcp_flags: f.data[20],
+        input: f.u16(28),
+        output: f.u16(30),
+        packets: f.u32(32),
+        bytes: f.u32(36),
+        src_addr: f.addr(40, v6),
+        dst_addr: f.addr(56, v6),
+        next_hop: f.addr(72, v6),
+    }
+}
+
+/// Decodes an FT_RWGENERIC version 5 record, which holds IPv4 flows only.
+fn parse_generic(f: &Fields) -> SilkFlow {
+    SilkFlow {
+        start: f.u64(40),
+        elapsed: f.u32(48),
+        src_port: f.u16(8),
+        dst_port: f.u16(10),
+        proto: f.data[12],
+        sensor: f.u16(14),
+        tcp_flags: f.data[16],
+        input: f.u16(24),
+        output: f.u16(26),
+        packets: f.u32(28),
+        bytes: f.u32(32),
+        src_addr: IpAddr::V4(Ipv4Addr::from(f.u32(0))),
+        dst_addr: IpAddr::V4(Ipv4Addr::from(f.u32(4))),
+        next_hop: IpAddr::V4(Ipv4Addr::from(f.u32(36))),
+    }
+}
+
+/// Returns the record decoder for a file format, record version and size.
+fn record_parser(header: &SilkHeader) -> Option<fn(&Fields) -> SilkFlow> {
+    match (header.file_format, header.record_version, header.record_size) {
+        (FT_RWIPV6ROUTING, 1, 88) => Some(parse_ipv6_routing),
+        (FT_RWGENERIC, 5, 52) => Some(parse_generic),
+        _ => None,
+    }
+}
+
+/// `SilkReader` reads flow files written by the SiLK tool suite and decodes
+/// their records into `RecordV3`, so SiLK and nfdump archives can be
+/// processed with the same code.
+///
+/// Files must use the FT_RWIPV6ROUTING (version 1) or FT_RWGENERIC (version
+/// 5) format, uncompressed or LZO compressed. The SiLK sensor id becomes the
+/// record's exporter id; SiLK's class, type and application fields are not
+/// carried over.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use nfdump::silk::SilkReader;
+///
+/// let file = BufReader::new(File::open("path_to_your_rwf_file").unwrap());
+/// let mut reader = SilkReader::new(file).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     println!("{:?}", record);
+/// }
+/// ```
+pub struct SilkReader<R> {
+    reader: R,
+    header: SilkHeader,
+    parse: fn(&Fields) -> SilkFlow,
+    // decompressed contents of the current block and the read position
+    block: Vec<u8>,
+    block_offset: usize,
+    record: Vec<u8>,
+    records_read: u32,
+}
+
+impl<R: Read> SilkReader<R> {
+    /// Reads the file header, including its header entries.
+    ///
+    /// Fails with `NfdumpError::InvalidFile` if the input is not a SiLK file,
+    /// `NfdumpError::UnsupportedVersion` for record formats other than the
+    /// supported ones and `NfdumpError::UnsupportedCompression` for zlib or
+    /// snappy compressed files.
+    pub fn new(mut reader: R) -> Result<Self, NfdumpError> {
+        let mut buf = [0; 16];
+        reader.read_exact(&mut buf)?;
+        if u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != SILK_MAGIC {
+            return Err(NfdumpError::InvalidFile);
+        }
+
+        let header = SilkHeader {
+            big_endian: buf[4] & 0x01 != 0,
+            file_format: buf[5],
+            file_version: buf[6],
+            compression: buf[7],
+            silk_version: u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+            record_size: u16::from_be_bytes([buf[12], buf[13]]),
+            record_version: u16::from_be_bytes([buf[14], buf[15]]),
+        };
+        if header.file_version < SILK_FILE_VERSION {
+            return Err(NfdumpError::UnsupportedVersion);
+        }
+        let parse = record_parser(&header).ok_or(NfdumpError::UnsupportedVersion)?;
+        if !matches!(header.compression, SK_COMPMETHOD_NONE | SK_COMPMETHOD_LZO1X) {
+            return Err(NfdumpError::UnsupportedCompression);
+        }
+
+        // header entries end with entry id 0, whose body pads the header
+        loop {
+            let mut entry = [0; 8];
+            reader.read_exact(&mut entry)?;
+            let id = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let len = u32::from_be_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            let body = (len as u64).checked_sub(8).ok_or(NfdumpError::InvalidFile)?;
+            std::io::copy(&mut (&mut reader).take(body), &mut std::io::sink())?;
+            if id == 0 {
+                break;
+            }
+        }
+
+        Ok(SilkReader {
+            reader,
+            record: vec![0; header.record_size as usize],
+            header,
+            parse,
+            block: Vec::new(),
+            block_offset: 0,
+            records_read: 0,
+        })
+    }
+
+    /// Returns the file header.
+    pub fn header(&self) -> &SilkHeader {
+        &self.header
+    }
+
+    /// Reads the next record. Returns `NfdumpError::EOF` after the last one.
+    pub fn read_record(&mut self) -> Result<RecordV3, NfdumpError> {
+        let size = self.record.len();
+        let n = if self.header.compression == SK_COMPMETHOD_NONE {
+            read_full(&mut self.reader, &mut self.record)?
+        } else {
+            if self.block_offset == self.block.len() && !self.read_block()? {
+                return Err(NfdumpError::EOF);
+            }
+            let n = size.min(self.block.len() - self.block_offset);
+            self.record[..n].copy_from_slice(&self.block[self.block_offset..self.block_offset + n]);
+            self.block_offset += n;
+            n
+        };
+        if n == 0 {
+            return Err(NfdumpError::EOF);
+        }
+        if n < size {
+            return Err(NfdumpError::UnexpectedEof {
+                context: ErrorContext {
+                    record: Some(self.records_read),
+                    expected: Some(size),
+                    available: Some(n),
+                    ..Default::default()
+                },
+            });
+        }
+
+        self.records_read += 1;
+        let fields = Fields {
+            data: &self.record,
+            big_endian: self.header.big_endian,
+        };
+        Ok((self.parse)(&fields).into_record())
+    }
+
+    /// Reads and decompresses the next block, or returns `false` at the end
+    /// of the file.
+    fn read_block(&mut self) -> Result<bool, NfdumpError> {
+        let mut buf = [0; 8];
+        match read_full(&mut self.reader, &mut buf)? {
+            0 => return Ok(false),
+            8 => {}
+            n => {
+                return Err(NfdumpError::UnexpectedEof {
+                    context: ErrorContext {
+                        expected: Some(8),
+                        available: Some(n),
+                        ..Default::default()
+                    },
+                })
+            }
+        }
+        let comp_len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        let uncomp_len = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
+        if comp_len > SILK_MAX_COMPRESSED_SIZE || uncomp_len > SILK_MAX_BLOCK_SIZE {
+            return Err(NfdumpError::BlockTooLarge {
+                limit: SILK_MAX_BLOCK_SIZE,
+                context: ErrorContext {
+                    expected: Some(uncomp_len.max(comp_len)),
+                    ..Default::default()
+                },
+            });
+        }
+
+        let mut data = vec![0; comp_len];
+        self.reader.read_exact(&mut data)?;
+        self.block = minilzo::decompress(&data, uncomp_len).map_err(|_| NfdumpError::ParseError)?;
+        self.block_offset = 0;
+        Ok(true)
+    }
+}
+
+/// `SilkWriter` writes flow records as a SiLK FT_RWIPV6ROUTING file, the
+/// format `rwcat` and `rwcut` read by default, so nfdump archives can be
+/// handed to teams using the SiLK tool suite.
+///
+/// Any `FlowRecord` can be written. Counters are saturated at the 32 bits
+/// SiLK keeps and the exporter sysid becomes the SiLK sensor id. Records
+/// without addresses are written with unspecified IPv4 addresses.
+///
+/// Buffered records are flushed on `finish` or, ignoring errors, when the
+/// writer is dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufWriter;
+/// use nfdump::{Compression, NfFileReader};
+/// use nfdump::record::RecordKind;
+/// use nfdump::silk::SilkWriter;
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// let out = BufWriter::new(File::create("flows.rwf").unwrap());
+/// let mut writer = SilkWriter::new(out, Compression::Lzo).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     if let RecordKind::RecordV3(r) = record {
+///         writer.write_record(&r).unwrap();
+///     }
+/// }
+/// writer.finish().unwrap();
+/// ```
+pub struct SilkWriter<W: Write> {
+    writer: Option<W>,
+    compression: Compression,
+    // records not yet written, a block's worth when compressing
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> SilkWriter<W> {
+    /// Creates a new `SilkWriter` and writes the file header. Records are
+    /// stored uncompressed or LZO compressed; other codecs fail with
+    /// `NfdumpError::UnsupportedCompression`.
+    pub fn new(mut writer: W, compression: Compression) -> Result<Self, NfdumpError> {
+        let method = match compression {
+            Compression::None => SK_COMPMETHOD_NONE,
+            Compression::Lzo => SK_COMPMETHOD_LZO1X,
+            _ => return Err(NfdumpError::UnsupportedCompression),
+        };
+
+        let mut header = Vec::with_capacity(FT_RWIPV6ROUTING_SIZE);
+        header.extend_from_slice(&SILK_MAGIC.to_be_bytes());
+        // big-endian records
+        header.extend_from_slice(&[0x01, FT_RWIPV6ROUTING, SILK_FILE_VERSION, method]);
+        header.extend_from_slice(&SILK_VERSION.to_be_bytes());
+        header.extend_from_slice(&(FT_RWIPV6ROUTING_SIZE as u16).to_be_bytes());
+        header.extend_from_slice(&1u16.to_be_bytes());
+        // the end entry pads the header to a multiple of the record size
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(&(FT_RWIPV6ROUTING_SIZE as u32 - 16).to_be_bytes());
+        header.resize(FT_RWIPV6ROUTING_SIZE, 0);
+        writer.write_all(&header)?;
+
+        Ok(SilkWriter {
+            writer: Some(writer),
+            compression,
+            buffer: Vec::with_capacity(SILK_BLOCK_SIZE),
+        })
+    }
+
+    /// Writes a flow record.
+    pub fn write_record(&mut self, record: &dyn FlowRecord) -> Result<(), NfdumpError> {
+        let unspecified = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let src = record.src_addr().unwrap_or(unspecified);
+        let dst = record.dst_addr().unwrap_or(unspecified);
+        let next_hop = record.next_hop().unwrap_or(unspecified);
+        let v6 = src.is_ipv6() || dst.is_ipv6() || next_hop.is_ipv6();
+
+        let start = record.first_msec().unwrap_or(0);
+        let elapsed = record.last_msec().unwrap_or(start).saturating_sub(start);
+        let saturate = |v: Option<u64>| v.unwrap_or(0).min(u32::MAX as u64) as u32;
+
+        let b = &mut self.buffer;
+        b.extend_from_slice(&start.to_be_bytes());
+        b.extend_from_slice(&saturate(Some(elapsed)).to_be_bytes());
+        b.extend_from_slice(&record.src_port().unwrap_or(0).to_be_bytes());
+        b.extend_from_slice(&record.dst_port().unwrap_or(0).to_be_bytes());
+        // protocol, class/type, sensor
+        b.push(record.proto().unwrap_or(0));
+        b.push(0);
+        b.extend_from_slice(&record.exporter_sysid().unwrap_or(0).to_be_bytes());
+        // all, initial and session TCP flags, TCP state
+        b.extend_from_slice(&[record.tcp_flags().unwrap_or(0), 0, 0, if v6 { SK_TCPSTATE_IPV6 } else { 0 }]);
+        // application, memo
+        b.extend_from_slice(&[0; 4]);
+        b.extend_from_slice(&(record.input().unwrap_or(0).min(u16::MAX as u32) as u16).to_be_bytes());
+        b.extend_from_slice(&(record.output().unwrap_or(0).min(u16::MAX as u32) as u16).to_be_bytes());
+        b.extend_from_slice(&saturate(record.packets()).to_be_bytes());
+        b.extend_from_slice(&saturate(record.bytes()).to_be_bytes());
+        for addr in [src, dst, next_hop] {
+            b.extend_from_slice(&to_ipv6(addr).octets());
+        }
+
+        if self.buffer.len() + FT_RWIPV6ROUTING_SIZE > SILK_BLOCK_SIZE {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered records, as one block when compressing.
+    fn flush_block(&mut self) -> Result<(), NfdumpError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let writer = self.writer.as_mut().unwrap();
+        if self.compression == Compression::Lzo {
+            let data = minilzo::compress(&self.buffer)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Lzo compression failed"))?;
+            writer.write_all(&(data.len() as u32).to_be_bytes())?;
+            writer.write_all(&(self.buffer.len() as u32).to_be_bytes())?;
+            writer.write_all(&data)?;
+        } else {
+            writer.write_all(&self.buffer)?;
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes the remaining records and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, NfdumpError> {
+        self.flush_block()?;
+        let mut writer = self.writer.take().unwrap();
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Drop for SilkWriter<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            _ = self.flush_block();
+        }
+    }
+}
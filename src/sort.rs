@@ -0,0 +1,309 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use byteorder::{LittleEndian, ReadBytesExt};
+use crate::error::NfdumpError;
+use crate::flow::FlowRecord;
+use crate::nfx_v3::RecordV3;
+use crate::record::{NfFileRecordHeader, RecordKind};
+use crate::NfFileReader;
+
+const DEFAULT_MAX_RECORDS: usize = 1_000_000;
+
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// `SortKey` is the field records are ordered by.
+///
+/// As with `nfdump -O`, time keys sort in ascending order and volume keys
+/// in descending order, largest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// First seen timestamp, oldest first.
+    StartTime,
+    /// Last seen timestamp, oldest first.
+    EndTime,
+    /// Byte count, largest first.
+    Bytes,
+    /// Packet count, largest first.
+    Packets,
+    /// Flow duration, longest first.
+    Duration,
+}
+
+impl SortKey {
+    /// Returns a value whose ascending order is the order of the key.
+    fn value(&self, record: &RecordV3) -> u64 {
+        match self {
+            SortKey::StartTime => record.first_msec().unwrap_or(0),
+            SortKey::EndTime => record.last_msec().unwrap_or(0),
+            SortKey::Bytes => !record.bytes().unwrap_or(0),
+            SortKey::Packets => !record.packets().unwrap_or(0),
+            SortKey::Duration => {
+                let first = record.first_msec().unwrap_or(0);
+                !record.last_msec().unwrap_or(0).saturating_sub(first)
+            }
+        }
+    }
+}
+
+/// `Sorter` sorts the records of one or more files with bounded memory.
+///
+/// Records are collected in runs of at most `max_records`; full runs are
+/// sorted and spilled to temporary files, which are merged while iterating.
+/// The sort is stable, so records with equal keys keep their input order.
+/// Legacy records are converted to V3 records.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::sort::{SortKey, Sorter};
+///
+/// let mut sorter = Sorter::new(SortKey::StartTime);
+/// sorter.set_max_records(100_000);
+/// for record in sorter.sort_files(&["nfcapd.1", "nfcapd.2"]).unwrap() {
+///     println!("{:?}", record.unwrap());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sorter {
+    key: SortKey,
+    reverse: bool,
+    max_records: usize,
+    temp_dir: PathBuf,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Sorter {
+    /// Creates a sorter ordering by `key`, holding up to one million records
+    /// in memory and spilling to the system temporary directory.
+    pub fn new(key: SortKey) -> Sorter {
+        Sorter {
+            key,
+            reverse: false,
+            max_records: DEFAULT_MAX_RECORDS,
+            temp_dir: std::env::temp_dir(),
+            cancel: None,
+        }
+    }
+
+    /// Reverses the order of the key.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    /// Sets the number of records held in memory before a run is spilled.
+    pub fn set_max_records(&mut self, max_records: usize) {
+        self.max_records = max_records.max(1);
+    }
+
+    /// Sets the directory spill files are written to.
+    pub fn set_temp_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.temp_dir = dir.as_ref().to_path_buf();
+    }
+
+    /// Sets a cancellation token. Once it is set to `true`, sorting stops
+    /// with `NfdumpError::Cancelled`.
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Sorts all flow records of the files at `paths`.
+    pub fn sort_files<P: AsRef<Path>>(&self, paths: &[P]) -> Result<SortedRecords, NfdumpError> {
+        let mut state = SortState::new(self);
+        for path in paths {
+            let mut reader = NfFileReader::new(BufReader::new(File::open(path)?))?;
+            if let Some(cancel) = &self.cancel {
+                reader.set_cancel(cancel.clone());
+            }
+            loop {
+                match reader.read_record() {
+                    Ok(RecordKind::RecordV3(r)) => state.push(r)?,
+                    Ok(RecordKind::Record(r)) => state.push(RecordV3::from(&r))?,
+                    Ok(_) => {}
+                    Err(NfdumpError::EOF) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        state.finish()
+    }
+
+    /// Sorts the given records.
+    pub fn sort<I: IntoIterator<Item = RecordV3>>(&self, records: I) -> Result<SortedRecords, NfdumpError> {
+        let mut state = SortState::new(self);
+        for r in records {
+            state.push(r)?;
+        }
+        state.finish()
+    }
+
+    fn value(&self, record: &RecordV3) -> u64 {
+        let value = self.key.value(record);
+        if self.reverse {
+            !value
+        } else {
+            value
+        }
+    }
+}
+
+struct SortState<'a> {
+    sorter: &'a Sorter,
+    run: Vec<(u64, RecordV3)>,
+    spills: Vec<SpillFile>,
+}
+
+impl<'a> SortState<'a> {
+    fn new(sorter: &'a Sorter) -> SortState<'a> {
+        SortState {
+            sorter,
+            run: Vec::new(),
+            spills: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, record: RecordV3) -> Result<(), NfdumpError> {
+        self.run.push((self.sorter.value(&record), record));
+        if self.run.len() >= self.sorter.max_records {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<(), NfdumpError> {
+        if self.sorter.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+            return Err(NfdumpError::Cancelled);
+        }
+        self.run.sort_by_key(|(k, _)| *k);
+        let spill = SpillFile::create(&self.sorter.temp_dir)?;
+        let mut writer = BufWriter::new(File::create(&spill.path)?);
+        for (_, record) in self.run.drain(..) {
+            writer.write_all(&record.to_bytes()?)?;
+        }
+        writer.flush()?;
+        self.spills.push(spill);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<SortedRecords, NfdumpError> {
+        if self.spills.is_empty() {
+            self.run.sort_by_key(|(k, _)| *k);
+            return Ok(SortedRecords {
+                inner: Inner::Memory(self.run.into_iter()),
+            });
+        }
+        if !self.run.is_empty() {
+            self.spill()?;
+        }
+
+        let mut runs = Vec::with_capacity(self.spills.len());
+        let mut heap = BinaryHeap::new();
+        for (idx, spill) in self.spills.into_iter().enumerate() {
+            let mut run = Run {
+                reader: BufReader::new(File::open(&spill.path)?),
+                _spill: spill,
+            };
+            if let Some(record) = run.next_record()? {
+                heap.push(Reverse((self.sorter.value(&record), idx)));
+                runs.push((run, Some(record)));
+            } else {
+                runs.push((run, None));
+            }
+        }
+
+        Ok(SortedRecords {
+            inner: Inner::Merge {
+                sorter: self.sorter.clone(),
+                runs,
+                heap,
+                pending_error: None,
+            },
+        })
+    }
+}
+
+/// Temporary spill file, removed when dropped.
+struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+    fn create(dir: &Path) -> Result<SpillFile, NfdumpError> {
+        let n = SPILL_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("nfdump-sort-{}-{}.tmp", std::process::id(), n));
+        File::create(&path)?;
+        Ok(SpillFile { path })
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+struct Run {
+    reader: BufReader<File>,
+    _spill: SpillFile,
+}
+
+impl Run {
+    fn next_record(&mut self) -> Result<Option<RecordV3>, NfdumpError> {
+        let rtype = match self.reader.read_u16::<LittleEndian>() {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let size = self.reader.read_u16::<LittleEndian>()?;
+        let mut data = vec![0; (size as usize).saturating_sub(4)];
+        self.reader.read_exact(&mut data)?;
+        RecordV3::new(NfFileRecordHeader { rtype, size }, data).map(Some)
+    }
+}
+
+enum Inner {
+    Memory(std::vec::IntoIter<(u64, RecordV3)>),
+    Merge {
+        sorter: Sorter,
+        runs: Vec<(Run, Option<RecordV3>)>,
+        heap: BinaryHeap<Reverse<(u64, usize)>>,
+        // a run's read error, returned after the record taken before it
+        pending_error: Option<NfdumpError>,
+    },
+}
+
+/// Iterator over sorted records returned by `Sorter`.
+pub struct SortedRecords {
+    inner: Inner,
+}
+
+impl Iterator for SortedRecords {
+    type Item = Result<RecordV3, NfdumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            Inner::Memory(records) => records.next().map(|(_, r)| Ok(r)),
+            Inner::Merge { sorter, runs, heap, pending_error } => {
+                if let Some(e) = pending_error.take() {
+                    return Some(Err(e));
+                }
+                let Reverse((_, idx)) = heap.pop()?;
+                let (run, head) = &mut runs[idx];
+                let record = head.take()?;
+                match run.next_record() {
+                    Ok(Some(next)) => {
+                        heap.push(Reverse((sorter.value(&next), idx)));
+                        *head = Some(next);
+                    }
+                    Ok(None) => {}
+                    Err(e) => *pending_error = Some(e),
+                }
+                Some(Ok(record))
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::io::{Seek, Write};
+use crate::error::NfdumpError;
+use crate::filter::Filter;
+use crate::nfx_v3::RecordV3;
+use crate::record::RecordKind;
+use crate::writer::NfFileWriter;
+
+/// `Splitter` routes records to several output files, each selected by its
+/// own filter. A record is written to every output whose filter matches it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::{Compression, NfFileReader};
+/// use nfdump::filter::AddrField;
+/// use nfdump::prefix::PrefixFilter;
+/// use nfdump::split::Splitter;
+/// use nfdump::writer::NfFileWriter;
+///
+/// let mut splitter = Splitter::new();
+/// let customer = PrefixFilter::new(AddrField::SrcOrDst, ["192.0.2.0/24".parse().unwrap()]);
+/// let out = NfFileWriter::new(File::create("customer.nf").unwrap(), Compression::Zstd).unwrap();
+/// splitter.add_output(customer, out);
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// while let Ok(record) = reader.read_record() {
+///     splitter.write_record_kind(&record).unwrap();
+/// }
+/// splitter.finish().unwrap();
+/// ```
+pub struct Splitter<W: Write + Seek> {
+    outputs: Vec<(Box<dyn Filter + Send + Sync>, NfFileWriter<W>)>,
+}
+
+impl<W: Write + Seek> Splitter<W> {
+    /// Creates a splitter without outputs.
+    pub fn new() -> Splitter<W> {
+        Splitter { outputs: Vec::new() }
+    }
+
+    /// Adds an output receiving the records matched by `filter` and returns
+    /// its index.
+    pub fn add_output<F: Filter + Send + Sync + 'static>(&mut self, filter: F, writer: NfFileWriter<W>) -> usize {
+        self.outputs.push((Box::new(filter), writer));
+        self.outputs.len() - 1
+    }
+
+    /// Returns the number of outputs.
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Returns `true` if the splitter has no outputs.
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Returns the writer of output `idx`.
+    pub fn writer(&self, idx: usize) -> Option<&NfFileWriter<W>> {
+        self.outputs.get(idx).map(|(_, w)| w)
+    }
+
+    /// Writes `record` to every matching output and returns the number of
+    /// outputs it was written to.
+    pub fn write_record(&mut self, record: &RecordV3) -> Result<usize, NfdumpError> {
+        let mut matched = 0;
+        for (filter, writer) in &mut self.outputs {
+            if filter.matches(record) {
+                writer.write_record(record)?;
+                matched += 1;
+            }
+        }
+        Ok(matched)
+    }
+
+    /// Writes a flow record of either version to every matching output.
+    /// Legacy records are converted to V3 records, other records are ignored.
+    pub fn write_record_kind(&mut self, record: &RecordKind) -> Result<usize, NfdumpError> {
+        match record {
+            RecordKind::RecordV3(r) => self.write_record(r),
+            RecordKind::Record(r) => {
+                if !self.outputs.iter().any(|(f, _)| f.matches(r)) {
+                    return Ok(0);
+                }
+                self.write_record(&RecordV3::from(r))
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// Finishes every output file and returns the underlying writers in the
+    /// order the outputs were added.
+    ///
+    /// All outputs are finished even if one of them fails; the first error
+    /// is returned.
+    pub fn finish(self) -> Result<Vec<W>, NfdumpError> {
+        let mut writers = Vec::with_capacity(self.outputs.len());
+        let mut error = None;
+        for (_, writer) in self.outputs {
+            match writer.finish() {
+                Ok(w) => writers.push(w),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(writers),
+        }
+    }
+}
+
+impl<W: Write + Seek> Default for Splitter<W> {
+    fn default() -> Self {
+        Splitter::new()
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+/// `ReadStats` cross-checks what a reader actually decoded against the counts
+/// declared in the file and block headers, exposing silently truncated or
+/// corrupted files.
+#[derive(Debug, Default, Clone)]
+pub struct ReadStats {
+    /// Number of data blocks declared in the file header.
+    pub blocks_expected: u32,
+    /// Number of data blocks read so far.
+    pub blocks_read: u32,
+    /// Sum of the record counts declared by the blocks read so far.
+    pub records_expected: u64,
+    /// Number of records (of any kind) decoded so far.
+    pub records_read: u64,
+    /// Blocks whose decoded record count differs from their header.
+    pub block_mismatches: Vec<BlockMismatch>,
+}
+
+impl ReadStats {
+    /// Returns `true` if no mismatch has been detected so far. The block count
+    /// is only known to match once the reader has reached the end of the file.
+    pub fn is_consistent(&self) -> bool {
+        self.block_mismatches.is_empty() && self.blocks_read <= self.blocks_expected
+    }
+}
+
+/// `BlockMismatch` records a data block whose record count did not match.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockMismatch {
+    /// Zero-based index of the data block.
+    pub block: u32,
+    /// Number of records declared in the block header.
+    pub expected: u32,
+    /// Number of records decoded from the block.
+    pub found: u32,
+}
+
+/// `IoStats` accounts for the work and memory of a reader, for capacity
+/// planning of long-running services. Collection is enabled with
+/// `NfFileReader::set_io_stats`.
+#[derive(Debug, Default, Clone)]
+pub struct IoStats {
+    /// Bytes of data blocks read from the file, including block headers.
+    pub bytes_read: u64,
+    /// Bytes of decompressed block contents decoded into records.
+    pub bytes_decompressed: u64,
+    /// Size of the largest compressed block read.
+    pub peak_block_size: usize,
+    /// Decompressed size of the largest block decoded.
+    pub peak_decompressed_size: usize,
+    /// Number of records decoded per record type, including internal records
+    /// such as extension maps.
+    pub records_by_type: BTreeMap<u16, u64>,
+    /// Number of records skipped by the predicates set with
+    /// `NfFileReader::set_pushdown`, not counted in `records_by_type`.
+    pub records_pruned: u64,
+    /// Number of errors returned while reading records, other than the end
+    /// of the file.
+    pub decode_errors: u64,
+}
+
+impl IoStats {
+    /// Returns the number of records decoded, of any type.
+    pub fn records(&self) -> u64 {
+        self.records_by_type.values().sum()
+    }
+
+    /// Returns decompressed bytes per byte read, or `None` before any block
+    /// was read.
+    pub fn compression_ratio(&self) -> Option<f64> {
+        match self.bytes_read {
+            0 => None,
+            n => Some(self.bytes_decompressed as f64 / n as f64),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+
+/// `ForwardingStatus` is the forwarding status of a flow (IPFIX element 89):
+/// the fate of its packets in the upper two bits and a reason code in the
+/// lower six.
+///
+/// Each variant holds the full status byte, so unknown reason codes are
+/// kept.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::status::ForwardingStatus;
+///
+/// let status = ForwardingStatus::from(129);
+/// assert_eq!(status, ForwardingStatus::Dropped(129));
+/// assert_eq!(status.to_string(), "Drop/ACL deny");
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ForwardingStatus {
+    Unknown(u8),
+    Forwarded(u8),
+    Dropped(u8),
+    Consumed(u8),
+}
+
+impl ForwardingStatus {
+    /// Returns the reason code, the lower six bits of the status.
+    pub fn reason_code(&self) -> u8 {
+        u8::from(*self) & 0x3f
+    }
+
+    /// Returns the name of the reason, if it is a registered one.
+    pub fn reason(&self) -> Option<&'static str> {
+        Some(match u8::from(*self) {
+            0 | 64 | 128 | 192 => "Unknown",
+            65 => "Fragmented",
+            66 => "Not Fragmented",
+            129 => "ACL deny",
+            130 => "ACL drop",
+            131 => "Unroutable",
+            132 => "Adjacency",
+            133 => "Fragmentation and DF set",
+            134 => "Bad header checksum",
+            135 => "Bad total length",
+            136 => "Bad header length",
+            137 => "Bad TTL",
+            138 => "Policer",
+            139 => "WRED",
+            140 => "RPF",
+            141 => "For us",
+            142 => "Bad output interface",
+            143 => "Hardware",
+            193 => "Punt adjacency",
+            194 => "Incomplete adjacency",
+            195 => "For us",
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the packets of the flow were dropped.
+    pub fn is_dropped(&self) -> bool {
+        matches!(self, ForwardingStatus::Dropped(_))
+    }
+}
+
+impl From<u8> for ForwardingStatus {
+    fn from(value: u8) -> ForwardingStatus {
+        match value >> 6 {
+            0 => ForwardingStatus::Unknown(value),
+            1 => ForwardingStatus::Forwarded(value),
+            2 => ForwardingStatus::Dropped(value),
+            _ => ForwardingStatus::Consumed(value),
+        }
+    }
+}
+
+impl From<ForwardingStatus> for u8 {
+    fn from(value: ForwardingStatus) -> u8 {
+        match value {
+            ForwardingStatus::Unknown(v)
+            | ForwardingStatus::Forwarded(v)
+            | ForwardingStatus::Dropped(v)
+            | ForwardingStatus::Consumed(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for ForwardingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = match self {
+            ForwardingStatus::Unknown(_) => "Unknown",
+            ForwardingStatus::Forwarded(_) => "Forward",
+            ForwardingStatus::Dropped(_) => "Drop",
+            ForwardingStatus::Consumed(_) => "Consume",
+        };
+        match self.reason() {
+            Some(reason) => write!(f, "{}/{}", class, reason),
+            None => write!(f, "{}/{}", class, self.reason_code()),
+        }
+    }
+}
+
+/// `FlowEndReason` is why the exporter ended a flow (IPFIX element 136).
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::status::FlowEndReason;
+///
+/// assert_eq!(FlowEndReason::from(2), FlowEndReason::ActiveTimeout);
+/// assert_eq!(FlowEndReason::ActiveTimeout.to_string(), "active timeout");
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FlowEndReason {
+    /// No packets were seen for the idle timeout.
+    IdleTimeout,
+    /// The flow was exported while still active.
+    ActiveTimeout,
+    /// The end of the flow was detected, e.g. a TCP FIN.
+    EndOfFlow,
+    /// The flow was ended by an external event, e.g. a shutdown.
+    ForcedEnd,
+    /// The flow was ended because the exporter ran out of resources.
+    LackOfResources,
+    /// An unassigned value.
+    Other(u8),
+}
+
+impl From<u8> for FlowEndReason {
+    fn from(value: u8) -> FlowEndReason {
+        match value {
+            1 => FlowEndReason::IdleTimeout,
+            2 => FlowEndReason::ActiveTimeout,
+            3 => FlowEndReason::EndOfFlow,
+            4 => FlowEndReason::ForcedEnd,
+            5 => FlowEndReason::LackOfResources,
+            v => FlowEndReason::Other(v),
+        }
+    }
+}
+
+impl From<FlowEndReason> for u8 {
+    fn from(value: FlowEndReason) -> u8 {
+        match value {
+            FlowEndReason::IdleTimeout => 1,
+            FlowEndReason::ActiveTimeout => 2,
+            FlowEndReason::EndOfFlow => 3,
+            FlowEndReason::ForcedEnd => 4,
+            FlowEndReason::LackOfResources => 5,
+            FlowEndReason::Other(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for FlowEndReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlowEndReason::IdleTimeout => f.write_str("idle timeout"),
+            FlowEndReason::ActiveTimeout => f.write_str("active timeout"),
+            FlowEndReason::EndOfFlow => f.write_str("end of flow"),
+            FlowEndReason::ForcedEnd => f.write_str("forced end"),
+            FlowEndReason::LackOfResources => f.write_str("lack of resources"),
+            FlowEndReason::Other(v) => write!(f, "{}", v),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::catalog::Catalog;
+use crate::compress::{compress, Compression, CompressionSettings};
+use crate::error::NfdumpError;
+use crate::hooks::BlockInfo;
+use crate::NfFileReader;
+
+/// `BlockCompression` describes how a data block is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockCompression {
+    /// Zero-based index of the data block.
+    pub index: u32,
+    /// File offset of the block header.
+    pub offset: u64,
+    /// Number of records declared in the block header.
+    pub num_records: u32,
+    /// Codec of the block. Blocks nfdump stored as they are, because
+    /// compressing them did not pay off, are `Compression::None`.
+    pub compression: Compression,
+    /// Size of the block data in the file, without the block header.
+    pub compressed: u64,
+    /// Size of the decompressed block data.
+    pub decompressed: u64,
+    /// Size of the block data compressed with each trial codec, in the
+    /// order the codecs were given.
+    pub trials: Vec<(Compression, u64)>,
+}
+
+impl BlockCompression {
+    /// Returns decompressed bytes per stored byte, or `None` for an empty
+    /// block.
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.decompressed, self.compressed)
+    }
+}
+
+/// `FileCompression` reports the compressed and decompressed sizes of the
+/// data blocks of a file, and optionally the sizes they would have with
+/// other codecs, to evaluate recompressing an archive before transcoding
+/// it.
+///
+/// Every data block is read and decompressed, and compressed again with
+/// each trial codec, so this costs about as much as a transcode run
+/// without writing anything.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::storage::FileCompression;
+/// use nfdump::Compression;
+///
+/// let report = FileCompression::read_with_trials("nfcapd.202401010000", &[Compression::Zstd]).unwrap();
+/// println!(
+///     "{}: {} of {} bytes, {} with zstd",
+///     report.compression,
+///     report.compressed(),
+///     report.decompressed(),
+///     report.trial_size(Compression::Zstd).unwrap(),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCompression {
+    /// Compression of the file as declared in its header.
+    pub compression: Compression,
+    /// Size of the whole file, if read from a path.
+    pub file_size: Option<u64>,
+    pub blocks: Vec<BlockCompression>,
+}
+
+impl FileCompression {
+    /// Reports on the file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<FileCompression, NfdumpError> {
+        FileCompression::read_with_trials(path, &[])
+    }
+
+    /// Reports on the file at `path`, compressing each block with the
+    /// `trials` codecs.
+    pub fn read_with_trials<P: AsRef<Path>>(path: P, trials: &[Compression]) -> Result<FileCompression, NfdumpError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let mut report = FileCompression::from_reader(NfFileReader::new(BufReader::new(file))?, trials)?;
+        report.file_size = Some(file_size);
+        Ok(report)
+    }
+
+    /// Reports on the data blocks of a freshly opened reader, compressing
+    /// each block with the `trials` codecs.
+    pub fn from_reader<R: Read + Seek>(mut reader: NfFileReader<R>, trials: &[Compression]) -> Result<FileCompression, NfdumpError> {
+        let compression = reader.header.compression().unwrap_or_default();
+        let settings: Vec<CompressionSettings> = trials.iter().map(|c| CompressionSettings::new(*c)).collect();
+        let block_info = Arc::new(Mutex::new(None));
+        let info = block_info.clone();
+        reader.set_on_block_start(move |b: &BlockInfo| *info.lock().unwrap() = Some(*b));
+
+        let mut blocks = Vec::new();
+        while let Some((_, _, data)) = reader.read_raw_block()? {
+            let Some(info) = block_info.lock().unwrap().take() else {
+                continue;
+            };
+            let mut sizes = Vec::with_capacity(settings.len());
+            for s in &settings {
+                sizes.push((s.ctype, compress(s, &data)?.len() as u64));
+            }
+            blocks.push(BlockCompression {
+                index: info.index,
+                offset: info.offset,
+                num_records: info.num_records,
+                compression: info.flags.compression(compression),
+                compressed: info.size as u64,
+                decompressed: data.len() as u64,
+                trials: sizes,
+            });
+        }
+        Ok(FileCompression {
+            compression,
+            file_size: None,
+            blocks,
+        })
+    }
+
+    /// Returns the stored size of the data blocks.
+    pub fn compressed(&self) -> u64 {
+        self.blocks.iter().map(|b| b.compressed).sum()
+    }
+
+    /// Returns the decompressed size of the data blocks.
+    pub fn decompressed(&self) -> u64 {
+        self.blocks.iter().map(|b| b.decompressed).sum()
+    }
+
+    /// Returns decompressed bytes per stored byte, or `None` for a file
+    /// without data.
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.decompressed(), self.compressed())
+    }
+
+    /// Returns the size of the data blocks compressed with `compression`,
+    /// or `None` if it was not among the trial codecs.
+    pub fn trial_size(&self, compression: Compression) -> Option<u64> {
+        self.blocks.iter().map(|b| trial(&b.trials, compression)).sum()
+    }
+}
+
+/// `CodecTotals` sums up the data blocks stored with one codec.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CodecTotals {
+    pub compression: Compression,
+    pub blocks: u64,
+    pub compressed: u64,
+    pub decompressed: u64,
+}
+
+impl CodecTotals {
+    /// Returns decompressed bytes per stored byte, or `None` without data.
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.decompressed, self.compressed)
+    }
+}
+
+/// `ArchiveCompression` collects the `FileCompression` reports of many
+/// files, e.g. those of a `Catalog`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::catalog::Catalog;
+/// use nfdump::storage::ArchiveCompression;
+/// use nfdump::Compression;
+///
+/// let catalog = Catalog::scan("/var/cache/nfdump").unwrap();
+/// let report = ArchiveCompression::from_catalog(&catalog, &[Compression::Zstd]);
+/// for totals in report.by_codec() {
+///     println!("{}: {} blocks, ratio {:?}", totals.compression, totals.blocks, totals.ratio());
+/// }
+/// let now = report.compressed();
+/// let zstd = report.trial_size(Compression::Zstd).unwrap();
+/// println!("zstd saves {} bytes", now as i64 - zstd as i64);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveCompression {
+    pub files: Vec<(PathBuf, FileCompression)>,
+    /// Files that could not be read.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl ArchiveCompression {
+    /// Reports on the files at `paths`, compressing each block with the
+    /// `trials` codecs. Unreadable files are skipped.
+    pub fn scan<P: AsRef<Path>>(paths: &[P], trials: &[Compression]) -> ArchiveCompression {
+        let mut report = ArchiveCompression::default();
+        for path in paths {
+            let path = path.as_ref().to_path_buf();
+            match FileCompression::read_with_trials(&path, trials) {
+                Ok(file) => report.files.push((path, file)),
+                Err(_) => report.skipped.push(path),
+            }
+        }
+        report
+    }
+
+    /// Reports on the files of `catalog`.
+    pub fn from_catalog(catalog: &Catalog, trials: &[Compression]) -> ArchiveCompression {
+        let paths: Vec<PathBuf> = catalog.entries().iter().map(|e| catalog.root().join(&e.path)).collect();
+        ArchiveCompression::scan(&paths, trials)
+    }
+
+    /// Returns the totals of the data blocks per codec, in order of the
+    /// codec's first appearance.
+    pub fn by_codec(&self) -> Vec<CodecTotals> {
+        let mut totals: Vec<CodecTotals> = Vec::new();
+        for block in self.files.iter().flat_map(|(_, f)| &f.blocks) {
+            let i = match totals.iter().position(|t| t.compression == block.compression) {
+                Some(i) => i,
+                None => {
+                    totals.push(CodecTotals {
+                        compression: block.compression,
+                        blocks: 0,
+                        compressed: 0,
+                        decompressed: 0,
+                    });
+                    totals.len() - 1
+                }
+            };
+            totals[i].blocks += 1;
+            totals[i].compressed += block.compressed;
+            totals[i].decompressed += block.decompressed;
+        }
+        totals
+    }
+
+    /// Returns the stored size of the data blocks of all files.
+    pub fn compressed(&self) -> u64 {
+        self.files.iter().map(|(_, f)| f.compressed()).sum()
+    }
+
+    /// Returns the decompressed size of the data blocks of all files.
+    pub fn decompressed(&self) -> u64 {
+        self.files.iter().map(|(_, f)| f.decompressed()).sum()
+    }
+
+    /// Returns the size of the data blocks of all files compressed with
+    /// `compression`, or `None` if it was not among the trial codecs.
+    pub fn trial_size(&self, compression: Compression) -> Option<u64> {
+        self.files.iter().map(|(_, f)| f.trial_size(compression)).sum()
+    }
+}
+
+fn trial(trials: &[(Compression, u64)], compression: Compression) -> Option<u64> {
+    trials.iter().find(|(c, _)| *c == compression).map(|(_, size)| *size)
+}
+
+fn ratio(decompressed: u64, compressed: u64) -> Option<f64> {
+    match compressed {
+        0 => None,
+        n => Some(decompressed as f64 / n as f64),
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::error::NfdumpError;
+use crate::exporter::{ExporterStat, SamplerRecord};
+use crate::record::NfFileRecordHeader;
+
+const CACHE_MAGIC: &[u8; 4] = b"NFTC";
+const CACHE_VERSION: u16 = 1;
+// set in a field id on the wire when an enterprise number follows
+const ENTERPRISE_BIT: u16 = 0x8000;
+// length of variable length IPFIX fields
+const VARIABLE_LENGTH: u16 = 65535;
+
+// information elements of sampling and exporter statistics options
+const IE_SAMPLING_INTERVAL: u16 = 34;
+const IE_SAMPLING_ALGORITHM: u16 = 35;
+const IE_EXPORTED_FLOW_RECORD_TOTAL_COUNT: u16 = 42;
+const IE_SAMPLER_ID: u16 = 48;
+const IE_SAMPLER_MODE: u16 = 49;
+const IE_SAMPLER_RANDOM_INTERVAL: u16 = 50;
+const IE_SELECTOR_ID: u16 = 302;
+const IE_SELECTOR_ALGORITHM: u16 = 304;
+const IE_SAMPLING_PACKET_INTERVAL: u16 = 305;
+const IE_SAMPLING_PACKET_SPACE: u16 = 306;
+
+/// `TemplateField` is one field specifier of a NetFlow v9 or IPFIX template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TemplateField {
+    /// Information element id, without the enterprise bit.
+    pub id: u16,
+    /// Field length in bytes, 65535 for variable length IPFIX fields.
+    pub length: u16,
+    /// Private enterprise number of enterprise specific IPFIX fields.
+    pub enterprise: Option<u32>,
+}
+
+/// `Template` describes the layout of data records of a NetFlow v9 or IPFIX
+/// template or option template.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Template {
+    pub id: u16,
+    /// Number of leading scope fields, 0 for regular templates.
+    pub scope_field_count: u16,
+    pub fields: Vec<TemplateField>,
+}
+
+impl Template {
+    /// Returns `true` for option templates.
+    pub fn is_options(&self) -> bool {
+        self.scope_field_count > 0
+    }
+
+    /// Returns the length of a data record, or `None` if the template has
+    /// variable length fields.
+    pub fn record_length(&self) -> Option<usize> {
+        self.fields
+            .iter()
+            .map(|f| (f.length != VARIABLE_LENGTH).then_some(f.length as usize))
+            .sum()
+    }
+
+    /// Splits a data record into the values of the template's fields, or
+    /// returns `None` if the record is too short.
+    pub fn values<'a>(&'a self, data: &'a [u8]) -> Option<Vec<(&'a TemplateField, &'a [u8])>> {
+        let mut values = Vec::with_capacity(self.fields.len());
+        let mut offset = 0;
+        for field in &self.fields {
+            let mut length = field.length as usize;
+            if field.length == VARIABLE_LENGTH {
+                // one length byte, or 255 followed by a u16 length
+                length = *data.get(offset)? as usize;
+                offset += 1;
+                if length == 255 {
+                    length = u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]) as usize;
+                    offset += 2;
+                }
+            }
+            values.push((field, data.get(offset..offset + length)?));
+            offset += length;
+        }
+        Some(values)
+    }
+
+    /// Decodes the sampler described by an option data record of a NetFlow v9
+    /// or IPFIX sampling option template.
+    ///
+    /// Both the NetFlow v9 elements (sampler id, mode and random interval, or
+    /// the exporter wide sampling interval and algorithm) and the IPFIX
+    /// selector elements of RFC 5477 are understood. Samplers without id apply
+    /// to the whole exporter and get id -1. `exporter_sysid` is left 0.
+    /// Returns `None` if the record carries no sampling interval.
+    pub fn decode_sampler(&self, data: &[u8]) -> Option<SamplerRecord> {
+        let mut id = -1;
+        let mut interval = None;
+        let mut packet_interval = None;
+        let mut space_interval = None;
+        let mut algorithm = 0;
+        for (field, value) in self.values(data)? {
+            if field.enterprise.is_some() {
+                continue;
+            }
+            let v = unsigned(value);
+            match field.id {
+                IE_SAMPLER_ID | IE_SELECTOR_ID => id = v as i64,
+                IE_SAMPLING_INTERVAL | IE_SAMPLER_RANDOM_INTERVAL => interval = Some(v as u32),
+                IE_SAMPLING_ALGORITHM | IE_SAMPLER_MODE | IE_SELECTOR_ALGORITHM => algorithm = v as u16,
+                IE_SAMPLING_PACKET_INTERVAL => packet_interval = Some(v as u32),
+                IE_SAMPLING_PACKET_SPACE => space_interval = Some(v as u32),
+                _ => {}
+            }
+        }
+
+        // 1 out of `interval` packets unless the intervals are given
+        let (packet_interval, space_interval) = match (packet_interval, space_interval, interval) {
+            (Some(p), Some(s), _) => (p, s),
+            (_, _, Some(i)) if i > 0 => (1, i - 1),
+            _ => return None,
+        };
+        Some(SamplerRecord {
+            header: NfFileRecordHeader::default(),
+            id,
+            packet_interval,
+            space_interval,
+            algorithm,
+            exporter_sysid: 0,
+        })
+    }
+
+    /// Decodes exporter statistics from an option data record of an IPFIX
+    /// exporting process reliability statistics template (RFC 7011).
+    ///
+    /// `flows` is the number of flow records the exporter reports having
+    /// sent since it started. The other counters of nfdump's exporter
+    /// statistics count what the collector received and have no IPFIX
+    /// counterpart, so they are left 0, as is `sysid`. Returns `None` if the
+    /// record carries no flow count.
+    pub fn decode_exporter_stat(&self, data: &[u8]) -> Option<ExporterStat> {
+        let mut stat = ExporterStat::default();
+        let mut found = false;
+        for (field, value) in self.values(data)? {
+            if field.enterprise.is_none() && field.id == IE_EXPORTED_FLOW_RECORD_TOTAL_COUNT {
+                stat.flows = unsigned(value);
+                found = true;
+            }
+        }
+        found.then_some(stat)
+    }
+}
+
+/// Decodes an unsigned big endian value of up to 8 bytes, as used by reduced
+/// size encoding.
+fn unsigned(value: &[u8]) -> u64 {
+    let value = &value[value.len().saturating_sub(8)..];
+    value.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+/// `TemplateKey` identifies a template: templates are scoped to the exporter
+/// and its source id (NetFlow v9) or observation domain (IPFIX).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TemplateKey {
+    pub exporter: IpAddr,
+    pub source_id: u32,
+    pub template_id: u16,
+}
+
+/// `TemplateCache` holds the templates learned from exporters.
+///
+/// The cache can be saved to disk and loaded on restart, so data records that
+/// arrive before the exporter retransmits its templates can still be decoded.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::template::{Template, TemplateCache, TemplateField, TemplateKey};
+///
+/// let mut cache = TemplateCache::load_or_default("templates.cache").unwrap();
+/// let key = TemplateKey {
+///     exporter: "192.0.2.1".parse().unwrap(),
+///     source_id: 0,
+///     template_id: 256,
+/// };
+/// cache.insert(key, Template {
+///     id: 256,
+///     scope_field_count: 0,
+///     fields: vec![TemplateField { id: 8, length: 4, enterprise: None }],
+/// });
+/// cache.save("templates.cache").unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TemplateCache {
+    templates: HashMap<TemplateKey, Template>,
+}
+
+impl TemplateCache {
+    /// Creates an empty cache.
+    pub fn new() -> TemplateCache {
+        TemplateCache::default()
+    }
+
+    /// Adds or replaces a template.
+    pub fn insert(&mut self, key: TemplateKey, template: Template) {
+        self.templates.insert(key, template);
+    }
+
+    /// Returns the template with the given key.
+    pub fn get(&self, key: &TemplateKey) -> Option<&Template> {
+        self.templates.get(key)
+    }
+
+    /// Removes a template, e.g. after a template withdrawal.
+    pub fn remove(&mut self, key: &TemplateKey) -> Option<Template> {
+        self.templates.remove(key)
+    }
+
+    /// Removes all templates of an exporter's source id.
+    pub fn remove_source(&mut self, exporter: IpAddr, source_id: u32) {
+        self.templates.retain(|k, _| k.exporter != exporter || k.source_id != source_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Returns an iterator over the templates and their keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&TemplateKey, &Template)> {
+        self.templates.iter()
+    }
+
+    /// Writes the cache to `path`. The file is replaced atomically, so a crash
+    /// while saving keeps the previous cache.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), NfdumpError> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        self.write_to(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a cache written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TemplateCache, NfdumpError> {
+        TemplateCache::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a cache written by `save`, or returns an empty cache if `path`
+    /// does not exist.
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<TemplateCache, NfdumpError> {
+        match File::open(path) {
+            Ok(file) => TemplateCache::read_from(&mut BufReader::new(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(TemplateCache::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Serializes the cache.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), NfdumpError> {
+        w.write_all(CACHE_MAGIC)?;
+        w.write_u16::<LittleEndian>(CACHE_VERSION)?;
+        w.write_u32::<LittleEndian>(self.templates.len() as u32)?;
+
+        // sorted for reproducible files
+        let mut keys: Vec<&TemplateKey> = self.templates.keys().collect();
+        keys.sort();
+        for key in keys {
+            let template = &self.templates[key];
+            match key.exporter {
+                IpAddr::V4(a) => {
+                    w.write_u8(4)?;
+                    w.write_all(&a.octets())?;
+                }
+                IpAddr::V6(a) => {
+                    w.write_u8(6)?;
+                    w.write_all(&a.octets())?;
+                }
+            }
+            w.write_u32::<LittleEndian>(key.source_id)?;
+            w.write_u16::<LittleEndian>(key.template_id)?;
+            w.write_u16::<LittleEndian>(template.id)?;
+            w.write_u16::<LittleEndian>(template.scope_field_count)?;
+            w.write_u16::<LittleEndian>(template.fields.len() as u16)?;
+            for field in &template.fields {
+                match field.enterprise {
+                    Some(enterprise) => {
+                        w.write_u16::<LittleEndian>(field.id | ENTERPRISE_BIT)?;
+                        w.write_u16::<LittleEndian>(field.length)?;
+                        w.write_u32::<LittleEndian>(enterprise)?;
+                    }
+                    None => {
+                        w.write_u16::<LittleEndian>(field.id)?;
+                        w.write_u16::<LittleEndian>(field.length)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserializes a cache written by `write_to`.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<TemplateCache, NfdumpError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != CACHE_MAGIC {
+            return Err(NfdumpError::InvalidFile);
+        }
+        if r.read_u16::<LittleEndian>()? != CACHE_VERSION {
+            return Err(NfdumpError::UnsupportedVersion);
+        }
+
+        let mut cache = TemplateCache::new();
+        for _ in 0..r.read_u32::<LittleEndian>()? {
+            let exporter = match r.read_u8()? {
+                4 => {
+                    let mut octets = [0; 4];
+                    r.read_exact(&mut octets)?;
+                    IpAddr::from(Ipv4Addr::from(octets))
+                }
+                6 => {
+                    let mut octets = [0; 16];
+                    r.read_exact(&mut octets)?;
+                    IpAddr::from(Ipv6Addr::from(octets))
+                }
+                _ => return Err(NfdumpError::ParseError),
+            };
+            let key = TemplateKey {
+                exporter,
+                source_id: r.read_u32::<LittleEndian>()?,
+                template_id: r.read_u16::<LittleEndian>()?,
+            };
+            let id = r.read_u16::<LittleEndian>()?;
+            let scope_field_count = r.read_u16::<LittleEndian>()?;
+            let num_fields = r.read_u16::<LittleEndian>()?;
+            let mut fields = Vec::with_capacity(num_fields as usize);
+            for _ in 0..num_fields {
+                let id = r.read_u16::<LittleEndian>()?;
+                let length = r.read_u16::<LittleEndian>()?;
+                let enterprise = match id & ENTERPRISE_BIT {
+                    0 => None,
+                    _ => Some(r.read_u32::<LittleEndian>()?),
+                };
+                fields.push(TemplateField {
+                    id: id & !ENTERPRISE_BIT,
+                    length,
+                    enterprise,
+                });
+            }
+            cache.insert(key, Template { id, scope_field_count, fields });
+        }
+        Ok(cache)
+    }
+}
@@ -0,0 +1,377 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use byteorder::{LittleEndian, WriteBytesExt};
+use crate::compress::{compress, Compression, CompressionSettings};
+use crate::error::NfdumpError;
+use crate::flow::FlowRecord;
+use crate::nffilev2::StatRecordV2;
+use crate::nfx::extension_map_bytes;
+use crate::nfx_v3::{
+    ExAsRouting, ExBgpNextHopIpv4, ExBgpNextHopIpv6, ExCntFlow, ExFlowMisc, ExGenericFlow, ExIpNextHopIpv4,
+    ExIpNextHopIpv6, ExIpv4Flow, ExIpv6Flow, ExVlan, RecordV3,
+};
+use crate::record::{NfFileRecordHeader, Record};
+use crate::writer::NfFileWriter;
+
+// start of the generated flows, 2023-11-14 22:13:20 UTC
+const BASE_MSEC: u64 = 1_700_000_000_000;
+// uncompressed size of the data blocks of v1 files
+const V1_BLOCK_SIZE: usize = 1048576;
+const DATA_BLOCK_TYPE_2: u16 = 2;
+
+/// `Extension` is an optional part of the generated records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Extension {
+    /// Input and output interfaces.
+    FlowMisc,
+    /// Output counters of bidirectional flows. Not stored in v1 files.
+    CntFlow,
+    /// Source and destination VLAN. Not stored in v1 files.
+    Vlan,
+    /// Source and destination AS numbers.
+    AsRouting,
+    /// IP next hop. Not stored in v1 files.
+    NextHop,
+    /// BGP next hop. Not stored in v1 files.
+    BgpNextHop,
+}
+
+/// splitmix64, a small deterministic generator
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+/// `FileGenerator` fabricates valid nfdump files with synthetic flows, as
+/// fixtures for tests of code built on this crate.
+///
+/// Records are derived from the seed and their index only, so the same
+/// settings always produce the same records, and `record` returns what a
+/// reader will find in the file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Cursor;
+/// use nfdump::{Compression, NfFileReader};
+/// use nfdump::testgen::{Extension, FileGenerator};
+///
+/// let mut generator = FileGenerator::new(2);
+/// generator.set_records(10_000);
+/// generator.set_compression(Compression::Lz4);
+/// generator.set_extensions(&[Extension::FlowMisc, Extension::AsRouting]);
+/// let file = generator.write(Cursor::new(Vec::new())).unwrap().into_inner();
+///
+/// let mut reader = NfFileReader::new(Cursor::new(file)).unwrap();
+/// assert_eq!(reader.count_records().unwrap(), 10_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileGenerator {
+    version: u16,
+    records: u64,
+    compression: Compression,
+    extensions: Vec<Extension>,
+    mixed: bool,
+    ipv6_ratio: f64,
+    seed: u64,
+    ident: String,
+}
+
+impl FileGenerator {
+    /// Creates a generator for files of layout `version` (1 or 2), with 1000
+    /// uncompressed IPv4 records without extensions.
+    pub fn new(version: u16) -> FileGenerator {
+        FileGenerator {
+            version,
+            records: 1000,
+            compression: Compression::None,
+            extensions: Vec::new(),
+            mixed: false,
+            ipv6_ratio: 0.0,
+            seed: 0,
+            ident: String::from("testgen"),
+        }
+    }
+
+    /// Sets the number of flow records.
+    pub fn set_records(&mut self, records: u64) {
+        self.records = records;
+    }
+
+    /// Sets the compression. v1 files can not be zstd compressed.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// Sets the extensions records carry.
+    pub fn set_extensions(&mut self, extensions: &[Extension]) {
+        self.extensions = extensions.to_vec();
+    }
+
+    /// Gives each record a random subset of the extensions instead of all of
+    /// them, so v1 files get several extension maps.
+    pub fn set_mixed_extensions(&mut self, mixed: bool) {
+        self.mixed = mixed;
+    }
+
+    /// Sets the share of IPv6 flows, from 0.0 to 1.0.
+    pub fn set_ipv6_ratio(&mut self, ratio: f64) {
+        self.ipv6_ratio = ratio.clamp(0.0, 1.0);
+    }
+
+    /// Sets the seed records are derived from.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Sets the identification string of the file.
+    pub fn set_ident(&mut self, ident: &str) {
+        self.ident = ident.to_string();
+    }
+
+    /// Returns record `index` as written to v2 files. v1 files hold its
+    /// common record translation, without the extensions v1 can not store.
+    pub fn record(&self, index: u64) -> RecordV3 {
+        let mut rng = Rng(self.seed ^ index.wrapping_mul(0x2545f4914f6cdd1d));
+        for _ in 0..2 {
+            rng.next();
+        }
+
+        let msec_first = BASE_MSEC + index * 10 + rng.below(1000);
+        let packets = 1 + rng.below(1000);
+        let (proto, src_port, dst_port, tcp_flags) = match rng.below(10) {
+            0..=5 => (6, 1024 + rng.below(64512) as u16, [22, 80, 443, 8080][rng.below(4) as usize], rng.below(64) as u8),
+            6..=8 => (17, 1024 + rng.below(64512) as u16, [53, 123, 443, 514][rng.below(4) as usize], 0),
+            // ICMP echo request, the type in the high byte of the destination port
+            _ => (1, 0, 8 << 8, 0),
+        };
+        let v6 = (rng.below(1_000_000) as f64) < self.ipv6_ratio * 1_000_000.0;
+        let proto = if v6 && proto == 1 { 58 } else { proto };
+
+        let mut r = RecordV3 {
+            generic_flow: Some(ExGenericFlow {
+                msec_first,
+                msec_last: msec_first + rng.below(60_000),
+                msec_received: 0,
+                in_packets: packets,
+                in_bytes: packets * (40 + rng.below(1460)),
+                src_port,
+                dst_port,
+                proto,
+                tcp_flags,
+                fwd_status: 0,
+                src_tos: 0,
+            }),
+            ..Default::default()
+        };
+        if v6 {
+            r.ipv6_flow = Some(ExIpv6Flow {
+                src_addr: Ipv6Addr::from(0x2001_0db8_0000_0000_0000_0000_0000_0000u128 | rng.below(1 << 32) as u128),
+                dst_addr: Ipv6Addr::from(0x2001_0db8_0001_0000_0000_0000_0000_0000u128 | rng.below(1 << 16) as u128),
+            });
+        } else {
+            r.ipv4_flow = Some(ExIpv4Flow {
+                src_addr: Ipv4Addr::from(0x0a00_0000 | rng.below(1 << 24) as u32),
+                dst_addr: Ipv4Addr::from(0xc0a8_0000 | rng.below(1 << 16) as u32),
+            });
+        }
+
+        for &ext in &self.extensions {
+            if self.mixed && rng.below(2) == 0 {
+                continue;
+            }
+            match ext {
+                Extension::FlowMisc => {
+                    r.flow_misc = Some(ExFlowMisc {
+                        input: 1 + rng.below(48) as u32,
+                        output: 1 + rng.below(48) as u32,
+                        src_mask: 24,
+                        dst_mask: 16,
+                        dir: 0,
+                        dst_tos: 0,
+                        bi_flow_dir: 0,
+                        flow_end_reason: 0,
+                        rev_tcp_flags: 0,
+                        fill: 0,
+                    })
+                }
+                Extension::CntFlow => {
+                    let out_packets = rng.below(1000);
+                    r.cnt_flow = Some(ExCntFlow {
+                        flows: 1,
+                        out_packets,
+                        out_bytes: out_packets * (40 + rng.below(1460)),
+                    })
+                }
+                Extension::Vlan => {
+                    r.vlan = Some(ExVlan {
+                        src_vlan: rng.below(4095) as u32,
+                        dst_vlan: rng.below(4095) as u32,
+                    })
+                }
+                Extension::AsRouting => {
+                    r.as_routing = Some(ExAsRouting {
+                        src_as: 64512 + rng.below(1023) as u32,
+                        dst_as: 64512 + rng.below(1023) as u32,
+                    })
+                }
+                Extension::NextHop if v6 => r.ip_next_hop_ipv6 = Some(ExIpNextHopIpv6 { ip: Ipv6Addr::from(0x2001_0db8_ffff_0000_0000_0000_0000_0001u128) }),
+                Extension::NextHop => r.ip_next_hop_ipv4 = Some(ExIpNextHopIpv4 { ip: Ipv4Addr::new(192, 0, 2, 1 + rng.below(254) as u8) }),
+                Extension::BgpNextHop if v6 => r.bgp_next_hop_ipv6 = Some(ExBgpNextHopIpv6 { ip: Ipv6Addr::from(0x2001_0db8_ffff_0000_0000_0000_0000_0002u128) }),
+                Extension::BgpNextHop => r.bgp_next_hop_ipv4 = Some(ExBgpNextHopIpv4 { ip: Ipv4Addr::new(198, 51, 100, 1 + rng.below(254) as u8) }),
+            }
+        }
+        r
+    }
+
+    /// Writes the file and returns the writer.
+    ///
+    /// Fails with `NfdumpError::UnsupportedVersion` for versions other than
+    /// 1 and 2, and with `NfdumpError::UnsupportedCompression` for zstd
+    /// compressed v1 files.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<W, NfdumpError> {
+        match self.version {
+            1 => self.write_v1(writer),
+            2 => {
+                let mut w = NfFileWriter::new(writer, self.compression)?;
+                w.set_ident(&self.ident);
+                for i in 0..self.records {
+                    w.write_record(&self.record(i))?;
+                }
+                w.finish()
+            }
+            _ => Err(NfdumpError::UnsupportedVersion),
+        }
+    }
+
+    /// Writes the file to `path`.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) -> Result<(), NfdumpError> {
+        let w = self.write(BufWriter::new(File::create(path)?))?;
+        w.into_inner().map_err(|e| NfdumpError::from(e.into_error()))?;
+        Ok(())
+    }
+
+    fn write_v1<W: Write + Seek>(&self, mut writer: W) -> Result<W, NfdumpError> {
+        // v1 headers flag the compression instead of naming it
+        let flags = match self.compression {
+            Compression::None => 0x00,
+            Compression::Lzo => 0x01,
+            Compression::Bz2 => 0x08,
+            Compression::Lz4 => 0x10,
+            Compression::Zstd => return Err(NfdumpError::UnsupportedCompression),
+        };
+        let settings = CompressionSettings::new(self.compression);
+
+        let mut stat = StatRecordV2::default();
+        let mut maps: Vec<Vec<u16>> = Vec::new();
+        let mut blocks: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut block = Vec::new();
+        let mut block_records = 0;
+        for i in 0..self.records {
+            let record = common_record(&self.record(i));
+            stat.add_record(&record);
+
+            let ex_id = record.extension_ids();
+            let map_id = match maps.iter().position(|m| *m == ex_id) {
+                Some(id) => id as u16,
+                None => {
+                    maps.push(ex_id.clone());
+                    let id = (maps.len() - 1) as u16;
+                    block.extend_from_slice(&extension_map_bytes(id, &ex_id));
+                    block_records += 1;
+                    id
+                }
+            };
+            block.extend_from_slice(&record.to_bytes(map_id));
+            block_records += 1;
+
+            if block.len() > V1_BLOCK_SIZE - 256 {
+                blocks.push((block_records, compress(&settings, &block)?));
+                block.clear();
+                block_records = 0;
+            }
+        }
+        if block_records > 0 {
+            blocks.push((block_records, compress(&settings, &block)?));
+        }
+
+        writer.write_u16::<LittleEndian>(0xa50c)?;
+        writer.write_u16::<LittleEndian>(0x0001)?;
+        writer.write_u32::<LittleEndian>(flags)?;
+        writer.write_u32::<LittleEndian>(blocks.len() as u32)?;
+        let mut ident = [0; 128];
+        let len = self.ident.len().min(127);
+        ident[..len].copy_from_slice(&self.ident.as_bytes()[..len]);
+        writer.write_all(&ident)?;
+
+        for v in [
+            stat.flows, stat.bytes, stat.packets,
+            stat.flows_tcp, stat.flows_udp, stat.flows_icmp, stat.flows_other,
+            stat.bytes_tcp, stat.bytes_udp, stat.bytes_icmp, stat.bytes_other,
+            stat.packets_tcp, stat.packets_udp, stat.packets_icmp, stat.packets_other,
+        ] {
+            writer.write_u64::<LittleEndian>(v)?;
+        }
+        writer.write_u32::<LittleEndian>((stat.first_seen / 1000) as u32)?;
+        writer.write_u32::<LittleEndian>((stat.last_seen / 1000) as u32)?;
+        writer.write_u16::<LittleEndian>((stat.first_seen % 1000) as u16)?;
+        writer.write_u16::<LittleEndian>((stat.last_seen % 1000) as u16)?;
+        writer.write_u32::<LittleEndian>(0)?;
+
+        for (num_records, data) in blocks {
+            writer.write_u32::<LittleEndian>(num_records)?;
+            writer.write_u32::<LittleEndian>(data.len() as u32)?;
+            writer.write_u16::<LittleEndian>(DATA_BLOCK_TYPE_2)?;
+            writer.write_u16::<LittleEndian>(0)?;
+            writer.write_all(&data)?;
+        }
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+/// Translates a generated record into a v1 common record.
+fn common_record(r: &RecordV3) -> Record {
+    let first = r.first_msec().unwrap_or(0);
+    let last = r.last_msec().unwrap_or(0);
+    let unspecified = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+    Record {
+        head: NfFileRecordHeader::default(),
+        flags: 0,
+        ext_map: 0,
+        msec_first: (first % 1000) as u16,
+        msec_last: (last % 1000) as u16,
+        first: (first / 1000) as u32,
+        last: (last / 1000) as u32,
+        fwd_status: r.fwd_status().unwrap_or(0),
+        tcp_flags: r.tcp_flags().unwrap_or(0),
+        prot: r.proto().unwrap_or(0),
+        tos: r.tos().unwrap_or(0),
+        src_port: r.src_port().unwrap_or(0),
+        dst_port: r.dst_port().unwrap_or(0),
+        exporter_sysid: r.head.exporter_id,
+        bi_flow_dir: 0,
+        flow_end_reason: 0,
+        src_addr: r.src_addr().unwrap_or(unspecified),
+        dst_addr: r.dst_addr().unwrap_or(unspecified),
+        packets: r.packets().unwrap_or(0),
+        bytes: r.bytes().unwrap_or(0),
+        input: r.input(),
+        output: r.output(),
+        src_as: r.src_as(),
+        dst_as: r.dst_as(),
+    }
+}
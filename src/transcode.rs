@@ -0,0 +1,99 @@
+use std::io::{Read, Seek, Write};
+use crate::compress::Compression;
+use crate::error::NfdumpError;
+use crate::nffilev2::StatRecordV2;
+use crate::nfx_v3::RecordV3;
+use crate::record::RecordKind;
+use crate::writer::NfFileWriter;
+use crate::{NfFileHeader, NfFileReader, StatRecord};
+
+/// Rewrites a v2 file with a different compression, like `nfdump -J`.
+///
+/// Data and appendix blocks are decompressed and recompressed as a whole;
+/// individual records are never decoded, so records of any type are copied
+/// unchanged. Block flags other than `UNCOMPRESSED` are carried over; the
+/// ident, stat record and creation time are preserved. Returns the
+/// destination writer once the new file is complete.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::Compression;
+/// use nfdump::transcode::transcode;
+///
+/// let src = File::open("path_to_your_file").unwrap();
+/// let dst = File::create("path_to_new_file").unwrap();
+/// transcode(src, dst, Compression::Zstd).unwrap();
+/// ```
+pub fn transcode<R: Read + Seek, W: Write + Seek>(
+    src: R,
+    dst: W,
+    compression: Compression,
+) -> Result<W, NfdumpError> {
+    let mut reader = NfFileReader::new(src)?;
+    let created = match &reader.header {
+        NfFileHeader::V1(_) => return Err(NfdumpError::UnsupportedVersion),
+        NfFileHeader::V2(h) => h.created,
+    };
+
+    let mut writer = NfFileWriter::new(dst, compression)?;
+    writer.set_created(created);
+
+    let appendix = reader.read_raw_appendix()?;
+    while let Some((num_records, flags, data)) = reader.read_raw_block()? {
+        writer.write_raw_block(num_records, flags, data)?;
+    }
+
+    writer.finish_with_appendix(appendix)
+}
+
+/// Converts a v1 file into a v2 file with the given compression.
+///
+/// Legacy common records are translated to the V3 record layout, and the
+/// ident and stat record move from the v1 header into the appendix.
+/// Other legacy records such as extension maps are not carried over.
+/// Returns the destination writer once the new file is complete.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use nfdump::Compression;
+/// use nfdump::transcode::upgrade;
+///
+/// let src = BufReader::new(File::open("path_to_your_v1_file").unwrap());
+/// let dst = File::create("path_to_new_file").unwrap();
+/// upgrade(src, dst, Compression::Zstd).unwrap();
+/// ```
+pub fn upgrade<R: Read + Seek, W: Write + Seek>(
+    src: R,
+    dst: W,
+    compression: Compression,
+) -> Result<W, NfdumpError> {
+    let mut reader = NfFileReader::new(src)?;
+    if !matches!(reader.header, NfFileHeader::V1(_)) {
+        return Err(NfdumpError::UnsupportedVersion);
+    }
+
+    let mut writer = NfFileWriter::new(dst, compression)?;
+    let ident = reader.get_ident();
+    let end = ident.iter().position(|&b| b == 0).unwrap_or(ident.len());
+    writer.set_ident(&String::from_utf8_lossy(&ident[..end]));
+    loop {
+        match reader.read_record() {
+            Ok(RecordKind::Record(r)) => writer.write_record(&RecordV3::from(&r))?,
+            Ok(RecordKind::RecordV3(r)) => writer.write_record(&r)?,
+            Ok(_) => {}
+            Err(NfdumpError::EOF) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    // keep the original counters rather than the recounted ones
+    if let StatRecord::V1(stat) = &reader.stat_record {
+        writer.set_stat_record(StatRecordV2::from(stat));
+    }
+    writer.finish()
+}
@@ -0,0 +1,205 @@
+use std::net::IpAddr;
+use crate::filter::Filter;
+use crate::flow::FlowRecord;
+use crate::nfx_v3::RecordV3;
+
+/// `FlowLayer` holds the endpoints of one layer of a tunnelled flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowLayer {
+    pub src_addr: IpAddr,
+    pub dst_addr: IpAddr,
+    pub proto: Option<u8>,
+}
+
+/// `Tunnel` splits a tunnelled flow into the tunnel endpoints (outer) and
+/// the encapsulated flow (inner).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tunnel {
+    pub outer: FlowLayer,
+    pub inner: Option<FlowLayer>,
+}
+
+impl RecordV3 {
+    /// Returns the outer and inner flow of a record carrying a tunnel
+    /// extension, or `None` if the flow is not tunnelled.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use nfdump::NfFileReader;
+    /// use nfdump::record::RecordKind;
+    ///
+    /// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+    /// if let Ok(RecordKind::RecordV3(record)) = reader.read_record() {
+    ///     if let Some(tunnel) = record.tunnel() {
+    ///         println!("{} -> {} carries {:?}", tunnel.outer.src_addr, tunnel.outer.dst_addr, tunnel.inner);
+    ///     }
+    /// }
+    /// ```
+    pub fn tunnel(&self) -> Option<Tunnel> {
+        let outer = match (&self.tun_ipv4, &self.tun_ipv6) {
+            (Some(t), _) => FlowLayer {
+                src_addr: IpAddr::V4(t.src_addr),
+                dst_addr: IpAddr::V4(t.dst_addr),
+                proto: Some(t.proto),
+            },
+            (None, Some(t)) => FlowLayer {
+                src_addr: IpAddr::V6(t.src_addr),
+                dst_addr: IpAddr::V6(t.dst_addr),
+                proto: Some(t.proto),
+            },
+            (None, None) => return None,
+        };
+        let inner = match (self.src_addr(), self.dst_addr()) {
+            (Some(src_addr), Some(dst_addr)) => Some(FlowLayer {
+                src_addr,
+                dst_addr,
+                proto: self.proto(),
+            }),
+            _ => None,
+        };
+        Some(Tunnel { outer, inner })
+    }
+}
+
+/// `TunnelLayer` selects which layer of a tunnelled flow a `LayerFilter`
+/// looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunnelLayer {
+    /// The encapsulated flow; for flows without a tunnel, the flow itself.
+    Inner,
+    /// The tunnel endpoints; flows without a tunnel never match.
+    Outer,
+    /// Either layer.
+    Either,
+}
+
+/// `LayerFilter` applies a filter to one layer of tunnelled flows. When
+/// matching the outer layer, the addresses and protocol seen by the inner
+/// filter are those of the tunnel; all other fields are the flow's own.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::filter::AddrField;
+/// use nfdump::prefix::PrefixFilter;
+/// use nfdump::tunnel::{LayerFilter, TunnelLayer};
+///
+/// let vtep = PrefixFilter::new(AddrField::SrcOrDst, ["198.51.100.0/24".parse().unwrap()]);
+/// let filter = LayerFilter::new(TunnelLayer::Outer, vtep);
+/// ```
+pub struct LayerFilter<F: Filter> {
+    layer: TunnelLayer,
+    filter: F,
+}
+
+impl<F: Filter> LayerFilter<F> {
+    /// Creates a filter applying `filter` to `layer`.
+    pub fn new(layer: TunnelLayer, filter: F) -> LayerFilter<F> {
+        LayerFilter { layer, filter }
+    }
+}
+
+impl<F: Filter> Filter for LayerFilter<F> {
+    fn matches(&self, record: &dyn FlowRecord) -> bool {
+        let outer = || {
+            record
+                .tunnel_endpoints()
+                .is_some_and(|layer| self.filter.matches(&OuterView { record, layer }))
+        };
+        match self.layer {
+            TunnelLayer::Inner => self.filter.matches(record),
+            TunnelLayer::Outer => outer(),
+            TunnelLayer::Either => self.filter.matches(record) || outer(),
+        }
+    }
+}
+
+/// Presents the outer layer of a tunnelled flow as a flow record.
+struct OuterView<'a> {
+    record: &'a dyn FlowRecord,
+    layer: FlowLayer,
+}
+
+impl FlowRecord for OuterView<'_> {
+    fn src_addr(&self) -> Option<IpAddr> {
+        Some(self.layer.src_addr)
+    }
+
+    fn dst_addr(&self) -> Option<IpAddr> {
+        Some(self.layer.dst_addr)
+    }
+
+    fn next_hop(&self) -> Option<IpAddr> {
+        self.record.next_hop()
+    }
+
+    fn bgp_next_hop(&self) -> Option<IpAddr> {
+        self.record.bgp_next_hop()
+    }
+
+    fn src_port(&self) -> Option<u16> {
+        self.record.src_port()
+    }
+
+    fn dst_port(&self) -> Option<u16> {
+        self.record.dst_port()
+    }
+
+    fn proto(&self) -> Option<u8> {
+        self.layer.proto
+    }
+
+    fn tcp_flags(&self) -> Option<u8> {
+        self.record.tcp_flags()
+    }
+
+    fn fwd_status(&self) -> Option<u8> {
+        self.record.fwd_status()
+    }
+
+    fn tos(&self) -> Option<u8> {
+        self.record.tos()
+    }
+
+    fn first_msec(&self) -> Option<u64> {
+        self.record.first_msec()
+    }
+
+    fn last_msec(&self) -> Option<u64> {
+        self.record.last_msec()
+    }
+
+    fn packets(&self) -> Option<u64> {
+        self.record.packets()
+    }
+
+    fn bytes(&self) -> Option<u64> {
+        self.record.bytes()
+    }
+
+    fn flows(&self) -> u64 {
+        self.record.flows()
+    }
+
+    fn src_as(&self) -> Option<u32> {
+        self.record.src_as()
+    }
+
+    fn dst_as(&self) -> Option<u32> {
+        self.record.dst_as()
+    }
+
+    fn input(&self) -> Option<u32> {
+        self.record.input()
+    }
+
+    fn output(&self) -> Option<u32> {
+        self.record.output()
+    }
+
+    fn exporter_sysid(&self) -> Option<u16> {
+        self.record.exporter_sysid()
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{Read, Seek};
+use std::net::IpAddr;
+use crate::error::NfdumpError;
+use crate::flow::FlowRecord;
+use crate::record::{RecordKind, RecordPosition};
+use crate::NfFileReader;
+
+// smallest IPv4 header; no IP packet is shorter
+const MIN_PACKET_SIZE: u64 = 20;
+
+/// `Finding` is an impossible or suspicious value in a flow record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finding {
+    /// The flow ends before it starts.
+    LastBeforeFirst { first_msec: u64, last_msec: u64 },
+    /// Bytes are counted without any packet.
+    BytesWithoutPackets { bytes: u64 },
+    /// Fewer bytes than a minimal IP header per packet.
+    TooFewBytes { packets: u64, bytes: u64 },
+    /// The protocol number is reserved (255) or unassigned (146 to 252).
+    ReservedProtocol(u8),
+    /// The source address can not send packets: a multicast, broadcast or
+    /// unspecified address.
+    InvalidSource(IpAddr),
+    /// Source and destination differ in address family.
+    MixedAddressFamilies,
+}
+
+/// Checks a flow record for impossible values, returning an empty list for
+/// a plausible record. Used by `FlowRecord::validate`.
+pub(crate) fn validate_record<R: FlowRecord + ?Sized>(r: &R) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if let (Some(first), Some(last)) = (r.first_msec(), r.last_msec()) {
+        if last < first {
+            findings.push(Finding::LastBeforeFirst { first_msec: first, last_msec: last });
+        }
+    }
+
+    let packets = r.packets().unwrap_or(0);
+    let bytes = r.bytes().unwrap_or(0);
+    if packets == 0 && bytes > 0 {
+        findings.push(Finding::BytesWithoutPackets { bytes });
+    } else if bytes < packets.saturating_mul(MIN_PACKET_SIZE) {
+        findings.push(Finding::TooFewBytes { packets, bytes });
+    }
+
+    if let Some(proto) = r.proto().filter(|p| (146..=252).contains(p) || *p == 255) {
+        findings.push(Finding::ReservedProtocol(proto));
+    }
+
+    if let Some(src) = r.src_addr() {
+        let invalid = src.is_multicast()
+            || src.is_unspecified()
+            || matches!(src, IpAddr::V4(a) if a.is_broadcast());
+        if invalid {
+            findings.push(Finding::InvalidSource(src));
+        }
+    }
+
+    if let (Some(src), Some(dst)) = (r.src_addr(), r.dst_addr()) {
+        if src.is_ipv4() != dst.is_ipv4() {
+            findings.push(Finding::MixedAddressFamilies);
+        }
+    }
+
+    findings
+}
+
+/// `RecordFinding` is a finding together with the record it was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordFinding {
+    /// Position of the record in the file.
+    pub position: Option<RecordPosition>,
+    pub finding: Finding,
+}
+
+/// `ValidationReport` is the result of validating a file.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// Number of flow records checked.
+    pub records: u64,
+    /// Number of records with at least one finding.
+    pub invalid_records: u64,
+    /// Findings, up to the validator's limit.
+    pub findings: Vec<RecordFinding>,
+}
+
+/// `Validator` checks every flow record of a file for impossible values,
+/// for data quality monitoring of collectors and exporters.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::NfFileReader;
+/// use nfdump::validate::Validator;
+///
+/// let mut reader = NfFileReader::new(File::open("path_to_your_file").unwrap()).unwrap();
+/// let report = Validator::new().validate(&mut reader).unwrap();
+/// println!("{} of {} records invalid", report.invalid_records, report.records);
+/// for f in &report.findings {
+///     println!("{:?}: {:?}", f.position, f.finding);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Validator {
+    max_findings: usize,
+}
+
+impl Validator {
+    /// Creates a validator keeping up to 1000 findings.
+    pub fn new() -> Validator {
+        Validator { max_findings: 1000 }
+    }
+
+    /// Sets the number of findings kept in the report. Records are still
+    /// counted once the limit is reached.
+    pub fn set_max_findings(&mut self, max_findings: usize) {
+        self.max_findings = max_findings;
+    }
+
+    /// Validates the remaining records of `reader`.
+    pub fn validate<R: Read + Seek>(&self, reader: &mut NfFileReader<R>) -> Result<ValidationReport, NfdumpError> {
+        let mut report = ValidationReport::default();
+        loop {
+            let findings = match reader.read_record() {
+                Ok(RecordKind::Record(r)) => r.validate(),
+                Ok(RecordKind::RecordV3(r)) => r.validate(),
+                Ok(_) => continue,
+                Err(NfdumpError::EOF) => break,
+                Err(e) => return Err(e),
+            };
+            report.records += 1;
+            if findings.is_empty() {
+                continue;
+            }
+            report.invalid_records += 1;
+            let position = reader.last_record_position();
+            for finding in findings {
+                if report.findings.len() < self.max_findings {
+                    report.findings.push(RecordFinding { position, finding });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator::new()
+    }
+}
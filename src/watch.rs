@@ -0,0 +1,197 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::demux::MultiReader;
+use crate::error::NfdumpError;
+use crate::NfFileReader;
+
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+// number of recently queued files remembered to drop duplicate reports
+const SEEN_WINDOW: usize = 4096;
+
+/// `SpoolWatcher` watches the spool directory of an nfcapd collector and
+/// yields each capture file once it is complete, i.e. renamed from
+/// `nfcapd.current.*` to its final `nfcapd.YYYYMMDDhhmm` name on rotation.
+///
+/// Subdirectories are watched too, for collectors storing files in a
+/// `yyyy/mm/dd` hierarchy. Files already in the directory are only yielded
+/// after `include_existing`. Duplicate reports of a file are dropped as long
+/// as it is among the last 4096 files queued.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nfdump::watch::SpoolWatcher;
+///
+/// let watcher = SpoolWatcher::new("/var/cache/nfdump/router1").unwrap();
+/// let mut reader = watcher.into_multi_reader();
+/// while let Ok(record) = reader.read_record_envelope() {
+///     println!("{:?}", record.record);
+/// }
+/// ```
+pub struct SpoolWatcher {
+    dir: PathBuf,
+    // dropping the watcher stops the events
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    pending: VecDeque<PathBuf>,
+    // the last SEEN_WINDOW files queued, oldest first
+    seen: HashSet<PathBuf>,
+    seen_order: VecDeque<PathBuf>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl SpoolWatcher {
+    /// Starts watching the directory at `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<SpoolWatcher, NfdumpError> {
+        let dir = dir.as_ref().to_path_buf();
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(notify_error)?;
+        watcher.watch(&dir, RecursiveMode::Recursive).map_err(notify_error)?;
+        Ok(SpoolWatcher {
+            dir,
+            _watcher: watcher,
+            events,
+            pending: VecDeque::new(),
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+            cancel: None,
+        })
+    }
+
+    /// Queues the completed files already in the directory, oldest first,
+    /// ahead of those completed from now on.
+    pub fn include_existing(&mut self) -> Result<(), NfdumpError> {
+        let mut files = Vec::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for dirent in fs::read_dir(&dir)? {
+                let dirent = dirent?;
+                let path = dirent.path();
+                if dirent.file_type()?.is_dir() {
+                    dirs.push(path);
+                } else if is_completed_file(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        // the names sort by time within a directory
+        files.sort_by(|a, b| a.file_name().cmp(&b.file_name()).then_with(|| a.cmp(b)));
+        for path in files {
+            self.queue(path);
+        }
+        Ok(())
+    }
+
+    /// Sets a cancellation token. Once it is set to `true`, waiting for a
+    /// file returns `NfdumpError::Cancelled`.
+    pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Returns the next completed file, waiting until there is one.
+    pub fn next_file(&mut self) -> Result<PathBuf, NfdumpError> {
+        loop {
+            if let Some(path) = self.next_file_timeout(CANCEL_CHECK_INTERVAL)? {
+                return Ok(path);
+            }
+        }
+    }
+
+    /// Returns the next completed file, or `None` if there is none within
+    /// `timeout`.
+    pub fn next_file_timeout(&mut self, timeout: Duration) -> Result<Option<PathBuf>, NfdumpError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+                return Err(NfdumpError::Cancelled);
+            }
+            if let Some(path) = self.pending.pop_front() {
+                return Ok(Some(path));
+            }
+            let wait = deadline
+                .saturating_duration_since(Instant::now())
+                .min(CANCEL_CHECK_INTERVAL);
+            match self.events.recv_timeout(wait) {
+                Ok(event) => self.handle(event.map_err(notify_error)?),
+                Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => return Ok(None),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::other("directory watcher stopped").into());
+                }
+            }
+        }
+    }
+
+    /// Turns the watcher into a `MultiReader` that reads the completed
+    /// files one after another as they appear, named by their path. Its
+    /// reads wait for the next file instead of ending; a cancellation token
+    /// set on the watcher beforehand also ends the wait.
+    pub fn into_multi_reader(mut self) -> MultiReader<BufReader<File>> {
+        let mut reader = MultiReader::new();
+        if let Some(cancel) = &self.cancel {
+            reader.set_cancel(cancel.clone());
+        }
+        reader.set_input_feed(Box::new(move || {
+            let path = self.next_file()?;
+            let file = NfFileReader::new(BufReader::new(File::open(&path)?))?;
+            Ok(Some((path.display().to_string(), file)))
+        }));
+        reader
+    }
+
+    fn handle(&mut self, event: Event) {
+        // a file is complete once renamed to its final name, or closed by a
+        // writer that wrote it in place
+        let completed = matches!(
+            event.kind,
+            EventKind::Modify(ModifyKind::Name(_)) | EventKind::Access(AccessKind::Close(AccessMode::Write))
+        );
+        if !completed {
+            return;
+        }
+        for path in event.paths {
+            if is_completed_file(&path) && path.is_file() {
+                self.queue(path);
+            }
+        }
+    }
+
+    fn queue(&mut self, path: PathBuf) {
+        // renames are reported both as the old and the new name on some
+        // platforms
+        if !self.seen.insert(path.clone()) {
+            return;
+        }
+        self.seen_order.push_back(path.clone());
+        if self.seen_order.len() > SEEN_WINDOW {
+            if let Some(old) = self.seen_order.pop_front() {
+                self.seen.remove(&old);
+            }
+        }
+        self.pending.push_back(path);
+    }
+}
+
+// Completed nfcapd files are named `nfcapd.YYYYMMDDhhmm`, with seconds
+// appended for collectors rotating more often than once a minute
+fn is_completed_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    match name.strip_prefix("nfcapd.") {
+        Some(stamp) => (stamp.len() == 12 || stamp.len() == 14) && stamp.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    }
+}
+
+fn notify_error(e: notify::Error) -> NfdumpError {
+    io::Error::other(e).into()
+}
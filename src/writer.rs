@@ -0,0 +1,596 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+use byteorder::{LittleEndian, WriteBytesExt};
+use crate::block::{BlockFlags, TYPE_IDENT};
+use crate::compress::{compress, Compression, CompressionSettings};
+use crate::error::NfdumpError;
+use crate::exporter::{ExporterInfo, ExporterStat, ExporterStatsRecord, SamplerRecord};
+use crate::nffilev2::StatRecordV2;
+use crate::nfx::extension_map_bytes;
+use crate::record::Record;
+use crate::StatRecord;
+use crate::nfx_v3::RecordV3;
+
+const NFFILE_MAGIC: u16 = 0xa50c;
+const NFFILE_VERSION: u16 = 0x0002;
+const DATA_BLOCK_TYPE_3: u16 = 3;
+// uncompressed size at which a data block is closed, as in nfdump
+const WRITE_BLOCK_SIZE: usize = 1048576;
+// window sizes the reader's zstd decoder accepts without raising its limit
+const ZSTD_WINDOW_LOG_MIN: u32 = 10;
+const ZSTD_WINDOW_LOG_MAX: u32 = 27;
+#[cfg(feature = "lz4-hc")]
+const LZ4_HC_LEVEL_MAX: i32 = 12;
+
+struct Block {
+    seq: u64,
+    num_records: u32,
+    flags: BlockFlags,
+    data: Vec<u8>,
+    settings: CompressionSettings,
+}
+
+struct CompressedBlock {
+    seq: u64,
+    num_records: u32,
+    flags: BlockFlags,
+    data: io::Result<Vec<u8>>,
+}
+
+/// Compresses blocks on worker threads. Blocks may finish out of order, the
+/// writer puts them back in sequence before writing them.
+struct WorkerPool {
+    threads: usize,
+    jobs: Option<SyncSender<Block>>,
+    results: Receiver<CompressedBlock>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    fn new(threads: usize) -> WorkerPool {
+        let (job_tx, job_rx) = mpsc::sync_channel::<Block>(threads);
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (result_tx, result_rx) = mpsc::channel();
+
+        let workers = (0..threads)
+            .map(|_| {
+                let job_rx = Arc::clone(&job_rx);
+                let result_tx = result_tx.clone();
+                thread::spawn(move || loop {
+                    let job = job_rx.lock().unwrap().recv();
+                    let Ok(block) = job else { break };
+                    let compressed = CompressedBlock {
+                        seq: block.seq,
+                        num_records: block.num_records,
+                        flags: block.flags,
+                        data: compress(&block.settings, &block.data),
+                    };
+                    if result_tx.send(compressed).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+
+        WorkerPool {
+            threads,
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers,
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // closing the job channel stops the workers
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            _ = worker.join();
+        }
+    }
+}
+
+/// `NfFileWriter` writes nfdump v2 files.
+///
+/// Records are collected into data blocks which are compressed either inline
+/// or, after `set_threads`, on worker threads while the caller keeps appending
+/// records. Blocks are always written in the order they were filled.
+///
+/// Until the file is finished its header claims no data blocks, so an
+/// interrupted file reads as empty rather than as a file without appendix.
+/// `finish` writes the appendix and patches the header; dropping an unfinished
+/// writer does the same on a best-effort basis, ignoring any errors.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use nfdump::Compression;
+/// use nfdump::writer::NfFileWriter;
+/// use nfdump::nfx_v3::RecordV3;
+///
+/// let file = File::create("path_to_your_file").unwrap();
+/// let mut writer = NfFileWriter::new(file, Compression::Zstd).unwrap();
+/// writer.set_threads(4).unwrap();
+/// writer.write_record(&RecordV3::default()).unwrap();
+/// writer.finish().unwrap();
+/// ```
+pub struct NfFileWriter<W: Write + Seek> {
+    // only taken by `finish`
+    writer: Option<W>,
+    settings: CompressionSettings,
+    ident: String,
+    created: u64,
+    block: Vec<u8>,
+    block_records: u32,
+    // flags of the current block, only set for raw blocks
+    block_flags: BlockFlags,
+    num_blocks: u32,
+    pool: Option<WorkerPool>,
+    next_seq: u64,
+    next_write: u64,
+    pending: BTreeMap<u64, (u32, BlockFlags, Vec<u8>)>,
+    stat_record: StatRecordV2,
+    // extension maps emitted so far, keyed by their extension ids
+    extension_maps: HashMap<Vec<u16>, u16>,
+    // exporters and samplers registered but not written yet, by exporter sysid
+    pending_exporters: HashMap<u16, ExporterInfo>,
+    pending_samplers: HashMap<u16, Vec<SamplerRecord>>,
+    written_exporters: HashSet<u16>,
+    // the header on disk does not describe the file yet
+    dirty: bool,
+}
+
+impl<W: Write + Seek> NfFileWriter<W> {
+    /// Creates a new `NfFileWriter` and writes a provisional file header.
+    pub fn new(mut writer: W, compression: Compression) -> Result<Self, NfdumpError> {
+        writer.seek(SeekFrom::Start(0))?;
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut ret = Self {
+            writer: Some(writer),
+            settings: CompressionSettings::new(compression),
+            ident: String::new(),
+            created,
+            block: Vec::with_capacity(WRITE_BLOCK_SIZE),
+            block_records: 0,
+            block_flags: BlockFlags::default(),
+            num_blocks: 0,
+            pool: None,
+            next_seq: 0,
+            next_write: 0,
+            pending: BTreeMap::new(),
+            stat_record: StatRecordV2::default(),
+            extension_maps: HashMap::new(),
+            pending_exporters: HashMap::new(),
+            pending_samplers: HashMap::new(),
+            written_exporters: HashSet::new(),
+            dirty: true,
+        };
+        ret.write_header(0, 0)?;
+
+        Ok(ret)
+    }
+
+    /// Sets the identification string stored in the file's appendix.
+    pub fn set_ident(&mut self, ident: &str) {
+        self.ident = ident.to_string();
+    }
+
+    /// Sets the number of worker threads compressing blocks. With 0 threads,
+    /// the default, blocks are compressed inline by the caller.
+    pub fn set_threads(&mut self, threads: usize) -> Result<(), NfdumpError> {
+        if self.pool.as_ref().map_or(0, |p| p.threads) == threads {
+            return Ok(());
+        }
+        self.drain()?;
+        self.pool = match threads {
+            0 => None,
+            n => Some(WorkerPool::new(n)),
+        };
+        Ok(())
+    }
+
+    /// Sets the zstd compression level, trading CPU time for file size.
+    ///
+    /// Higher levels compress better but slower; the default is zstd's
+    /// default level 3, which suits real-time collection. Offline
+    /// recompression of archives can afford levels up to 19 or more. The level
+    /// applies to blocks filled from now on and is ignored by other codecs.
+    pub fn set_zstd_level(&mut self, level: i32) -> Result<(), NfdumpError> {
+        if !zstd::compression_level_range().contains(&level) {
+            return Err(NfdumpError::from(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zstd compression level out of range",
+            )));
+        }
+        self.settings.zstd_level = level;
+        Ok(())
+    }
+
+    /// Sets the base-2 logarithm of the zstd window size, between 10 and 27.
+    ///
+    /// Larger windows can find matches further apart at the cost of memory
+    /// for both compression and decompression. Blocks are at most 1 MiB, so
+    /// windows beyond 2^20 bytes rarely help. By default the window follows
+    /// the compression level.
+    pub fn set_zstd_window_log(&mut self, window_log: u32) -> Result<(), NfdumpError> {
+        if !(ZSTD_WINDOW_LOG_MIN..=ZSTD_WINDOW_LOG_MAX).contains(&window_log) {
+            return Err(NfdumpError::from(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zstd window log out of range",
+            )));
+        }
+        self.settings.zstd_window_log = window_log;
+        Ok(())
+    }
+
+    /// Enables LZ4 high compression mode with the given level (1 to 12), or
+    /// switches back to the fast compressor with `None`.
+    ///
+    /// High compression mode produces smaller blocks at a much higher CPU
+    /// cost, while decompression stays as fast as for regular LZ4 blocks.
+    /// Requires the `lz4-hc` feature.
+    #[cfg(feature = "lz4-hc")]
+    pub fn set_lz4_high_compression(&mut self, level: Option<i32>) -> Result<(), NfdumpError> {
+        let level = level.unwrap_or(0);
+        if !(0..=LZ4_HC_LEVEL_MAX).contains(&level) {
+            return Err(NfdumpError::from(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "lz4 high compression level out of range",
+            )));
+        }
+        self.settings.lz4_hc_level = level;
+        Ok(())
+    }
+
+    /// Registers an exporter. Its exporter info record is written right before
+    /// the first flow record referencing its `sysid`, or when the file is
+    /// finished if no record does.
+    ///
+    /// Registering an exporter again replaces its definition, which is then
+    /// written again before the next record referencing it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::net::IpAddr;
+    /// use nfdump::exporter::ExporterInfo;
+    /// use nfdump::nfx_v3::{RecordHeaderV3, RecordV3};
+    /// use nfdump::record::NfFileRecordHeader;
+    /// use nfdump::Compression;
+    /// use nfdump::writer::NfFileWriter;
+    ///
+    /// let file = File::create("path_to_your_file").unwrap();
+    /// let mut writer = NfFileWriter::new(file, Compression::Zstd).unwrap();
+    /// writer.add_exporter(&ExporterInfo {
+    ///     header: NfFileRecordHeader::default(),
+    ///     version: 9,
+    ///     address: IpAddr::from([192, 0, 2, 1]),
+    ///     sa_family: 2,
+    ///     sysid: 1,
+    ///     id: 0,
+    /// });
+    /// let record = RecordV3 {
+    ///     head: RecordHeaderV3 { exporter_id: 1, ..Default::default() },
+    ///     ..Default::default()
+    /// };
+    /// writer.write_record(&record).unwrap();
+    /// writer.finish().unwrap();
+    /// ```
+    pub fn add_exporter(&mut self, exporter: &ExporterInfo) {
+        self.written_exporters.remove(&exporter.sysid);
+        self.pending_exporters.insert(exporter.sysid, *exporter);
+    }
+
+    /// Registers a sampler of an exporter. It is written together with its
+    /// exporter, or right away if the exporter has been written already.
+    pub fn add_sampler(&mut self, sampler: &SamplerRecord) -> Result<(), NfdumpError> {
+        let sysid = sampler.exporter_sysid;
+        self.pending_samplers.entry(sysid).or_default().push(*sampler);
+        if self.written_exporters.contains(&sysid) {
+            self.write_exporter(sysid)?;
+        }
+        Ok(())
+    }
+
+    /// Appends an exporter stat record with the statistics of one or more
+    /// exporters.
+    pub fn write_exporter_stats(&mut self, stats: &[ExporterStat]) -> Result<(), NfdumpError> {
+        // the record size is a u16
+        for chunk in stats.chunks(2048) {
+            self.append(&ExporterStatsRecord::to_bytes(chunk))?;
+        }
+        Ok(())
+    }
+
+    /// Appends a V3 flow record to the file.
+    pub fn write_record(&mut self, record: &RecordV3) -> Result<(), NfdumpError> {
+        let data = record.to_bytes()?;
+        self.write_exporter(record.head.exporter_id)?;
+        self.append(&data)?;
+        self.stat_record.add_record(record);
+        Ok(())
+    }
+
+    /// Appends a legacy common record to the file.
+    ///
+    /// The extension map describing the record's optional fields is written
+    /// the first time it is needed and reused by later records, so the
+    /// record's `ext_map` and `flags` are ignored.
+    pub fn write_common_record(&mut self, record: &Record) -> Result<(), NfdumpError> {
+        let ex_id = record.extension_ids();
+        let map_id = match self.extension_maps.get(&ex_id) {
+            Some(&map_id) => map_id,
+            None => {
+                let map_id = self.extension_maps.len() as u16;
+                self.append(&extension_map_bytes(map_id, &ex_id))?;
+                self.extension_maps.insert(ex_id, map_id);
+                map_id
+            }
+        };
+        self.write_exporter(record.exporter_sysid)?;
+        self.append(&record.to_bytes(map_id))?;
+        self.stat_record.add_record(record);
+        Ok(())
+    }
+
+    /// Appends a record this crate does not decode, as returned by
+    /// `RecordKind::Unimplemented` with `NfFileReader::set_return_unknown`,
+    /// so copies of a file keep it.
+    ///
+    /// Fails with `NfdumpError::ParseError` if the body does not fit into a
+    /// record.
+    pub fn write_unimplemented(&mut self, rtype: u16, data: &[u8]) -> Result<(), NfdumpError> {
+        let size = u16::try_from(data.len() + 4).map_err(|_| NfdumpError::ParseError)?;
+        let mut record = Vec::with_capacity(size as usize);
+        record.write_u16::<LittleEndian>(rtype)?;
+        record.write_u16::<LittleEndian>(size)?;
+        record.extend_from_slice(data);
+        self.append(&record)
+    }
+
+    /// Returns the statistics of the records written so far, as they will be
+    /// stored in the appendix.
+    pub fn stat_record(&self) -> StatRecord {
+        StatRecord::V2(self.stat_record.clone())
+    }
+
+    /// Flushes all pending blocks, writes the appendix and the final file
+    /// header, and returns the underlying writer.
+    pub fn finish(self) -> Result<W, NfdumpError> {
+        let appendix = self.appendix()?;
+        self.finish_with_appendix(appendix)
+    }
+
+    /// Builds the default appendix holding the ident and stat records.
+    fn appendix(&self) -> Result<Vec<(u32, Vec<u8>)>, NfdumpError> {
+        let mut appendix = Vec::new();
+        let mut ident = self.ident.as_bytes().to_vec();
+        ident.push(0);
+        ident.resize(ident.len().next_multiple_of(4), 0);
+        appendix.write_u16::<LittleEndian>(TYPE_IDENT)?;
+        appendix.write_u16::<LittleEndian>((ident.len() + 4) as u16)?;
+        appendix.extend_from_slice(&ident);
+        appendix.extend_from_slice(&self.stat_record.to_bytes());
+        Ok(vec![(2, appendix)])
+    }
+
+    /// Finishes the file with the given uncompressed appendix blocks, each
+    /// given as its record count and records.
+    pub(crate) fn finish_with_appendix(mut self, appendix: Vec<(u32, Vec<u8>)>) -> Result<W, NfdumpError> {
+        self.finalize(appendix)?;
+        Ok(self.writer.take().expect("writer already taken"))
+    }
+
+    /// Flushes all pending blocks, writes the appendix and patches the file
+    /// header. Only the first call does anything.
+    fn finalize(&mut self, appendix: Vec<(u32, Vec<u8>)>) -> Result<(), NfdumpError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        // a failed attempt is not retried on drop
+        self.dirty = false;
+
+        // exporters no record referenced
+        let mut sysids: Vec<u16> = self.pending_exporters.keys().chain(self.pending_samplers.keys()).copied().collect();
+        sysids.sort_unstable();
+        sysids.dedup();
+        for sysid in sysids {
+            self.write_exporter(sysid)?;
+            for sampler in self.pending_samplers.remove(&sysid).unwrap_or_default() {
+                self.append(&sampler.to_bytes())?;
+            }
+        }
+
+        self.submit_block()?;
+        self.drain()?;
+        self.pool = None;
+
+        let off_appendix = self.writer().stream_position()?;
+        let data_blocks = self.num_blocks;
+        for (num_records, data) in &appendix {
+            let compressed = compress(&self.settings, data)?;
+            self.write_block(*num_records, BlockFlags::default(), &compressed)?;
+        }
+        // appendix blocks are not counted as data blocks
+        self.num_blocks = data_blocks;
+
+        self.writer().seek(SeekFrom::Start(0))?;
+        self.write_header(appendix.len() as u16, off_appendix)?;
+        let w = self.writer();
+        w.seek(SeekFrom::End(0))?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Replaces the stat record written to the appendix. Records written
+    /// afterwards are still added to it.
+    pub(crate) fn set_stat_record(&mut self, stat_record: StatRecordV2) {
+        self.stat_record = stat_record;
+    }
+
+    /// Sets the creation time stored in the file header, in seconds since the epoch.
+    pub(crate) fn set_created(&mut self, created: u64) {
+        self.created = created;
+    }
+
+    /// Appends an already assembled, uncompressed data block. The flags of
+    /// the source block are kept, except `UNCOMPRESSED`, which follows the
+    /// compression of this file.
+    pub(crate) fn write_raw_block(&mut self, num_records: u32, flags: BlockFlags, data: Vec<u8>) -> Result<(), NfdumpError> {
+        self.submit_block()?;
+        self.block = data;
+        self.block_records = num_records;
+        self.block_flags = BlockFlags::from_bits(flags.unknown());
+        self.submit_block()
+    }
+
+    /// Writes the exporter with the given `sysid` and its samplers, if they
+    /// are registered and not written yet.
+    fn write_exporter(&mut self, sysid: u16) -> Result<(), NfdumpError> {
+        if let Some(exporter) = self.pending_exporters.remove(&sysid) {
+            self.append(&exporter.to_bytes())?;
+            self.written_exporters.insert(sysid);
+        }
+        if !self.written_exporters.contains(&sysid) {
+            return Ok(());
+        }
+        for sampler in self.pending_samplers.remove(&sysid).unwrap_or_default() {
+            self.append(&sampler.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, data: &[u8]) -> Result<(), NfdumpError> {
+        if self.block_records > 0 && self.block.len() + data.len() > WRITE_BLOCK_SIZE {
+            self.submit_block()?;
+        }
+        self.block.extend_from_slice(data);
+        self.block_records += 1;
+        Ok(())
+    }
+
+    /// Hands the current block to the compressor and writes whatever blocks
+    /// are ready.
+    fn submit_block(&mut self) -> Result<(), NfdumpError> {
+        if self.block_records == 0 {
+            return Ok(());
+        }
+        let block = Block {
+            seq: self.next_seq,
+            num_records: self.block_records,
+            flags: std::mem::take(&mut self.block_flags),
+            data: std::mem::replace(&mut self.block, Vec::with_capacity(WRITE_BLOCK_SIZE)),
+            settings: self.settings,
+        };
+        self.next_seq += 1;
+        self.block_records = 0;
+
+        let Some(pool) = &self.pool else {
+            let compressed = compress(&block.settings, &block.data)?;
+            self.next_write += 1;
+            return self.write_block(block.num_records, block.flags, &compressed);
+        };
+
+        if let Some(jobs) = &pool.jobs {
+            if jobs.send(block).is_err() {
+                return Err(NfdumpError::from(io::Error::other("compression worker exited")));
+            }
+        }
+
+        // bound the number of blocks held in memory
+        let max_in_flight = 2 * pool.threads as u64;
+        while self.next_seq - self.next_write > max_in_flight {
+            self.receive_block(true)?;
+        }
+        while self.receive_block(false)? {}
+        Ok(())
+    }
+
+    /// Receives one compressed block from the workers and writes all blocks
+    /// that are next in sequence. Returns `false` if no block was available.
+    fn receive_block(&mut self, wait: bool) -> Result<bool, NfdumpError> {
+        let Some(pool) = &self.pool else {
+            return Ok(false);
+        };
+        let compressed = if wait {
+            match pool.results.recv() {
+                Ok(c) => c,
+                Err(_) => return Err(NfdumpError::from(io::Error::other("compression worker exited"))),
+            }
+        } else {
+            match pool.results.try_recv() {
+                Ok(c) => c,
+                Err(_) => return Ok(false),
+            }
+        };
+
+        self.pending.insert(compressed.seq, (compressed.num_records, compressed.flags, compressed.data?));
+        while let Some((num_records, flags, data)) = self.pending.remove(&self.next_write) {
+            self.write_block(num_records, flags, &data)?;
+            self.next_write += 1;
+        }
+        Ok(true)
+    }
+
+    /// Waits until every submitted block has been written.
+    fn drain(&mut self) -> Result<(), NfdumpError> {
+        while self.next_write < self.next_seq {
+            self.receive_block(true)?;
+        }
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer already taken")
+    }
+
+    fn write_block(&mut self, num_records: u32, flags: BlockFlags, data: &[u8]) -> Result<(), NfdumpError> {
+        let w = self.writer();
+        w.write_u32::<LittleEndian>(num_records)?;
+        w.write_u32::<LittleEndian>(data.len() as u32)?;
+        w.write_u16::<LittleEndian>(DATA_BLOCK_TYPE_3)?;
+        w.write_u16::<LittleEndian>(flags.bits())?;
+        w.write_all(data)?;
+        self.num_blocks += 1;
+        Ok(())
+    }
+
+    fn write_header(&mut self, appendix_blocks: u16, off_appendix: u64) -> Result<(), NfdumpError> {
+        let (created, ctype, num_blocks) = (self.created, self.settings.ctype, self.num_blocks);
+        let w = self.writer();
+        w.write_u16::<LittleEndian>(NFFILE_MAGIC)?;
+        w.write_u16::<LittleEndian>(NFFILE_VERSION)?;
+        w.write_u32::<LittleEndian>(0)?;
+        w.write_u64::<LittleEndian>(created)?;
+        w.write_u8(u8::from(ctype))?;
+        w.write_u8(0)?;
+        w.write_u16::<LittleEndian>(appendix_blocks)?;
+        w.write_u32::<LittleEndian>(0)?;
+        w.write_u64::<LittleEndian>(off_appendix)?;
+        w.write_u32::<LittleEndian>(WRITE_BLOCK_SIZE as u32)?;
+        w.write_u32::<LittleEndian>(num_blocks)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Drop for NfFileWriter<W> {
+    fn drop(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        if let Ok(appendix) = self.appendix() {
+            _ = self.finalize(appendix);
+        }
+    }
+}